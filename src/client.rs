@@ -1,18 +1,21 @@
 use std::path::{Path, PathBuf};
-use std::collections::HashMap;
-use std::fs;
+use std::{env, fs};
 
 use clap::{Arg, App, SubCommand, ArgGroup, ArgMatches, AppSettings};
-use json::JsonValue;
+use json::{JsonValue, object};
 use log::{error, LevelFilter};
 
-use crate::inspector::base::BaseInspector;
-use crate::inspector::Inspect;
-use crate::dominator::base::BaseDominator;
-use crate::merge::Merge;
-use crate::validator::{valid_alphabet, valid_int};
-use crate::util::{load_config, init_path};
-use crate::errors::{TerminalError, LayerSwordError, InternalError, raise};
+use crate::inspector::{Inspect, ImageInfo, LayerInfo};
+use crate::plan::{SplitSpec, SplitStrategy, HistoryRule};
+use crate::job::{SplitJob, MergeJob, pick_dominator_and_inspector};
+use crate::verify::{verify_splits, verify_signed};
+use crate::sign::{generate_key, load_trusted_key};
+use crate::format::SplitFormat;
+use crate::config::{SplitConfig, parse_level};
+use crate::validator::{valid_alphabet, valid_int, valid_positive_int, valid_size};
+use crate::path_to_string;
+use crate::util::{init_work_path, extract_tar, parse_size};
+use crate::errors::{TerminalError, LayerSwordError, FileCheckError, InternalError};
 
 /// set logger and decide whether display by argument '**quiet**'
 fn parse_and_set_logger(sub: &ArgMatches) {
@@ -32,53 +35,124 @@ fn parse_and_set_logger(sub: &ArgMatches) {
 }
 
 /// check path or its parent exists and normalize it to standard format
-fn normalize_path(path: PathBuf) -> Result<PathBuf, TerminalError> {
+fn normalize_path(path: PathBuf) -> Result<PathBuf, LayerSwordError> {
     if path.exists() {
-        Ok(raise(fs::canonicalize(path)))
+        Ok(fs::canonicalize(path)?)
     } else {
         Ok(path)
     }
 }
 
+/// parse parent directory of working directory from arguments, temp directory of os by default
+fn parse_work_path(sub: &ArgMatches) -> Result<PathBuf, LayerSwordError> {
+    match sub.value_of("work") {
+        Some(work) => normalize_path(PathBuf::from(work)),
+        None => Ok(env::temp_dir()),
+    }
+}
+
 /// parse target_path, work_path and out_path from arguments
 fn parse_path(sub: &ArgMatches, mode: &str)
-              -> Result<(PathBuf, PathBuf, PathBuf), TerminalError> {
+              -> Result<(PathBuf, PathBuf, PathBuf), LayerSwordError> {
     let target = sub.value_of("target")
         .ok_or_else(|| TerminalError::WithoutArgError {
             arg: format!("target"),
             msg: sub.usage().to_string(),
         })?;
-    let work = sub.value_of("work")
+    // inspect has no output directory
+    let out = match mode {
+        "inspect" => "",
+        _ => sub.value_of("output")
+            .ok_or_else(|| TerminalError::WithoutArgError {
+                arg: "target".to_string(),
+                msg: sub.usage().to_string(),
+            })?
+    };
+
+    let target_path = PathBuf::from(target);
+    let raw_out_path = PathBuf::from(out);
+
+    if !target_path.exists() {
+        return Err(TerminalError::NotExistError { path: target.to_string() }.into());
+    }
+    if !target_path.is_file() && (mode == "split" || mode == "inspect") {
+        return Err(TerminalError::NotFileError { path: target.to_string() }.into());
+    }
+    let work_path = parse_work_path(sub)?;
+    let out_path = normalize_path(raw_out_path.to_path_buf())?;
+    Ok((target_path, work_path, out_path))
+}
+
+/// parse paths of target directories and split files, work_path and out_path
+/// from arguments of merge
+fn parse_merge_path(sub: &ArgMatches)
+                    -> Result<(Vec<PathBuf>, PathBuf, PathBuf), LayerSwordError> {
+    let out = sub.value_of("output")
+        .ok_or_else(|| TerminalError::WithoutArgError {
+            arg: "output".to_string(),
+            msg: sub.usage().to_string(),
+        })?;
+
+    let mut source_vec: Vec<PathBuf> = Vec::new();
+    for target in sub.values_of("target").into_iter().flatten() {
+        let target_path = PathBuf::from(target);
+        if !target_path.exists() {
+            return Err(TerminalError::NotExistError { path: target.to_string() }.into());
+        }
+        if !target_path.is_dir() {
+            return Err(TerminalError::NotDirectoryError { path: target.to_string() }.into());
+        }
+        source_vec.push(target_path);
+    }
+    for split in sub.values_of("split").into_iter().flatten() {
+        let split_path = PathBuf::from(split);
+        if !split_path.exists() {
+            return Err(TerminalError::NotExistError { path: split.to_string() }.into());
+        }
+        if !split_path.is_file() {
+            return Err(TerminalError::NotFileError { path: split.to_string() }.into());
+        }
+        source_vec.push(split_path);
+    }
+    if source_vec.is_empty() {
+        return Err(TerminalError::WithoutArgError {
+            arg: "target".to_string(),
+            msg: sub.usage().to_string(),
+        }.into());
+    }
+    let work_path = parse_work_path(sub)?;
+    let out_path = normalize_path(PathBuf::from(out))?;
+    Ok((source_vec, work_path, out_path))
+}
+
+/// parse store_path, work_path and out_path from arguments of merge from store
+fn parse_store_path(sub: &ArgMatches)
+                    -> Result<(PathBuf, PathBuf, PathBuf), LayerSwordError> {
+    let store = sub.value_of("store")
         .ok_or_else(|| TerminalError::WithoutArgError {
-            arg: format!("work"),
+            arg: "store".to_string(),
             msg: sub.usage().to_string(),
         })?;
     let out = sub.value_of("output")
         .ok_or_else(|| TerminalError::WithoutArgError {
-            arg: format!("target"),
+            arg: "output".to_string(),
             msg: sub.usage().to_string(),
         })?;
 
-    let target_path = PathBuf::from(target);
-    let raw_work_path = PathBuf::from(work);
-    let raw_out_path = PathBuf::from(out);
-
-    if !target_path.exists() {
-        return Err(TerminalError::NotExistError { path: target.to_string() });
+    let store_path = PathBuf::from(store);
+    if !store_path.exists() {
+        return Err(TerminalError::NotExistError { path: store.to_string() }.into());
     }
-    if !target_path.is_file() && mode == "split" {
-        return Err(TerminalError::NotFileError { path: target.to_string() });
-    } else if !target_path.is_dir() && mode == "merge" {
-        return Err(TerminalError::NotDirectoryError { path: target.to_string() });
+    if !store_path.is_dir() {
+        return Err(TerminalError::NotDirectoryError { path: store.to_string() }.into());
     }
-    let work_path = normalize_path(raw_work_path.to_path_buf())?;
-    let out_path = normalize_path(raw_out_path.to_path_buf())?;
-    Ok((target_path, work_path, out_path))
+    let work_path = parse_work_path(sub)?;
+    let out_path = normalize_path(PathBuf::from(out))?;
+    Ok((store_path, work_path, out_path))
 }
 
-/// parse split names and numbers from config file
-fn parse_cfg_from_file(sub: &ArgMatches)
-                       -> Result<(Vec<String>, HashMap<String, i16>), TerminalError> {
+/// parse splits and options of split procedure from config file in json, toml or yaml
+fn parse_cfg_from_file(sub: &ArgMatches) -> Result<SplitConfig, LayerSwordError> {
     let config = sub.value_of("config")
         .ok_or_else(|| TerminalError::WithoutArgError {
             arg: format!("config"),
@@ -86,38 +160,65 @@ fn parse_cfg_from_file(sub: &ArgMatches)
         })?;
     let config_path = Path::new(config);
     if !config_path.exists() {
-        return Err(TerminalError::NotExistError { path: config.to_string() });
+        return Err(TerminalError::NotExistError { path: config.to_string() }.into());
     }
     if !config_path.is_file() {
-        return Err(TerminalError::NotFileError { path: config.to_string() });
+        return Err(TerminalError::NotFileError { path: config.to_string() }.into());
     }
-    let client_config = raise(load_config(config_path));
-
-    let names = match &client_config["names"] {
-        JsonValue::Array(n) => { Ok(n) }
-        _ => { Err(TerminalError::InputConfigError) }
-    }?.iter().map(|v| v.to_string()).collect::<Vec<_>>();
-    let layers = match &client_config["layers"] {
-        JsonValue::Array(names) => { Ok(names) }
-        _ => { Err(TerminalError::InputConfigError) }
-    }?.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+    SplitConfig::load(config_path)
+}
 
-    if names.len() != layers.len() {
-        return Err(TerminalError::BadArgError {
-            arg: format!("names || layers"),
-            msg: format!("count of names '{}' isn't equal to count of layers '{}'",
-                         names.len(), layers.len()),
-        });
+/// parse strategy planning splits by sizes of layers from arguments,
+/// None if splits are given by layer numbers
+fn parse_strategy(sub: &ArgMatches) -> Result<Option<SplitStrategy>, LayerSwordError> {
+    let strategy = match (sub.value_of("strategy"), sub.is_present("max-split-size")) {
+        (Some(strategy), _) => strategy,
+        (None, true) => "greedy",
+        (None, false) => return Ok(None),
+    };
+    if strategy == "greedy" {
+        if sub.is_present("count") {
+            return Err(TerminalError::BadArgError {
+                arg: "count".to_string(),
+                msg: "count of splits is only used by 'balanced' strategy".to_string(),
+            }.into());
+        }
+        let size = sub.value_of("max-split-size")
+            .ok_or_else(|| TerminalError::WithoutArgError {
+                arg: "max-split-size".to_string(),
+                msg: sub.usage().to_string(),
+            })?;
+        let size = parse_size(size).ok_or(InternalError::ConvertError)?;
+        Ok(Some(SplitStrategy::MaxSize(size)))
+    } else {
+        if sub.is_present("max-split-size") {
+            return Err(TerminalError::BadArgError {
+                arg: "max-split-size".to_string(),
+                msg: "max split size is only used by 'greedy' strategy".to_string(),
+            }.into());
+        }
+        let count = sub.value_of("count")
+            .ok_or_else(|| TerminalError::WithoutArgError {
+                arg: "count".to_string(),
+                msg: sub.usage().to_string(),
+            })?
+            .parse::<usize>()
+            .map_err(|_| InternalError::ConvertError)?;
+        Ok(Some(SplitStrategy::Balanced(count)))
     }
+}
 
-    let (split_names, split_map) =
-        prepare_splits_info(names, layers)?;
-    Ok((split_names, split_map))
+/// check whether splits are planned by strategy, references or history rules
+/// rather than given by layer numbers
+fn is_planned(sub: &ArgMatches) -> bool {
+    ["max-split-size", "strategy", "reference", "split-after", "split-by-label"]
+        .iter()
+        .any(|arg| sub.is_present(arg))
 }
 
 /// parse split names and numbers from arguments
 fn parse_cfg_from_cli(sub: &ArgMatches)
-                      -> Result<(Vec<String>, HashMap<String, i16>), TerminalError> {
+                      -> Result<Vec<(String, SplitSpec)>, LayerSwordError> {
     let names = sub
         .values_of("names")
         .ok_or_else(|| TerminalError::WithoutArgError {
@@ -136,60 +237,88 @@ fn parse_cfg_from_cli(sub: &ArgMatches)
         .collect::<Vec<_>>();
 
     if names.len() != layers.len() {
-        TerminalError::BadArgError {
+        return Err(TerminalError::BadArgError {
             arg: format!("names || layers"),
             msg: format!("count of names '{}' isn't equal to count of layers '{}'",
                          names.len(), layers.len()),
-        };
+        }.into());
     }
-    let (split_names, split_map) =
-        prepare_splits_info(names, layers)?;
-    Ok((split_names, split_map))
+    prepare_splits_info(names, layers)
 }
 
-/// inspect split names and numbers
+/// convert split names and numbers into split specs
 fn prepare_splits_info(names: Vec<String>, layers: Vec<String>)
-                       -> Result<(Vec<String>, HashMap<String, i16>), TerminalError> {
-    let mut split_names: Vec<String> = Vec::new();
-    for name in names {
-        if name.len() == 0 {
-            return Err(TerminalError::BadArgError {
-                arg: format!("names"),
-                msg: format!("one of arg names NULL string"),
-            });
-        }
-        split_names.push(name.to_string());
-    }
-    let mut split_map: HashMap<String, i16> = HashMap::new();
-    let mut deduct_split = String::new();
-    for (i, num) in layers.iter().enumerate() {
-        let name = raise(split_names
-            .get(i)
-            .ok_or_else(|| InternalError::KeyError { key: i.to_string() }));
-        let value = raise(num.parse::<i16>());
-        if deduct_split.len() == 0 && value == -1 {
-            deduct_split = name.clone();
-        } else if deduct_split.len() > 0 && value == -1 {
-            return Err(TerminalError::BadArgError {
-                arg: format!("layers"),
-                msg: format!("more than 1 split of -1 layers: '{:?}' and '{:?}'",
-                             deduct_split, name),
-            });
-        } else if value < 1 {
-            return Err(TerminalError::BadArgError {
+                       -> Result<Vec<(String, SplitSpec)>, LayerSwordError> {
+    let mut splits: Vec<(String, SplitSpec)> = Vec::new();
+    for (name, num) in names.into_iter().zip(layers.iter()) {
+        let value = num.parse::<i16>()
+            .map_err(|_| TerminalError::BadArgError {
                 arg: format!("layers"),
-                msg: format!("split number can only be positive or -1, actuall '{:?}'", value),
-            });
-        }
-        split_map.insert(name.clone(), value);
+                msg: format!("layer number '{}' is not an integer", num),
+            })?;
+        let spec = match value {
+            -1 => SplitSpec::Rest,
+            value if value >= 1 => SplitSpec::Count(value as usize),
+            _ => {
+                return Err(TerminalError::BadArgError {
+                    arg: "layers".to_string(),
+                    msg: format!("split number can only be positive or -1, actuall '{:?}'", value),
+                }.into());
+            }
+        };
+        splits.push((name, spec));
     }
-    Ok((split_names, split_map))
+    Ok(splits)
 }
 
-/// choose exact dominator and inspector
-fn pick_dominator_and_inspector()
-    -> (Box<dyn Merge>, Box<dyn Inspect>) {
-    (Box::new(BaseDominator {}), Box::new(BaseInspector {}))
+/// extract image tar file and fetch structure info of layers of all images inside
+fn inspect_layers(inspector: Box<dyn Inspect>, target_path: &Path, work_path: &Path)
+                  -> Result<Vec<(ImageInfo, Vec<LayerInfo>)>, LayerSwordError> {
+    let mut extract_path = work_path.to_path_buf();
+    extract_path.push("merge");
+    if target_path.extension().unwrap_or_default() != "tar" {
+        return Err(FileCheckError::FileExtensionError {
+            extension: "tar".to_string(),
+            path: target_path.to_path_buf(),
+        }.into());
+    }
+    log::info!("Extracting tar file of dock image at {}", path_to_string!(target_path.to_path_buf())?);
+    extract_tar(target_path, &extract_path)?;
+    log::info!("[inspect begin]");
+    let image_vec = inspector.inspect_all(&extract_path)?;
+    log::info!("[inspect end]");
+    let mut layer_map: Vec<(ImageInfo, Vec<LayerInfo>)> = Vec::new();
+    for image in image_vec {
+        let layer_vec = inspector.describe_layers(&image)?;
+        layer_map.push((image, layer_vec));
+    }
+    fs::remove_dir_all(work_path)?;
+    Ok(layer_map)
+}
+
+/// print structure info of layers as table or json
+fn print_layers(layer_map: Vec<(ImageInfo, Vec<LayerInfo>)>, as_json: bool) {
+    if as_json {
+        let images: Vec<JsonValue> = layer_map
+            .iter()
+            .map(|(image, layer_vec)| object! {
+                repo_tags: image.repo_tags.clone(),
+                layers: layer_vec.iter().map(|l| l.to_json()).collect::<Vec<_>>()
+            })
+            .collect();
+        println!("{}", json::stringify_pretty(images, 4));
+        return;
+    }
+    for (image, layer_vec) in layer_map {
+        println!("IMAGE {:?} ({} layers)", image.repo_tags, layer_vec.len());
+        println!("{:<8} {:<12} {:<12} {:>12}  CREATED BY",
+                 "POSITION", "DIR HASH", "DIFF ID", "SIZE");
+        for layer in layer_vec {
+            let diff_id = layer.diff_id.trim_start_matches("sha256:");
+            println!("{:<8} {:<12.12} {:<12.12} {:>12}  {}",
+                     layer.position, layer.dir_hash, diff_id, layer.size, layer.created_by);
+        }
+    }
 }
 
 /// function called for a whole procedure
@@ -209,7 +338,7 @@ pub fn cli_main(args: Vec<String>) -> Result<(), LayerSwordError> {
                 .long("config")
                 .takes_value(true)
                 .value_name("FILE")
-                .help("Pick [names && layers] settings from a custom config file"))
+                .help("Pick splits and options from a config file in json, toml or yaml"))
             .group(ArgGroup::with_name("from_file")
                 .args(&["config"])
             )
@@ -219,36 +348,82 @@ pub fn cli_main(args: Vec<String>) -> Result<(), LayerSwordError> {
                 .takes_value(true)
                 .value_name("STR,STR...")
                 .use_delimiter(true)
-                .required_unless("config")
+                .required_unless_one(&["config", "max-split-size", "strategy", "reference",
+                    "split-after", "split-by-label"])
                 .conflicts_with("config")
-                .requires("layers")
                 .validator(valid_alphabet)
-                .help("Names of the splits"))
+                .help("Names of the splits, 'part0', 'part1'... by default when splits are planned"))
             .arg(Arg::with_name("layers")
                 .short("l")
                 .long("layers")
                 .takes_value(true)
                 .value_name("INT,INT...")
                 .use_delimiter(true)
-                .required_unless("config")
-                .conflicts_with("config")
+                .required_unless_one(&["config", "max-split-size", "strategy", "reference",
+                    "split-after", "split-by-label"])
+                .conflicts_with_all(&["config", "max-split-size", "strategy", "reference",
+                    "split-after", "split-by-label"])
                 .requires("names")
                 .validator(valid_int)
                 .help("Layer number of splits"))
+            .arg(Arg::with_name("max-split-size")
+                .long("max-split-size")
+                .takes_value(true)
+                .value_name("SIZE")
+                .conflicts_with("config")
+                .validator(valid_size)
+                .help("Pack consecutive layers greedily into splits of at most SIZE like 4G or 512M"))
+            .arg(Arg::with_name("strategy")
+                .long("strategy")
+                .takes_value(true)
+                .value_name("STRATEGY")
+                .possible_values(&["greedy", "balanced"])
+                .conflicts_with("config")
+                .help("Strategy planning splits by sizes of layers, 'greedy' with --max-split-size \
+                or 'balanced' with --count"))
+            .arg(Arg::with_name("count")
+                .long("count")
+                .takes_value(true)
+                .value_name("INT")
+                .requires("strategy")
+                .validator(valid_positive_int)
+                .help("Number of splits with similar sizes planned by 'balanced' strategy"))
+            .arg(Arg::with_name("reference")
+                .long("reference")
+                .takes_value(true)
+                .value_name("PATH")
+                .multiple(true)
+                .number_of_values(1)
+                .conflicts_with_all(&["config", "max-split-size", "strategy"])
+                .help("Image tar file or directory of splits of an image the target is built on, \
+                a split boundary is placed where its layers end"))
+            .arg(Arg::with_name("split-after")
+                .long("split-after")
+                .takes_value(true)
+                .value_name("REGEX")
+                .multiple(true)
+                .number_of_values(1)
+                .conflicts_with_all(&["config", "max-split-size", "strategy", "reference"])
+                .help("Place a split boundary after the last layer whose history 'created_by' \
+                matches REGEX"))
+            .arg(Arg::with_name("split-by-label")
+                .long("split-by-label")
+                .conflicts_with_all(&["config", "max-split-size", "strategy", "reference"])
+                .help("Place split boundaries at 'LABEL layer_sword.split=<name>' markers inside \
+                history, which name the splits below them"))
             .arg(Arg::with_name("target")
                 .short("t")
                 .long("target")
                 .takes_value(true)
                 .value_name("FILE")
                 .required(true)
-                .help("Path of target image tar file"))
+                .help("Path of target image tar file(docker-save or OCI layout)"))
             .arg(Arg::with_name("work")
                 .short("w")
                 .long("work")
                 .takes_value(true)
                 .value_name("DIRECTORY")
-                .default_value("tmp")
-                .help("Path of temporary working directory"))
+                .help("Directory to create a unique temporary working directory in, temp directory of os by default"))
             .arg(Arg::with_name("output")
                 .short("o")
                 .long("output")
@@ -256,6 +431,27 @@ pub fn cli_main(args: Vec<String>) -> Result<(), LayerSwordError> {
                 .value_name("DIRECTORY")
                 .default_value("out")
                 .help("Path of output directory"))
+            .arg(Arg::with_name("force")
+                .long("force")
+                .help("Write into output directory even if it is not empty, existing files are kept"))
+            .arg(Arg::with_name("store")
+                .short("s")
+                .long("store")
+                .takes_value(true)
+                .value_name("DIRECTORY")
+                .help("Path of store directory to file splits by sha256 with an index of image"))
+            .arg(Arg::with_name("sign-key")
+                .long("sign-key")
+                .takes_value(true)
+                .value_name("FILE")
+                .conflicts_with("store")
+                .help("Path of ed25519 key file written by keygen to sign a manifest of splits with"))
+            .arg(Arg::with_name("report")
+                .short("r")
+                .long("report")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Path of json file to write a report of split into"))
             .arg(Arg::with_name("quiet")
                 .short("q")
                 .long("quiet")
@@ -266,47 +462,168 @@ pub fn cli_main(args: Vec<String>) -> Result<(), LayerSwordError> {
                 .takes_value(true)
                 .default_value("6")
                 .value_name("INT[0-9]/NONE/FAST/DEFAULT/BEST")
-                .possible_value("0")
-                .possible_value("1")
-                .possible_value("2")
-                .possible_value("3")
-                .possible_value("4")
-                .possible_value("5")
-                .possible_value("6")
-                .possible_value("7")
-                .possible_value("8")
-                .possible_value("9")
+                .help("Compress level of split file(0->none, 1->fast,...9->best)"))
+            .arg(Arg::with_name("format")
+                .short("f")
+                .long("format")
+                .takes_value(true)
+                .default_value("gz")
+                .value_name("GZ/ZST/XZ/NONE")
+                .possible_value("gz")
+                .possible_value("zst")
+                .possible_value("xz")
                 .possible_value("none")
-                .possible_value("fast")
-                .possible_value("best")
                 .case_insensitive(true)
-                .help("Compress level of tar.gz split file(0->none, 1->fast,...9->best)")))
-        .subcommand(SubCommand::with_name("merge")
+                .help("Container format of split file"))
+            .arg(Arg::with_name("jobs")
+                .short("j")
+                .long("jobs")
+                .takes_value(true)
+                .value_name("INT")
+                .validator(valid_positive_int)
+                .help("Number of threads compressing split files, number of cpus by default"))
+            .arg(Arg::with_name("extract")
+                .short("e")
+                .long("extract")
+                .help("Extract the whole image into working directory rather than streaming it into splits")))
+        .subcommand(SubCommand::with_name("inspect")
+            .arg(Arg::with_name("target")
+                .short("t")
+                .long("target")
+                .takes_value(true)
+                .value_name("FILE")
+                .required(true)
+                .help("Path of target image tar file(docker-save or OCI layout)"))
+            .arg(Arg::with_name("work")
+                .short("w")
+                .long("work")
+                .takes_value(true)
+                .value_name("DIRECTORY")
+                .help("Directory to create a unique temporary working directory in, temp directory of os by default"))
+            .arg(Arg::with_name("json")
+                .short("j")
+                .long("json")
+                .help("Print layers in json rather than table"))
+            .arg(Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .help("Not print anything except layers to terminal"))
+        )
+        .subcommand(SubCommand::with_name("verify")
             .arg(Arg::with_name("target")
                 .short("t")
                 .long("target")
                 .takes_value(true)
                 .value_name("DIRECTORY")
                 .required(true)
-                .help("Path of target directory of tar.gz split files"))
+                .help("Path of target directory of split files"))
+            .arg(Arg::with_name("trust")
+                .long("trust")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Path of public key file written by keygen, splits not covered by a manifest signed by it fail"))
+            .arg(Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .help("Not print anything except results to terminal"))
+        )
+        .subcommand(SubCommand::with_name("merge")
+            .arg(Arg::with_name("target")
+                .short("t")
+                .long("target")
+                .takes_value(true)
+                .value_name("DIRECTORY")
+                .multiple(true)
+                .number_of_values(1)
+                .required_unless_one(&["store", "split"])
+                .conflicts_with("store")
+                .help("Path of target directory of split files, may be given several times"))
+            .arg(Arg::with_name("split")
+                .long("split")
+                .takes_value(true)
+                .value_name("FILE")
+                .multiple(true)
+                .number_of_values(1)
+                .conflicts_with("store")
+                .help("Path of a split file to merge, may be given several times, \
+                       splits outside the chains of images are ignored"))
+            .arg(Arg::with_name("store")
+                .short("s")
+                .long("store")
+                .takes_value(true)
+                .value_name("DIRECTORY")
+                .requires("image")
+                .help("Path of store directory to resolve splits of image from"))
             .arg(Arg::with_name("work")
                 .short("w")
                 .long("work")
                 .takes_value(true)
                 .value_name("DIRECTORY")
-                .default_value("tmp")
-                .help("Path of temporary working directory")).
+                .help("Directory to create a unique temporary working directory in, temp directory of os by default")).
             arg(Arg::with_name("output")
                 .short("o")
                 .long("output")
                 .takes_value(true)
-                .value_name("DIRECTORY")
+                .value_name("PATH")
                 .default_value("out")
-                .help("Path of output directory"))
+                .help("Path of output directory or merged tar file, '-' writes merged tar file to stdout"))
+            .arg(Arg::with_name("image")
+                .short("i")
+                .long("image")
+                .takes_value(true)
+                .value_name("TAG")
+                .help("Repo tag of the only image to merge, all images are merged if not given"))
+            .arg(Arg::with_name("list")
+                .long("list")
+                .conflicts_with("store")
+                .help("List every complete chain of splits with repo tags of its image rather than merging"))
+            .arg(Arg::with_name("upto")
+                .long("upto")
+                .takes_value(true)
+                .value_name("NAME|INDEX")
+                .help("Name or index of the last split to merge, a base image is made of layers of splits up to it"))
+            .arg(Arg::with_name("force")
+                .long("force")
+                .help("Write into output directory even if it is not empty, existing files are kept"))
+            .arg(Arg::with_name("no-overwrite")
+                .long("no-overwrite")
+                .help("Refuse to replace an existing merged tar file"))
+            .arg(Arg::with_name("continue")
+                .long("continue-on-error")
+                .help("Check all splits and list every failure rather than stopping at the first"))
+            .arg(Arg::with_name("report")
+                .short("r")
+                .long("report")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Path of json file to write a report of merge into"))
+            .arg(Arg::with_name("extract")
+                .short("e")
+                .long("extract")
+                .help("Extract splits into working directory rather than streaming them into merged tar file"))
+            .arg(Arg::with_name("trust")
+                .long("trust")
+                .takes_value(true)
+                .value_name("FILE")
+                .conflicts_with("store")
+                .help("Path of public key file written by keygen, splits not covered by a manifest signed by it are refused"))
             .arg(Arg::with_name("quiet")
                 .short("q")
                 .long("quiet")
                 .help("Not print anything to terminal"))
+        )
+        .subcommand(SubCommand::with_name("keygen")
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .value_name("FILE")
+                .default_value("layer_sword.key")
+                .help("Path of secret key file, public key is written beside it with extension 'pub'"))
+            .arg(Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .help("Not print anything except paths of key files to terminal"))
         ).get_matches_from_safe(args);
     let map_result: Result<ArgMatches, TerminalError>;
     if result.is_err()
@@ -324,71 +641,202 @@ pub fn cli_main(args: Vec<String>) -> Result<(), LayerSwordError> {
     }
     let matches = map_result?;
 
-    let (dominator, inspector) = pick_dominator_and_inspector();
-
     if let Some(sub) = matches.subcommand_matches("split") {
         let (target_path, work_path, out_path) =
-            raise(parse_path(&sub, "split"));
+            parse_path(sub, "split")?;
         let level_str = sub.value_of("level")
             .ok_or_else(|| TerminalError::WithoutArgError {
                 arg: format!("level"),
                 msg: sub.usage().to_string(),
             })?;
-        let level_map: HashMap<&str, u8> = [("none", 0), ("fast", 1), ("best", 9)]
-            .iter().cloned().collect();
-        let level_from_map = level_map.get(level_str);
-        let level_from_conv = level_str.parse::<u8>();
-        let mut level: u8 = 6;
-        if level_from_map.is_some() {
-            level = *raise(level_from_map.ok_or_else(|| InternalError::ConvertError));
-        } else if level_from_conv.is_ok() {
-            level = raise(level_from_conv);
-        }
-        let split_names: Vec<String>;
-        let split_map: HashMap<String, i16>;
+        let format = sub.value_of("format")
+            .ok_or_else(|| TerminalError::WithoutArgError {
+                arg: "format".to_string(),
+                msg: sub.usage().to_string(),
+            })?
+            .parse::<SplitFormat>()?;
+        let level = parse_level(level_str)
+            .ok_or_else(|| TerminalError::BadArgError {
+                arg: "level".to_string(),
+                msg: format!("level '{}' of format {} should be {}",
+                             level_str, format.name(), format.level_range()),
+            })?;
+        let strategy = parse_strategy(sub)?;
+        let splits: Vec<(String, SplitSpec)>;
+        let mut config: Option<SplitConfig> = None;
         if sub.is_present("config") {
             parse_and_set_logger(&sub);
-            let (ret_names, ret_map) = parse_cfg_from_file(sub)?;
-            split_names = ret_names;
-            split_map = ret_map;
+            config = Some(parse_cfg_from_file(sub)?);
+            splits = Vec::new();
+        } else if is_planned(sub) {
+            parse_and_set_logger(sub);
+            splits = Vec::new();
         } else if sub.is_present("names") & sub.is_present("layers") {
             parse_and_set_logger(&sub);
-            let (ret_names, ret_map) = parse_cfg_from_cli(sub)?;
-            split_names = ret_names;
-            split_map = ret_map;
+            splits = parse_cfg_from_cli(sub)?;
         } else {
             return Err(TerminalError::WithoutArgError {
                 arg: format!("(names && layers) || config"),
                 msg: matches.usage().to_string(),
             }.into());
         }
-        init_path(work_path.as_path(), out_path.as_path());
+        let mut job = SplitJob::new(&target_path)
+            .splits(splits)
+            .work_dir(&work_path)
+            .out_dir(&out_path)
+            .format(format)
+            .compress_level(level)
+            .force(sub.is_present("force"))
+            .stream(!sub.is_present("extract"));
+        if let Some(config) = &config {
+            // arguments given explicitly take place of options of config file
+            job = job.config(config);
+            if sub.occurrences_of("output") > 0 {
+                job = job.out_dir(&out_path);
+            }
+            if sub.is_present("work") {
+                job = job.work_dir(&work_path);
+            }
+            if sub.occurrences_of("format") > 0 {
+                job = job.format(format);
+            }
+            if sub.occurrences_of("level") > 0 {
+                job = job.compress_level(level);
+            }
+        }
+        if let Some(strategy) = strategy {
+            job = job.strategy(strategy);
+        }
+        if let Some(references) = sub.values_of("reference") {
+            for reference in references {
+                job = job.reference(normalize_path(PathBuf::from(reference))?);
+            }
+        }
+        if let Some(patterns) = sub.values_of("split-after") {
+            for pattern in patterns {
+                job = job.history_rule(HistoryRule::After(pattern.to_string()));
+            }
+        }
+        if sub.is_present("split-by-label") {
+            job = job.history_rule(HistoryRule::Label);
+        }
+        if is_planned(sub) {
+            if let Some(names) = sub.values_of("names") {
+                job = job.names(names.map(|name| name.to_string()).collect());
+            }
+        }
+        if let Some(jobs) = sub.value_of("jobs") {
+            job = job.jobs(jobs.parse::<usize>().map_err(|_| InternalError::ConvertError)?);
+        }
+        if let Some(store) = sub.value_of("store") {
+            job = job.store(store);
+        }
+        if let Some(sign_key) = sub.value_of("sign-key") {
+            job = job.sign_key(sign_key);
+        }
+        if let Some(report) = sub.value_of("report") {
+            job = job.report(report);
+        }
+        job.run()?;
+    } else if let Some(sub) = matches.subcommand_matches("inspect") {
+        parse_and_set_logger(sub);
+        let (target_path, work_path, _) =
+            parse_path(sub, "inspect")?;
+        let (_, inspector) =
+            pick_dominator_and_inspector(&target_path, "split");
+        let work_dir = init_work_path(work_path.as_path())?;
 
-        if let Err(e) = dominator.split_layer(
-            inspector,
-            target_path.as_path(),
-            split_names,
-            split_map,
-            work_path.as_path(),
-            out_path.as_path(),
-            level) {
-            error!("{}", e);
-            return Err(e.into());
+        let layer_map = inspect_layers(inspector, &target_path, work_dir.path())?;
+        print_layers(layer_map, sub.is_present("json"));
+    } else if let Some(sub) = matches.subcommand_matches("verify") {
+        parse_and_set_logger(sub);
+        let target = sub.value_of("target")
+            .ok_or_else(|| TerminalError::WithoutArgError {
+                arg: "target".to_string(),
+                msg: sub.usage().to_string(),
+            })?;
+        let target_path = Path::new(target);
+        if !target_path.is_dir() {
+            return Err(TerminalError::NotDirectoryError { path: target.to_string() }.into());
+        }
+        let mut result_vec = verify_splits(target_path)?;
+        if let Some(trust) = sub.value_of("trust") {
+            let trusted_key = load_trusted_key(Path::new(trust))?;
+            verify_signed(&mut result_vec, target_path, &trusted_key);
+        }
+        let mut failed: usize = 0;
+        for split in result_vec.iter() {
+            let filename = split.path.file_name().unwrap_or_default().to_string_lossy();
+            if split.passed() {
+                println!("PASS {}", filename);
+            } else {
+                failed += 1;
+                println!("FAIL {}: {}", filename, split.errors.join("; "));
+            }
+        }
+        if failed > 0 {
+            return Err(FileCheckError::VerifyFailedError { failed, total: result_vec.len() }.into());
         }
     } else if let Some(sub) = matches.subcommand_matches("merge") {
         parse_and_set_logger(&sub);
-        let (target_path, work_path, out_path) =
-            parse_path(&sub, "merge")?;
-        init_path(work_path.as_path(), out_path.as_path());
-
-
-        if let Err(e) = dominator.merge_layer(inspector,
-                                              target_path.as_path(),
-                                              work_path.as_path(),
-                                              out_path.as_path()) {
-            error!("{}", e);
-            return Err(e.into());
+        let mut job = if sub.is_present("store") {
+            let (store_path, work_path, out_path) = parse_store_path(sub)?;
+            let image = sub
+                .value_of("image")
+                .ok_or_else(|| InternalError::KeyError { key: "image".to_string() })?;
+            MergeJob::from_store(&store_path, image)
+                .work_dir(&work_path)
+                .output(&out_path)
+        } else {
+            let (source_vec, work_path, out_path) = parse_merge_path(sub)?;
+            let mut job = MergeJob::new(&source_vec[0])
+                .work_dir(&work_path)
+                .output(&out_path);
+            for source_path in source_vec.iter().skip(1) {
+                job = job.source(source_path);
+            }
+            if let Some(image) = sub.value_of("image") {
+                job = job.image(image);
+            }
+            job
+        };
+        if let Some(report) = sub.value_of("report") {
+            job = job.report(report);
+        }
+        if let Some(upto) = sub.value_of("upto") {
+            job = job.upto(upto);
+        }
+        if let Some(trust) = sub.value_of("trust") {
+            job = job.trust(trust);
+        }
+        if sub.is_present("list") {
+            let chain_vec = job.list()?;
+            for chain in chain_vec {
+                let split_vec: Vec<String> = chain.splits
+                    .iter()
+                    .map(|path| path.to_string_lossy().to_string())
+                    .collect();
+                let tags = match chain.repo_tags.is_empty() {
+                    true => "<none>".to_string(),
+                    false => chain.repo_tags.join(","),
+                };
+                println!("{} {}", tags, split_vec.join(" "));
+            }
+            return Ok(());
         }
+        let job = job
+            .continue_on_error(sub.is_present("continue"))
+            .stream(!sub.is_present("extract"))
+            .force(sub.is_present("force"))
+            .no_overwrite(sub.is_present("no-overwrite"))
+            .stdout(sub.value_of("output") == Some("-"));
+        job.run()?;
+    } else if let Some(sub) = matches.subcommand_matches("keygen") {
+        parse_and_set_logger(sub);
+        let key_path = PathBuf::from(sub.value_of("output").unwrap_or("layer_sword.key"));
+        let pub_path = generate_key(&key_path)?;
+        println!("{}", key_path.to_string_lossy());
+        println!("{}", pub_path.to_string_lossy());
     }
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,395 @@
+use std::path::{Component, Path, PathBuf};
+use std::collections::HashMap;
+
+use json::JsonValue;
+use yaml_rust::{Yaml, YamlLoader};
+
+use crate::plan::{SplitSpec, SplitStrategy};
+use crate::split::SplitFileOptions;
+use crate::format::SplitFormat;
+use crate::validator::valid_alphabet;
+use crate::util::{read_config_text, parse_size};
+use crate::errors::{LayerSwordError, TerminalError};
+
+/// version of the schema of split config files
+pub const CONFIG_VERSION: u64 = 1;
+
+/// keys allowed at top of a split config file
+const CONFIG_KEYS: [&str; 10] = ["version", "splits", "work", "output", "format", "level",
+    "strategy", "max_split_size", "count", "store"];
+/// keys allowed inside a split of a split config file
+const SPLIT_KEYS: [&str; 5] = ["name", "layers", "format", "level", "dir"];
+
+/// a split of a split config file
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitEntry {
+    /// name of the split
+    pub name: String,
+    /// layers inside the split, None if splits are planned by strategy
+    pub spec: Option<SplitSpec>,
+    /// options of the split file taking place of global ones
+    pub options: SplitFileOptions,
+}
+
+/// settings of a split procedure loaded from a split config file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SplitConfig {
+    /// splits from bottom up
+    pub splits: Vec<SplitEntry>,
+    /// strategy planning splits by sizes of layers
+    pub strategy: Option<SplitStrategy>,
+    /// container format of all split files
+    pub format: Option<SplitFormat>,
+    /// compress level of all split files
+    pub compress_level: Option<u8>,
+    /// parent directory of working directory
+    pub work_path: Option<PathBuf>,
+    /// output directory of split files
+    pub out_path: Option<PathBuf>,
+    /// store directory to file splits into
+    pub store_path: Option<PathBuf>,
+}
+
+/// build the error of a key inside config file
+fn key_error(key: &str, msg: String) -> LayerSwordError {
+    TerminalError::ConfigKeyError { key: key.to_string(), msg }.into()
+}
+
+/// parse compress level from an integer 0 to 9 or 'none', 'fast', 'default' and 'best'
+///
+/// # Examples
+///
+/// ```rust
+/// use layer_sword::config::parse_level;
+///
+/// assert_eq!(parse_level("7"), Some(7));
+/// assert_eq!(parse_level("BEST"), Some(9));
+/// assert_eq!(parse_level("10"), None);
+/// ```
+pub fn parse_level(level: &str) -> Option<u8> {
+    match level.to_ascii_lowercase().as_str() {
+        "none" => Some(0),
+        "fast" => Some(1),
+        "default" => Some(6),
+        "best" => Some(9),
+        level => level.parse::<u8>().ok().filter(|level| *level <= 9),
+    }
+}
+
+/// convert a toml value into json
+fn toml_to_json(value: toml::Value) -> JsonValue {
+    match value {
+        toml::Value::String(s) => s.into(),
+        toml::Value::Integer(i) => i.into(),
+        toml::Value::Float(f) => f.into(),
+        toml::Value::Boolean(b) => b.into(),
+        toml::Value::Datetime(d) => d.to_string().into(),
+        toml::Value::Array(a) => JsonValue::Array(a.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(t) => {
+            let mut object = JsonValue::new_object();
+            for (key, value) in t {
+                object[key.as_str()] = toml_to_json(value);
+            }
+            object
+        }
+    }
+}
+
+/// convert a yaml value into json, keys of mappings should be strings
+fn yaml_to_json(value: Yaml, key: &str) -> Result<JsonValue, LayerSwordError> {
+    Ok(match value {
+        Yaml::String(s) => s.into(),
+        Yaml::Integer(i) => i.into(),
+        Yaml::Real(r) => r.parse::<f64>()
+            .map_err(|_| key_error(key, format!("'{}' is not a number", r)))?
+            .into(),
+        Yaml::Boolean(b) => b.into(),
+        Yaml::Null => JsonValue::Null,
+        Yaml::Array(a) => {
+            let mut array: Vec<JsonValue> = Vec::new();
+            for (i, value) in a.into_iter().enumerate() {
+                array.push(yaml_to_json(value, &format!("{}[{}]", key, i))?);
+            }
+            JsonValue::Array(array)
+        }
+        Yaml::Hash(h) => {
+            let mut object = JsonValue::new_object();
+            for (name, value) in h {
+                let name = match name {
+                    Yaml::String(name) => name,
+                    name => return Err(key_error(key, format!("key {:?} is not a string", name))),
+                };
+                let child = if key.is_empty() { name.clone() } else { format!("{}.{}", key, name) };
+                object[name.as_str()] = yaml_to_json(value, &child)?;
+            }
+            object
+        }
+        _ => return Err(key_error(key, "value is not supported".to_string())),
+    })
+}
+
+/// load a config file into json by its extension, toml for 'toml', yaml for 'yaml' or 'yml'
+/// and json for others
+pub fn load_config_file(config_path: &Path) -> Result<JsonValue, LayerSwordError> {
+    let contents = read_config_text(config_path)?;
+    let extension = config_path.extension().unwrap_or_default().to_string_lossy().to_lowercase();
+    match extension.as_str() {
+        "toml" => {
+            let value = contents.parse::<toml::Value>()
+                .map_err(|e| key_error("", format!("toml parse failed: {}", e)))?;
+            Ok(toml_to_json(value))
+        }
+        "yaml" | "yml" => {
+            let mut doc_vec = YamlLoader::load_from_str(&contents)
+                .map_err(|e| key_error("", format!("yaml parse failed: {}", e)))?;
+            if doc_vec.len() != 1 {
+                return Err(key_error("", format!("yaml should have 1 document, actually {}",
+                                                 doc_vec.len())));
+            }
+            yaml_to_json(doc_vec.remove(0), "")
+        }
+        _ => json::parse(&contents)
+            .map_err(|e| key_error("", format!("json parse failed: {}", e))),
+    }
+}
+
+/// check that an object only has the keys allowed
+fn check_keys(value: &JsonValue, key: &str, allowed: &[&str]) -> Result<(), LayerSwordError> {
+    if !value.is_object() {
+        return Err(key_error(key, "should be an object".to_string()));
+    }
+    for (name, _) in value.entries() {
+        if !allowed.contains(&name) {
+            let child = if key.is_empty() { name.to_string() } else { format!("{}.{}", key, name) };
+            return Err(key_error(&child, format!("unknown key, allowed keys are {:?}", allowed)));
+        }
+    }
+    Ok(())
+}
+
+/// parse an optional string
+fn parse_str<'a>(value: &'a JsonValue, key: &str) -> Result<Option<&'a str>, LayerSwordError> {
+    if value.is_null() {
+        return Ok(None);
+    }
+    value.as_str().map(Some).ok_or_else(|| key_error(key, "should be a string".to_string()))
+}
+
+/// parse an optional compress level
+fn parse_level_value(value: &JsonValue, key: &str) -> Result<Option<u8>, LayerSwordError> {
+    if value.is_null() {
+        return Ok(None);
+    }
+    let level = match value.as_str() {
+        Some(level) => parse_level(level),
+        None => value.as_u8().filter(|level| *level <= 9),
+    };
+    level.map(Some).ok_or_else(|| key_error(key, "should be 0 to 9, none, fast, default or best".to_string()))
+}
+
+/// parse an optional container format
+fn parse_format_value(value: &JsonValue, key: &str) -> Result<Option<SplitFormat>, LayerSwordError> {
+    match parse_str(value, key)? {
+        Some(format) => format.parse::<SplitFormat>()
+            .map(Some)
+            .map_err(|_| key_error(key, "should be gz, zst, xz or none".to_string())),
+        None => Ok(None),
+    }
+}
+
+/// parse an optional path, which should be relative without '..' if `nested` is set
+fn parse_path_value(value: &JsonValue, key: &str, nested: bool)
+                    -> Result<Option<PathBuf>, LayerSwordError> {
+    let path = match parse_str(value, key)? {
+        Some(path) => PathBuf::from(path),
+        None => return Ok(None),
+    };
+    if nested && !path.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(key_error(key, "should be a relative path without '..'".to_string()));
+    }
+    Ok(Some(path))
+}
+
+/// parse layers of a split from a number of layers(-1 for the rest), 'rest'
+/// or a list of diff_ids
+fn parse_layers(value: &JsonValue, key: &str) -> Result<SplitSpec, LayerSwordError> {
+    if value.as_str() == Some("rest") {
+        return Ok(SplitSpec::Rest);
+    }
+    if let Some(num) = value.as_i64() {
+        return match num {
+            -1 => Ok(SplitSpec::Rest),
+            num if num >= 1 && num <= i16::MAX as i64 => Ok(SplitSpec::Count(num as usize)),
+            _ => Err(key_error(key, format!("number of layers can only be positive or -1, \
+            actually '{}'", num))),
+        };
+    }
+    if value.is_array() && !value.is_empty() {
+        let mut diff_id_vec: Vec<String> = Vec::new();
+        for (i, diff_id) in value.members().enumerate() {
+            let diff_id = diff_id
+                .as_str()
+                .ok_or_else(|| key_error(&format!("{}[{}]", key, i), "should be a diff_id".to_string()))?;
+            diff_id_vec.push(diff_id.to_string());
+        }
+        return Ok(SplitSpec::DiffIds(diff_id_vec));
+    }
+    Err(key_error(key, "should be a number of layers, 'rest' or a list of diff_ids".to_string()))
+}
+
+/// parse name of a split
+fn parse_name(value: &JsonValue, key: &str) -> Result<String, LayerSwordError> {
+    let name = parse_str(value, key)?
+        .ok_or_else(|| key_error(key, "name of split is missing".to_string()))?;
+    valid_alphabet(name.to_string()).map_err(|msg| key_error(key, msg))?;
+    Ok(name.to_string())
+}
+
+impl SplitConfig {
+    /// load a split config file in json, toml or yaml
+    pub fn load<P: AsRef<Path>>(config_path: P) -> Result<Self, LayerSwordError> {
+        Self::parse(&load_config_file(config_path.as_ref())?)
+    }
+
+    /// parse split settings of config schema version 1, or the unversioned config holding
+    /// arrays `names` and `layers`
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use json::object;
+    /// use layer_sword::config::SplitConfig;
+    /// use layer_sword::plan::SplitSpec;
+    /// use layer_sword::errors::{LayerSwordError, TerminalError};
+    ///
+    /// let config = SplitConfig::parse(&object! {
+    ///     version: 1,
+    ///     format: "zst",
+    ///     splits: [
+    ///         { name: "os", layers: 1, format: "gz" },
+    ///         { name: "lib", layers: "rest" },
+    ///         { name: "app", layers: 1, level: "best", dir: "app" }
+    ///     ]
+    /// }).unwrap();
+    /// assert_eq!(config.splits[1].spec, Some(SplitSpec::Rest));
+    /// assert_eq!(config.splits[2].options.compress_level, Some(9));
+    ///
+    /// let err = SplitConfig::parse(&object! {
+    ///     version: 1,
+    ///     splits: [{ name: "os", layers: 0 }]
+    /// }).unwrap_err();
+    /// assert!(matches!(err, LayerSwordError::TerminalError(TerminalError::ConfigKeyError { key, .. })
+    ///     if key == "splits[0].layers"));
+    /// ```
+    pub fn parse(value: &JsonValue) -> Result<Self, LayerSwordError> {
+        if !value.is_object() {
+            return Err(key_error("", "config should be an object".to_string()));
+        }
+        if value["version"].is_null() && value.has_key("names") {
+            return Self::parse_unversioned(value);
+        }
+        match value["version"].as_u64() {
+            Some(CONFIG_VERSION) => {}
+            _ => return Err(key_error("version", format!("version of config should be {}",
+                                                         CONFIG_VERSION))),
+        }
+        check_keys(value, "", &CONFIG_KEYS)?;
+
+        let strategy = match parse_str(&value["strategy"], "strategy")? {
+            None if value.has_key("max_split_size") => Some("greedy"),
+            None if value.has_key("count") => Some("balanced"),
+            strategy => strategy,
+        };
+        let strategy = match strategy {
+            Some("greedy") => {
+                let size = &value["max_split_size"];
+                let size = match size.as_str() {
+                    Some(size) => parse_size(size),
+                    None => size.as_u64(),
+                };
+                let size = size.filter(|size| *size > 0).ok_or_else(|| key_error(
+                    "max_split_size", "'greedy' strategy needs a size like 4G or 512M".to_string()))?;
+                Some(SplitStrategy::MaxSize(size))
+            }
+            Some("balanced") => {
+                let count = value["count"]
+                    .as_usize()
+                    .filter(|count| *count > 0)
+                    .ok_or_else(|| key_error(
+                        "count", "'balanced' strategy needs a positive count".to_string()))?;
+                Some(SplitStrategy::Balanced(count))
+            }
+            Some(strategy) => return Err(key_error(
+                "strategy", format!("'{}' should be greedy or balanced", strategy))),
+            None => None,
+        };
+
+        let split_value = &value["splits"];
+        if !split_value.is_null() && !split_value.is_array() {
+            return Err(key_error("splits", "should be a list of splits".to_string()));
+        }
+        if split_value.is_empty() && strategy.is_none() {
+            return Err(key_error("splits", "no split is given".to_string()));
+        }
+        let mut split_vec: Vec<SplitEntry> = Vec::new();
+        let mut name_map: HashMap<String, usize> = HashMap::new();
+        for (i, split) in split_value.members().enumerate() {
+            let key = format!("splits[{}]", i);
+            check_keys(split, &key, &SPLIT_KEYS)?;
+            let name = parse_name(&split["name"], &format!("{}.name", key))?;
+            if let Some(j) = name_map.insert(name.clone(), i) {
+                return Err(key_error(&format!("{}.name", key),
+                                     format!("split name '{}' is given by splits[{}] as well", name, j)));
+            }
+            let layers_key = format!("{}.layers", key);
+            let spec = match (split["layers"].is_null(), strategy.is_some()) {
+                (true, true) => None,
+                (false, false) => Some(parse_layers(&split["layers"], &layers_key)?),
+                (true, false) => return Err(key_error(&layers_key, "layers are missing".to_string())),
+                (false, true) => return Err(key_error(
+                    &layers_key, "layers can't be given when splits are planned by strategy".to_string())),
+            };
+            let options = SplitFileOptions {
+                format: parse_format_value(&split["format"], &format!("{}.format", key))?,
+                compress_level: parse_level_value(&split["level"], &format!("{}.level", key))?,
+                sub_dir: parse_path_value(&split["dir"], &format!("{}.dir", key), true)?,
+            };
+            split_vec.push(SplitEntry { name, spec, options });
+        }
+
+        Ok(SplitConfig {
+            splits: split_vec,
+            strategy,
+            format: parse_format_value(&value["format"], "format")?,
+            compress_level: parse_level_value(&value["level"], "level")?,
+            work_path: parse_path_value(&value["work"], "work", false)?,
+            out_path: parse_path_value(&value["output"], "output", false)?,
+            store_path: parse_path_value(&value["store"], "store", false)?,
+        })
+    }
+
+    /// parse the unversioned config holding arrays `names` and `layers` of the same length
+    fn parse_unversioned(value: &JsonValue) -> Result<Self, LayerSwordError> {
+        check_keys(value, "", &["names", "layers"])?;
+        if !value["names"].is_array() {
+            return Err(key_error("names", "should be a list of names".to_string()));
+        }
+        if !value["layers"].is_array() {
+            return Err(key_error("layers", "should be a list of numbers of layers".to_string()));
+        }
+        if value["names"].len() != value["layers"].len() {
+            return Err(key_error("layers", format!(
+                "count of names '{}' isn't equal to count of layers '{}'",
+                value["names"].len(), value["layers"].len())));
+        }
+        let mut split_vec: Vec<SplitEntry> = Vec::new();
+        for (i, (name, layers)) in value["names"].members().zip(value["layers"].members()).enumerate() {
+            split_vec.push(SplitEntry {
+                name: parse_name(name, &format!("names[{}]", i))?,
+                spec: Some(parse_layers(layers, &format!("layers[{}]", i))?),
+                options: SplitFileOptions::default(),
+            });
+        }
+        Ok(SplitConfig { splits: split_vec, ..SplitConfig::default() })
+    }
+}
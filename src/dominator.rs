@@ -1,19 +1,147 @@
 pub mod base;
+pub mod oci;
 
 use std::collections::HashMap;
 
-use json::JsonValue;
+use json::{JsonValue, object};
 
-use crate::errors::FileCheckError;
+use crate::errors::{LayerSwordError, FileCheckError};
+
+/// source image of a split
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImageMeta {
+    /// sha256 of config file of the image
+    pub config_digest: String,
+    /// repo tags of the image
+    pub repo_tags: Vec<String>,
+}
+
+impl ImageMeta {
+    /// first repo tag of the image, or short config digest if it has no repo tag
+    pub fn name(&self) -> String {
+        match self.repo_tags.first() {
+            Some(tag) => tag.clone(),
+            None => format!("sha256:{}", &self.config_digest[..self.config_digest.len().min(12)]),
+        }
+    }
+}
+
+/// source images inside an image tar file with diff_id of each layer directory,
+/// which are recorded into metadata of splits of the images
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceMeta {
+    /// every image inside the tar file
+    pub images: Vec<ImageMeta>,
+    /// diff_id of every layer keyed by name of its layer directory
+    pub diff_id_map: HashMap<String, String>,
+}
+
+impl SourceMeta {
+    /// diff_ids of layer directories, empty for an unknown one
+    pub fn diff_ids(&self, dir_hashes: &[String]) -> Vec<String> {
+        dir_hashes
+            .iter()
+            .map(|dir_hash| self.diff_id_map.get(dir_hash).cloned().unwrap_or_default())
+            .collect()
+    }
+}
+
+/// metadata of source images recorded inside split_config.json of every split,
+/// which is empty for splits written before it was recorded
+///
+/// # Examples
+///
+/// ```rust
+/// use layer_sword::dominator::{SplitMeta, ImageMeta};
+///
+/// let meta = SplitMeta {
+///     images: vec![ImageMeta { config_digest: format!("0a1b"), repo_tags: vec![format!("foo:1.2")] }],
+///     split_names: vec![format!("os"), format!("lib"), format!("app")],
+///     diff_ids: Vec::new(),
+/// };
+/// assert_eq!(SplitMeta::from_json(&meta.to_json()), meta);
+/// assert_eq!(meta.missing_error(2).unwrap().to_string(),
+///            "Split 'app' (index 2 of 3) of image foo:1.2 is missing");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SplitMeta {
+    /// images holding the split, several ones for a split shared by images
+    pub images: Vec<ImageMeta>,
+    /// names of all splits of the image from bottom up
+    pub split_names: Vec<String>,
+    /// diff_ids of layers inside the split from bottom up
+    pub diff_ids: Vec<String>,
+}
+
+impl SplitMeta {
+    /// fields of metadata to put into split_config.json
+    pub fn to_json(&self) -> JsonValue {
+        let images: Vec<JsonValue> = self.images
+            .iter()
+            .map(|image| object! {
+                config_digest: image.config_digest.clone(),
+                repo_tags: image.repo_tags.clone()
+            })
+            .collect();
+        object! {
+            images: images,
+            split_count: self.split_names.len(),
+            split_names: self.split_names.clone(),
+            diff_ids: self.diff_ids.clone()
+        }
+    }
+
+    /// read metadata from split_config.json, missing fields are left empty
+    pub fn from_json(j: &JsonValue) -> Self {
+        let strings = |value: &JsonValue| -> Vec<String> {
+            value.members().filter_map(|s| s.as_str()).map(|s| s.to_string()).collect()
+        };
+        Self {
+            images: j["images"]
+                .members()
+                .map(|image| ImageMeta {
+                    config_digest: image["config_digest"].as_str().unwrap_or_default().to_string(),
+                    repo_tags: strings(&image["repo_tags"]),
+                })
+                .collect(),
+            split_names: strings(&j["split_names"]),
+            diff_ids: strings(&j["diff_ids"]),
+        }
+    }
+
+    /// names of the images holding the split
+    pub fn image_name(&self) -> String {
+        match self.images.is_empty() {
+            true => "unknown".to_string(),
+            false => self.images.iter().map(|image| image.name()).collect::<Vec<_>>().join(", "),
+        }
+    }
+
+    /// error of a missing split of the image by its index,
+    /// none if names of splits are not recorded
+    pub fn missing_error(&self, index: usize) -> Option<FileCheckError> {
+        self.split_names.get(index).map(|name| FileCheckError::SplitMissingError {
+            name: name.clone(),
+            index,
+            count: self.split_names.len(),
+            image: self.image_name(),
+        })
+    }
+}
 
 pub trait Config {
     fn new() -> Self where Self: Sized;
-    fn to_json(&self) -> JsonValue;
-    fn load_json(&mut self, j: JsonValue);
+    fn to_json(&self) -> Result<JsonValue, LayerSwordError>;
+    fn load_json(&mut self, j: JsonValue) -> Result<(), LayerSwordError>;
     fn set_path(&mut self, dir_path: String, tar_path: String, config_path: String);
     fn get_dir(&self) -> String;
     fn get_tar(&self) -> String;
     fn get_config(&self) -> String;
+    fn get_parent(&self) -> String;
     fn key(&self) -> usize;
-    fn check_hash(&self, check_map: HashMap<String, String>) -> Result<(), FileCheckError>;
-}
\ No newline at end of file
+    fn check_hash(&self, check_map: HashMap<String, String>) -> Result<(), LayerSwordError>;
+    /// metadata of source images recorded inside the split
+    fn get_meta(&self) -> SplitMeta {
+        SplitMeta::default()
+    }
+}
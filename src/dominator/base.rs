@@ -5,9 +5,9 @@ use json::{JsonValue, object};
 
 use crate::split::Split;
 use crate::merge::Merge;
-use crate::dominator::Config;
+use crate::dominator::{Config, SplitMeta};
 use crate::util::{fetch_file_sha256, dump_config, get_stack_id};
-use crate::errors::{FileCheckError, InternalError, raise};
+use crate::errors::{LayerSwordError, FileCheckError, InternalError};
 
 #[derive(Debug)]
 pub struct BaseConfig {
@@ -16,6 +16,7 @@ pub struct BaseConfig {
     tar_path: String,
     config_path: String,
     index: usize,
+    meta: SplitMeta,
 }
 
 impl Config for BaseConfig {
@@ -26,30 +27,36 @@ impl Config for BaseConfig {
             tar_path: "".to_string(),
             config_path: "".to_string(),
             index: 0,
+            meta: SplitMeta::default(),
         }
     }
 
-    fn to_json(&self) -> JsonValue {
-        let parent_id = raise(self.hash_vec
+    fn to_json(&self) -> Result<JsonValue, LayerSwordError> {
+        let parent_id = self.hash_vec
             .get("parent_id")
-            .ok_or_else(|| InternalError::KeyError { key: "parent_id".into() }));
-        let stack_id = raise(self.hash_vec
+            .ok_or_else(|| InternalError::KeyError { key: "parent_id".into() })?;
+        let stack_id = self.hash_vec
             .get("stack_id")
-            .ok_or_else(|| InternalError::KeyError { key: "stack_id".into() }));
-        let split_data: JsonValue = object! {
+            .ok_or_else(|| InternalError::KeyError { key: "stack_id".into() })?;
+        let mut split_data: JsonValue = object! {
                 parent_id: parent_id.clone(),
                 stack_id: stack_id.clone(),
                 index: self.index
             };
-        split_data
+        for (key, value) in self.meta.to_json().entries() {
+            split_data[key] = value.clone();
+        }
+        Ok(split_data)
     }
 
-    fn load_json(&mut self, j: JsonValue) {
+    fn load_json(&mut self, j: JsonValue) -> Result<(), LayerSwordError> {
         self.hash_vec.insert("parent_id".into(), j["parent_id"].to_string());
         self.hash_vec.insert("stack_id".into(), j["stack_id"].to_string());
-        self.index = raise(j["index"]
+        self.index = j["index"]
             .as_usize()
-            .ok_or_else(|| InternalError::ConvertError));
+            .ok_or(InternalError::ConvertError)?;
+        self.meta = SplitMeta::from_json(&j);
+        Ok(())
     }
 
     fn set_path(&mut self,
@@ -73,21 +80,27 @@ impl Config for BaseConfig {
         self.config_path.clone()
     }
 
+    fn get_parent(&self) -> String {
+        self.hash_vec.get("parent_id").cloned().unwrap_or_default()
+    }
+
     fn key(&self) -> usize { self.index }
 
+    fn get_meta(&self) -> SplitMeta { self.meta.clone() }
+
     fn check_hash(&self, check_map: HashMap<String, String>)
-                  -> Result<(), FileCheckError> {
+                  -> Result<(), LayerSwordError> {
         for (name, hash) in check_map.iter() {
             if !self.hash_vec.contains_key(name) {
                 return Err(FileCheckError::HashCheckError {
                     right: hash.clone(),
                     real: "".into(),
-                });
+                }.into());
             } else if self.hash_vec[name] != hash.clone() {
                 return Err(FileCheckError::HashCheckError {
                     right: hash.clone(),
                     real: self.hash_vec[name].clone(),
-                });
+                }.into());
             }
         }
         Ok(())
@@ -95,6 +108,47 @@ impl Config for BaseConfig {
 }
 
 
+/// build config of BaseConfig type for a split with metadata of its source images,
+/// return it with stack_id of the split
+pub fn base_split_config(split_index: usize,
+                         stack_id: &String,
+                         parent_id: &String,
+                         meta: &SplitMeta)
+                         -> Result<(JsonValue, String), LayerSwordError> {
+    let mut cfg = BaseConfig::new();
+    cfg.hash_vec.insert("parent_id".into(), parent_id.clone());
+    cfg.hash_vec.insert(
+        "stack_id".into(),
+        get_stack_id(stack_id, parent_id));
+    cfg.index = split_index;
+    cfg.meta = meta.clone();
+    let split_data = cfg.to_json()?;
+    Ok((split_data, cfg.hash_vec["stack_id"].clone()))
+}
+
+/// dump config of BaseConfig type into a split and compress the split into tar file
+pub fn pack_with_base_config(
+    dominator: &dyn Split,
+    split_index: usize,
+    split_name: &String,
+    split_path: &PathBuf,
+    stack_id: String,
+    parent_id: String,
+    meta: &SplitMeta)
+    -> Result<(PathBuf, String, String), LayerSwordError> {
+    let (split_data, now_stack_id) =
+        base_split_config(split_index, &stack_id, &parent_id, meta)?;
+
+    let mut config_pathbuf = split_path.clone();
+    config_pathbuf.push(split_name);
+    config_pathbuf.push("split_config.json");
+    dump_config(split_data, &config_pathbuf)?;
+
+    let (tar_path, now_id) = dominator.pack_into_tar(split_path, split_name)?;
+
+    Ok((tar_path, now_stack_id, now_id))
+}
+
 pub struct BaseDominator {}
 
 impl Split for BaseDominator {
@@ -104,26 +158,21 @@ impl Split for BaseDominator {
         split_name: &String,
         split_path: &PathBuf,
         stack_id: String,
-        parent_id: String)
-        -> Result<(PathBuf, String, String), FileCheckError> {
-        let mut cfg = BaseConfig::new();
-        cfg.hash_vec.insert("parent_id".into(), parent_id.clone().into());
-        cfg.hash_vec.insert(
-            "stack_id".into(),
-            get_stack_id(&stack_id, &parent_id));
-        cfg.index = split_index.into();
-        let split_data = cfg.to_json();
-
-        let mut config_pathbuf = split_path.clone();
-        config_pathbuf.push(split_name.to_owned());
-        config_pathbuf.push("split_config.json");
-        dump_config(split_data, &config_pathbuf);
-
-        let tar_path = self.pack_into_tar(split_path, split_name)?;
-
-        let now_id = fetch_file_sha256(&tar_path);
-        let now_stack_id = cfg.hash_vec["stack_id"].clone();
-        Ok((tar_path, now_stack_id, now_id))
+        parent_id: String,
+        meta: &SplitMeta)
+        -> Result<(PathBuf, String, String), LayerSwordError> {
+        pack_with_base_config(self, split_index, split_name, split_path, stack_id, parent_id, meta)
+    }
+
+    fn can_stream(&self) -> bool { true }
+
+    fn split_config(&self,
+                    split_index: usize,
+                    stack_id: &String,
+                    parent_id: &String,
+                    meta: &SplitMeta)
+                    -> Result<(JsonValue, String), LayerSwordError> {
+        base_split_config(split_index, stack_id, parent_id, meta)
     }
 }
 
@@ -132,8 +181,17 @@ impl Merge for BaseDominator {
                          config_body: &Box<dyn Config>,
                          stack_id: String,
                          parent_id: String)
-                         -> Result<(String, String), FileCheckError> {
-        let now_id = fetch_file_sha256(&config_body.get_tar());
+                         -> Result<(String, String), LayerSwordError> {
+        let now_id = fetch_file_sha256(config_body.get_tar())?;
+        self.check_with_hash(config_body.as_ref(), now_id, stack_id, parent_id)
+    }
+
+    fn check_with_hash(&self,
+                       config_body: &dyn Config,
+                       now_id: String,
+                       stack_id: String,
+                       parent_id: String)
+                       -> Result<(String, String), LayerSwordError> {
         let now_stack_id = get_stack_id(&stack_id, &parent_id);
 
         let mut check_map: HashMap<String, String> = HashMap::new();
@@ -0,0 +1,271 @@
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::fs::{self, File};
+
+use json::{JsonValue, object};
+use tar::Archive;
+
+use crate::split::Split;
+use crate::merge::Merge;
+use crate::dominator::{Config, SplitMeta, SourceMeta};
+use crate::inspector::{Inspect, ImageInfo};
+use crate::dominator::base::{BaseDominator, pack_with_base_config};
+use crate::util::{compress_tar_with_depth, dump_config, load_config};
+use crate::errors::{LayerSwordError, FileCheckError, InternalError, report_err};
+
+/// file inside the top split recording how the source image tar file is laid out
+pub const ARCHIVE_LAYOUT_FILE: &str = "archive_layout.json";
+
+/// encode bytes into lowercase hex string
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// decode lowercase hex string into bytes
+fn from_hex(hex: &str) -> Result<Vec<u8>, LayerSwordError> {
+    if !hex.len().is_multiple_of(2) {
+        return Err(FileCheckError::BadOciFileError {
+            msg: format!("bad hex string inside {}", ARCHIVE_LAYOUT_FILE)
+        }.into());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16)
+            .map_err(|_| FileCheckError::BadOciFileError {
+                msg: format!("bad hex string inside {}", ARCHIVE_LAYOUT_FILE)
+            }.into()))
+        .collect()
+}
+
+/// fetch relative path of an item inside image layout, blobs are kept under 'blobs/<algorithm>'
+fn layout_relative_path(path: &Path) -> Result<PathBuf, LayerSwordError> {
+    let filename = path
+        .file_name()
+        .ok_or_else(|| InternalError::FilePathError { path: path.to_path_buf() })?;
+    let algorithm = path.parent().and_then(|p| p.file_name());
+    let blobs = path.parent().and_then(|p| p.parent()).and_then(|p| p.file_name());
+    let mut relative_path = PathBuf::new();
+    if let (Some(algorithm), Some(blobs)) = (algorithm, blobs) {
+        if blobs == "blobs" {
+            relative_path.push(blobs);
+            relative_path.push(algorithm);
+        }
+    }
+    relative_path.push(filename);
+    Ok(relative_path)
+}
+
+/// fetch path of an entry recorded inside archive layout
+fn record_path(record: &JsonValue) -> Result<PathBuf, LayerSwordError> {
+    let path = record["path"]
+        .as_str()
+        .ok_or_else(|| FileCheckError::BadOciFileError {
+            msg: format!("bad path inside {}", ARCHIVE_LAYOUT_FILE)
+        })?;
+    Ok(PathBuf::from(path))
+}
+
+pub struct OciDominator {}
+
+impl Split for OciDominator {
+    fn copy_split_directories(&self,
+                              split_names: &Vec<String>,
+                              split_map: &HashMap<String, i16>,
+                              layer_dir_set: &Vec<PathBuf>,
+                              top_path: &Path) -> Result<(), LayerSwordError> {
+        let mut id_from: i16 = 0;
+        for name in split_names {
+            let mut split_path = top_path.to_path_buf();
+            split_path.push(name.clone());
+            fs::create_dir(&split_path)?;
+
+            for id in 0..split_map[name] {
+                let src_path = layer_dir_set
+                    .get((id_from + id) as usize)
+                    .ok_or_else(|| InternalError::KeyError { key: (id_from + id).to_string() })?;
+                let mut dst_path = split_path.clone();
+                dst_path.push(layout_relative_path(src_path)?);
+                if let Some(parent) = dst_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(src_path, dst_path)?;
+            }
+            id_from += split_map[name];
+        }
+        Ok(())
+    }
+
+    fn copy_split_files(&self,
+                        split_names: &Vec<String>,
+                        file_map: HashMap<String, PathBuf>,
+                        top_pathbuf: &Path) -> Result<(), LayerSwordError> {
+        let top_layer = split_names
+            .last()
+            .ok_or(InternalError::VecEmptyError)?;
+        for (_, src_path) in file_map {
+            let mut dst_pathbuf = top_pathbuf.to_path_buf();
+            dst_pathbuf.push(top_layer);
+            dst_pathbuf.push(layout_relative_path(&src_path)?);
+            if let Some(parent) = dst_pathbuf.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(&src_path, &dst_pathbuf)?;
+        }
+        Ok(())
+    }
+
+    fn record_archive_layout(&self,
+                             tar_path: &Path,
+                             split_names: &Vec<String>,
+                             split_path: &PathBuf) -> Result<(), LayerSwordError> {
+        // every entry is recorded with its raw header blocks(extension headers included),
+        // so that merge can rebuild the source tar file byte by byte
+        let mut positions: Vec<(u64, u64)> = Vec::new();
+        let mut entries: Vec<JsonValue> = Vec::new();
+        let mut archive = Archive::new(File::open(tar_path)?);
+        let mut prev_end: u64 = 0;
+        let bad_archive = || FileCheckError::BadOciFileError {
+            msg: "tar file parse failed".to_string()
+        };
+        for entry in archive
+            .entries()
+            .map_err(|e| report_err(e, bad_archive()))? {
+            let mut entry = entry
+                .map_err(|e| report_err(e, bad_archive()))?;
+            let path = entry.path()?.into_owned();
+            let size = entry.header().entry_size()?;
+            let in_splits = entry.header().entry_type().is_file()
+                && split_names.iter().any(|name| {
+                    let mut item_path = split_path.clone();
+                    item_path.push(name);
+                    item_path.push(&path);
+                    item_path.is_file()
+                });
+            let mut record = object! {
+                path: path.to_str().ok_or(InternalError::ConvertError)?,
+                size: size
+            };
+            if !in_splits && size > 0 {
+                let mut data: Vec<u8> = Vec::new();
+                entry.read_to_end(&mut data)?;
+                record["data"] = to_hex(&data).into();
+            }
+            positions.push((prev_end, entry.raw_header_position() + 512));
+            entries.push(record);
+            prev_end = entry.raw_file_position() + size.div_ceil(512) * 512;
+        }
+
+        let mut file = File::open(tar_path)?;
+        for ((head_start, head_end), record) in positions.iter().zip(entries.iter_mut()) {
+            let mut head = vec![0u8; (head_end - head_start) as usize];
+            file.seek(SeekFrom::Start(*head_start))?;
+            file.read_exact(&mut head)?;
+            record["head"] = to_hex(&head).into();
+        }
+        let tail = file.metadata()?.len() - prev_end;
+
+        let top_layer = split_names
+            .last()
+            .ok_or(InternalError::VecEmptyError)?;
+        let mut layout_path = split_path.clone();
+        layout_path.push(top_layer);
+        layout_path.push(ARCHIVE_LAYOUT_FILE);
+        dump_config(object! { entries: entries, tail: tail }, layout_path)?;
+        Ok(())
+    }
+
+    fn pack_into_tar(&self,
+                     split_path: &PathBuf, name: &String)
+                     -> Result<(PathBuf, String), LayerSwordError> {
+        let mut tar_path = split_path.clone();
+        tar_path.pop();
+        tar_path.push(name.as_str().to_owned() + ".tar");
+
+        let mut compress_path = split_path.clone();
+        compress_path.push(name);
+        // blobs are placed at 'blobs/<algorithm>/<hash>'
+        let hash = compress_tar_with_depth(&tar_path, &compress_path, 3)?;
+        fs::remove_dir_all(compress_path)?;
+        Ok((tar_path, hash))
+    }
+
+    fn split_images(&self,
+                    _inspector: &dyn Inspect,
+                    image_vec: Vec<ImageInfo>,
+                    _source: &SourceMeta,
+                    _split_names: &Vec<String>,
+                    _split_map: HashMap<String, i16>,
+                    _work_path: &Path)
+                    -> Result<Vec<(PathBuf, String, Vec<String>)>, LayerSwordError> {
+        // layout of the source tar file is only recorded for a single image
+        Err(FileCheckError::BadOciFileError {
+            msg: format!("OCI layout holds {} images, only 1 image can be split", image_vec.len())
+        }.into())
+    }
+
+    fn pack_tar_with_config(
+        &self,
+        split_index: usize,
+        split_name: &String,
+        split_path: &PathBuf,
+        stack_id: String,
+        parent_id: String,
+        meta: &SplitMeta)
+        -> Result<(PathBuf, String, String), LayerSwordError> {
+        pack_with_base_config(self, split_index, split_name, split_path, stack_id, parent_id, meta)
+    }
+}
+
+impl Merge for OciDominator {
+    fn pack_merged(&self, merge_path: &Path, tar_path: &Path) -> Result<(), LayerSwordError> {
+        let mut layout_path = merge_path.to_path_buf();
+        layout_path.push(ARCHIVE_LAYOUT_FILE);
+        if !layout_path.is_file() {
+            return Err(FileCheckError::BadOciFileError {
+                msg: format!("no {} inside splits", ARCHIVE_LAYOUT_FILE)
+            }.into());
+        }
+        let layout = load_config(&layout_path)?;
+
+        let mut file = File::create(tar_path)?;
+        for record in layout["entries"].members() {
+            let head = from_hex(record["head"].as_str().unwrap_or_default())?;
+            let size = record["size"]
+                .as_u64()
+                .ok_or(InternalError::ConvertError)?;
+            file.write_all(&head)?;
+            if let Some(data) = record["data"].as_str() {
+                file.write_all(&from_hex(data)?)?;
+            } else if size > 0 {
+                let mut item_path = merge_path.to_path_buf();
+                item_path.push(record_path(record)?);
+                let mut item = File::open(&item_path)?;
+                if io::copy(&mut item, &mut file)? != size {
+                    return Err(FileCheckError::BadOciFileError {
+                        msg: format!("size of '{:?}' differs from {}", item_path, ARCHIVE_LAYOUT_FILE)
+                    }.into());
+                }
+            }
+            let padding = (512 - size % 512) % 512;
+            file.write_all(&vec![0u8; padding as usize])?;
+        }
+        let tail = layout["tail"]
+            .as_u64()
+            .ok_or(InternalError::ConvertError)?;
+        file.write_all(&vec![0u8; tail as usize])?;
+        Ok(())
+    }
+
+    fn check_with_config(&self,
+                         config_body: &Box<dyn Config>,
+                         stack_id: String,
+                         parent_id: String)
+                         -> Result<(String, String), LayerSwordError> {
+        BaseDominator {}.check_with_config(config_body, stack_id, parent_id)
+    }
+
+    fn init_config(&self) -> Box<dyn Config> {
+        BaseDominator {}.init_config()
+    }
+}
@@ -1,24 +1,38 @@
 use std::path::PathBuf;
 use std::fmt::{Debug, Display};
-use std::sync::RwLock;
-use std::fs;
+use std::io;
 
-use lazy_static::lazy_static;
 use thiserror::Error as ThisError;
 use log::error;
 
-lazy_static! {
-    pub static ref GENERATE_PATH: RwLock<Vec<PathBuf>> = RwLock::new(Vec::new());
-}
-
+/// errors returned by layer_sword, whose messages carry the message of the error inside them
+///
+/// # Examples
+///
+/// ```rust
+/// use layer_sword::errors::{LayerSwordError, FileCheckError};
+///
+/// let e: LayerSwordError = FileCheckError::SplitFileError.into();
+/// assert_eq!(e.to_string(), "[Error from file inspection]\nSplit file is invalid");
+/// ```
 #[derive(ThisError, Debug)]
 pub enum LayerSwordError {
-    #[error("[Something happened unexpectedly]")]
+    #[error("[Something happened unexpectedly]\n{0}")]
     InternalError(#[from] InternalError),
-    #[error("[Error from terminal inspection]")]
+    #[error("[Error from terminal inspection]\n{0}")]
     TerminalError(#[from] TerminalError),
-    #[error("[Error from file inspection]")]
+    #[error("[Error from file inspection]\n{0}")]
     FileCheckError(#[from] FileCheckError),
+    #[error("[Error from file system]\n{0}")]
+    IoError(#[from] io::Error),
+    #[error("[Error from copying files]\n{0}")]
+    CopyError(#[from] fs_extra::error::Error),
+    #[error("[Error from walking directory]\n{0}")]
+    WalkError(#[from] walkdir::Error),
+    #[error("[Error from json data]\n{0}")]
+    JsonError(#[from] json::Error),
+    #[error("[Error from regular expression]\n{0}")]
+    RegexError(#[from] regex::Error),
 }
 
 #[derive(ThisError, Debug)]
@@ -41,6 +55,8 @@ pub enum InternalError {
 pub enum TerminalError {
     #[error("Config file of arguments is invalid")]
     InputConfigError,
+    #[error("Key '{key}' of config file is invalid\n{msg}")]
+    ConfigKeyError { key: String, msg: String },
     #[error("No arg '{arg}' when running client\n{msg}")]
     WithoutArgError { arg: String, msg: String },
     #[error("Arg '{arg}' error when running client\n{msg}")]
@@ -51,6 +67,10 @@ pub enum TerminalError {
     NotFileError { path: String },
     #[error("Path `{path}` not exist")]
     NotExistError { path: String },
+    #[error("Path `{path}` already exists")]
+    ExistError { path: String },
+    #[error("Directory `{path}` is not empty, use --force to write into it")]
+    NotEmptyError { path: String },
     #[error("Clap arguments check failed")]
     ClapError,
 }
@@ -63,32 +83,69 @@ pub enum FileCheckError {
     SplitFileError,
     #[error("Docker file check failed:\n{msg:?}")]
     BadDockerFileError { msg: String },
+    #[error("OCI layout check failed:\n{msg:?}")]
+    BadOciFileError { msg: String },
     #[error("Checksum is not valid\nright:'{right}'\nreal:'{real}'")]
     HashCheckError { right: String, real: String },
     #[error("File should have extension '{extension}' at path:\n'{path}'")]
     FileExtensionError { extension: String, path: PathBuf },
+    #[error("Split file should be in format gz, zst, xz or none at path:\n'{path}'")]
+    SplitFormatError { path: PathBuf },
+    #[error("Split file should keep sha256 of split tar and split_config.json at path:\n'{path}'")]
+    SplitContentError { path: PathBuf },
     #[error("File have item '{path}' more than 2")]
     TooManyDepthError { path: String },
     #[error("Splits unmatched with more than 1 index '{index}'")]
     SplitsUnmatchedError { index: usize },
+    #[error("Split '{name}' (index {index} of {count}) of image {image} is missing")]
+    SplitMissingError { name: String, index: usize, count: usize, image: String },
+    #[error("Image selection failed:\n{msg:?}")]
+    ImageSelectError { msg: String },
+    #[error("Split store check failed:\n{msg:?}")]
+    BadStoreError { msg: String },
+    #[error("Split '{path}' failed {stage} check\nright:'{right}'\nreal:'{real}'")]
+    SplitCheckError { path: PathBuf, stage: CheckStage, right: String, real: String },
+    #[error("{} splits failed check:\n{}", .failures.len(), join_errors(.failures))]
+    SplitsCheckError { failures: Vec<FileCheckError> },
+    #[error("Verification failed on {failed} of {total} splits")]
+    VerifyFailedError { failed: usize, total: usize },
+    #[error("Key file should hold 32 bytes of an ed25519 key in hex at path:\n'{path}'")]
+    KeyFileError { path: PathBuf },
+    #[error("Signature check failed:\n{msg:?}")]
+    SignatureError { msg: String },
+    #[error("Splits unmatched with sums file at path:\n'{path}'\n{msg}")]
+    SplitSumsError { path: PathBuf, msg: String },
 }
 
-/// clean temporary files defined in error.rs GENERATE_PATH
-pub fn clean_workspace() {
-    let path_reader = GENERATE_PATH.read();
-    if path_reader.is_ok() {
-        let real_path_vec = path_reader
-            .expect("An impossible error occurred");
-        for path in &*real_path_vec {
-            if path.is_dir() {
-                if let Err(e) = fs::remove_dir_all(path) {
-                    error!("{:#?}", e);
-                }
-            }
+/// stage of split check where a checksum mismatched
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheckStage {
+    /// crc32 inside gzip trailer of the split tar.gz file
+    GzipCrc,
+    /// sha256 inside gzip comment of the inner split tar file
+    TarHash,
+    /// header of the gzip container of the split file
+    GzipHeader,
+    /// end of the container of the split file, which is missing once it is truncated
+    StreamEnd,
+}
+
+impl Display for CheckStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckStage::GzipCrc => write!(f, "gzip crc32"),
+            CheckStage::TarHash => write!(f, "inner tar sha256"),
+            CheckStage::GzipHeader => write!(f, "gzip header"),
+            CheckStage::StreamEnd => write!(f, "stream end"),
         }
     }
 }
 
+/// join messages of several errors into lines
+fn join_errors(errors: &[FileCheckError]) -> String {
+    errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n")
+}
+
 /// print error info from result and convert it to another type
 ///
 /// # Examples
@@ -123,74 +180,3 @@ pub fn report_err<E: Display, O>(err: E, map: O) -> O {
     error!("{:#}", err);
     map
 }
-
-/// print error info from result and interrupt the program
-///
-/// # Examples
-///
-/// ```rust
-/// use layer_sword::errors::raise;
-///
-/// let x: Result<u8, u8> = Ok(1);
-/// assert_eq!(raise(x), 1);
-/// ```
-/// ```no_run
-/// use layer_sword::errors::raise;
-/// let x: Result<u8, u8> = Err(11);
-/// raise(x);
-/// ```
-pub fn raise<V: Debug, E: Debug + Display>(ret: Result<V, E>) -> V {
-    if ret.is_err() {
-        raise_err(ret.as_ref().expect_err("An impossible error occurred"));
-    }
-    ret.expect("An impossible error occurred")
-}
-
-/// print error info and interrupt the program
-///
-/// # Examples
-///
-/// ```no_run
-/// use layer_sword::errors::raise_err;
-///
-/// let x: u8 = 11;
-/// raise_err(x);
-/// ```
-pub fn raise_err<E: Display>(err: E) {
-    env_logger::builder().is_test(false).try_init().unwrap_or_else(|_| {});
-    error!("{:#}", err);
-    // clean temporary files if an error is raised
-    clean_workspace();
-    std::process::exit(-1);
-}
-
-/// print error info and interrupt the program(used for types without Display trait)
-///
-/// # Examples
-///
-/// ```rust
-/// use std::ffi::OsString;
-/// use layer_sword::errors::raise_debug;
-///
-/// let x: Result<u8, OsString> = Ok(1);
-/// assert_eq!(raise_debug(x), 1);
-/// ```
-/// ```no_run
-/// use std::ffi::OsString;
-/// use layer_sword::errors::raise_debug;
-///
-/// let e = OsString::new();
-/// let x: Result<u8, OsString> = Err(e);
-/// raise_debug(x);
-/// ```
-pub fn raise_debug<V: Debug, E: Debug>(ret: Result<V, E>) -> V {
-    if ret.is_err() {
-        env_logger::builder().is_test(false).try_init().unwrap_or_else(|_| {});
-        let err = ret.as_ref().expect_err("An impossible error occurred");
-        error!("{:#?}", err);
-        // clean temporary files if an error is raised
-        clean_workspace();
-        std::process::exit(-1);
-    }
-    ret.expect("An impossible error occurred")
-}
\ No newline at end of file
@@ -0,0 +1,713 @@
+use std::fmt::{self, Display};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use flate2::{Compress, Compression, Crc, FlushCompress, GzBuilder};
+use flate2::read::GzDecoder;
+use tar::{Builder, Entry, EntryType, Header};
+
+use crate::util::{parallel_map, deterministic_header, fetch_file_sha256};
+use crate::errors::{LayerSwordError, FileCheckError, InternalError, TerminalError};
+
+/// magic bytes at the beginning of a gzip member
+const GZ_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// magic bytes at the beginning of a zstd frame
+const ZST_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+/// magic number of zstd skippable frames, whose lowest 4 bits are free to use
+const ZST_SKIPPABLE_MAGIC: u32 = 0x184d2a50;
+/// magic bytes at the beginning of a xz stream
+const XZ_MAGIC: [u8; 6] = [0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00];
+/// magic bytes of ustar header at offset 257 of a tar file
+const TAR_MAGIC: [u8; 5] = [0x75, 0x73, 0x74, 0x61, 0x72];
+/// flag of gzip header telling a comment is kept
+const GZ_FLAG_COMMENT: u8 = 0x10;
+/// offset of comment inside a gzip header without extra field or file name
+const GZ_COMMENT_OFFSET: u64 = 10;
+/// offset of data inside a zstd skippable frame
+const ZST_FRAME_OFFSET: u64 = 8;
+/// size of blocks inside a tar file
+const TAR_BLOCK_SIZE: u64 = 512;
+/// length of sha256 in hex
+const DIGEST_LEN: usize = 64;
+/// size of blocks compressed independently inside a large gzip split, the same as pigz
+const PARALLEL_BLOCK_SIZE: usize = 128 * 1024;
+/// number of blocks buffered for every thread before compressing them
+const PARALLEL_BLOCK_BATCH: usize = 8;
+/// splits larger than this are compressed on several threads
+pub const PARALLEL_SPLIT_SIZE: u64 = 16 * 1024 * 1024;
+/// key of pax extended header keeping sha256 of split tar for containers without metadata
+pub const PAX_DIGEST_KEY: &str = "LAYER_SWORD.sha256";
+/// name of the file listing sha256 of split files, written last once a set of splits is published
+pub const SPLIT_SUMS_NAME: &str = "SHA256SUMS";
+/// name of the manifest of splits signed by an ed25519 key, written beside split files
+pub const SPLIT_SIGNATURE_NAME: &str = "SIGNATURE.json";
+/// suffix of hidden files written inside output directory before they are published
+pub const PART_SUFFIX: &str = ".part";
+
+/// container format of split files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitFormat {
+    /// gzip, sha256 is kept inside gzip comment
+    Gz,
+    /// zstandard, sha256 is kept inside a skippable frame ahead of data
+    Zst,
+    /// xz, sha256 is kept inside a pax extended header of the split tar
+    Xz,
+    /// uncompressed tar, sha256 is kept inside a pax extended header of the split tar
+    None,
+}
+
+impl SplitFormat {
+    /// all formats in the order file names of splits are looked up
+    pub const ALL: [SplitFormat; 4] =
+        [SplitFormat::Gz, SplitFormat::Zst, SplitFormat::Xz, SplitFormat::None];
+
+    /// name of format used by argument '**format**'
+    pub fn name(&self) -> &'static str {
+        match self {
+            SplitFormat::Gz => "gz",
+            SplitFormat::Zst => "zst",
+            SplitFormat::Xz => "xz",
+            SplitFormat::None => "none",
+        }
+    }
+
+    /// extension of split files in this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SplitFormat::Gz => "tar.gz",
+            SplitFormat::Zst => "tar.zst",
+            SplitFormat::Xz => "tar.xz",
+            SplitFormat::None => "tar",
+        }
+    }
+
+    /// values of compress level accepted by argument '**level**' for this format
+    pub fn level_range(&self) -> &'static str {
+        match self {
+            SplitFormat::Gz | SplitFormat::Xz => "0 to 9, none, fast, default or best",
+            SplitFormat::Zst => "0 to 9 (zstd levels 1 to 19), none, fast, default or best",
+            SplitFormat::None => "0 to 9, none, fast, default or best (ignored by uncompressed tar)",
+        }
+    }
+
+    /// sha256 kept inside pax extended header of split tar for formats without metadata
+    fn pax_digest<'a>(&self, digest: &'a str) -> Option<&'a str> {
+        match self {
+            SplitFormat::Gz | SplitFormat::Zst => None,
+            SplitFormat::Xz | SplitFormat::None => Some(digest),
+        }
+    }
+
+    /// detect format of a file from its magic bytes,
+    /// return None if it is unreadable or in none of the formats
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use layer_sword::format::SplitFormat;
+    /// fn main() -> std::io::Result<()> {
+    ///     let format = SplitFormat::detect("out/os.tar.zst");
+    ///     assert_eq!(format, Some(SplitFormat::Zst));
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn detect<P>(path: P) -> Option<SplitFormat>
+        where
+            P: AsRef<Path> {
+        let mut file = File::open(path).ok()?;
+        let mut magic: Vec<u8> = Vec::new();
+        (&mut file).take(262).read_to_end(&mut magic).ok()?;
+        if magic.starts_with(&GZ_MAGIC) {
+            Some(SplitFormat::Gz)
+        } else if magic.starts_with(&ZST_MAGIC) || is_zst_skippable(&magic) {
+            Some(SplitFormat::Zst)
+        } else if magic.starts_with(&XZ_MAGIC) {
+            Some(SplitFormat::Xz)
+        } else if magic.len() >= 262 && magic[257..262] == TAR_MAGIC {
+            Some(SplitFormat::None)
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for SplitFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for SplitFormat {
+    type Err = LayerSwordError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SplitFormat::ALL
+            .iter()
+            .find(|format| format.name().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| TerminalError::BadArgError {
+                arg: "format".to_string(),
+                msg: format!("format should be gz, zst, xz or none, actually '{}'", s),
+            }.into())
+    }
+}
+
+/// check whether bytes begin with the magic number of a zstd skippable frame
+fn is_zst_skippable(magic: &[u8]) -> bool {
+    match magic.get(0..4) {
+        Some(head) => {
+            let number = u32::from_le_bytes([head[0], head[1], head[2], head[3]]);
+            number & 0xfffffff0 == ZST_SKIPPABLE_MAGIC
+        }
+        None => false
+    }
+}
+
+/// decompressing reader of a split file
+pub struct SplitReader {
+    /// format detected from magic bytes
+    pub format: SplitFormat,
+    /// sha256 kept inside metadata of container, None if the format has no metadata
+    pub digest: Option<String>,
+    /// reader of the decompressed outer tar
+    pub reader: Box<dyn Read>,
+}
+
+/// open a split file in any format, reading sha256 from metadata of container
+///
+/// # Examples
+///
+/// ```no_run
+/// use layer_sword::format::open_split;
+/// use layer_sword::errors::LayerSwordError;
+/// fn main() -> Result<(), LayerSwordError> {
+///     let split = open_split("out/os.tar.zst")?;
+///     println!("{} {:?}", split.format, split.digest);
+///     Ok(())
+/// }
+/// ```
+pub fn open_split<P>(split_path: P) -> Result<SplitReader, LayerSwordError>
+    where
+        P: AsRef<Path> {
+    let format = SplitFormat::detect(&split_path)
+        .ok_or_else(|| FileCheckError::SplitFormatError { path: split_path.as_ref().to_path_buf() })?;
+    let mut file = File::open(&split_path)?;
+    let (digest, reader): (Option<String>, Box<dyn Read>) = match format {
+        SplitFormat::Gz => {
+            let dec = GzDecoder::new(file);
+            let digest = dec
+                .header()
+                .and_then(|header| header.comment())
+                .and_then(|comment| String::from_utf8(Vec::from(comment)).ok());
+            (digest, Box::new(dec))
+        }
+        SplitFormat::Zst => {
+            let mut head = [0u8; 8];
+            file.read_exact(&mut head)?;
+            if !is_zst_skippable(&head) {
+                return Err(FileCheckError::SplitFileError.into());
+            }
+            let size = u32::from_le_bytes([head[4], head[5], head[6], head[7]]);
+            let mut frame: Vec<u8> = Vec::new();
+            (&mut file).take(size.into()).read_to_end(&mut frame)?;
+            let digest = String::from_utf8(frame).ok();
+            (digest, Box::new(zstd::stream::read::Decoder::new(file)?))
+        }
+        SplitFormat::Xz => (None, Box::new(xz2::read::XzDecoder::new(file))),
+        SplitFormat::None => (None, Box::new(file)),
+    };
+    Ok(SplitReader { format, digest, reader })
+}
+
+/// fetch sha256 kept inside pax extended header of an entry
+pub fn fetch_pax_digest<R: Read>(entry: &mut Entry<R>) -> Option<String> {
+    for extension in entry.pax_extensions().ok()?? {
+        let extension = extension.ok()?;
+        if extension.key().ok()? == PAX_DIGEST_KEY {
+            return extension.value().ok().map(|value| value.to_string());
+        }
+    }
+    None
+}
+
+/// build a pax extended header record, whose length counts itself in decimal
+fn pax_record(key: &str, value: &str) -> Vec<u8> {
+    let body = format!(" {}={}\n", key, value);
+    let mut len = body.len();
+    while len != len.to_string().len() + body.len() {
+        len = len.to_string().len() + body.len();
+    }
+    format!("{}{}", len, body).into_bytes()
+}
+
+/// build headers of the only item of a split tar named `tar_name` of `tar_size` bytes,
+/// ahead of a pax extended header keeping sha256 if it is given
+fn split_tar_head(tar_name: &str, tar_size: u64, pax_digest: Option<&str>)
+                  -> Result<Vec<u8>, LayerSwordError> {
+    let mut tar = Builder::new(Vec::new());
+    if let Some(digest) = pax_digest {
+        let record = pax_record(PAX_DIGEST_KEY, digest);
+        let mut header = Header::new_ustar();
+        header.set_path(Path::new("PaxHeaders").join(tar_name))?;
+        header.set_entry_type(EntryType::XHeader);
+        header.set_size(record.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(0);
+        header.set_cksum();
+        tar.append(&header, record.as_slice())?;
+    }
+    let mut header = deterministic_header(EntryType::Regular, false, tar_size);
+    tar.append_data(&mut header, tar_name, io::empty())?;
+    // take headers out before the builder appends end of archive
+    Ok(mem::take(tar.get_mut()))
+}
+
+/// write the only item of a split tar into writer, whose data is written by `fill`,
+/// ahead of a pax extended header keeping sha256 if it is given,
+/// return what `fill` returns
+fn write_split_tar<W, T, F>(writer: &mut W,
+                            tar_name: &str,
+                            tar_size: u64,
+                            pax_digest: Option<&str>,
+                            fill: F)
+                            -> Result<T, LayerSwordError>
+    where
+        W: Write + ?Sized,
+        F: FnOnce(&mut dyn Write) -> Result<T, LayerSwordError> {
+    writer.write_all(&split_tar_head(tar_name, tar_size, pax_digest)?)?;
+    let mut counter = CountWriter { inner: &mut *writer, count: 0 };
+    let result = fill(&mut counter)?;
+    if counter.count != tar_size {
+        return Err(InternalError::ImpossibleError {
+            msg: format!("item '{}' of split tar has {} bytes rather than {}",
+                         tar_name, counter.count, tar_size),
+        }.into());
+    }
+    let padding = (TAR_BLOCK_SIZE - tar_size % TAR_BLOCK_SIZE) % TAR_BLOCK_SIZE;
+    writer.write_all(&vec![0u8; padding as usize])?;
+    // end of archive
+    writer.write_all(&[0u8; 2 * TAR_BLOCK_SIZE as usize])?;
+    Ok(result)
+}
+
+/// pack a file as the only item of a tar into writer,
+/// ahead of a pax extended header keeping sha256 if it is given
+fn pack_split_tar<W, P>(writer: &mut W, file_path: P, pax_digest: Option<&str>)
+                        -> Result<(), LayerSwordError>
+    where
+        W: Write + ?Sized,
+        P: AsRef<Path> {
+    let filename = file_path
+        .as_ref()
+        .file_name()
+        .ok_or_else(|| InternalError::FilePathError { path: file_path.as_ref().to_path_buf() })?
+        .to_str()
+        .ok_or(InternalError::ConvertError)?;
+    let mut file = File::open(&file_path)?;
+    let size = file.metadata()?.len();
+    write_split_tar(writer, filename, size, pax_digest, |writer| {
+        io::copy(&mut file, writer)?;
+        Ok(())
+    })
+}
+
+/// writer counting bytes written through it
+struct CountWriter<W: Write> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.count += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// writer of a gzip member whose data is compressed by independent blocks on several threads,
+/// like `pigz --independent`, so output is the same for any number of threads
+struct BlockGzWriter<W: Write> {
+    inner: W,
+    buf: Vec<u8>,
+    level: Compression,
+    threads: usize,
+    crc: Crc,
+}
+
+impl<W: Write> BlockGzWriter<W> {
+    /// write gzip header keeping comment, in the same bytes as flate2 does
+    fn new(mut inner: W, comment: &str, level: Compression, threads: usize)
+           -> Result<Self, LayerSwordError> {
+        let xfl: u8 = if level.level() >= Compression::best().level() {
+            2
+        } else if level.level() <= Compression::fast().level() {
+            4
+        } else {
+            0
+        };
+        inner.write_all(&[0x1f, 0x8b, 8, GZ_FLAG_COMMENT, 0, 0, 0, 0, xfl, 255])?;
+        inner.write_all(comment.as_bytes())?;
+        inner.write_all(&[0])?;
+        Ok(Self { inner, buf: Vec::new(), level, threads, crc: Crc::new() })
+    }
+
+    /// compress buffered blocks in parallel, the last partial block is kept unless at the end
+    fn write_blocks(&mut self, is_end: bool) -> io::Result<()> {
+        let end = match is_end {
+            true => self.buf.len(),
+            false => self.buf.len() / PARALLEL_BLOCK_SIZE * PARALLEL_BLOCK_SIZE,
+        };
+        let blocks: Vec<&[u8]> = self.buf[..end].chunks(PARALLEL_BLOCK_SIZE).collect();
+        let level = self.level;
+        for block in parallel_map(&blocks, self.threads, |block| deflate_block(block, level)) {
+            self.inner.write_all(&block?)?;
+        }
+        self.crc.update(&self.buf[..end]);
+        self.buf.drain(..end);
+        Ok(())
+    }
+
+    /// write the final deflate block and gzip trailer
+    fn finish(mut self) -> io::Result<W> {
+        self.write_blocks(true)?;
+        let mut compress = Compress::new(self.level, false);
+        let mut tail: Vec<u8> = Vec::with_capacity(64);
+        compress.compress_vec(&[], &mut tail, FlushCompress::Finish)?;
+        self.inner.write_all(&tail)?;
+        self.inner.write_all(&self.crc.sum().to_le_bytes())?;
+        self.inner.write_all(&self.crc.amount().to_le_bytes())?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for BlockGzWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if self.buf.len() >= PARALLEL_BLOCK_SIZE * PARALLEL_BLOCK_BATCH * self.threads {
+            self.write_blocks(false)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// compress a block into raw deflate data ending at a byte boundary without the final bit,
+/// so that blocks can be joined one after another
+fn deflate_block(block: &[u8], level: Compression) -> io::Result<Vec<u8>> {
+    let mut compress = Compress::new(level, false);
+    let mut data: Vec<u8> = Vec::with_capacity(block.len() / 2 + 64);
+    loop {
+        let consumed = compress.total_in() as usize;
+        data.reserve(PARALLEL_BLOCK_SIZE / 4);
+        compress.compress_vec(&block[consumed..], &mut data, FlushCompress::Sync)?;
+        if compress.total_in() as usize == block.len() && data.len() < data.capacity() {
+            return Ok(data);
+        }
+    }
+}
+
+/// compress a split file in format from a file with tar suffix and its sha256,
+/// compress level from 0 to 9 is mapped onto zstd levels from 1 to 19
+/// and ignored by uncompressed tar
+///
+/// gzip and zstd splits larger than `PARALLEL_SPLIT_SIZE` are compressed on `threads` threads,
+/// their output doesn't change with the number of threads
+///
+/// # Examples
+///
+/// ```no_run
+/// use layer_sword::format::{compress_split, SplitFormat};
+/// use layer_sword::util::fetch_file_sha256;
+/// use layer_sword::errors::LayerSwordError;
+/// fn main() -> Result<(), LayerSwordError> {
+///     let digest = fetch_file_sha256("os.tar")?;
+///     compress_split("os.tar.zst", "os.tar", &digest, SplitFormat::Zst, 6, 4)?;
+///     Ok(())
+/// }
+/// ```
+pub fn compress_split<P>(split_path: P,
+                         file_path: P,
+                         digest: &str,
+                         format: SplitFormat,
+                         compress_level: u8,
+                         threads: usize)
+                         -> Result<(), LayerSwordError>
+    where
+        P: AsRef<Path> {
+    let is_large = fs::metadata(&file_path)?.len() > PARALLEL_SPLIT_SIZE;
+    encode_split(split_path.as_ref(), digest, format, compress_level, threads, is_large,
+                 |writer| pack_split_tar(writer, &file_path, format.pax_digest(digest)))
+}
+
+/// write a split file in format from a tar named `tar_name` of `tar_size` bytes
+/// written by `fill`, which returns sha256 of the tar, so that the tar is never kept on disk
+///
+/// sha256 is patched into metadata of gzip and zstd containers or pax extended header of
+/// uncompressed tar once the tar is written, while `fill` is called twice for xz splits
+/// to fetch sha256 ahead of the compressed pax extended header
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use std::io::copy;
+/// use layer_sword::format::{write_split, SplitFormat};
+/// use layer_sword::util::fetch_file_sha256;
+/// use layer_sword::errors::LayerSwordError;
+/// fn main() -> Result<(), LayerSwordError> {
+///     let size = std::fs::metadata("os.tar")?.len();
+///     let digest = write_split("os.tar.zst", "os.tar", size, SplitFormat::Zst, 6, 4, |writer| {
+///         copy(&mut File::open("os.tar")?, writer)?;
+///         fetch_file_sha256("os.tar")
+///     })?;
+///     println!("{}", digest);
+///     Ok(())
+/// }
+/// ```
+pub fn write_split<P, F>(split_path: P,
+                         tar_name: &str,
+                         tar_size: u64,
+                         format: SplitFormat,
+                         compress_level: u8,
+                         threads: usize,
+                         mut fill: F)
+                         -> Result<String, LayerSwordError>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&mut dyn Write) -> Result<String, LayerSwordError> {
+    let placeholder = "0".repeat(DIGEST_LEN);
+    let ahead_digest = match format {
+        SplitFormat::Xz => Some(fill(&mut io::sink())?),
+        _ => None,
+    };
+    let meta_digest = ahead_digest.clone().unwrap_or_else(|| placeholder.clone());
+    let is_large = tar_size > PARALLEL_SPLIT_SIZE;
+    let mut digest = String::new();
+    encode_split(split_path.as_ref(), &placeholder, format, compress_level, threads, is_large,
+                 |writer| {
+                     digest = write_split_tar(writer, tar_name, tar_size,
+                                              format.pax_digest(&meta_digest), &mut fill)?;
+                     Ok(())
+                 })?;
+    if digest.len() != DIGEST_LEN {
+        return Err(InternalError::ImpossibleError {
+            msg: format!("sha256 '{}' of split tar has bad length", digest),
+        }.into());
+    }
+    let offset = match format {
+        SplitFormat::Gz => GZ_COMMENT_OFFSET,
+        SplitFormat::Zst => ZST_FRAME_OFFSET,
+        SplitFormat::Xz if ahead_digest.as_ref() == Some(&digest) => return Ok(digest),
+        SplitFormat::Xz => {
+            return Err(InternalError::ImpossibleError {
+                msg: format!("split tar '{}' changes while written twice", tar_name),
+            }.into());
+        }
+        SplitFormat::None => {
+            let record = pax_record(PAX_DIGEST_KEY, &placeholder);
+            TAR_BLOCK_SIZE + (record.len() - DIGEST_LEN - 1) as u64
+        }
+    };
+    let mut file = OpenOptions::new().write(true).open(&split_path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(digest.as_bytes())?;
+    Ok(digest)
+}
+
+/// write a split file in format, whose outer tar is written into compressor by `pack`,
+/// `digest` is kept inside metadata of gzip and zstd containers
+fn encode_split<F>(split_path: &Path,
+                   digest: &str,
+                   format: SplitFormat,
+                   compress_level: u8,
+                   threads: usize,
+                   is_large: bool,
+                   pack: F)
+                   -> Result<(), LayerSwordError>
+    where
+        F: FnOnce(&mut dyn Write) -> Result<(), LayerSwordError> {
+    let mut file = File::create(split_path)?;
+    match format {
+        SplitFormat::Gz if is_large => {
+            let mut enc = BlockGzWriter::new(
+                file, digest, Compression::new(compress_level.into()), threads.max(1))?;
+            pack(&mut enc)?;
+            enc.finish()?;
+        }
+        SplitFormat::Gz => {
+            let mut enc = GzBuilder::new()
+                .comment(digest)
+                .write(file, Compression::new(compress_level.into()));
+            pack(&mut enc)?;
+            enc.finish()?;
+        }
+        SplitFormat::Zst => {
+            let frame = digest.as_bytes();
+            file.write_all(&(ZST_SKIPPABLE_MAGIC.to_le_bytes()))?;
+            file.write_all(&(frame.len() as u32).to_le_bytes())?;
+            file.write_all(frame)?;
+            let mut enc = zstd::stream::write::Encoder::new(file, i32::from(compress_level) * 2 + 1)?;
+            enc.include_checksum(true)?;
+            // output of zstd with any number of workers is the same, but differs without them
+            if is_large {
+                enc.multithread(threads.max(1) as u32)?;
+            }
+            pack(&mut enc)?;
+            enc.finish()?;
+        }
+        SplitFormat::Xz => {
+            let mut enc = xz2::write::XzEncoder::new(file, compress_level.into());
+            pack(&mut enc)?;
+            enc.finish()?;
+        }
+        SplitFormat::None => {
+            pack(&mut file)?;
+        }
+    }
+    Ok(())
+}
+
+/// find the split file of a name under directory in any format
+pub fn find_split_file(dir_path: &Path, name: &str) -> Option<PathBuf> {
+    SplitFormat::ALL
+        .iter()
+        .map(|format| dir_path.join(format!("{}.{}", name, format.extension())))
+        .find(|path| path.is_file())
+}
+
+/// fetch name of a split from its file name by extension of its format
+pub fn split_name(split_path: &Path, format: SplitFormat) -> Option<String> {
+    let filename = split_path.file_name()?.to_str()?;
+    filename
+        .strip_suffix(&format!(".{}", format.extension()))
+        .map(|name| name.to_string())
+}
+
+/// path of the hidden part file a file is written into before it is published
+///
+/// # Examples
+///
+/// ```rust
+/// use std::path::Path;
+/// use layer_sword::format::part_path;
+///
+/// assert_eq!(part_path(Path::new("out/os.tar.gz")), Path::new("out/.os.tar.gz.part"));
+/// ```
+pub fn part_path(path: &Path) -> PathBuf {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}{}", file_name, PART_SUFFIX))
+}
+
+/// whether a file under a directory of splits is a split file published,
+/// rather than the sums file, the signed manifest or a part file still being written
+pub fn is_published_split(path: &Path) -> bool {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    file_name != SPLIT_SUMS_NAME && file_name != SPLIT_SIGNATURE_NAME
+        && !(file_name.starts_with('.') && file_name.ends_with(PART_SUFFIX))
+}
+
+/// read names and sha256 of split files listed inside a sums file
+///
+/// # Examples
+///
+/// ```rust
+/// use std::fs;
+/// use std::env;
+/// use layer_sword::format::read_split_sums;
+///
+/// let sums_path = env::temp_dir().join("read_split_sums_SHA256SUMS");
+/// fs::write(&sums_path, format!("{}  os.tar.gz\n", "0".repeat(64))).unwrap();
+/// assert_eq!(read_split_sums(&sums_path).unwrap(), vec![(format!("os.tar.gz"), "0".repeat(64))]);
+/// fs::remove_file(&sums_path).unwrap();
+/// ```
+pub fn read_split_sums(sums_path: &Path) -> Result<Vec<(String, String)>, LayerSwordError> {
+    let mut sums_vec: Vec<(String, String)> = Vec::new();
+    for line in fs::read_to_string(sums_path)?.lines().filter(|line| !line.trim().is_empty()) {
+        match line.split_once("  ") {
+            Some((hash, name)) if hash.len() == DIGEST_LEN && !name.is_empty() =>
+                sums_vec.push((name.to_string(), hash.to_string())),
+            _ => return Err(FileCheckError::SplitSumsError {
+                path: sums_path.to_path_buf(),
+                msg: format!("line '{}' parse failed", line),
+            }.into()),
+        }
+    }
+    Ok(sums_vec)
+}
+
+/// check split files directly under a directory against the sums file published beside them,
+/// a split listed but missing, a split not listed or a split whose sha256 is not the listed one
+/// means the set of splits was changed after it was published, nothing is checked
+/// if there is no sums file
+///
+/// splits listed by relative paths are matched by their file names,
+/// since they are moved back into one directory before merging
+///
+/// # Examples
+///
+/// ```rust
+/// use std::path::{Path, PathBuf};
+/// use layer_sword::format::check_split_sums;
+///
+/// let split_vec = vec![PathBuf::from("tests/data/splits_base/os.tar.gz")];
+/// assert!(check_split_sums(Path::new("tests/data/splits_base"), &split_vec).is_ok());
+/// ```
+pub fn check_split_sums(dir_path: &Path, split_vec: &[PathBuf]) -> Result<(), LayerSwordError> {
+    let sums_path = dir_path.join(SPLIT_SUMS_NAME);
+    if !sums_path.is_file() {
+        return Ok(());
+    }
+    let listed_vec = read_split_sums(&sums_path)?;
+    let file_name_of = |name: &str| name.rsplit('/').next().unwrap_or_default().to_string();
+
+    let mut msg_vec: Vec<String> = Vec::new();
+    for (name, _) in listed_vec.iter() {
+        let file_name = file_name_of(name);
+        if !split_vec.iter().any(|path| path.file_name() == Some(file_name.as_ref())) {
+            msg_vec.push(format!("split '{}' is listed but missing", name));
+        }
+    }
+    for split_path in split_vec {
+        let file_name = split_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        match listed_vec.iter().find(|(listed, _)| file_name_of(listed) == file_name) {
+            None => msg_vec.push(format!("split '{}' is not listed", file_name)),
+            Some((_, hash)) => {
+                let real = fetch_file_sha256(split_path)?;
+                if real != *hash {
+                    msg_vec.push(format!("split '{}' has sha256 '{}' rather than listed '{}'",
+                                         file_name, real, hash));
+                }
+            }
+        }
+    }
+    match msg_vec.is_empty() {
+        true => Ok(()),
+        false => Err(FileCheckError::SplitSumsError { path: sums_path, msg: msg_vec.join("\n") }.into()),
+    }
+}
+
+/// remove part files left by a failed procedure, failures are only logged
+/// so that the error causing them is kept
+pub(crate) fn remove_part_files<'a, I>(part_paths: I)
+    where
+        I: Iterator<Item=&'a Path> {
+    for part_path in part_paths {
+        if part_path.is_file() {
+            if let Err(e) = fs::remove_file(part_path) {
+                log::error!("{}", e);
+            }
+        }
+    }
+}
@@ -1,38 +1,190 @@
 pub mod base;
+pub mod oci;
 
 use std::path::{Path, PathBuf};
 use std::collections::{HashMap, HashSet};
+use std::fs;
 
-use crate::errors::FileCheckError;
+use json::{JsonValue, object};
+
+use crate::os_str_to_string;
+use crate::util::load_config;
+use crate::stream::TarIndex;
+use crate::errors::{LayerSwordError, FileCheckError, InternalError};
+
+/// files and ordered layers of one image inside image tar file
+#[derive(Debug, Clone)]
+pub struct ImageInfo {
+    pub file_map: HashMap<String, PathBuf>,
+    pub layer_dir_vec: Vec<PathBuf>,
+    pub repo_tags: Vec<String>,
+}
+
+/// structure info of one layer inside image
+#[derive(Debug, Clone)]
+pub struct LayerInfo {
+    pub position: usize,
+    pub dir_hash: String,
+    pub diff_id: String,
+    pub size: u64,
+    pub created_by: String,
+}
+
+impl LayerInfo {
+    pub fn to_json(&self) -> JsonValue {
+        object! {
+            position: self.position,
+            dir_hash: self.dir_hash.clone(),
+            diff_id: self.diff_id.clone(),
+            size: self.size,
+            created_by: self.created_by.clone()
+        }
+    }
+}
 
 pub trait Inspect {
     /// inspect route existence and fetch route map
     fn inspect_route(&self, extract_path: &Path)
                      -> Result<(HashMap<String, PathBuf>, HashSet<String>),
-                         FileCheckError>;
+                         LayerSwordError>;
 
     /// inspect info inside image config file
     fn inspect_config(&self, file_map: &HashMap<String, PathBuf>)
-                      -> Result<HashSet<String>, FileCheckError>;
+                      -> Result<HashSet<String>, LayerSwordError>;
 
     /// inspect config inside all layers
     fn inspect_layer(&self,
                      extract_path: &Path,
                      layer_hash_set: &HashSet<String>,
                      config_tar_hash: &HashSet<String>)
-                     -> Result<(), FileCheckError>;
+                     -> Result<(), LayerSwordError>;
+
+    /// inspect config inside all layers by names of files inside layer directories
+    /// and sha256 of their layer tar files, for layer tar files which are never extracted
+    fn inspect_layer_files(&self,
+                           _extract_path: &Path,
+                           _layer_file_map: &HashMap<String, Vec<String>>,
+                           _layer_hash_set: &HashSet<String>,
+                           _config_tar_hash: &HashSet<String>,
+                           _real_tar_hash: &HashSet<String>)
+                           -> Result<(), LayerSwordError> {
+        Err(FileCheckError::BadDockerFileError {
+            msg: "inspecting layers without extracting is not supported by this image layout".to_string()
+        }.into())
+    }
 
-    /// inspect info inside image manifest file
+    /// inspect info inside image manifest file and fetch every image it lists
     fn inspect_manifest(&self,
                         extract_path: &Path,
                         file_map: &HashMap<String, PathBuf>,
                         layer_hash_set: &HashSet<String>)
-                        -> Result<Vec<PathBuf>, FileCheckError>;
+                        -> Result<Vec<ImageInfo>, LayerSwordError>;
 
-    /// function called for a whole inspection procedure
-    fn inspect(&self, extract_path: &Path)
-               -> Result<(HashMap<String, PathBuf>, Vec<PathBuf>),
-                   FileCheckError> {
+    /// write files describing only one image of a multi-image tar file into
+    /// a directory and fetch the route map of them
+    fn isolate_image(&self, image: &ImageInfo, _image_path: &Path)
+                     -> Result<HashMap<String, PathBuf>, LayerSwordError> {
+        Ok(image.file_map.clone())
+    }
+
+    /// combine files describing separate images inside directories into files
+    /// describing all of them inside merge directory
+    fn combine_images(&self, _image_path_vec: &Vec<PathBuf>, _merge_path: &Path)
+                      -> Result<(), LayerSwordError> {
+        Err(FileCheckError::BadDockerFileError {
+            msg: "multiple images are not supported by this image layout".to_string()
+        }.into())
+    }
+
+    /// write files describing a new image made of the bottom layers of the image described
+    /// by files inside top directory, which are the layers present inside merge directory,
+    /// with diff_ids and history of its config truncated to them
+    fn truncate_image(&self, _top_path: &Path, _merge_path: &Path)
+                      -> Result<(), LayerSwordError> {
+        Err(FileCheckError::BadDockerFileError {
+            msg: "merging a part of splits is not supported by this image layout".to_string()
+        }.into())
+    }
+
+    /// fetch repo tags of the image described by files inside a directory
+    fn fetch_repo_tags(&self, _image_path: &Path) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// fetch sizes of layer tar files(or blobs) of every image inside an image tar file
+    /// from bottom up by its index, without extracting it
+    fn fetch_layer_sizes(&self, _index: &TarIndex, _tar_path: &Path)
+                         -> Result<Vec<Vec<u64>>, LayerSwordError> {
+        Err(FileCheckError::BadDockerFileError {
+            msg: "fetching layer sizes is not supported by this image layout".to_string()
+        }.into())
+    }
+
+    /// fetch config of every image by loading json files of image layout through their paths,
+    /// which are read from an image tar file by its index or from an extracted split
+    fn fetch_image_configs(&self, _load: &dyn Fn(&str) -> Result<JsonValue, LayerSwordError>)
+                           -> Result<Vec<JsonValue>, LayerSwordError> {
+        Err(FileCheckError::BadDockerFileError {
+            msg: "fetching image configs is not supported by this image layout".to_string()
+        }.into())
+    }
+
+    /// fetch hashes of diff_ids of every image from bottom up by loading json files
+    /// of image layout through their paths, which are read from an image tar file
+    /// by its index or from an extracted split
+    fn fetch_diff_ids(&self, _load: &dyn Fn(&str) -> Result<JsonValue, LayerSwordError>)
+                      -> Result<Vec<Vec<String>>, LayerSwordError> {
+        Err(FileCheckError::BadDockerFileError {
+            msg: "fetching diff_ids is not supported by this image layout".to_string()
+        }.into())
+    }
+
+    /// fetch structure info of all layers of an image from bottom up
+    fn describe_layers(&self, image: &ImageInfo) -> Result<Vec<LayerInfo>, LayerSwordError> {
+        let config_path = image.file_map
+            .get("config_path")
+            .ok_or_else(|| InternalError::KeyError { key: "config_path".to_string() })?;
+        let config = load_config(config_path)?;
+        let diff_ids: Vec<String> = config["rootfs"]["diff_ids"]
+            .members()
+            .map(|id| id.to_string())
+            .collect();
+        if diff_ids.len() != image.layer_dir_vec.len() {
+            return Err(FileCheckError::BadDockerFileError {
+                msg: format!("config file has {} diff_ids rather than {}",
+                             diff_ids.len(), image.layer_dir_vec.len())
+            }.into());
+        }
+        // history entries of empty layers have no directory
+        let history: Vec<String> = config["history"]
+            .members()
+            .filter(|item| !item["empty_layer"].as_bool().unwrap_or(false))
+            .map(|item| item["created_by"].as_str().unwrap_or_default().to_string())
+            .collect();
+
+        let mut layer_vec: Vec<LayerInfo> = Vec::new();
+        for (i, layer_path) in image.layer_dir_vec.iter().enumerate() {
+            let dir_hash = layer_path
+                .file_name()
+                .ok_or_else(|| InternalError::FilePathError { path: layer_path.clone() })?;
+            let mut tar_path = layer_path.clone();
+            if tar_path.is_dir() {
+                tar_path.push("layer.tar");
+            }
+            layer_vec.push(LayerInfo {
+                position: i,
+                dir_hash: os_str_to_string!(dir_hash)?,
+                diff_id: diff_ids[i].clone(),
+                size: fs::metadata(&tar_path)?.len(),
+                created_by: history.get(i).cloned().unwrap_or_default(),
+            });
+        }
+        Ok(layer_vec)
+    }
+
+    /// function called for a whole inspection procedure on all images
+    fn inspect_all(&self, extract_path: &Path)
+                   -> Result<Vec<ImageInfo>, LayerSwordError> {
         log::info!("Inspecting route of required files");
         let (file_map, layer_hash_set) =
             self.inspect_route(extract_path)?;
@@ -41,8 +193,23 @@ pub trait Inspect {
         log::info!("Inspecting file inside each layer");
         self.inspect_layer(&extract_path, &layer_hash_set, &config_tar_hash)?;
         log::info!("Inspecting items inside manifest file");
-        let layer_dir_vec =
+        let image_vec =
             self.inspect_manifest(&extract_path, &file_map, &layer_hash_set)?;
-        Ok((file_map, layer_dir_vec))
+        Ok(image_vec)
+    }
+
+    /// function called for a whole inspection procedure
+    fn inspect(&self, extract_path: &Path)
+               -> Result<(HashMap<String, PathBuf>, Vec<PathBuf>),
+                   LayerSwordError> {
+        let mut image_vec = self.inspect_all(extract_path)?;
+        if image_vec.len() != 1 {
+            return Err(FileCheckError::BadDockerFileError {
+                msg: format!("manifest file has {} config entries rather than 1",
+                             image_vec.len())
+            }.into());
+        }
+        let image = image_vec.remove(0);
+        Ok((image.file_map, image.layer_dir_vec))
     }
-}
\ No newline at end of file
+}
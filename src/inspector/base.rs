@@ -1,39 +1,105 @@
 use std::path::{Path, PathBuf};
 use std::collections::{HashMap, HashSet};
-use std::fs::read_dir;
+use std::fs::{read_dir, rename};
 
 use regex::Regex;
-use json::JsonValue;
+use json::{JsonValue, object};
 
-use crate::inspector::Inspect;
+use crate::inspector::{Inspect, ImageInfo};
 use crate::os_str_to_string;
-use crate::util::{fetch_file_sha256, load_config};
-use crate::errors::{FileCheckError, InternalError, raise, report, raise_debug};
+use crate::stream::TarIndex;
+use crate::util::{fetch_file_sha256, load_config, dump_config};
+use crate::errors::{LayerSwordError, FileCheckError, InternalError, report};
+
+/// inspect info inside config file of one image and fetch its diff_ids
+fn inspect_one_config(config_path: &Path) -> Result<HashSet<String>, LayerSwordError> {
+    let mut layer_tar_hash: HashSet<String> = HashSet::new();
+
+    // check hash of config.json itself
+    let config_filestem = config_path
+        .file_stem()
+        .ok_or_else(|| InternalError::FilePathError { path: config_path.to_path_buf() })?;
+    let config_filestem = os_str_to_string!(config_filestem)?;
+    let hash = fetch_file_sha256(config_path)?;
+    if config_filestem != hash {
+        return Err(FileCheckError::BadDockerFileError {
+            msg: format!("config.json sha256 check failed \
+                \nreal:'{}'\nright:'{}'", config_filestem.clone(), hash.clone())
+        }.into());
+    }
+    // check diff_id's existence
+    let config = load_config(config_path)?;
+    for hash in fetch_config_diff_ids(&config)? {
+        layer_tar_hash.insert(hash);
+    }
+    Ok(layer_tar_hash)
+}
+
+/// fetch hashes of diff_ids inside config of one image from bottom up
+fn fetch_config_diff_ids(config: &JsonValue) -> Result<Vec<String>, LayerSwordError> {
+    let diff_ids = &config["rootfs"]["diff_ids"];
+    let diff_ids = match diff_ids {
+        JsonValue::Array(ids) => { Ok(ids) }
+        _ => {
+            Err(FileCheckError::BadDockerFileError {
+                msg: "config file parse failed".to_string()
+            })
+        }
+    }?;
+
+    let mut hash_vec: Vec<String> = Vec::new();
+    for diff_id in diff_ids {
+        let diff_id = match diff_id {
+            JsonValue::String(ids) => { Ok(ids) }
+            _ => {
+                Err(FileCheckError::BadDockerFileError { msg: "config file parse failed".to_string() })
+            }
+        }?;
+        let prefix = &diff_id[0..7];
+        let hash = &diff_id[7..];
+        if prefix != "sha256:" {
+            return Err(FileCheckError::BadDockerFileError {
+                msg: format!("bad hash 'diff_id' prefix inside config.json) \
+            \nreal:'{}'\nright:'sha256:'", prefix)
+            }.into());
+        }
+        hash_vec.push(hash.to_string());
+    }
+    Ok(hash_vec)
+}
+
+/// fetch repo tags of an entry inside manifest file
+fn fetch_item_repo_tags(item: &JsonValue) -> Vec<String> {
+    item["RepoTags"]
+        .members()
+        .filter_map(|tag| tag.as_str())
+        .map(|tag| tag.to_string())
+        .collect()
+}
 
 pub struct BaseInspector {}
 
 impl Inspect for BaseInspector {
     fn inspect_route(&self, extract_path: &Path)
-                     -> Result<(HashMap<String, PathBuf>, HashSet<String>), FileCheckError> {
+                     -> Result<(HashMap<String, PathBuf>, HashSet<String>), LayerSwordError> {
         let mut file_map: HashMap<String, PathBuf> = HashMap::new();
         let mut layer_hash_set: HashSet<String> = HashSet::new();
 
-        let mut config_num: u8 = 0;
-        let mut config_path: PathBuf = PathBuf::new();
+        let mut config_num: usize = 0;
         let mut manifest_path: PathBuf = PathBuf::new();
         let mut repositories_path: PathBuf = PathBuf::new();
 
-        let expr_config = raise(Regex::new(r#"^[a-z0-9]{64}.json$"#));
-        let expr_layer_and_config = raise(Regex::new(r#"^[a-z0-9]{64}"#));
+        let expr_config = Regex::new(r#"^[a-z0-9]{64}.json$"#)?;
+        let expr_layer_and_config = Regex::new(r#"^[a-z0-9]{64}"#)?;
 
-        let all_extracted_paths = raise(read_dir(extract_path));
+        let all_extracted_paths = read_dir(extract_path)?;
         let mut now_path: String;
         for entry in all_extracted_paths {
-            let entry = raise(entry);
-            now_path = raise(entry
+            let entry = entry?;
+            now_path = entry
                 .file_name()
                 .into_string()
-                .map_err(|_| InternalError::ConvertError));
+                .map_err(|_| InternalError::ConvertError)?;
             // construct manifest
             if manifest_path.components().next().is_none() && now_path == "manifest.json" {
                 manifest_path.push(extract_path);
@@ -49,15 +115,11 @@ impl Inspect for BaseInspector {
             if judge_sha256 == true {
                 // this is a config
                 if expr_config.is_match(&*now_path) == true {
-                    if config_num > 1 {
-                        return Err(FileCheckError::BadDockerFileError {
-                            msg: format!("more than one config.json '{}' and '{:?}'",
-                                         now_path.clone(), config_path.clone())
-                        });
-                    }
+                    // every image inside a multi-image tar file has its own config
                     config_num += 1;
-                    config_path.push(extract_path);
+                    let mut config_path = extract_path.to_path_buf();
                     config_path.push(&now_path);
+                    file_map.insert(format!("config_path:{}", now_path), config_path);
                 } else {
                     // this is a layer
                     layer_hash_set.insert(now_path);
@@ -65,13 +127,12 @@ impl Inspect for BaseInspector {
             }
         }
         if config_num == 0 {
-            return Err(FileCheckError::BadDockerFileError { msg: format!("no config.json") });
+            return Err(FileCheckError::BadDockerFileError { msg: "no config.json".to_string() }.into());
         } else if manifest_path.components().next().is_none() {
-            return Err(FileCheckError::BadDockerFileError { msg: format!("no manifest.json") });
+            return Err(FileCheckError::BadDockerFileError { msg: "no manifest.json".to_string() }.into());
         } else if repositories_path.components().next().is_none() {
-            return Err(FileCheckError::BadDockerFileError { msg: format!("no repositories") });
+            return Err(FileCheckError::BadDockerFileError { msg: "no repositories".to_string() }.into());
         }
-        file_map.insert(format!("config_path"), config_path);
         file_map.insert(format!("manifest_path"), manifest_path);
         file_map.insert(format!("repositories_path"), repositories_path);
         Ok((file_map, layer_hash_set))
@@ -79,53 +140,16 @@ impl Inspect for BaseInspector {
 
 
     fn inspect_config(&self, file_map: &HashMap<String, PathBuf>)
-                      -> Result<HashSet<String>, FileCheckError> {
+                      -> Result<HashSet<String>, LayerSwordError> {
         let mut layer_tar_hash: HashSet<String> = HashSet::new();
-
-        // check hash of config.json itself
-        let config_path: &Path = raise(file_map
-            .get("config_path")
-            .ok_or_else(|| InternalError::KeyError { key: format!("config_path") }))
-            .as_path();
-        let config_filestem = raise(config_path
-            .file_stem()
-            .ok_or_else(|| InternalError::FilePathError { path: config_path.to_path_buf() }));
-        let config_filestem = os_str_to_string!(config_filestem);
-        let hash = fetch_file_sha256(config_path);
-        if config_filestem != hash {
-            return Err(FileCheckError::BadDockerFileError {
-                msg: format!("config.json sha256 check failed \
-                    \nreal:'{}'\nright:'{}'", config_filestem.clone(), hash.clone())
-            });
-        }
-        // check diff_id's existence
-        let config = load_config(config_path)?;
-        let diff_ids = &config["rootfs"]["diff_ids"];
-        let diff_ids = match diff_ids {
-            JsonValue::Array(ids) => { Ok(ids) }
-            _ => {
-                Err(FileCheckError::BadDockerFileError {
-                    msg: format!("config file parse failed")
-                })
-            }
-        }?;
-
-        for diff_id in diff_ids {
-            let diff_id = match diff_id {
-                JsonValue::String(ids) => { Ok(ids) }
-                _ => {
-                    Err(FileCheckError::BadDockerFileError { msg: format!("config file parse failed") })
-                }
-            }?;
-            let prefix = &diff_id[0..7];
-            let hash = &diff_id[7..];
-            if prefix != "sha256:" {
-                return Err(FileCheckError::BadDockerFileError {
-                    msg: format!("bad hash 'diff_id' prefix inside config.json) \
-                \nreal:'{}'\nright:'sha256:'", prefix.clone())
-                });
-            }
-            layer_tar_hash.insert(hash.to_string());
+        let mut config_path_vec: Vec<&PathBuf> = file_map
+            .iter()
+            .filter(|(key, _)| key.starts_with("config_path"))
+            .map(|(_, path)| path)
+            .collect();
+        config_path_vec.sort();
+        for config_path in config_path_vec {
+            layer_tar_hash.extend(inspect_one_config(config_path)?);
         }
         Ok(layer_tar_hash)
     }
@@ -133,39 +157,58 @@ impl Inspect for BaseInspector {
     fn inspect_layer(&self,
                      extract_path: &Path,
                      layer_hash_set: &HashSet<String>,
-                     config_tar_hash: &HashSet<String>) -> Result<(), FileCheckError> {
+                     config_tar_hash: &HashSet<String>) -> Result<(), LayerSwordError> {
+        let mut layer_file_map: HashMap<String, Vec<String>> = HashMap::new();
+        let mut real_tar_hash: HashSet<String> = HashSet::new();
+        for layer in layer_hash_set {
+            let mut layer_dir_path = extract_path.to_path_buf();
+            layer_dir_path.push(layer.clone());
+            let mut file_vec: Vec<String> = Vec::new();
+            for entry in read_dir(&layer_dir_path)? {
+                let entry = entry?;
+                let file_name = entry.file_name();
+                let now_path = file_name
+                    .to_str()
+                    .ok_or(InternalError::ConvertError)?;
+                if now_path == "layer.tar" {
+                    let mut layer_tar_path = layer_dir_path.clone();
+                    layer_tar_path.push(now_path);
+                    real_tar_hash.insert(fetch_file_sha256(layer_tar_path)?);
+                }
+                file_vec.push(now_path.to_string());
+            }
+            layer_file_map.insert(layer.clone(), file_vec);
+        }
+        self.inspect_layer_files(extract_path, &layer_file_map, layer_hash_set,
+                                 config_tar_hash, &real_tar_hash)
+    }
+
+    fn inspect_layer_files(&self,
+                           extract_path: &Path,
+                           layer_file_map: &HashMap<String, Vec<String>>,
+                           layer_hash_set: &HashSet<String>,
+                           config_tar_hash: &HashSet<String>,
+                           real_tar_hash: &HashSet<String>) -> Result<(), LayerSwordError> {
         if config_tar_hash.len() != layer_hash_set.len() {
             return Err(FileCheckError::BadDockerFileError {
                 msg: format!("layer number is different from what inside config.json\
                     \nreal:'{}'\nright:'{}'", layer_hash_set.len(), config_tar_hash.len())
-            });
+            }.into());
         }
 
-        let mut real_tar_hash: HashSet<String> = HashSet::new();
-
         for layer in layer_hash_set {
-            let mut layer_dir_path = extract_path.clone().to_path_buf();
+            let mut layer_dir_path = extract_path.to_path_buf();
             layer_dir_path.push(layer.clone());
-            let layer_paths = raise(read_dir(&layer_dir_path));
             let mut has_layer = false;
             let mut has_json = false;
-            for entry in layer_paths {
-                let entry = raise(entry);
-                let file_name = entry.file_name();
-                let now_path = raise(file_name
-                    .to_str()
-                    .ok_or_else(|| InternalError::ConvertError));
+            for now_path in layer_file_map.get(layer).into_iter().flatten() {
                 if now_path != "json" && now_path != "layer.tar" && now_path != "VERSION" {
                     return Err(FileCheckError::BadDockerFileError {
                         msg: format!("unrecognized file '{}' inside layer '{:?}'",
                                      now_path, layer)
-                    });
+                    }.into());
                 }
                 if now_path == "layer.tar" {
-                    let mut layer_tar_path = layer_dir_path.clone();
-                    layer_tar_path.push(now_path);
-                    let hash = fetch_file_sha256(layer_tar_path);
-                    real_tar_hash.insert(hash);
                     has_layer = true;
                 } else if now_path == "json" {
                     let mut layer_json_path = layer_dir_path.clone();
@@ -184,7 +227,7 @@ impl Inspect for BaseInspector {
                     if !parent_layer.len() == 0 && !layer_hash_set.contains(&*parent_layer) {
                         return Err(FileCheckError::BadDockerFileError {
                             msg: format!("bad json inside layer '{:?}'", layer)
-                        });
+                        }.into());
                     }
                     has_json = true;
                 }
@@ -192,21 +235,21 @@ impl Inspect for BaseInspector {
             if !has_json {
                 return Err(FileCheckError::BadDockerFileError {
                     msg: format!("no json inside layer '{:?}'", layer)
-                });
+                }.into());
             } else if !has_layer {
                 return Err(FileCheckError::BadDockerFileError {
                     msg: format!("no layer.tar inside layer '{:?}'", layer)
-                });
+                }.into());
             }
         }
         // check same layers inside config and path
         let error_layer: Vec<_> = config_tar_hash
-            .symmetric_difference(&real_tar_hash)
+            .symmetric_difference(real_tar_hash)
             .collect();
         if error_layer.len() != 0 {
             return Err(FileCheckError::BadDockerFileError {
                 msg: format!("some file 'layer.tar' sha256 is not equal to what inside config.json")
-            });
+            }.into());
         }
         Ok(())
     }
@@ -214,10 +257,13 @@ impl Inspect for BaseInspector {
     fn inspect_manifest(&self,
                         extract_path: &Path,
                         file_map: &HashMap<String, PathBuf>,
-                        layer_hash_set: &HashSet<String>) -> Result<Vec<PathBuf>, FileCheckError> {
-        let manifest_path = raise(file_map
+                        layer_hash_set: &HashSet<String>) -> Result<Vec<ImageInfo>, LayerSwordError> {
+        let manifest_path = file_map
             .get("manifest_path")
-            .ok_or_else(|| InternalError::KeyError { key: format!("manifest_path") }));
+            .ok_or_else(|| InternalError::KeyError { key: "manifest_path".to_string() })?;
+        let repositories_path = file_map
+            .get("repositories_path")
+            .ok_or_else(|| InternalError::KeyError { key: "repositories_path".to_string() })?;
         let config = report(load_config(manifest_path),
                             FileCheckError::BadDockerFileError {
                                 msg: format!("manifest file parse failed")
@@ -230,65 +276,298 @@ impl Inspect for BaseInspector {
                 })
             }
         }?;
-        if config_array.len() != 1 {
+        let config_num = file_map.keys().filter(|key| key.starts_with("config_path")).count();
+        if config_array.len() != config_num {
             return Err(FileCheckError::BadDockerFileError {
-                msg: format!("manifest file has {} config entries rather than 1",
-                             config_array.len())
-            });
+                msg: format!("manifest file has {} config entries rather than {}",
+                             config_array.len(), config_num)
+            }.into());
         }
 
-        let mut layer_dir_vec: Vec<PathBuf> = Vec::new();
-        let config_path = match &config[0]["Config"] {
-            JsonValue::String(path) => { Ok(path) }
-            _ => {
-                Err(FileCheckError::BadDockerFileError {
-                    msg: format!("manifest file parse failed")
-                })
-            }
-        }?;
-        let mut log_config_path = extract_path.to_path_buf();
-        log_config_path.push(config_path);
-        if file_map["config_path"] != log_config_path {
-            return Err(FileCheckError::BadDockerFileError {
-                msg: format!("config file path '{:?}' is not equal to '{:?}' inside manefist.json",
-                             file_map["config_path"], log_config_path)
-            });
-        }
-        let layers = match &config[0]["Layers"] {
-            JsonValue::Array(layers) => { Ok(layers) }
-            _ => {
-                Err(FileCheckError::BadDockerFileError {
-                    msg: format!("manifest file parse failed")
-                })
-            }
-        }?;
-        for layer in layers {
-            let layer_parent_path = match layer {
-                JsonValue::String(layers) => { Ok(Path::new(layers)) }
+        let mut image_vec: Vec<ImageInfo> = Vec::new();
+        for item in config_array {
+            let config_path = match &item["Config"] {
+                JsonValue::String(path) => { Ok(path) }
                 _ => {
                     Err(FileCheckError::BadDockerFileError {
                         msg: format!("manifest file parse failed")
                     })
                 }
             }?;
-            let layer_path = raise(
-                layer_parent_path
-                    .parent()
-                    .ok_or_else(|| InternalError::FilePathError {
-                        path: layer_parent_path.to_path_buf()
-                    }));
-            if !layer_hash_set.contains(
-                raise(layer_path
-                    .to_str()
-                    .ok_or_else(|| InternalError::ConvertError))) {
+            let mut log_config_path = extract_path.to_path_buf();
+            log_config_path.push(config_path);
+            if file_map.get(&format!("config_path:{}", config_path)) != Some(&log_config_path) {
                 return Err(FileCheckError::BadDockerFileError {
-                    msg: format!("layer inside manifest doesn't exist")
-                });
+                    msg: format!("config file path '{:?}' inside manefist.json doesn't exist",
+                                 log_config_path)
+                }.into());
+            }
+            let layers = match &item["Layers"] {
+                JsonValue::Array(layers) => { Ok(layers) }
+                _ => {
+                    Err(FileCheckError::BadDockerFileError {
+                        msg: "manifest file parse failed".to_string()
+                    })
+                }
+            }?;
+            let mut layer_dir_vec: Vec<PathBuf> = Vec::new();
+            for layer in layers {
+                let layer_parent_path = match layer {
+                    JsonValue::String(layers) => { Ok(Path::new(layers)) }
+                    _ => {
+                        Err(FileCheckError::BadDockerFileError {
+                            msg: "manifest file parse failed".to_string()
+                        })
+                    }
+                }?;
+                let layer_path = 
+                    layer_parent_path
+                        .parent()
+                        .ok_or_else(|| InternalError::FilePathError {
+                            path: layer_parent_path.to_path_buf()
+                        })?;
+                if !layer_hash_set.contains(
+                    layer_path
+                        .to_str()
+                        .ok_or(InternalError::ConvertError)?) {
+                    return Err(FileCheckError::BadDockerFileError {
+                        msg: "layer inside manifest doesn't exist".to_string()
+                    }.into());
+                }
+                let mut layer_full_path = extract_path.to_path_buf();
+                layer_full_path.push(layer_path);
+                layer_dir_vec.push(layer_full_path);
+            }
+            let mut image_file_map: HashMap<String, PathBuf> = HashMap::new();
+            image_file_map.insert("config_path".to_string(), log_config_path);
+            image_file_map.insert("manifest_path".to_string(), manifest_path.clone());
+            image_file_map.insert("repositories_path".to_string(), repositories_path.clone());
+            image_vec.push(ImageInfo {
+                file_map: image_file_map,
+                layer_dir_vec,
+                repo_tags: fetch_item_repo_tags(item),
+            });
+        }
+        Ok(image_vec)
+    }
+
+    fn isolate_image(&self, image: &ImageInfo, image_path: &Path)
+                     -> Result<HashMap<String, PathBuf>, LayerSwordError> {
+        let config_path = image.file_map
+            .get("config_path")
+            .ok_or_else(|| InternalError::KeyError { key: "config_path".to_string() })?;
+        let config_name = config_path
+            .file_name()
+            .ok_or_else(|| InternalError::FilePathError { path: config_path.clone() })?;
+        let config_name = os_str_to_string!(config_name)?;
+
+        // keep only the manifest entry of this image
+        let manifest = load_config(&image.file_map["manifest_path"])?;
+        let mut image_manifest = JsonValue::new_array();
+        for item in manifest.members() {
+            if item["Config"].as_str() == Some(&config_name) {
+                image_manifest.push(item.clone())?;
+            }
+        }
+        // keep only the tags of this image
+        let repositories = load_config(&image.file_map["repositories_path"])?;
+        let mut image_repositories = JsonValue::new_object();
+        for (repo, tags) in repositories.entries() {
+            for (tag, layer) in tags.entries() {
+                if image.repo_tags.contains(&format!("{}:{}", repo, tag)) {
+                    if image_repositories[repo].is_null() {
+                        image_repositories[repo] = JsonValue::new_object();
+                    }
+                    image_repositories[repo][tag] = layer.clone();
+                }
+            }
+        }
+
+        let mut manifest_path = image_path.to_path_buf();
+        manifest_path.push("manifest.json");
+        dump_config(image_manifest, &manifest_path)?;
+        let mut repositories_path = image_path.to_path_buf();
+        repositories_path.push("repositories");
+        dump_config(image_repositories, &repositories_path)?;
+
+        let mut file_map: HashMap<String, PathBuf> = HashMap::new();
+        file_map.insert("config_path".to_string(), config_path.clone());
+        file_map.insert("manifest_path".to_string(), manifest_path);
+        file_map.insert("repositories_path".to_string(), repositories_path);
+        Ok(file_map)
+    }
+
+    fn combine_images(&self, image_path_vec: &Vec<PathBuf>, merge_path: &Path)
+                      -> Result<(), LayerSwordError> {
+        let mut manifest = JsonValue::new_array();
+        let mut repositories = JsonValue::new_object();
+        for image_path in image_path_vec {
+            let mut manifest_path = image_path.clone();
+            manifest_path.push("manifest.json");
+            for item in load_config(&manifest_path)?.members() {
+                manifest.push(item.clone())?;
+            }
+            let mut repositories_path = image_path.clone();
+            repositories_path.push("repositories");
+            for (repo, tags) in load_config(&repositories_path)?.entries() {
+                if repositories[repo].is_null() {
+                    repositories[repo] = JsonValue::new_object();
+                }
+                for (tag, layer) in tags.entries() {
+                    repositories[repo][tag] = layer.clone();
+                }
+            }
+        }
+        let mut manifest_path = merge_path.to_path_buf();
+        manifest_path.push("manifest.json");
+        dump_config(manifest, manifest_path)?;
+        let mut repositories_path = merge_path.to_path_buf();
+        repositories_path.push("repositories");
+        dump_config(repositories, repositories_path)?;
+        Ok(())
+    }
+
+    fn truncate_image(&self, top_path: &Path, merge_path: &Path)
+                      -> Result<(), LayerSwordError> {
+        let mut manifest_path = top_path.to_path_buf();
+        manifest_path.push("manifest.json");
+        let manifest = load_config(&manifest_path)?;
+        if manifest.len() != 1 {
+            return Err(FileCheckError::BadDockerFileError {
+                msg: format!("manifest file has {} config entries rather than 1", manifest.len())
+            }.into());
+        }
+        let item = &manifest[0];
+        let config_name = item["Config"]
+            .as_str()
+            .ok_or_else(|| FileCheckError::BadDockerFileError {
+                msg: "manifest file parse failed".to_string()
+            })?;
+        let mut config_path = top_path.to_path_buf();
+        config_path.push(config_name);
+        let config = load_config(&config_path)?;
+
+        // layers of the new image are the bottom ones whose directories are merged
+        let mut layers = JsonValue::new_array();
+        for layer in item["Layers"].members() {
+            let layer_str = layer
+                .as_str()
+                .ok_or_else(|| FileCheckError::BadDockerFileError {
+                    msg: "manifest file parse failed".to_string()
+                })?;
+            let mut layer_path = merge_path.to_path_buf();
+            layer_path.push(layer_str);
+            if !layer_path.exists() { break; }
+            layers.push(layer.clone())?;
+        }
+        let layer_num = layers.len();
+        if layer_num == 0 {
+            return Err(FileCheckError::BadDockerFileError {
+                msg: "no layer inside merged splits".to_string()
+            }.into());
+        }
+        if config["rootfs"]["diff_ids"].len() < layer_num {
+            return Err(FileCheckError::BadDockerFileError {
+                msg: format!("config file has {} diff_ids rather than at least {}",
+                             config["rootfs"]["diff_ids"].len(), layer_num)
+            }.into());
+        }
+
+        let mut new_config = config.clone();
+        let mut diff_ids = JsonValue::new_array();
+        for diff_id in config["rootfs"]["diff_ids"].members().take(layer_num) {
+            diff_ids.push(diff_id.clone())?;
+        }
+        new_config["rootfs"]["diff_ids"] = diff_ids;
+        // history entries of empty layers have no directory and are kept below the last layer
+        let mut history = JsonValue::new_array();
+        let mut history_layer_num = 0;
+        for entry in config["history"].members() {
+            if history_layer_num == layer_num { break; }
+            if !entry["empty_layer"].as_bool().unwrap_or(false) {
+                history_layer_num += 1;
             }
-            let mut layer_full_path = extract_path.to_path_buf();
-            layer_full_path.push(layer_path);
-            layer_dir_vec.push(layer_full_path);
+            history.push(entry.clone())?;
+        }
+        if config.has_key("history") {
+            new_config["history"] = history;
+        }
+
+        // config file is named after its sha256
+        let mut new_config_path = merge_path.to_path_buf();
+        new_config_path.push("config.json");
+        dump_config(new_config, &new_config_path)?;
+        let new_config_name = format!("{}.json", fetch_file_sha256(&new_config_path)?);
+        let mut named_config_path = merge_path.to_path_buf();
+        named_config_path.push(&new_config_name);
+        rename(&new_config_path, &named_config_path)?;
+
+        // repo tags belong to the whole image rather than the new one
+        let mut new_manifest = JsonValue::new_array();
+        new_manifest.push(object! {
+            Config: new_config_name,
+            RepoTags: JsonValue::new_array(),
+            Layers: layers
+        })?;
+        let mut new_manifest_path = merge_path.to_path_buf();
+        new_manifest_path.push("manifest.json");
+        dump_config(new_manifest, &new_manifest_path)?;
+        let mut repositories_path = merge_path.to_path_buf();
+        repositories_path.push("repositories");
+        dump_config(JsonValue::new_object(), &repositories_path)?;
+        Ok(())
+    }
+
+    fn fetch_layer_sizes(&self, index: &TarIndex, tar_path: &Path)
+                         -> Result<Vec<Vec<u64>>, LayerSwordError> {
+        let manifest = index.load_item(tar_path, "manifest.json")?;
+        let mut size_vec_vec: Vec<Vec<u64>> = Vec::new();
+        for item in manifest.members() {
+            let mut size_vec: Vec<u64> = Vec::new();
+            for layer in item["Layers"].members() {
+                let layer = layer
+                    .as_str()
+                    .ok_or_else(|| FileCheckError::BadDockerFileError {
+                        msg: "manifest file parse failed".to_string()
+                    })?;
+                let item = index.item_map
+                    .get(layer)
+                    .ok_or_else(|| FileCheckError::BadDockerFileError {
+                        msg: format!("layer '{}' inside manifest.json doesn't exist", layer)
+                    })?;
+                size_vec.push(item.size);
+            }
+            size_vec_vec.push(size_vec);
+        }
+        Ok(size_vec_vec)
+    }
+
+    fn fetch_image_configs(&self, load: &dyn Fn(&str) -> Result<JsonValue, LayerSwordError>)
+                           -> Result<Vec<JsonValue>, LayerSwordError> {
+        let manifest = load("manifest.json")?;
+        let mut config_vec: Vec<JsonValue> = Vec::new();
+        for item in manifest.members() {
+            let config_name = item["Config"]
+                .as_str()
+                .ok_or_else(|| FileCheckError::BadDockerFileError {
+                    msg: "manifest file parse failed".to_string()
+                })?;
+            config_vec.push(load(config_name)?);
+        }
+        Ok(config_vec)
+    }
+
+    fn fetch_diff_ids(&self, load: &dyn Fn(&str) -> Result<JsonValue, LayerSwordError>)
+                      -> Result<Vec<Vec<String>>, LayerSwordError> {
+        self.fetch_image_configs(load)?.iter().map(fetch_config_diff_ids).collect()
+    }
+
+    fn fetch_repo_tags(&self, image_path: &Path) -> Vec<String> {
+        let mut manifest_path = image_path.to_path_buf();
+        manifest_path.push("manifest.json");
+        match load_config(&manifest_path) {
+            Ok(manifest) => manifest.members().flat_map(fetch_item_repo_tags).collect(),
+            Err(_) => Vec::new()
         }
-        Ok(layer_dir_vec)
     }
-}
\ No newline at end of file
+}
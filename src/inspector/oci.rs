@@ -0,0 +1,385 @@
+use std::path::{Path, PathBuf};
+use std::collections::{HashMap, HashSet};
+
+use json::JsonValue;
+
+use crate::inspector::{Inspect, ImageInfo};
+use crate::os_str_to_string;
+use crate::stream::TarIndex;
+use crate::util::{fetch_file_sha256, load_config};
+use crate::errors::{LayerSwordError, FileCheckError, InternalError, report};
+
+/// fetch hex part of a digest like 'sha256:<hex>'
+fn digest_hex(digest: &JsonValue) -> Result<String, LayerSwordError> {
+    let digest = digest
+        .as_str()
+        .ok_or_else(|| FileCheckError::BadOciFileError { msg: "digest is not a string".to_string() })?;
+    if !digest.starts_with("sha256:") || digest.len() != 71 {
+        return Err(FileCheckError::BadOciFileError {
+            msg: format!("bad digest '{}', only 'sha256:' is supported", digest)
+        }.into());
+    }
+    Ok(digest[7..].to_string())
+}
+
+/// fetch path of a blob inside image layout
+fn blob_path(extract_path: &Path, hex: &String) -> PathBuf {
+    let mut path = extract_path.to_path_buf();
+    path.push("blobs");
+    path.push("sha256");
+    path.push(hex);
+    path
+}
+
+/// check a blob for its sha256 against its file name
+fn check_blob(path: &Path) -> Result<(), LayerSwordError> {
+    let filename = path
+        .file_name()
+        .ok_or_else(|| InternalError::FilePathError { path: path.to_path_buf() })?;
+    let filename = os_str_to_string!(filename)?;
+    let hash = fetch_file_sha256(path)?;
+    if filename != hash {
+        return Err(FileCheckError::BadOciFileError {
+            msg: format!("blob sha256 check failed \
+                    \nreal:'{}'\nright:'{}'", hash, filename)
+        }.into());
+    }
+    Ok(())
+}
+
+/// fetch hashes of diff_ids inside config blob of one image from bottom up
+fn fetch_config_diff_ids(config: &JsonValue) -> Result<Vec<String>, LayerSwordError> {
+    let diff_ids = match &config["rootfs"]["diff_ids"] {
+        JsonValue::Array(ids) => { Ok(ids) }
+        _ => {
+            Err(FileCheckError::BadOciFileError { msg: "config blob parse failed".to_string() })
+        }
+    }?;
+    diff_ids.iter().map(digest_hex).collect()
+}
+
+/// media types of descriptors pointing to an image index rather than an image manifest
+const INDEX_MEDIA_TYPES: [&str; 2] = [
+    "application/vnd.oci.image.index.v1+json",
+    "application/vnd.docker.distribution.manifest.list.v2+json",
+];
+/// depth limit of image indexes nested inside each other
+const MAX_INDEX_DEPTH: usize = 8;
+
+/// an image manifest reached from index.json through image indexes nested in between
+#[derive(Debug, Clone)]
+struct ManifestRef {
+    hex: String,
+    index_hex_vec: Vec<String>,
+    repo_tags: Vec<String>,
+}
+
+/// fetch repo tags annotated on a descriptor, the full image name written by containerd
+/// is preferred over the reference name
+fn fetch_descriptor_repo_tags(descriptor: &JsonValue) -> Vec<String> {
+    let annotations = &descriptor["annotations"];
+    annotations["io.containerd.image.name"]
+        .as_str()
+        .or_else(|| annotations["org.opencontainers.image.ref.name"].as_str())
+        .map(|tag| vec![tag.to_string()])
+        .unwrap_or_default()
+}
+
+/// follow descriptors of an image index into image manifests, nested image indexes are
+/// followed as well, where manifests of platforms not saved and attestation manifests are skipped,
+/// and manifests without tags of their own are tagged by the descriptor of the index holding them
+fn follow_index(index: &JsonValue,
+                index_hex_vec: &[String],
+                index_repo_tags: &[String],
+                load: &dyn Fn(&str) -> Result<JsonValue, LayerSwordError>,
+                exists: &dyn Fn(&str) -> bool,
+                manifest_vec: &mut Vec<ManifestRef>) -> Result<(), LayerSwordError> {
+    let descriptors = match &index["manifests"] {
+        JsonValue::Array(descriptors) => { Ok(descriptors) }
+        _ => {
+            Err(FileCheckError::BadOciFileError { msg: "index file parse failed".to_string() })
+        }
+    }?;
+    for descriptor in descriptors {
+        if !descriptor["annotations"]["vnd.docker.reference.type"].is_null() {
+            continue;
+        }
+        let hex = digest_hex(&descriptor["digest"])?;
+        let blob = format!("blobs/sha256/{}", hex);
+        if !exists(&blob) {
+            if !index_hex_vec.is_empty() {
+                continue;
+            }
+            return Err(FileCheckError::BadOciFileError {
+                msg: format!("manifest blob '{}' doesn't exist", hex)
+            }.into());
+        }
+        let mut repo_tags = fetch_descriptor_repo_tags(descriptor);
+        if repo_tags.is_empty() {
+            repo_tags = index_repo_tags.to_vec();
+        }
+        let blob_json = report(load(&blob),
+                               FileCheckError::BadOciFileError {
+                                   msg: format!("blob '{}' parse failed", hex)
+                               })?;
+        let is_index = match descriptor["mediaType"].as_str() {
+            Some(media_type) => INDEX_MEDIA_TYPES.contains(&media_type),
+            None => blob_json["manifests"].is_array(),
+        };
+        if is_index {
+            if index_hex_vec.len() >= MAX_INDEX_DEPTH {
+                return Err(FileCheckError::BadOciFileError {
+                    msg: format!("image indexes are nested deeper than {}", MAX_INDEX_DEPTH)
+                }.into());
+            }
+            let mut nested_hex_vec = index_hex_vec.to_vec();
+            nested_hex_vec.push(hex);
+            follow_index(&blob_json, &nested_hex_vec, &repo_tags, load, exists, manifest_vec)?;
+        } else if let Some(manifest) = manifest_vec.iter_mut().find(|m| m.hex == hex) {
+            // the same image saved under several tags
+            repo_tags.retain(|tag| !manifest.repo_tags.contains(tag));
+            manifest.repo_tags.extend(repo_tags);
+        } else {
+            manifest_vec.push(ManifestRef { hex, index_hex_vec: index_hex_vec.to_vec(), repo_tags });
+        }
+    }
+    Ok(())
+}
+
+/// fetch every image manifest referenced from index.json by loading json files of
+/// image layout through their paths and checking existence of them
+fn fetch_manifest_refs(load: &dyn Fn(&str) -> Result<JsonValue, LayerSwordError>,
+                       exists: &dyn Fn(&str) -> bool) -> Result<Vec<ManifestRef>, LayerSwordError> {
+    let index = report(load("index.json"),
+                       FileCheckError::BadOciFileError {
+                           msg: "index file parse failed".to_string()
+                       })?;
+    let mut manifest_vec: Vec<ManifestRef> = Vec::new();
+    follow_index(&index, &Vec::new(), &Vec::new(), load, exists, &mut manifest_vec)?;
+    if manifest_vec.is_empty() {
+        return Err(FileCheckError::BadOciFileError {
+            msg: "no image manifest inside index file".to_string()
+        }.into());
+    }
+    Ok(manifest_vec)
+}
+
+/// fetch image manifests referenced from index.json of an extracted image layout
+fn fetch_layout_manifest_refs(extract_path: &Path) -> Result<Vec<ManifestRef>, LayerSwordError> {
+    fetch_manifest_refs(&|path| load_config(&extract_path.join(path)),
+                        &|path| extract_path.join(path).is_file())
+}
+
+pub struct OciInspector {}
+
+impl Inspect for OciInspector {
+    fn inspect_route(&self, extract_path: &Path)
+                     -> Result<(HashMap<String, PathBuf>, HashSet<String>), LayerSwordError> {
+        let mut file_map: HashMap<String, PathBuf> = HashMap::new();
+        let mut layer_hash_set: HashSet<String> = HashSet::new();
+
+        let mut layout_path = extract_path.to_path_buf();
+        layout_path.push("oci-layout");
+        let mut index_path = extract_path.to_path_buf();
+        index_path.push("index.json");
+        let mut blobs_path = extract_path.to_path_buf();
+        blobs_path.push("blobs");
+        blobs_path.push("sha256");
+        if !layout_path.is_file() {
+            return Err(FileCheckError::BadOciFileError { msg: "no oci-layout".to_string() }.into());
+        } else if !index_path.is_file() {
+            return Err(FileCheckError::BadOciFileError { msg: "no index.json".to_string() }.into());
+        } else if !blobs_path.is_dir() {
+            return Err(FileCheckError::BadOciFileError { msg: "no blobs/sha256".to_string() }.into());
+        }
+        let layout = load_config(&layout_path)?;
+        if layout["imageLayoutVersion"].is_null() {
+            return Err(FileCheckError::BadOciFileError {
+                msg: "no imageLayoutVersion inside oci-layout".to_string()
+            }.into());
+        }
+
+        // follow index to find image manifests and their configs and layers,
+        // blobs referenced by none of them are left alone
+        for manifest_ref in fetch_layout_manifest_refs(extract_path)? {
+            for index_hex in manifest_ref.index_hex_vec.iter() {
+                file_map.insert(format!("index_blob_path:{}", index_hex),
+                                blob_path(extract_path, index_hex));
+            }
+            let manifest_path = blob_path(extract_path, &manifest_ref.hex);
+            let manifest = report(load_config(&manifest_path),
+                                  FileCheckError::BadOciFileError {
+                                      msg: "manifest blob parse failed".to_string()
+                                  })?;
+            let config_hex = digest_hex(&manifest["config"]["digest"])?;
+            let config_path = blob_path(extract_path, &config_hex);
+            if !config_path.is_file() {
+                return Err(FileCheckError::BadOciFileError {
+                    msg: format!("config blob '{}' doesn't exist", config_hex)
+                }.into());
+            }
+            for layer in manifest["layers"].members() {
+                layer_hash_set.insert(digest_hex(&layer["digest"])?);
+            }
+            file_map.insert(format!("manifest_path:{}", manifest_ref.hex), manifest_path);
+            file_map.insert(format!("config_path:{}", config_hex), config_path);
+        }
+
+        // docker engines save these files along with OCI layout
+        for name in ["manifest.json", "repositories"] {
+            let mut path = extract_path.to_path_buf();
+            path.push(name);
+            if path.is_file() {
+                file_map.insert(format!("docker_{}", name.replace(".", "_")), path);
+            }
+        }
+        file_map.insert("layout_path".to_string(), layout_path);
+        file_map.insert("index_path".to_string(), index_path);
+        Ok((file_map, layer_hash_set))
+    }
+
+    fn inspect_config(&self, file_map: &HashMap<String, PathBuf>)
+                      -> Result<HashSet<String>, LayerSwordError> {
+        let mut layer_tar_hash: HashSet<String> = HashSet::new();
+
+        let mut config_path_vec: Vec<&PathBuf> = file_map
+            .iter()
+            .filter(|(key, _)| key.starts_with("config_path"))
+            .map(|(_, path)| path)
+            .collect();
+        if config_path_vec.is_empty() {
+            return Err(InternalError::KeyError { key: "config_path".to_string() }.into());
+        }
+        config_path_vec.sort();
+        for config_path in config_path_vec {
+            check_blob(config_path)?;
+            let config = load_config(config_path)?;
+            layer_tar_hash.extend(fetch_config_diff_ids(&config)?);
+        }
+        Ok(layer_tar_hash)
+    }
+
+    fn inspect_layer(&self,
+                     extract_path: &Path,
+                     layer_hash_set: &HashSet<String>,
+                     config_tar_hash: &HashSet<String>) -> Result<(), LayerSwordError> {
+        if config_tar_hash.len() != layer_hash_set.len() {
+            return Err(FileCheckError::BadOciFileError {
+                msg: format!("layer number is different from what inside config blob\
+                    \nreal:'{}'\nright:'{}'", layer_hash_set.len(), config_tar_hash.len())
+            }.into());
+        }
+        for layer in layer_hash_set {
+            let layer_path = blob_path(extract_path, layer);
+            if !layer_path.is_file() {
+                return Err(FileCheckError::BadOciFileError {
+                    msg: format!("layer blob '{}' is not a file", layer)
+                }.into());
+            }
+            check_blob(&layer_path)?;
+        }
+        Ok(())
+    }
+
+    fn inspect_manifest(&self,
+                        extract_path: &Path,
+                        file_map: &HashMap<String, PathBuf>,
+                        layer_hash_set: &HashSet<String>) -> Result<Vec<ImageInfo>, LayerSwordError> {
+        // files shared by all images, like oci-layout and index.json
+        let shared_map: HashMap<String, PathBuf> = file_map
+            .iter()
+            .filter(|(key, _)| !key.contains(':'))
+            .map(|(key, path)| (key.clone(), path.clone()))
+            .collect();
+        let mut image_vec: Vec<ImageInfo> = Vec::new();
+        for manifest_ref in fetch_layout_manifest_refs(extract_path)? {
+            let mut image_file_map = shared_map.clone();
+            for index_hex in manifest_ref.index_hex_vec.iter() {
+                let index_blob_path = file_map
+                    .get(&format!("index_blob_path:{}", index_hex))
+                    .ok_or_else(|| InternalError::KeyError { key: "index_blob_path".to_string() })?;
+                check_blob(index_blob_path)?;
+                image_file_map.insert(format!("index_blob_path:{}", index_hex),
+                                      index_blob_path.clone());
+            }
+            let manifest_path = file_map
+                .get(&format!("manifest_path:{}", manifest_ref.hex))
+                .ok_or_else(|| InternalError::KeyError { key: "manifest_path".to_string() })?;
+            check_blob(manifest_path)?;
+            let manifest = report(load_config(manifest_path),
+                                  FileCheckError::BadOciFileError {
+                                      msg: "manifest blob parse failed".to_string()
+                                  })?;
+            let config_hex = digest_hex(&manifest["config"]["digest"])?;
+            let config_path = file_map
+                .get(&format!("config_path:{}", config_hex))
+                .filter(|path| **path == blob_path(extract_path, &config_hex))
+                .ok_or_else(|| FileCheckError::BadOciFileError {
+                    msg: format!("config blob '{}' inside manifest blob doesn't exist", config_hex)
+                })?;
+            let layers = match &manifest["layers"] {
+                JsonValue::Array(layers) => { Ok(layers) }
+                _ => {
+                    Err(FileCheckError::BadOciFileError { msg: "manifest blob parse failed".to_string() })
+                }
+            }?;
+
+            let mut layer_dir_vec: Vec<PathBuf> = Vec::new();
+            for layer in layers {
+                let layer_hex = digest_hex(&layer["digest"])?;
+                if !layer_hash_set.contains(&layer_hex) {
+                    return Err(FileCheckError::BadOciFileError {
+                        msg: "layer inside manifest blob doesn't exist".to_string()
+                    }.into());
+                }
+                layer_dir_vec.push(blob_path(extract_path, &layer_hex));
+            }
+            image_file_map.insert("manifest_path".to_string(), manifest_path.clone());
+            image_file_map.insert("config_path".to_string(), config_path.clone());
+            image_vec.push(ImageInfo {
+                file_map: image_file_map,
+                layer_dir_vec,
+                repo_tags: manifest_ref.repo_tags,
+            });
+        }
+        Ok(image_vec)
+    }
+
+    fn fetch_layer_sizes(&self, index: &TarIndex, tar_path: &Path)
+                         -> Result<Vec<Vec<u64>>, LayerSwordError> {
+        let manifest_vec = fetch_manifest_refs(&|path| index.load_item(tar_path, path),
+                                               &|path| index.item_map.contains_key(path))?;
+        let mut size_vec_vec: Vec<Vec<u64>> = Vec::new();
+        for manifest_ref in manifest_vec {
+            let manifest =
+                index.load_item(tar_path, &format!("blobs/sha256/{}", manifest_ref.hex))?;
+            let mut size_vec: Vec<u64> = Vec::new();
+            for layer in manifest["layers"].members() {
+                size_vec.push(layer["size"]
+                    .as_u64()
+                    .ok_or_else(|| FileCheckError::BadOciFileError {
+                        msg: "manifest blob parse failed".to_string()
+                    })?);
+            }
+            size_vec_vec.push(size_vec);
+        }
+        Ok(size_vec_vec)
+    }
+
+    fn fetch_image_configs(&self, load: &dyn Fn(&str) -> Result<JsonValue, LayerSwordError>)
+                           -> Result<Vec<JsonValue>, LayerSwordError> {
+        let manifest_vec = fetch_manifest_refs(load, &|path| load(path).is_ok())?;
+        let mut config_vec: Vec<JsonValue> = Vec::new();
+        for manifest_ref in manifest_vec {
+            let manifest = load(&format!("blobs/sha256/{}", manifest_ref.hex))?;
+            let config_hex = digest_hex(&manifest["config"]["digest"])?;
+            config_vec.push(load(&format!("blobs/sha256/{}", config_hex))?);
+        }
+        Ok(config_vec)
+    }
+
+    fn fetch_diff_ids(&self, load: &dyn Fn(&str) -> Result<JsonValue, LayerSwordError>)
+                      -> Result<Vec<Vec<String>>, LayerSwordError> {
+        self.fetch_image_configs(load)?.iter().map(fetch_config_diff_ids).collect()
+    }
+}
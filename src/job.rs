@@ -0,0 +1,876 @@
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use std::{env, fs, thread};
+
+use json::{JsonValue, object};
+
+use crate::inspector::Inspect;
+use crate::inspector::base::BaseInspector;
+use crate::inspector::oci::OciInspector;
+use crate::dominator::base::BaseDominator;
+use crate::dominator::oci::OciDominator;
+use crate::split::{SplitOutput, SplitOptions, SplitFileOptions, describe_split};
+use crate::config::SplitConfig;
+use crate::plan::{SplitSpec, SplitStrategy, HistoryRule, plan_layer_counts, plan_reference_counts,
+                  plan_history_splits, resolve_diff_id_splits};
+use crate::format::{SplitFormat, SPLIT_SIGNATURE_NAME, is_published_split};
+use crate::merge::{Merge, MergeOptions, SplitChain, collect_split_files, list_chains};
+use crate::store::{store_splits, fetch_store_chain, find_store_split};
+use crate::stream::{SplitStream, TarIndex};
+use crate::sign::load_signing_key;
+use crate::util::{GeneratedDir, init_out_path, init_work_path, unique_work_path, create_generated_dir, tar_has_item,
+                  fetch_split_first_item, extract_split};
+use crate::errors::{LayerSwordError, TerminalError, FileCheckError};
+
+/// fetch the split with the largest index inside a directory of splits, which holds config
+/// files of the image
+fn fetch_top_split(split_dir: &Path) -> Result<SplitOutput, LayerSwordError> {
+    let mut split_vec: Vec<SplitOutput> = Vec::new();
+    for entry in fs::read_dir(split_dir)? {
+        let path = entry?.path();
+        if path.is_file() && is_published_split(&path) && SplitFormat::detect(&path).is_some() {
+            split_vec.push(describe_split(&path)?);
+        }
+    }
+    let top_index = split_vec.iter().map(|split| split.index).max();
+    let mut top_vec: Vec<SplitOutput> = split_vec
+        .into_iter()
+        .filter(|split| Some(split.index) == top_index)
+        .collect();
+    if top_vec.len() != 1 {
+        return Err(TerminalError::BadArgError {
+            arg: "reference".to_string(),
+            msg: format!("directory '{}' should have 1 top split, actually {}",
+                         split_dir.to_string_lossy(), top_vec.len()),
+        }.into());
+    }
+    Ok(top_vec.remove(0))
+}
+
+/// fetch hashes of diff_ids of the only image inside a reference, which is an image tar file
+/// or a directory of its splits, config files are read from the top split extracted under
+/// `work_parent`
+fn fetch_reference_diff_ids(reference_path: &Path, work_parent: &Path)
+                            -> Result<Vec<String>, LayerSwordError> {
+    let mut diff_id_vec_vec = if reference_path.is_dir() {
+        let top_split = fetch_top_split(reference_path)?;
+        let extract_dir = GeneratedDir::new(&unique_work_path(work_parent));
+        let extract_path = extract_dir.path().to_path_buf();
+        fs::create_dir_all(&extract_path)?;
+        let tar_path = extract_path.join(format!("{}.tar", top_split.name));
+        let (_, inspector) = pick_dominator_and_inspector(reference_path, "merge");
+        let result = extract_split(top_split.path.as_path(), extract_path.as_path())
+            .and_then(|_| TarIndex::scan(&tar_path))
+            .and_then(|index| inspector.fetch_diff_ids(&|path| index.load_item(&tar_path, path)));
+        drop(extract_dir);
+        result?
+    } else {
+        let index = TarIndex::scan(reference_path)?;
+        let (_, inspector) = pick_dominator_and_inspector(reference_path, "split");
+        inspector.fetch_diff_ids(&|path| index.load_item(reference_path, path))?
+    };
+    if diff_id_vec_vec.len() != 1 {
+        return Err(TerminalError::BadArgError {
+            arg: "reference".to_string(),
+            msg: format!("reference '{}' should have 1 image, actually {}",
+                         reference_path.to_string_lossy(), diff_id_vec_vec.len()),
+        }.into());
+    }
+    Ok(diff_id_vec_vec.remove(0))
+}
+
+/// report of a split job
+#[derive(Debug, Clone)]
+pub struct SplitReport {
+    /// sha256 of config file of every source image
+    pub image_digests: Vec<String>,
+    /// split files produced from bottom up, paths are inside store if it is set
+    pub splits: Vec<SplitOutput>,
+    /// index files of images written into store
+    pub index_paths: Vec<PathBuf>,
+    /// manifest of splits signed by the key if it is given
+    pub signature: Option<PathBuf>,
+    /// container format of split files
+    pub format: SplitFormat,
+    /// compress level of split files
+    pub compress_level: u8,
+    /// time spent by the whole split procedure
+    pub elapsed: Duration,
+}
+
+impl SplitReport {
+    pub fn to_json(&self) -> JsonValue {
+        object! {
+            image_digests: self.image_digests.clone(),
+            format: self.format.name(),
+            compress_level: self.compress_level,
+            elapsed_seconds: self.elapsed.as_secs_f64(),
+            splits: self.splits.iter().map(|s| s.to_json()).collect::<Vec<_>>(),
+            index_paths: self.index_paths
+                .iter()
+                .map(|p| p.to_string_lossy().to_string())
+                .collect::<Vec<_>>(),
+            signature: self.signature
+                .as_ref()
+                .map(|p| p.to_string_lossy().to_string())
+        }
+    }
+}
+
+/// report of a merge job
+#[derive(Debug, Clone)]
+pub struct MergeReport {
+    /// path of the merged image tar file
+    pub path: PathBuf,
+    /// sha256 of the merged image tar file
+    pub digest: String,
+    /// sha256 of config file of every merged image
+    pub image_digests: Vec<String>,
+    /// split files used by merge from bottom up, paths are inside store if it is set
+    pub splits: Vec<PathBuf>,
+    /// time spent by the whole merge procedure
+    pub elapsed: Duration,
+}
+
+impl MergeReport {
+    /// info of splits is read from split files, which should still exist
+    pub fn to_json(&self) -> Result<JsonValue, LayerSwordError> {
+        let mut split_vec: Vec<JsonValue> = Vec::new();
+        for path in self.splits.iter() {
+            split_vec.push(describe_split(path)?.to_json());
+        }
+        Ok(object! {
+            path: self.path.to_string_lossy().to_string(),
+            digest: self.digest.clone(),
+            image_digests: self.image_digests.clone(),
+            elapsed_seconds: self.elapsed.as_secs_f64(),
+            splits: split_vec
+        })
+    }
+}
+
+/// write report into a json file
+fn write_report(report: JsonValue, report_path: &Path) -> Result<(), LayerSwordError> {
+    log::info!("Writing report into '{}'", report_path.to_string_lossy());
+    fs::write(report_path, json::stringify_pretty(report, 4))?;
+    Ok(())
+}
+
+/// check whether target is an OCI image layout or splits cut from it
+fn is_oci_target(target_path: &Path, mode: &str) -> bool {
+    if mode == "split" {
+        return tar_has_item(target_path, "oci-layout");
+    }
+    if target_path.is_file() {
+        return fetch_split_first_item(target_path).as_deref() == Some(Path::new("blobs"));
+    }
+    match fs::read_dir(target_path) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_file() && SplitFormat::detect(p).is_some())
+            .filter_map(fetch_split_first_item)
+            .any(|item| item == Path::new("blobs")),
+        Err(_) => false
+    }
+}
+
+/// choose exact dominator and inspector by the layout of target
+pub fn pick_dominator_and_inspector(target_path: &Path, mode: &str)
+                                    -> (Box<dyn Merge>, Box<dyn Inspect>) {
+    if is_oci_target(target_path, mode) {
+        (Box::new(OciDominator {}), Box::new(OciInspector {}))
+    } else {
+        (Box::new(BaseDominator {}), Box::new(BaseInspector {}))
+    }
+}
+
+/// builder of a whole split procedure
+///
+/// # Examples
+///
+/// ```no_run
+/// use layer_sword::job::SplitJob;
+/// use layer_sword::plan::SplitSpec;
+/// use layer_sword::errors::LayerSwordError;
+/// fn main() -> Result<(), LayerSwordError> {
+///     let report = SplitJob::new("base.tar")
+///         .split("os", SplitSpec::Count(1))
+///         .split("lib", SplitSpec::Rest)
+///         .split("app", SplitSpec::Count(1))
+///         .out_dir("out")
+///         .run()?;
+///     for split in report.splits {
+///         println!("{} {} {:?}", split.name, split.digest, split.diff_ids);
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SplitJob {
+    target_path: PathBuf,
+    splits: Vec<(String, SplitSpec)>,
+    strategy: Option<SplitStrategy>,
+    references: Vec<PathBuf>,
+    history_rules: Vec<HistoryRule>,
+    names: Vec<String>,
+    work_path: Option<PathBuf>,
+    out_path: PathBuf,
+    force: bool,
+    options: SplitOptions,
+    store_path: Option<PathBuf>,
+    sign_key_path: Option<PathBuf>,
+    report_path: Option<PathBuf>,
+}
+
+impl SplitJob {
+    /// split the image tar file(docker-save or OCI layout) at target path
+    pub fn new<P: AsRef<Path>>(target_path: P) -> Self {
+        Self {
+            target_path: target_path.as_ref().to_path_buf(),
+            splits: Vec::new(),
+            strategy: None,
+            references: Vec::new(),
+            history_rules: Vec::new(),
+            names: Vec::new(),
+            work_path: None,
+            out_path: PathBuf::from("out"),
+            force: false,
+            options: SplitOptions {
+                jobs: thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+                ..SplitOptions::default()
+            },
+            store_path: None,
+            sign_key_path: None,
+            report_path: None,
+        }
+    }
+
+    /// add a split above the ones added before
+    pub fn split(mut self, name: &str, spec: SplitSpec) -> Self {
+        self.splits.push((name.to_string(), spec));
+        self
+    }
+
+    /// add splits from bottom up
+    pub fn splits(mut self, splits: Vec<(String, SplitSpec)>) -> Self {
+        self.splits.extend(splits);
+        self
+    }
+
+    /// plan splits by sizes of layers inside image tar file rather than adding them,
+    /// which are named by `names` or 'part0', 'part1'... from bottom up
+    pub fn strategy(mut self, strategy: SplitStrategy) -> Self {
+        self.strategy = Some(strategy);
+        self
+    }
+
+    /// plan splits by a reference image the target is built on rather than adding them,
+    /// a split boundary is placed where layers of the reference end, and the reference is
+    /// an image tar file or a directory of its splits
+    pub fn reference<P: AsRef<Path>>(mut self, reference_path: P) -> Self {
+        self.references.push(reference_path.as_ref().to_path_buf());
+        self
+    }
+
+    /// plan splits by a rule on history entries of image config rather than adding them,
+    /// splits are named by label markers or by `names`
+    pub fn history_rule(mut self, rule: HistoryRule) -> Self {
+        self.history_rules.push(rule);
+        self
+    }
+
+    /// names of splits planned by strategy, references or history rules from bottom up,
+    /// which take place of names from label markers
+    pub fn names(mut self, names: Vec<String>) -> Self {
+        self.names = names;
+        self
+    }
+
+    /// parent directory of the unique temporary working directory, temp directory of os by default
+    pub fn work_dir<P: AsRef<Path>>(mut self, work_path: P) -> Self {
+        self.work_path = Some(work_path.as_ref().to_path_buf());
+        self
+    }
+
+    /// output directory of split files, 'out' by default
+    pub fn out_dir<P: AsRef<Path>>(mut self, out_path: P) -> Self {
+        self.out_path = out_path.as_ref().to_path_buf();
+        self
+    }
+
+    /// write into a non-empty output directory, existing files are kept unless replaced
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// container format of split files, gzip by default
+    pub fn format(mut self, format: SplitFormat) -> Self {
+        self.options.format = format;
+        self
+    }
+
+    /// compress level of split files from 0 to 9, 6 by default
+    pub fn compress_level(mut self, compress_level: u8) -> Self {
+        self.options.compress_level = compress_level;
+        self
+    }
+
+    /// options of a single split file taking place of those of all splits
+    pub fn split_options(mut self, name: &str, options: SplitFileOptions) -> Self {
+        self.options.split_files.insert(name.to_string(), options);
+        self
+    }
+
+    /// take splits and options from a split config file, options set later take place of them
+    pub fn config(mut self, config: &SplitConfig) -> Self {
+        for entry in config.splits.iter() {
+            match &entry.spec {
+                Some(spec) => self.splits.push((entry.name.clone(), spec.clone())),
+                None => self.names.push(entry.name.clone()),
+            }
+            if entry.options != SplitFileOptions::default() {
+                self.options.split_files.insert(entry.name.clone(), entry.options.clone());
+            }
+        }
+        if let Some(strategy) = config.strategy {
+            self.strategy = Some(strategy);
+        }
+        if let Some(format) = config.format {
+            self.options.format = format;
+        }
+        if let Some(compress_level) = config.compress_level {
+            self.options.compress_level = compress_level;
+        }
+        if let Some(work_path) = &config.work_path {
+            self.work_path = Some(work_path.clone());
+        }
+        if let Some(out_path) = &config.out_path {
+            self.out_path = out_path.clone();
+        }
+        if let Some(store_path) = &config.store_path {
+            self.store_path = Some(store_path.clone());
+        }
+        self
+    }
+
+    /// number of threads compressing splits, number of cpus by default,
+    /// split files are the same for any number of threads
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.options.jobs = jobs;
+        self
+    }
+
+    /// stream docker-save image tar file into splits rather than extracting it, true by default,
+    /// OCI layout and multi-image tar files are always extracted
+    pub fn stream(mut self, stream: bool) -> Self {
+        self.options.stream = stream;
+        self
+    }
+
+    /// store directory to file splits by sha256 with an index of image
+    pub fn store<P: AsRef<Path>>(mut self, store_path: P) -> Self {
+        self.store_path = Some(store_path.as_ref().to_path_buf());
+        self
+    }
+
+    /// ed25519 key file written by keygen to sign a manifest of splits with,
+    /// which is written into output directory beside split files
+    pub fn sign_key<P: AsRef<Path>>(mut self, key_path: P) -> Self {
+        self.sign_key_path = Some(key_path.as_ref().to_path_buf());
+        self
+    }
+
+    /// json file to write the report into when the job finishes
+    pub fn report<P: AsRef<Path>>(mut self, report_path: P) -> Self {
+        self.report_path = Some(report_path.as_ref().to_path_buf());
+        self
+    }
+
+    /// fetch splits added, or plan them by strategy from sizes of layers inside image tar file,
+    /// by references from diff_ids of image, or by rules on history of image
+    fn plan_splits(&self, inspector: &dyn Inspect)
+                   -> Result<Vec<(String, SplitSpec)>, LayerSwordError> {
+        let planner_num = [self.strategy.is_some(),
+            !self.references.is_empty(),
+            !self.history_rules.is_empty()]
+            .iter()
+            .filter(|planned| **planned)
+            .count();
+        if planner_num == 0 {
+            if !self.splits.iter().any(|(_, spec)| matches!(spec, SplitSpec::DiffIds(_))) {
+                return Ok(self.splits.clone());
+            }
+            let index = TarIndex::scan(&self.target_path)?;
+            let diff_id_vec_vec =
+                inspector.fetch_diff_ids(&|path| index.load_item(&self.target_path, path))?;
+            if diff_id_vec_vec.len() != 1 {
+                return Err(TerminalError::BadArgError {
+                    arg: "layers".to_string(),
+                    msg: format!("diff_ids only give splits of 1 image, actually {} images",
+                                 diff_id_vec_vec.len()),
+                }.into());
+            }
+            return resolve_diff_id_splits(&self.splits, &diff_id_vec_vec[0]);
+        }
+        if planner_num > 1 {
+            return Err(TerminalError::BadArgError {
+                arg: "strategy".to_string(),
+                msg: "splits can be planned by only one of strategy, references \
+                and history rules".to_string(),
+            }.into());
+        }
+        if !self.splits.is_empty() {
+            return Err(TerminalError::BadArgError {
+                arg: "strategy".to_string(),
+                msg: "splits of layer numbers can't be added with a strategy, \
+                references or history rules".to_string(),
+            }.into());
+        }
+        let index = TarIndex::scan(&self.target_path)?;
+        let load = |path: &str| index.load_item(&self.target_path, path);
+        let planned_vec: Vec<(Option<String>, usize)> = if let Some(strategy) = self.strategy {
+            let size_vec_vec = inspector.fetch_layer_sizes(&index, &self.target_path)?;
+            if size_vec_vec.len() != 1 {
+                return Err(TerminalError::BadArgError {
+                    arg: "strategy".to_string(),
+                    msg: format!("strategy only plans splits of 1 image, actually {} images",
+                                 size_vec_vec.len()),
+                }.into());
+            }
+            let count_vec = plan_layer_counts(&size_vec_vec[0], strategy)?;
+            log::info!("Planned layers into splits of {:?} layers by {:?}", count_vec, strategy);
+            count_vec.into_iter().map(|count| (None, count)).collect()
+        } else if !self.references.is_empty() {
+            let diff_id_vec_vec = inspector.fetch_diff_ids(&load)?;
+            if diff_id_vec_vec.len() != 1 {
+                return Err(TerminalError::BadArgError {
+                    arg: "reference".to_string(),
+                    msg: format!("references only plan splits of 1 image, actually {} images",
+                                 diff_id_vec_vec.len()),
+                }.into());
+            }
+            let mut reference_vec: Vec<Vec<String>> = Vec::new();
+            for reference_path in self.references.iter() {
+                log::info!("Fetching diff_ids of reference '{}'", reference_path.to_string_lossy());
+                reference_vec.push(fetch_reference_diff_ids(reference_path, &self.work_parent())?);
+            }
+            let count_vec = plan_reference_counts(&diff_id_vec_vec[0], &reference_vec)?;
+            log::info!("Planned layers into splits of {:?} layers by references", count_vec);
+            count_vec.into_iter().map(|count| (None, count)).collect()
+        } else {
+            let config_vec = inspector.fetch_image_configs(&load)?;
+            if config_vec.len() != 1 {
+                return Err(TerminalError::BadArgError {
+                    arg: "history".to_string(),
+                    msg: format!("history rules only plan splits of 1 image, actually {} images",
+                                 config_vec.len()),
+                }.into());
+            }
+            let planned_vec = plan_history_splits(&config_vec[0]["history"], &self.history_rules)?;
+            let layer_num: usize = planned_vec.iter().map(|(_, count)| count).sum();
+            let diff_id_num = config_vec[0]["rootfs"]["diff_ids"].len();
+            if layer_num != diff_id_num {
+                return Err(FileCheckError::BadDockerFileError {
+                    msg: format!("history of config file has {} layers rather than {}",
+                                 layer_num, diff_id_num)
+                }.into());
+            }
+            log::info!("Planned layers into splits of {:?} by history", planned_vec);
+            planned_vec
+        };
+        if !self.names.is_empty() && self.names.len() != planned_vec.len() {
+            return Err(TerminalError::BadArgError {
+                arg: "names".to_string(),
+                msg: format!("{} names are given but layers are planned into {} splits",
+                             self.names.len(), planned_vec.len()),
+            }.into());
+        }
+        Ok(planned_vec
+            .into_iter()
+            .enumerate()
+            .map(|(i, (planned_name, count))| {
+                let name = self.names
+                    .get(i)
+                    .cloned()
+                    .or(planned_name)
+                    .unwrap_or_else(|| format!("part{}", i));
+                (name, SplitSpec::Count(count))
+            })
+            .collect())
+    }
+
+    /// check split specs and convert them into split names and layer numbers
+    fn split_settings(&self, splits: &[(String, SplitSpec)])
+                      -> Result<(Vec<String>, HashMap<String, i16>), LayerSwordError> {
+        if splits.is_empty() {
+            return Err(TerminalError::BadArgError {
+                arg: "names".to_string(),
+                msg: "no split is given".to_string(),
+            }.into());
+        }
+        let level_iter = self.options.split_files
+            .values()
+            .filter_map(|options| options.compress_level);
+        if let Some(level) = level_iter.chain([self.options.compress_level]).find(|l| *l > 9) {
+            return Err(TerminalError::BadArgError {
+                arg: "level".to_string(),
+                msg: format!("compress level should be 0 to 9, actually '{}'", level),
+            }.into());
+        }
+        if self.store_path.is_some() && self.options.split_files.values().any(|o| o.sub_dir.is_some()) {
+            return Err(TerminalError::BadArgError {
+                arg: "store".to_string(),
+                msg: "splits inside subdirectories of output can't be filed into store".to_string(),
+            }.into());
+        }
+        if self.options.jobs == 0 {
+            return Err(TerminalError::BadArgError {
+                arg: "jobs".to_string(),
+                msg: "number of threads should be positive".to_string(),
+            }.into());
+        }
+        let mut split_names: Vec<String> = Vec::new();
+        let mut split_map: HashMap<String, i16> = HashMap::new();
+        let mut rest_split: Option<&String> = None;
+        for (name, spec) in splits.iter() {
+            if name.is_empty() {
+                return Err(TerminalError::BadArgError {
+                    arg: "names".to_string(),
+                    msg: "one of arg names NULL string".to_string(),
+                }.into());
+            }
+            if split_map.contains_key(name) {
+                return Err(TerminalError::BadArgError {
+                    arg: "names".to_string(),
+                    msg: format!("split name '{}' is given more than once", name),
+                }.into());
+            }
+            let value = match spec {
+                SplitSpec::Rest => {
+                    if let Some(rest) = rest_split {
+                        return Err(TerminalError::BadArgError {
+                            arg: "layers".to_string(),
+                            msg: format!("more than 1 split of -1 layers: '{:?}' and '{:?}'",
+                                         rest, name),
+                        }.into());
+                    }
+                    rest_split = Some(name);
+                    -1
+                }
+                SplitSpec::Count(count) if *count >= 1 && *count <= i16::MAX as usize =>
+                    *count as i16,
+                SplitSpec::Count(count) => {
+                    return Err(TerminalError::BadArgError {
+                        arg: "layers".to_string(),
+                        msg: format!("split number can only be positive or -1, actuall '{:?}'",
+                                     count),
+                    }.into());
+                }
+                SplitSpec::DiffIds(_) => {
+                    return Err(TerminalError::BadArgError {
+                        arg: "layers".to_string(),
+                        msg: format!("diff_ids of split '{}' are not resolved", name),
+                    }.into());
+                }
+            };
+            split_names.push(name.clone());
+            split_map.insert(name.clone(), value);
+        }
+        Ok((split_names, split_map))
+    }
+
+    /// parent directory of working directory
+    fn work_parent(&self) -> PathBuf {
+        self.work_path.clone().unwrap_or_else(env::temp_dir)
+    }
+
+    /// run the split procedure
+    pub fn run(&self) -> Result<SplitReport, LayerSwordError> {
+        let start = Instant::now();
+        let (dominator, inspector) =
+            pick_dominator_and_inspector(&self.target_path, "split");
+        let splits = self.plan_splits(inspector.as_ref())?;
+        let (split_names, split_map) = self.split_settings(&splits)?;
+        let signing_key = match &self.sign_key_path {
+            Some(_) if self.store_path.is_some() => return Err(TerminalError::BadArgError {
+                arg: "sign-key".to_string(),
+                msg: "signed manifest is not kept inside store".to_string(),
+            }.into()),
+            Some(key_path) => Some(load_signing_key(key_path)?),
+            None => None,
+        };
+        let out_dir = init_out_path(&self.out_path, self.force)?;
+        let work_dir = init_work_path(&self.work_parent())?;
+        let work_path = work_dir.path().to_path_buf();
+
+        let signature = signing_key.as_ref().map(|_| self.out_path.join(SPLIT_SIGNATURE_NAME));
+        let mut options = self.options.clone();
+        options.signing_key = signing_key;
+
+        let stream = SplitStream {
+            tar_path: &self.target_path,
+            split_names: &split_names,
+            split_map: &split_map,
+            work_path: &work_path,
+            out_path: &self.out_path,
+            options: &options,
+        };
+        let (image_digests, mut splits) = dominator.split_image_layer(inspector, &stream)?;
+        let mut index_paths: Vec<PathBuf> = Vec::new();
+        if let Some(store_path) = &self.store_path {
+            index_paths = store_splits(&self.out_path, store_path)?;
+            for split in splits.iter_mut() {
+                if let Some(path) = find_store_split(store_path, &split.digest) {
+                    split.path = path;
+                }
+            }
+        }
+        let report = SplitReport {
+            image_digests,
+            splits,
+            index_paths,
+            signature,
+            format: self.options.format,
+            compress_level: self.options.compress_level,
+            elapsed: start.elapsed(),
+        };
+        if let Some(report_path) = &self.report_path {
+            write_report(report.to_json(), report_path)?;
+        }
+        if let Some(out_dir) = out_dir {
+            out_dir.keep();
+        }
+        Ok(report)
+    }
+}
+
+/// builder of a whole merge procedure
+///
+/// # Examples
+///
+/// ```no_run
+/// use layer_sword::job::MergeJob;
+/// use layer_sword::errors::LayerSwordError;
+/// fn main() -> Result<(), LayerSwordError> {
+///     let report = MergeJob::new("out")
+///         .out_dir("merged")
+///         .continue_on_error(true)
+///         .run()?;
+///     println!("{:?} {}", report.path, report.digest);
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct MergeJob {
+    target_path: PathBuf,
+    store_path: Option<PathBuf>,
+    work_path: Option<PathBuf>,
+    out_path: PathBuf,
+    force: bool,
+    options: MergeOptions,
+    report_path: Option<PathBuf>,
+}
+
+impl MergeJob {
+    /// merge split files under target directory, or the target split file
+    pub fn new<P: AsRef<Path>>(target_path: P) -> Self {
+        Self {
+            target_path: target_path.as_ref().to_path_buf(),
+            store_path: None,
+            work_path: None,
+            out_path: PathBuf::from("out"),
+            force: false,
+            options: MergeOptions::default(),
+            report_path: None,
+        }
+    }
+
+    /// merge split files under another directory or another split file together with target,
+    /// splits outside the chains of images are ignored
+    pub fn source<P: AsRef<Path>>(mut self, source_path: P) -> Self {
+        self.options.sources.push(source_path.as_ref().to_path_buf());
+        self
+    }
+
+    /// merge the chain of splits of an image inside store directory
+    pub fn from_store<P: AsRef<Path>>(store_path: P, image: &str) -> Self {
+        Self {
+            target_path: PathBuf::new(),
+            store_path: Some(store_path.as_ref().to_path_buf()),
+            work_path: None,
+            out_path: PathBuf::from("out"),
+            force: false,
+            options: MergeOptions { image: Some(image.to_string()), ..Default::default() },
+            report_path: None,
+        }
+    }
+
+    /// parent directory of the unique temporary working directory, temp directory of os by default
+    pub fn work_dir<P: AsRef<Path>>(mut self, work_path: P) -> Self {
+        self.work_path = Some(work_path.as_ref().to_path_buf());
+        self
+    }
+
+    /// output directory of merged image tar file, 'out' by default,
+    /// which is not used if the image tar file is written to stdout
+    pub fn out_dir<P: AsRef<Path>>(mut self, out_path: P) -> Self {
+        self.out_path = out_path.as_ref().to_path_buf();
+        self
+    }
+
+    /// output path of merged image tar file, which is taken as the file itself if it has
+    /// extension 'tar' and is not a directory, or as output directory otherwise
+    pub fn output<P: AsRef<Path>>(mut self, output_path: P) -> Self {
+        let output_path = output_path.as_ref();
+        if output_path.extension().unwrap_or_default() == "tar" && !output_path.is_dir() {
+            self.out_path = output_path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+            self.options.file_name = output_path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string());
+        } else {
+            self.out_path = output_path.to_path_buf();
+        }
+        self
+    }
+
+    /// write into a non-empty output directory, existing files are kept unless replaced
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// refuse to replace an existing merged image tar file rather than overwriting it
+    pub fn no_overwrite(mut self, no_overwrite: bool) -> Self {
+        self.options.no_overwrite = no_overwrite;
+        self
+    }
+
+    /// repo tag of the only image to merge, all images are merged if not given
+    pub fn image(mut self, image: &str) -> Self {
+        self.options.image = Some(image.to_string());
+        self
+    }
+
+    /// check all splits and report every failure rather than stopping at the first
+    pub fn continue_on_error(mut self, continue_on_error: bool) -> Self {
+        self.options.continue_on_error = continue_on_error;
+        self
+    }
+
+    /// name or index of the last split to merge, the merged image is made of layers
+    /// of splits up to it and named after it by default
+    pub fn upto(mut self, upto: &str) -> Self {
+        self.options.upto = Some(upto.to_string());
+        self
+    }
+
+    /// stream splits into image tar file rather than extracting them when the layout allows,
+    /// true by default
+    pub fn stream(mut self, stream: bool) -> Self {
+        self.options.stream = stream;
+        self
+    }
+
+    /// public key file written by keygen, splits are refused unless they are covered
+    /// by a manifest signed by its secret key
+    pub fn trust<P: AsRef<Path>>(mut self, pub_path: P) -> Self {
+        self.options.trust = Some(pub_path.as_ref().to_path_buf());
+        self
+    }
+
+    /// write image tar file to stdout rather than into output directory,
+    /// the written bytes are only trustworthy if the job succeeds
+    pub fn stdout(mut self, stdout: bool) -> Self {
+        self.options.stdout = stdout;
+        self
+    }
+
+    /// json file to write the report into when the job finishes
+    pub fn report<P: AsRef<Path>>(mut self, report_path: P) -> Self {
+        self.report_path = Some(report_path.as_ref().to_path_buf());
+        self
+    }
+
+    /// parent directory of working directory
+    fn work_parent(&self) -> PathBuf {
+        self.work_path.clone().unwrap_or_else(env::temp_dir)
+    }
+
+    /// list every complete chain of splits under target and other sources
+    /// with repo tags recorded inside their top splits, without merging any of them
+    pub fn list(&self) -> Result<Vec<SplitChain>, LayerSwordError> {
+        let mut source_vec = vec![self.target_path.clone()];
+        source_vec.extend(self.options.sources.iter().cloned());
+        let split_file_vec = collect_split_files(&source_vec)?;
+        let (dominator, _) = pick_dominator_and_inspector(&self.target_path, "merge");
+        list_chains(dominator.as_ref(), &split_file_vec)
+    }
+
+    /// run the merge procedure
+    pub fn run(&self) -> Result<MergeReport, LayerSwordError> {
+        let start = Instant::now();
+        if self.store_path.is_some() && self.options.trust.is_some() {
+            return Err(TerminalError::BadArgError {
+                arg: "trust".to_string(),
+                msg: "signed manifest is not kept inside store".to_string(),
+            }.into());
+        }
+        // a named output file is guarded by no_overwrite rather than the directory check
+        let out_dir = if self.options.file_name.is_some() {
+            create_generated_dir(&self.out_path)?
+        } else if !self.options.stdout {
+            init_out_path(&self.out_path, self.force)?
+        } else {
+            None
+        };
+        let work_dir = init_work_path(&self.work_parent())?;
+        let work_path = work_dir.path().to_path_buf();
+
+        let mut options = self.options.clone();
+        let mut target_path = self.target_path.clone();
+        let mut store_split_vec: Vec<PathBuf> = Vec::new();
+        if let Some(store_path) = &self.store_path {
+            let image = options.image
+                .take()
+                .ok_or_else(|| TerminalError::WithoutArgError {
+                    arg: "image".to_string(),
+                    msg: "image is needed to merge from store".to_string(),
+                })?;
+            target_path = work_path.clone();
+            target_path.push("store");
+            fs::create_dir(&target_path)?;
+            store_split_vec = fetch_store_chain(store_path, &image, &target_path)?;
+            log::info!("Resolved splits of image '{}' from store '{}'",
+                       image, store_path.to_string_lossy());
+        }
+
+        let (dominator, inspector) =
+            pick_dominator_and_inspector(&target_path, "merge");
+        let output = dominator.merge_image_layer(inspector,
+                                                 &target_path,
+                                                 &work_path,
+                                                 &self.out_path,
+                                                 &options)?;
+        let splits = match self.store_path {
+            Some(_) => store_split_vec,
+            None => output.splits,
+        };
+        let report = MergeReport {
+            path: output.path,
+            digest: output.digest,
+            image_digests: output.image_digests,
+            splits,
+            elapsed: start.elapsed(),
+        };
+        if let Some(report_path) = &self.report_path {
+            write_report(report.to_json()?, report_path)?;
+        }
+        if let Some(out_dir) = out_dir {
+            out_dir.keep();
+        }
+        Ok(report)
+    }
+}
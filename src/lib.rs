@@ -1,8 +1,16 @@
 pub mod dominator;
 pub mod inspector;
 pub mod split;
+pub mod stream;
 pub mod merge;
+pub mod store;
+pub mod verify;
+pub mod sign;
+pub mod plan;
+pub mod job;
 pub mod client;
+pub mod config;
 pub mod validator;
+pub mod format;
 pub mod util;
 pub mod errors;
\ No newline at end of file
@@ -1,21 +1,14 @@
-mod dominator;
-mod inspector;
-mod split;
-mod merge;
-mod client;
-mod validator;
-mod util;
-mod errors;
-
 use std::env;
 
-use crate::client::cli_main;
-use crate::errors::raise_err;
+use log::error;
+
+use layer_sword::client::cli_main;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     if let Err(e) = cli_main(args) {
         env_logger::builder().is_test(false).try_init().unwrap_or_else(|_| {});
-        raise_err(e);
+        error!("{:#}", e);
+        std::process::exit(-1);
     }
 }
\ No newline at end of file
@@ -1,147 +1,637 @@
 use std::path::{Path, PathBuf};
-use std::fs;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::collections::{HashMap, HashSet};
 
 use fs_extra::{dir, file};
 
 use crate::split::Split;
 use crate::dominator::Config;
-use crate::inspector::Inspect;
+use crate::inspector::{Inspect, ImageInfo};
 use crate::path_to_string;
-use crate::util::{extract_tar, load_config, compress_tar, extract_tar_gz};
-use crate::errors::{FileCheckError, InternalError, raise, raise_debug};
+use crate::stream::stream_merge_layer;
+use crate::store::sanitize_name;
+use crate::sign::{load_trusted_key, check_signed_chain};
+use crate::format::{SplitFormat, part_path, is_published_split, check_split_sums};
+use crate::util::{extract_tar, load_config, compress_tar, extract_split, fetch_file_sha256,
+                  fetch_split_config, fetch_split_digest};
+use crate::errors::{LayerSwordError, FileCheckError, InternalError, TerminalError};
+
+/// options of a merge procedure
+#[derive(Debug, Clone)]
+pub struct MergeOptions {
+    /// repo tag of the only image to merge, all images are merged if not given
+    pub image: Option<String>,
+    /// check all splits and report every failure rather than stopping at the first
+    pub continue_on_error: bool,
+    /// stream splits into image tar file rather than extracting them when the layout allows
+    pub stream: bool,
+    /// write image tar file to stdout rather than into output directory
+    pub stdout: bool,
+    /// name of image tar file, named after repo tag of the image if not given
+    pub file_name: Option<String>,
+    /// refuse to replace an existing image tar file rather than overwriting it
+    pub no_overwrite: bool,
+    /// name or index of the last split to merge, layers above it are left out
+    /// of a new image, all splits are merged if not given
+    pub upto: Option<String>,
+    /// split files or directories of them merged together with the target,
+    /// splits outside the chains of images are ignored
+    pub sources: Vec<PathBuf>,
+    /// ed25519 public key file written by keygen, splits are refused unless every one of them
+    /// is covered by a manifest signed by its secret key
+    pub trust: Option<PathBuf>,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        Self {
+            image: None,
+            continue_on_error: false,
+            stream: true,
+            stdout: false,
+            file_name: None,
+            no_overwrite: false,
+            upto: None,
+            sources: Vec::new(),
+            trust: None,
+        }
+    }
+}
+
+/// an image tar file produced by a merge procedure
+#[derive(Debug, Clone)]
+pub struct MergeOutput {
+    /// path of the merged image tar file, '-' if it is written to stdout
+    pub path: PathBuf,
+    /// sha256 of the merged image tar file
+    pub digest: String,
+    /// sha256 of config file of every merged image
+    pub image_digests: Vec<String>,
+    /// split files used by merge from bottom up
+    pub splits: Vec<PathBuf>,
+}
+
+/// a complete chain of splits of an image found among split files
+#[derive(Debug, Clone)]
+pub struct SplitChain {
+    /// repo tags of images recorded inside the top split
+    pub repo_tags: Vec<String>,
+    /// split files of the chain from bottom up
+    pub splits: Vec<PathBuf>,
+}
+
+/// name of image tar file after the first repo tag of the only image,
+/// 'merge.tar' if there are several images or the image has no repo tag
+///
+/// # Examples
+///
+/// ```rust
+/// use layer_sword::merge::merged_tar_name;
+///
+/// assert_eq!(merged_tar_name(&[]), "merge.tar");
+/// ```
+pub fn merged_tar_name(image_vec: &[ImageInfo]) -> String {
+    match image_vec {
+        [image] => image.repo_tags
+            .first()
+            .map(|tag| format!("{}.tar", sanitize_name(tag)))
+            .unwrap_or_else(|| "merge.tar".to_string()),
+        _ => "merge.tar".to_string(),
+    }
+}
+
+/// fetch path of image tar file inside output directory,
+/// as an error if it exists and overwriting is refused
+fn output_tar_path(out_path: &Path, file_name: &str, options: &MergeOptions)
+                   -> Result<PathBuf, LayerSwordError> {
+    let mut out_tar_path = out_path.to_path_buf();
+    out_tar_path.push(file_name);
+    if options.no_overwrite && out_tar_path.exists() {
+        let path = out_tar_path.to_string_lossy().to_string();
+        return Err(TerminalError::ExistError { path }.into());
+    }
+    Ok(out_tar_path)
+}
+
+/// move a merged image tar file of sha256 `digest` into output directory, which is named
+/// by options or after repo tag of the only image, return path of it
+///
+/// the image tar file only appears under its name once it is complete, a copy across
+/// file systems is written into a part file and checked against `digest` before renamed
+pub fn publish_merged(tar_path: &Path,
+                      digest: &str,
+                      out_path: &Path,
+                      image_vec: &[ImageInfo],
+                      options: &MergeOptions)
+                      -> Result<PathBuf, LayerSwordError> {
+    let file_name = options.file_name
+        .clone()
+        .unwrap_or_else(|| merged_tar_name(image_vec));
+    let out_tar_path = output_tar_path(out_path, &file_name, options)?;
+    log::info!("Publishing merged tar file at {}", out_tar_path.to_string_lossy());
+    // work path may be on another file system
+    if fs::rename(tar_path, &out_tar_path).is_err() {
+        let out_part_path = part_path(&out_tar_path);
+        fs::copy(tar_path, &out_part_path)?;
+        let real = fetch_file_sha256(&out_part_path)?;
+        if real != digest {
+            fs::remove_file(&out_part_path)?;
+            return Err(FileCheckError::HashCheckError { right: digest.to_string(), real }.into());
+        }
+        fs::rename(&out_part_path, &out_tar_path)?;
+        fs::remove_file(tar_path)?;
+    }
+    Ok(out_tar_path)
+}
+
+/// collect split files from directories and files in order, files inside a directory
+/// which are not splits are ignored, while a file given by itself must be a split,
+/// splits inside a directory must match the sums file beside them if any
+///
+/// # Examples
+///
+/// ```rust
+/// use std::path::PathBuf;
+/// use layer_sword::merge::collect_split_files;
+///
+/// let split_vec = collect_split_files(&[PathBuf::from("tests/data/splits_base")]).unwrap();
+/// assert_eq!(split_vec.len(), 3);
+/// assert!(collect_split_files(&[PathBuf::from("tests/data/config.json")]).is_err());
+/// ```
+pub fn collect_split_files(source_vec: &[PathBuf]) -> Result<Vec<PathBuf>, LayerSwordError> {
+    let mut split_vec: Vec<PathBuf> = Vec::new();
+    for source_path in source_vec {
+        if source_path.is_dir() {
+            let mut path_vec: Vec<PathBuf> = Vec::new();
+            for entry in fs::read_dir(source_path)? {
+                let path = entry?.path();
+                if path.is_dir() || !is_published_split(&path) { continue; }
+                if SplitFormat::detect(&path).is_none() || fetch_split_digest(&path).is_none() {
+                    log::info!("Ignoring file '{}' which is not a split", path.to_string_lossy());
+                    continue;
+                }
+                if fetch_split_config(&path).is_none() {
+                    log::warn!("Ignoring file '{}' without split_config.json, which may be broken",
+                               path.to_string_lossy());
+                    continue;
+                }
+                path_vec.push(path);
+            }
+            path_vec.sort();
+            check_split_sums(source_path, &path_vec)?;
+            split_vec.extend(path_vec);
+        } else if SplitFormat::detect(source_path).is_none() {
+            return Err(FileCheckError::SplitFormatError { path: source_path.clone() }.into());
+        } else if fetch_split_digest(source_path).is_none()
+            || fetch_split_config(source_path).is_none() {
+            return Err(FileCheckError::SplitContentError { path: source_path.clone() }.into());
+        } else {
+            split_vec.push(source_path.clone());
+        }
+    }
+    split_vec.dedup();
+    Ok(split_vec)
+}
+
+/// fetch repo tags of the image of a chain recorded inside its top split,
+/// or from image files of the top split extracted before they were recorded
+fn chain_repo_tags(inspector: &dyn Inspect, split_config_vec: &[Box<dyn Config>], chain: &[usize])
+                   -> Vec<String> {
+    let top = &split_config_vec[chain[chain.len() - 1]];
+    let meta = top.get_meta();
+    match meta.images.is_empty() {
+        true => inspector.fetch_repo_tags(Path::new(&top.get_dir())),
+        false => meta.images.iter().flat_map(|image| image.repo_tags.clone()).collect(),
+    }
+}
+
+/// name chains of images by their repo tags, or by the images recorded inside their top splits
+fn chain_names(inspector: &dyn Inspect, split_config_vec: &[Box<dyn Config>], chain_vec: &[Vec<usize>])
+               -> Vec<String> {
+    chain_vec
+        .iter()
+        .map(|chain| match chain_repo_tags(inspector, split_config_vec, chain) {
+            tags if tags.is_empty() => split_config_vec[chain[chain.len() - 1]].get_meta().image_name(),
+            tags => tags.join(", "),
+        })
+        .collect()
+}
+
+/// split configs with sha256 recorded inside split files and paths of split files
+type SplitFileConfigs = (Vec<Box<dyn Config>>, Vec<String>, Vec<PathBuf>);
+
+/// read split config and sha256 recorded inside every split file without extracting it,
+/// return them sorted by index of splits together with paths of split files
+pub(crate) fn read_split_configs<M>(dominator: &M, split_file_vec: &[PathBuf])
+                                    -> Result<SplitFileConfigs, LayerSwordError>
+    where
+        M: Merge + ?Sized {
+    let mut split_vec: Vec<(Box<dyn Config>, String, PathBuf)> = Vec::new();
+    for split_path in split_file_vec.iter().cloned() {
+        let (digest, json_config) =
+            match (fetch_split_digest(&split_path), fetch_split_config(&split_path)) {
+                (Some(digest), Some(json_config)) => (digest, json_config),
+                _ => return Err(FileCheckError::SplitContentError { path: split_path }.into()),
+            };
+        let mut split_config = dominator.init_config();
+        split_config.load_json(json_config)?;
+        let split_name = split_path.to_string_lossy().to_string();
+        split_config.set_path(split_name.clone(), split_name, String::new());
+        split_vec.push((split_config, digest, split_path));
+    }
+    split_vec.sort_unstable_by_key(|(c, _, _)| c.key());
+    let mut split_config_vec: Vec<Box<dyn Config>> = Vec::new();
+    let mut digest_vec: Vec<String> = Vec::new();
+    let mut split_path_vec: Vec<PathBuf> = Vec::new();
+    for (split_config, digest, split_path) in split_vec {
+        split_config_vec.push(split_config);
+        digest_vec.push(digest);
+        split_path_vec.push(split_path);
+    }
+    Ok((split_config_vec, digest_vec, split_path_vec))
+}
+
+/// list every complete chain of splits among split files with repo tags recorded inside
+/// their top splits, by sha256 recorded inside split files without extracting them
+pub fn list_chains(dominator: &dyn Merge, split_file_vec: &[PathBuf])
+                   -> Result<Vec<SplitChain>, LayerSwordError> {
+    let (split_config_vec, digest_vec, split_path_vec) =
+        read_split_configs(dominator, split_file_vec)?;
+    let chain_vec = dominator.link_chains(&split_config_vec, &digest_vec)?;
+    Ok(chain_vec
+        .iter()
+        .map(|chain| {
+            let top = chain[chain.len() - 1];
+            SplitChain {
+                repo_tags: split_config_vec[top]
+                    .get_meta()
+                    .images
+                    .iter()
+                    .flat_map(|image| image.repo_tags.clone())
+                    .collect(),
+                splits: chain.iter().map(|i| split_path_vec[*i].clone()).collect(),
+            }
+        })
+        .collect())
+}
+
+/// fetch position of the last split to merge inside a chain by the name of its split file
+/// or by its index
+fn upto_position(split_config_vec: &[Box<dyn Config>], chain: &[usize], upto: &str)
+                 -> Result<usize, LayerSwordError> {
+    let name_vec: Vec<String> = chain
+        .iter()
+        .map(|i| {
+            let tar_path = PathBuf::from(split_config_vec[*i].get_tar());
+            tar_path.file_stem().unwrap_or_default().to_string_lossy().to_string()
+        })
+        .collect();
+    if let Some(position) = name_vec.iter().position(|name| name == upto) {
+        return Ok(position);
+    }
+    match upto.parse::<usize>() {
+        Ok(index) if index < chain.len() => Ok(index),
+        _ => Err(TerminalError::BadArgError {
+            arg: "upto".to_string(),
+            msg: format!("no split named '{}' or of that index, available splits: {:?}",
+                         upto, name_vec)
+        }.into()),
+    }
+}
 
 pub trait Merge: Split {
-    /// decompress all splits from tar.gz file
-    fn extract_to_tar(&self, target_path: &Path, work_path: &Path) -> Result<Vec<PathBuf>, FileCheckError> {
-        let all_extracted_paths = raise(fs::read_dir(target_path));
-
-        for entry in all_extracted_paths {
-            let entry = raise(entry);
-            let path = entry.path();
-            if path.is_dir() { continue; }
-            if path.extension().unwrap_or_default() != "gz" {
-                return Err(FileCheckError::FileExtensionError {
-                    extension: format!("gz"),
-                    path: entry.path(),
+    /// decompress splits from split files in any format detected by magic bytes,
+    /// failures of all splits are collected
+    /// rather than stopping at the first one if continue_on_error is set,
+    /// return tar files of splits with the split files they are extracted from
+    fn extract_to_tar(&self, split_file_vec: &[PathBuf], work_path: &Path, continue_on_error: bool)
+                      -> Result<Vec<(PathBuf, PathBuf)>, LayerSwordError> {
+        let mut failures: Vec<FileCheckError> = Vec::new();
+        let mut extract_path_vec: Vec<PathBuf> = Vec::new();
+        // splits of the same name from several directories are extracted apart
+        for (i, path) in split_file_vec.iter().enumerate() {
+            let mut extract_path = work_path.to_path_buf();
+            extract_path.push(format!("tar_{}", i));
+            fs::create_dir_all(&extract_path)?;
+            extract_path_vec.push(extract_path.clone());
+            if let Err(e) = extract_split(path.clone(), extract_path) {
+                if !continue_on_error {
+                    return Err(e);
+                }
+                log::error!("{}", e);
+                failures.push(match e {
+                    LayerSwordError::FileCheckError(e) => e,
+                    _ => FileCheckError::SplitFileError,
                 });
             }
-            extract_tar_gz(path, work_path.to_path_buf())?;
+        }
+        if !failures.is_empty() {
+            return Err(FileCheckError::SplitsCheckError { failures }.into());
         }
 
-        let mut tar_vec: Vec<PathBuf> = Vec::new();
-        let all_extracted_paths = raise(fs::read_dir(work_path));
-
-        for entry in all_extracted_paths {
-            let entry = raise(entry);
-            let path = entry.path();
-            if path.is_dir() { continue; }
-            if path.extension().unwrap_or_default() != "tar" { continue; }
-            tar_vec.push(entry.path());
+        let mut tar_vec: Vec<(PathBuf, PathBuf)> = Vec::new();
+        for (extract_path, split_file) in extract_path_vec.iter().zip(split_file_vec) {
+            for entry in fs::read_dir(extract_path)? {
+                let entry = entry?;
+                let path = entry.path();
+                if path.is_dir() { continue; }
+                if path.extension().unwrap_or_default() != "tar" { continue; }
+                tar_vec.push((path, split_file.clone()));
+            }
         }
         Ok(tar_vec)
     }
 
     /// decompress all splits from tar file
     fn extract_to_directory(&self, tar_vec: Vec<PathBuf>, split_path: &Path)
-                            -> Result<Vec<Box<dyn Config>>, FileCheckError> {
+                            -> Result<Vec<Box<dyn Config>>, LayerSwordError> {
         let mut split_config_vec: Vec<Box<dyn Config>> = Vec::new();
         for tar_file in tar_vec {
-            let split_name = raise(tar_file
+            let split_name = tar_file
                 .file_name()
-                .ok_or_else(|| InternalError::FilePathError { path: tar_file.clone() }));
+                .ok_or_else(|| InternalError::FilePathError { path: tar_file.clone() })?;
             let mut dir_name = PathBuf::from(split_path);
             dir_name.push(split_name);
-            raise(fs::create_dir(&dir_name));
+            // splits of the same name are told apart by a number
+            let mut n = 1;
+            while dir_name.exists() {
+                dir_name.set_file_name(format!("{}_{}", split_name.to_string_lossy(), n));
+                n += 1;
+            }
+            fs::create_dir(&dir_name)?;
             if tar_file.extension().unwrap_or_default() != "tar" {
                 return Err(FileCheckError::FileExtensionError {
                     extension: format!("tar"),
                     path: tar_file.clone(),
-                });
+                }.into());
             }
-            extract_tar(&tar_file, &dir_name);
-            let mut config_path = PathBuf::from(split_path);
-            config_path.push(split_name);
+            extract_tar(&tar_file, &dir_name)?;
+            let mut config_path = dir_name.clone();
             config_path.push("split_config.json");
             let json_config = load_config(&config_path)?;
             let mut split_config = self.init_config();
-            split_config.load_json(json_config);
+            split_config.load_json(json_config)?;
             split_config.set_path(
-                path_to_string!(dir_name),
-                path_to_string!(tar_file),
-                path_to_string!(config_path));
+                path_to_string!(dir_name)?,
+                path_to_string!(tar_file)?,
+                path_to_string!(config_path)?);
             split_config_vec.push(split_config);
         }
         split_config_vec.sort_unstable_by_key(|c| c.key());
-        for (i, split) in split_config_vec.iter().enumerate() {
-            if i != split.key() {
-                return Err(FileCheckError::SplitsUnmatchedError { index: split.key() });
+        Ok(split_config_vec)
+    }
+
+    /// resolve chains of splits from bottom to top by parent id, every chain is an image
+    fn resolve_chains(&self, split_config_vec: &[Box<dyn Config>])
+                      -> Result<Vec<Vec<usize>>, LayerSwordError> {
+        let tar_hash_vec: Vec<String> = split_config_vec
+            .iter()
+            .map(|c| fetch_file_sha256(c.get_tar()))
+            .collect::<Result<_, _>>()?;
+        self.link_chains(split_config_vec, &tar_hash_vec)
+    }
+
+    /// link chains of splits from bottom to top by parent id matched with sha256
+    /// of tar files of splits, every chain is an image,
+    /// splits outside all complete chains are ignored as splits of other images,
+    /// a missing split is reported by names of splits recorded inside the others
+    /// if there is no complete chain
+    fn link_chains(&self, split_config_vec: &[Box<dyn Config>], tar_hash_vec: &[String])
+                   -> Result<Vec<Vec<usize>>, LayerSwordError> {
+        // a split found twice in several directories is only linked once
+        let unique_vec: Vec<usize> = (0..split_config_vec.len())
+            .filter(|i| !tar_hash_vec[..*i].contains(&tar_hash_vec[*i]))
+            .collect();
+        // with loose linking, a split with unmatched parent id is still linked to the only
+        // split below it, so that hash check reports the broken one later
+        let find_parent = |split: &dyn Config, loose: bool| {
+            let below: Vec<usize> = unique_vec
+                .iter()
+                .copied()
+                .filter(|i| split_config_vec[*i].key() + 1 == split.key())
+                .collect();
+            match below.iter().find(|i| tar_hash_vec[**i] == split.get_parent()) {
+                Some(parent) => Some(*parent),
+                None if loose && below.len() == 1 => Some(below[0]),
+                None => None,
             }
+        };
+        let link = |loose: bool| {
+            let mut is_parent = vec![false; split_config_vec.len()];
+            for i in unique_vec.iter() {
+                if let Some(parent) = find_parent(split_config_vec[*i].as_ref(), loose) {
+                    is_parent[parent] = true;
+                }
+            }
+            let mut result_vec: Vec<Result<Vec<usize>, FileCheckError>> = Vec::new();
+            for top in unique_vec.iter().copied().filter(|i| !is_parent[*i]) {
+                let top_meta = split_config_vec[top].get_meta();
+                if let Some(e) = top_meta.missing_error(split_config_vec[top].key() + 1) {
+                    result_vec.push(Err(e));
+                    continue;
+                }
+                let mut chain = vec![top];
+                let mut now = top;
+                let mut missing: Option<FileCheckError> = None;
+                while split_config_vec[now].key() != 0 {
+                    let index = split_config_vec[now].key() - 1;
+                    match find_parent(split_config_vec[now].as_ref(), loose) {
+                        Some(parent) => now = parent,
+                        None => {
+                            missing = Some(split_config_vec[now]
+                                .get_meta()
+                                .missing_error(index)
+                                .unwrap_or(FileCheckError::SplitsUnmatchedError { index }));
+                            break;
+                        }
+                    }
+                    chain.push(now);
+                }
+                chain.reverse();
+                result_vec.push(missing.map_or(Ok(chain), Err));
+            }
+            result_vec
+        };
+
+        let (chain_vec, broken_vec): (Vec<_>, Vec<_>) = link(false)
+            .into_iter()
+            .partition(|result| result.is_ok());
+        let mut chain_vec: Vec<Vec<usize>> = match chain_vec.is_empty() {
+            true => link(true).into_iter().collect::<Result<_, _>>()?,
+            false => {
+                for e in broken_vec.into_iter().filter_map(|result| result.err()) {
+                    log::info!("Ignoring splits outside complete chains: {}", e);
+                }
+                chain_vec.into_iter().filter_map(|result| result.ok()).collect()
+            }
+        };
+        if chain_vec.is_empty() {
+            return Err(FileCheckError::SplitsUnmatchedError { index: 0 }.into());
         }
-        Ok(split_config_vec)
+        // keep images in order of their top split names
+        chain_vec.sort_by_key(|chain| {
+            let top_dir = split_config_vec[chain[chain.len() - 1]].get_dir();
+            (top_dir.len(), top_dir)
+        });
+        Ok(chain_vec)
     }
 
+    /// pick chains of the image with the tag, or all of them if tag is not given,
+    /// chains sharing no split with the others are images of different tar files
+    /// and one of them has to be picked by tag
+    fn select_chains(&self,
+                     inspector: &dyn Inspect,
+                     split_config_vec: &[Box<dyn Config>],
+                     chain_vec: Vec<Vec<usize>>,
+                     image: Option<&str>)
+                     -> Result<Vec<Vec<usize>>, LayerSwordError> {
+        let name_vec = chain_names(inspector, split_config_vec, &chain_vec);
+        let image = match image {
+            Some(image) => image,
+            None => {
+                let mut group_vec: Vec<HashSet<usize>> = Vec::new();
+                for chain in chain_vec.iter() {
+                    let mut group: HashSet<usize> = chain.iter().copied().collect();
+                    group_vec.retain(|other| match other.is_disjoint(&group) {
+                        true => true,
+                        false => {
+                            group.extend(other);
+                            false
+                        }
+                    });
+                    group_vec.push(group);
+                }
+                if group_vec.len() > 1 {
+                    return Err(FileCheckError::ImageSelectError {
+                        msg: format!("{} images inside splits of different tar files, \
+                            select one of them by tag, available images: {:?}",
+                                     chain_vec.len(), name_vec)
+                    }.into());
+                }
+                return Ok(chain_vec);
+            }
+        };
+        let mut selected: Vec<Vec<usize>> = Vec::new();
+        for chain in chain_vec {
+            let repo_tags = chain_repo_tags(inspector, split_config_vec, &chain);
+            if repo_tags.iter().any(|tag| tag == image) {
+                selected.push(chain);
+            }
+        }
+        if selected.len() != 1 {
+            return Err(FileCheckError::ImageSelectError {
+                msg: format!("{} images tagged '{}' inside splits, available images: {:?}",
+                             selected.len(), image, name_vec)
+            }.into());
+        }
+        Ok(selected)
+    }
 
-    /// check all splits for its hash
-    fn check_all_splits(&self, split_config_vec: Vec<Box<dyn Config>>)
-                        -> Result<Vec<String>, FileCheckError> {
+    /// check a chain of splits for its hash
+    fn check_chain_splits(&self, split_config_vec: &[Box<dyn Config>], chain: &[usize])
+                          -> Result<Vec<String>, LayerSwordError> {
         let mut stack_id = String::new();
         let mut parent_id = String::new();
 
         let mut dir_path_vec: Vec<String> = Vec::new();
 
-        for config_body in split_config_vec.iter() {
+        for (i, index) in chain.iter().enumerate() {
+            let config_body = &split_config_vec[*index];
+            if config_body.key() != i {
+                return Err(FileCheckError::SplitsUnmatchedError { index: i }.into());
+            }
             let (now_stack_id, now_id) = self.check_with_config(
                 config_body,
                 stack_id,
                 parent_id)?;
             parent_id = now_id;
             stack_id = now_stack_id;
-            raise(fs::remove_file(config_body.get_config()));
             dir_path_vec.push(config_body.get_dir());
         }
         Ok(dir_path_vec)
     }
 
+    /// check a chain of splits for sha256 of their tar files hashed while streaming,
+    /// `tar_hash_vec` keeps sha256 of every split of the chain in order
+    fn check_chain_hashes(&self,
+                          split_config_vec: &[Box<dyn Config>],
+                          chain: &[usize],
+                          tar_hash_vec: &[String])
+                          -> Result<(), LayerSwordError> {
+        let mut stack_id = String::new();
+        let mut parent_id = String::new();
+
+        for (i, index) in chain.iter().enumerate() {
+            let config_body = &split_config_vec[*index];
+            if config_body.key() != i {
+                return Err(FileCheckError::SplitsUnmatchedError { index: i }.into());
+            }
+            let (now_stack_id, now_id) = self.check_with_hash(
+                config_body.as_ref(),
+                tar_hash_vec[i].clone(),
+                stack_id,
+                parent_id)?;
+            parent_id = now_id;
+            stack_id = now_stack_id;
+        }
+        Ok(())
+    }
+
+    /// check all splits for its hash
+    fn check_all_splits(&self, split_config_vec: Vec<Box<dyn Config>>)
+                        -> Result<Vec<String>, LayerSwordError> {
+        let chain: Vec<usize> = (0..split_config_vec.len()).collect();
+        let dir_path_vec = self.check_chain_splits(&split_config_vec, &chain)?;
+        for config_body in split_config_vec.iter() {
+            fs::remove_file(config_body.get_config())?;
+        }
+        Ok(dir_path_vec)
+    }
+
     /// copy and merge files and directories
     fn merge_checked_files(&self,
                            dir_path_vec: Vec<String>,
-                           merge_path: &Path) {
+                           merge_path: &Path) -> Result<(), LayerSwordError> {
         let mut copy_options_file = file::CopyOptions::new();
         copy_options_file.overwrite = true;
 
         let mut copy_options_dir = dir::CopyOptions::new();
         copy_options_dir.overwrite = true;
         copy_options_dir.copy_inside = true;
+        // directories shared by several splits are merged rather than nested
+        copy_options_dir.content_only = true;
 
         for dir_path in dir_path_vec.iter() {
-            let all_extracted_paths = raise(fs::read_dir(dir_path));
+            let all_extracted_paths = fs::read_dir(dir_path)?;
             for entry in all_extracted_paths {
-                let entry = raise(entry);
+                let entry = entry?;
                 let item_name = entry.file_name();
 
-                let item_name_str = raise(item_name
+                let item_name_str = item_name
                     .as_os_str()
                     .to_str()
-                    .ok_or_else(|| InternalError::ConvertError));
+                    .ok_or(InternalError::ConvertError)?;
 
                 let mut item_pathbuf = PathBuf::from(dir_path);
                 item_pathbuf.push(item_name_str);
 
-                let mut dst_pathbuf = merge_path.clone().to_path_buf();
+                let mut dst_pathbuf = merge_path.to_path_buf();
                 dst_pathbuf.push(item_name);
                 if item_pathbuf.is_dir() {
-                    raise(dir::copy(
+                    dir::copy(
                         item_pathbuf,
                         dst_pathbuf,
-                        &copy_options_dir));
+                        &copy_options_dir)?;
                 } else {
-                    raise(file::copy(
+                    file::copy(
                         item_pathbuf,
                         dst_pathbuf,
-                        &copy_options_file));
+                        &copy_options_file)?;
                 }
             }
         }
+        Ok(())
     }
 
     /// function called for a whole merge procedure
@@ -150,32 +640,170 @@ pub trait Merge: Split {
                    target_path: &Path,
                    work_path: &Path,
                    out_path: &Path)
-                   -> Result<(), FileCheckError> {
+                   -> Result<MergeOutput, LayerSwordError> {
+        self.merge_image_layer(inspector, target_path, work_path, out_path,
+                               &MergeOptions::default())
+    }
+
+    /// function called for a whole merge procedure with options
+    fn merge_image_layer(&self,
+                         inspector: Box<dyn Inspect>,
+                         target_path: &Path,
+                         work_path: &Path,
+                         out_path: &Path,
+                         options: &MergeOptions)
+                         -> Result<MergeOutput, LayerSwordError> {
         let mut merge_pathbuf = work_path.to_path_buf();
         merge_pathbuf.push("merge");
         let mut split_pathbuf = work_path.to_path_buf();
         split_pathbuf.push("split");
-        let mut tar_pathbuf = out_path.to_path_buf();
+        // image tar file is packed inside work path and then published
+        let mut tar_pathbuf = work_path.to_path_buf();
         tar_pathbuf.push("merge.tar");
+        if let (false, Some(file_name)) = (options.stdout, &options.file_name) {
+            output_tar_path(out_path, file_name, options)?;
+        }
+
+        let mut source_vec = vec![target_path.to_path_buf()];
+        source_vec.extend(options.sources.iter().cloned());
+        let split_file_vec = collect_split_files(&source_vec)?;
+        let trusted_key = options.trust.as_deref().map(load_trusted_key).transpose()?;
 
-        log::info!("Extracting tar.gz file to tar from file under '{}'",
-                   raise(target_path.to_str().ok_or_else(|| InternalError::ConvertError)));
-        let tar_vec = self.extract_to_tar(target_path, work_path)?;
+        // splits of several images or checked all together are merged after extracting
+        if options.stream && self.can_stream() && options.upto.is_none()
+            && !options.continue_on_error {
+            if let Some(output) = stream_merge_layer(self, inspector.as_ref(), &split_file_vec,
+                                                     work_path, out_path, options)? {
+                return Ok(output);
+            }
+        }
+
+        log::info!("Extracting {} split files to tar", split_file_vec.len());
+        let tar_vec =
+            self.extract_to_tar(&split_file_vec, work_path, options.continue_on_error)?;
+        let split_file_map: HashMap<PathBuf, PathBuf> = tar_vec.iter().cloned().collect();
         log::info!("Extracting tar file to directories");
-        let split_config_vec: Vec<Box<dyn Config>> =
-            self.extract_to_directory(tar_vec, &split_pathbuf)?;
+        let split_config_vec: Vec<Box<dyn Config>> = self.extract_to_directory(
+            tar_vec.into_iter().map(|(tar_path, _)| tar_path).collect(), &split_pathbuf)?;
+        log::info!("Resolving split chains of images");
+        let chain_vec = self.resolve_chains(&split_config_vec)?;
+        let chain_vec =
+            self.select_chains(inspector.as_ref(), &split_config_vec, chain_vec,
+                               options.image.as_deref())?;
+        if let Some(trusted_key) = &trusted_key {
+            log::info!("Checking signed manifest of splits");
+            for chain in chain_vec.iter() {
+                let mut split_vec: Vec<(PathBuf, String)> = Vec::new();
+                for i in chain.iter() {
+                    let tar_path = PathBuf::from(split_config_vec[*i].get_tar());
+                    let split_path = split_file_map
+                        .get(&tar_path)
+                        .ok_or_else(|| InternalError::FilePathError { path: tar_path.clone() })?;
+                    split_vec.push((split_path.clone(), fetch_file_sha256(&tar_path)?));
+                }
+                check_signed_chain(trusted_key, &split_vec)?;
+            }
+        }
+        // the whole chain is checked, but only splits up to the position are merged
+        let mut options = options.clone();
+        let upto_position = match &options.upto {
+            Some(upto) => {
+                if chain_vec.len() != 1 {
+                    return Err(FileCheckError::ImageSelectError {
+                        msg: format!("{} images inside splits, \
+                            select one of them by tag to merge a part of it", chain_vec.len())
+                    }.into());
+                }
+                let position = upto_position(&split_config_vec, &chain_vec[0], upto)?;
+                if position + 1 == chain_vec[0].len() {
+                    None
+                } else {
+                    if options.file_name.is_none() {
+                        let tar_path =
+                            PathBuf::from(split_config_vec[chain_vec[0][position]].get_tar());
+                        options.file_name = tar_path
+                            .file_name()
+                            .map(|name| name.to_string_lossy().to_string());
+                    }
+                    Some(position)
+                }
+            }
+            None => None,
+        };
         log::info!("Check split hash for all the splits");
-        let dir_path_vec = self.check_all_splits(split_config_vec)?;
+        let mut dir_path_vec: Vec<String> = Vec::new();
+        let mut top_path_vec: Vec<PathBuf> = Vec::new();
+        let mut used_path_vec: Vec<PathBuf> = Vec::new();
+        for chain in chain_vec.iter() {
+            let chain_dir_vec = self.check_chain_splits(&split_config_vec, chain)?;
+            let merged_len = upto_position.map_or(chain.len(), |position| position + 1);
+            for dir_path in chain_dir_vec.into_iter().take(merged_len) {
+                if !dir_path_vec.contains(&dir_path) {
+                    dir_path_vec.push(dir_path);
+                }
+            }
+            for i in chain.iter() {
+                let tar_path = PathBuf::from(split_config_vec[*i].get_tar());
+                if let Some(split_path) = split_file_map.get(&tar_path) {
+                    if !used_path_vec.contains(split_path) {
+                        log::info!("Using split file '{}'", split_path.to_string_lossy());
+                        used_path_vec.push(split_path.clone());
+                    }
+                }
+            }
+            top_path_vec.push(PathBuf::from(split_config_vec[chain[chain.len() - 1]].get_dir()));
+        }
+        for config_body in split_config_vec.iter() {
+            if dir_path_vec.contains(&config_body.get_dir()) {
+                fs::remove_file(config_body.get_config())?;
+            }
+        }
         log::info!("Merging split directories and check split hash");
-        self.merge_checked_files(dir_path_vec, &merge_pathbuf);
+        self.merge_checked_files(dir_path_vec, &merge_pathbuf)?;
+        if let Some(position) = upto_position {
+            log::info!("Truncating image to layers of the first {} splits", position + 1);
+            inspector.truncate_image(&top_path_vec[0], &merge_pathbuf)?;
+        } else if top_path_vec.len() > 1 {
+            log::info!("Combining files of {} images", top_path_vec.len());
+            inspector.combine_images(&top_path_vec, &merge_pathbuf)?;
+        }
         log::info!("Checking merged dock image files");
         log::info!("[inspect begin]");
-        inspector.inspect(&merge_pathbuf)?;
+        let image_vec = inspector.inspect_all(&merge_pathbuf)?;
         log::info!("[inspect end]");
+        let mut image_digest_vec: Vec<String> = Vec::new();
+        for image in image_vec.iter() {
+            let config_path = image.file_map
+                .get("config_path")
+                .ok_or_else(|| InternalError::KeyError { key: "config_path".to_string() })?;
+            image_digest_vec.push(fetch_file_sha256(config_path)?);
+        }
         log::info!("Compressing merged dock image files to tar file");
-        compress_tar(&tar_pathbuf, &merge_pathbuf)?;
+        self.pack_merged(&merge_pathbuf, &tar_pathbuf)?;
+        let digest = fetch_file_sha256(&tar_pathbuf)?;
+        let path = match options.stdout {
+            true => {
+                log::info!("Writing merged tar file to stdout");
+                let mut stdout = io::stdout();
+                io::copy(&mut File::open(&tar_pathbuf)?, &mut stdout)?;
+                stdout.flush()?;
+                PathBuf::from("-")
+            }
+            false => publish_merged(&tar_pathbuf, &digest, out_path, &image_vec, &options)?,
+        };
         log::info!("Cleaning items inside work path");
-        raise(fs::remove_dir_all(work_path));
+        fs::remove_dir_all(work_path)?;
+        Ok(MergeOutput {
+            path,
+            digest,
+            image_digests: image_digest_vec,
+            splits: used_path_vec,
+        })
+    }
+
+    /// compress merged image directory into the final tar file
+    fn pack_merged(&self, merge_path: &Path, tar_path: &Path) -> Result<(), LayerSwordError> {
+        compress_tar(tar_path, merge_path)?;
         Ok(())
     }
 
@@ -184,7 +812,20 @@ pub trait Merge: Split {
                          config_body: &Box<dyn Config>,
                          stack_id: String,
                          parent_id: String)
-                         -> Result<(String, String), FileCheckError>;
+                         -> Result<(String, String), LayerSwordError>;
+
+    /// check config of one split against sha256 of its tar file,
+    /// for splits streamed without extracting their tar files
+    fn check_with_hash(&self,
+                       _config_body: &dyn Config,
+                       _now_id: String,
+                       _stack_id: String,
+                       _parent_id: String)
+                       -> Result<(String, String), LayerSwordError> {
+        Err(InternalError::ImpossibleError {
+            msg: "splits of this image layout can't be merged by streaming".to_string()
+        }.into())
+    }
 
     /// get a instance of layer_sword::dominator::Config struct object
     fn init_config(&self) -> Box<dyn Config>;
@@ -0,0 +1,359 @@
+use std::collections::BTreeMap;
+
+use json::JsonValue;
+use regex::Regex;
+
+use crate::validator::valid_alphabet;
+use crate::errors::{LayerSwordError, TerminalError};
+
+/// number of layers inside a split
+#[derive(Debug, Clone, PartialEq)]
+pub enum SplitSpec {
+    /// a fixed number of layers
+    Count(usize),
+    /// all layers left by other splits, at most one split can take it
+    Rest,
+    /// the consecutive layers of these diff_ids from bottom up
+    DiffIds(Vec<String>),
+}
+
+/// strategy planning layers of splits by sizes of layers rather than by layer numbers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SplitStrategy {
+    /// pack consecutive layers greedily into splits of at most a size in bytes
+    MaxSize(u64),
+    /// divide layers into a number of splits with similar sizes
+    Balanced(usize),
+}
+
+/// plan numbers of layers inside splits from sizes of layers from bottom up by a strategy,
+/// layers keep their order and every split holds at least one layer
+///
+/// # Examples
+///
+/// ```rust
+/// use layer_sword::plan::{plan_layer_counts, SplitStrategy};
+///
+/// let sizes = [3, 1, 1, 2, 4];
+/// assert_eq!(plan_layer_counts(&sizes, SplitStrategy::MaxSize(4)).unwrap(), vec![2, 2, 1]);
+/// assert_eq!(plan_layer_counts(&sizes, SplitStrategy::Balanced(2)).unwrap(), vec![3, 2]);
+/// ```
+pub fn plan_layer_counts(size_vec: &[u64], strategy: SplitStrategy)
+                         -> Result<Vec<usize>, LayerSwordError> {
+    if size_vec.is_empty() {
+        return Err(TerminalError::BadArgError {
+            arg: "strategy".to_string(),
+            msg: "image has no layer to split".to_string(),
+        }.into());
+    }
+    match strategy {
+        SplitStrategy::MaxSize(max_size) => {
+            let mut count_vec: Vec<usize> = Vec::new();
+            let mut now_size: u64 = 0;
+            let mut now_count: usize = 0;
+            for (i, size) in size_vec.iter().enumerate() {
+                if *size > max_size {
+                    return Err(TerminalError::BadArgError {
+                        arg: "max-split-size".to_string(),
+                        msg: format!("layer {} of {} bytes is larger than max split size {}",
+                                     i, size, max_size),
+                    }.into());
+                }
+                if now_count > 0 && now_size + size > max_size {
+                    count_vec.push(now_count);
+                    now_size = 0;
+                    now_count = 0;
+                }
+                now_size += size;
+                now_count += 1;
+            }
+            count_vec.push(now_count);
+            Ok(count_vec)
+        }
+        SplitStrategy::Balanced(split_count) => {
+            let layer_count = size_vec.len();
+            if split_count == 0 || split_count > layer_count {
+                return Err(TerminalError::BadArgError {
+                    arg: "count".to_string(),
+                    msg: format!("count of splits should be 1 to {}, actually '{}'",
+                                 layer_count, split_count),
+                }.into());
+            }
+            let mut prefix_vec: Vec<u64> = vec![0];
+            for size in size_vec {
+                prefix_vec.push(prefix_vec[prefix_vec.len() - 1] + size);
+            }
+            // sizes of splits are similar when the sum of their squares is the least,
+            // best[g][i] is the least sum when the bottom i layers are divided into g + 1 splits,
+            // and cut[g][i] is where its top split begins
+            let square = |size: u64| u128::from(size) * u128::from(size);
+            let mut best: Vec<Vec<u128>> = vec![prefix_vec.iter().map(|size| square(*size)).collect()];
+            let mut cut: Vec<Vec<usize>> = vec![vec![0; layer_count + 1]];
+            for g in 1..split_count {
+                let mut best_row = vec![u128::MAX; layer_count + 1];
+                let mut cut_row = vec![0; layer_count + 1];
+                for i in (g + 1)..=layer_count {
+                    for j in g..i {
+                        let sum = best[g - 1][j] + square(prefix_vec[i] - prefix_vec[j]);
+                        if sum < best_row[i] {
+                            best_row[i] = sum;
+                            cut_row[i] = j;
+                        }
+                    }
+                }
+                best.push(best_row);
+                cut.push(cut_row);
+            }
+            let mut count_vec: Vec<usize> = Vec::new();
+            let mut top = layer_count;
+            for g in (0..split_count).rev() {
+                let bottom = cut[g][top];
+                count_vec.push(top - bottom);
+                top = bottom;
+            }
+            count_vec.reverse();
+            Ok(count_vec)
+        }
+    }
+}
+
+/// plan numbers of layers inside splits from diff_ids of image from bottom up and diff_ids of
+/// reference images it is built on, so that every split boundary is placed where a reference
+/// image ends and the splits below it are the same for all images built on the reference
+///
+/// # Examples
+///
+/// ```rust
+/// use layer_sword::plan::plan_reference_counts;
+///
+/// let image: Vec<String> = ["a", "b", "c", "d"].iter().map(|s| s.to_string()).collect();
+/// let python = image[..3].to_vec();
+/// let ubuntu = image[..1].to_vec();
+/// assert_eq!(plan_reference_counts(&image, &[python, ubuntu]).unwrap(), vec![1, 2, 1]);
+/// ```
+pub fn plan_reference_counts(diff_id_vec: &[String], reference_vec: &[Vec<String>])
+                             -> Result<Vec<usize>, LayerSwordError> {
+    let mut length_vec: Vec<usize> = Vec::new();
+    for (i, reference) in reference_vec.iter().enumerate() {
+        if reference.is_empty() {
+            return Err(TerminalError::BadArgError {
+                arg: "reference".to_string(),
+                msg: format!("reference {} has no layer", i),
+            }.into());
+        }
+        if let Some(position) = reference
+            .iter()
+            .zip(diff_id_vec.iter())
+            .position(|(reference_id, diff_id)| reference_id != diff_id) {
+            return Err(TerminalError::BadArgError {
+                arg: "reference".to_string(),
+                msg: format!("image is not built on reference {}, their diff_ids differ at layer {}",
+                             i, position),
+            }.into());
+        }
+        if reference.len() >= diff_id_vec.len() {
+            return Err(TerminalError::BadArgError {
+                arg: "reference".to_string(),
+                msg: format!("reference {} has {} layers, which leaves no layer of image above it",
+                             i, reference.len()),
+            }.into());
+        }
+        length_vec.push(reference.len());
+    }
+    length_vec.sort_unstable();
+    if length_vec.windows(2).any(|pair| pair[0] == pair[1]) {
+        return Err(TerminalError::BadArgError {
+            arg: "reference".to_string(),
+            msg: "more than 1 reference has the same layers".to_string(),
+        }.into());
+    }
+    let mut count_vec: Vec<usize> = Vec::new();
+    let mut bottom: usize = 0;
+    for length in length_vec.into_iter().chain(std::iter::once(diff_id_vec.len())) {
+        count_vec.push(length - bottom);
+        bottom = length;
+    }
+    Ok(count_vec)
+}
+
+/// resolve splits given by diff_ids into numbers of layers by hashes of diff_ids of image
+/// from bottom up, diff_ids of a split should be the layers at its place, with or without
+/// prefix 'sha256:'
+///
+/// # Examples
+///
+/// ```rust
+/// use layer_sword::plan::{resolve_diff_id_splits, SplitSpec};
+///
+/// let diff_ids: Vec<String> = ["a", "b", "c", "d"]
+///     .iter().map(|s| s.to_string()).collect();
+/// let splits = vec![
+///     (format!("os"), SplitSpec::DiffIds(vec![format!("a")])),
+///     (format!("lib"), SplitSpec::Rest),
+///     (format!("app"), SplitSpec::DiffIds(vec![format!("sha256:d")])),
+/// ];
+/// let splits = resolve_diff_id_splits(&splits, &diff_ids).unwrap();
+/// assert_eq!(splits[0].1, SplitSpec::Count(1));
+/// assert_eq!(splits[1].1, SplitSpec::Rest);
+/// assert_eq!(splits[2].1, SplitSpec::Count(1));
+///
+/// let splits = vec![
+///     (format!("os"), SplitSpec::Count(1)),
+///     (format!("app"), SplitSpec::DiffIds(vec![format!("c"), format!("d")])),
+/// ];
+/// assert!(resolve_diff_id_splits(&splits, &diff_ids).is_err());
+/// ```
+pub fn resolve_diff_id_splits(splits: &[(String, SplitSpec)], diff_id_vec: &[String])
+                              -> Result<Vec<(String, SplitSpec)>, LayerSwordError> {
+    let count_of = |spec: &SplitSpec| match spec {
+        SplitSpec::Count(count) => Some(*count),
+        SplitSpec::Rest => None,
+        SplitSpec::DiffIds(ids) => Some(ids.len()),
+    };
+    let given_num: usize = splits.iter().filter_map(|(_, spec)| count_of(spec)).sum();
+    let rest_num = diff_id_vec.len().saturating_sub(given_num);
+
+    let mut split_vec: Vec<(String, SplitSpec)> = Vec::new();
+    let mut layer_from: usize = 0;
+    for (name, spec) in splits {
+        let layer_num = count_of(spec).unwrap_or(rest_num);
+        if let SplitSpec::DiffIds(ids) = spec {
+            let expect_vec: Vec<String> = ids
+                .iter()
+                .map(|id| id.trim_start_matches("sha256:").to_string())
+                .collect();
+            if diff_id_vec.get(layer_from..layer_from + layer_num) != Some(&expect_vec[..]) {
+                return Err(TerminalError::BadArgError {
+                    arg: "layers".to_string(),
+                    msg: format!("diff_ids of split '{}' are not layers {} to {} of image",
+                                 name, layer_from, layer_from + layer_num),
+                }.into());
+            }
+        }
+        let spec = match spec {
+            SplitSpec::DiffIds(ids) => SplitSpec::Count(ids.len()),
+            spec => spec.clone(),
+        };
+        split_vec.push((name.clone(), spec));
+        layer_from += layer_num;
+    }
+    Ok(split_vec)
+}
+
+/// key of image label marking the top of a split inside history of image,
+/// like `LABEL layer_sword.split=os`
+pub const SPLIT_LABEL: &str = "layer_sword.split";
+
+/// rule placing split boundaries by entries inside history of image config
+#[derive(Debug, Clone, PartialEq)]
+pub enum HistoryRule {
+    /// place a boundary after the last layer whose `created_by` matches a regular expression
+    After(String),
+    /// place a boundary at every `LABEL layer_sword.split=<name>` marker,
+    /// which names the split of layers below it
+    Label,
+}
+
+/// plan names and numbers of layers inside splits by rules on history entries of image config,
+/// a boundary is placed after the layers built before a matched entry, splits are named by
+/// label markers or left unnamed
+///
+/// # Examples
+///
+/// ```rust
+/// use json::array;
+/// use layer_sword::plan::{plan_history_splits, HistoryRule};
+///
+/// let history = array![
+///     { created_by: "/bin/sh -c #(nop) ADD file:0a1b in / " },
+///     { created_by: "/bin/sh -c #(nop)  LABEL layer_sword.split=os", empty_layer: true },
+///     { created_by: "/bin/sh -c pip install flask" },
+///     { created_by: "/bin/sh -c pip install requests" },
+///     { created_by: "/bin/sh -c #(nop) COPY dir:2c3d in /app " }
+/// ];
+/// let splits = plan_history_splits(&history, &[HistoryRule::Label]).unwrap();
+/// assert_eq!(splits, vec![(Some("os".to_string()), 1), (None, 3)]);
+///
+/// let rules = [HistoryRule::Label, HistoryRule::After("pip install".to_string())];
+/// let splits = plan_history_splits(&history, &rules).unwrap();
+/// assert_eq!(splits, vec![(Some("os".to_string()), 1), (None, 2), (None, 1)]);
+/// ```
+pub fn plan_history_splits(history: &JsonValue, rules: &[HistoryRule])
+                           -> Result<Vec<(Option<String>, usize)>, LayerSwordError> {
+    // number of layers built until each history entry
+    let mut layer_num_vec: Vec<usize> = Vec::new();
+    let mut layer_num: usize = 0;
+    for entry in history.members() {
+        if !entry["empty_layer"].as_bool().unwrap_or(false) {
+            layer_num += 1;
+        }
+        layer_num_vec.push(layer_num);
+    }
+    let created_by_vec: Vec<&str> = history
+        .members()
+        .map(|entry| entry["created_by"].as_str().unwrap_or_default())
+        .collect();
+
+    let mut boundary_map: BTreeMap<usize, Option<String>> = BTreeMap::new();
+    for rule in rules {
+        match rule {
+            HistoryRule::After(pattern) => {
+                let re = Regex::new(pattern)?;
+                let position = created_by_vec
+                    .iter()
+                    .rposition(|created_by| re.is_match(created_by))
+                    .ok_or_else(|| TerminalError::BadArgError {
+                        arg: "split-after".to_string(),
+                        msg: format!("no history entry matches '{}'", pattern),
+                    })?;
+                boundary_map.entry(layer_num_vec[position]).or_insert(None);
+            }
+            HistoryRule::Label => {
+                let re = Regex::new(&format!(r#"\bLABEL\b.*\b{}="?([^"\s]+)"?"#,
+                                             regex::escape(SPLIT_LABEL)))?;
+                for (i, created_by) in created_by_vec.iter().enumerate() {
+                    let name = match re.captures(created_by) {
+                        Some(caps) => caps[1].to_string(),
+                        None => continue,
+                    };
+                    valid_alphabet(name.clone()).map_err(|msg| TerminalError::BadArgError {
+                        arg: "split-by-label".to_string(),
+                        msg: format!("label name '{}' is invalid: {}", name, msg),
+                    })?;
+                    match boundary_map.get(&layer_num_vec[i]) {
+                        Some(Some(other)) if *other != name => {
+                            return Err(TerminalError::BadArgError {
+                                arg: "split-by-label".to_string(),
+                                msg: format!("labels '{}' and '{}' mark the same layers", other, name),
+                            }.into());
+                        }
+                        _ => { boundary_map.insert(layer_num_vec[i], Some(name)); }
+                    }
+                }
+            }
+        }
+    }
+    if boundary_map.contains_key(&0) {
+        return Err(TerminalError::BadArgError {
+            arg: "history".to_string(),
+            msg: "a split boundary is placed before any layer".to_string(),
+        }.into());
+    }
+    if boundary_map.is_empty() {
+        return Err(TerminalError::BadArgError {
+            arg: "history".to_string(),
+            msg: "no split boundary is found inside history".to_string(),
+        }.into());
+    }
+
+    let mut split_vec: Vec<(Option<String>, usize)> = Vec::new();
+    let mut bottom: usize = 0;
+    for (top, name) in boundary_map {
+        split_vec.push((name, top - bottom));
+        bottom = top;
+    }
+    if bottom < layer_num {
+        split_vec.push((None, layer_num - bottom));
+    }
+    Ok(split_vec)
+}
@@ -0,0 +1,200 @@
+use std::path::{Path, PathBuf};
+use std::convert::TryInto;
+use std::fs;
+use std::io::Write;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use json::{JsonValue, object};
+
+use crate::split::SplitOutput;
+use crate::format::{SPLIT_SIGNATURE_NAME, part_path};
+use crate::util::{get_stack_id, load_config};
+use crate::errors::{LayerSwordError, FileCheckError, TerminalError};
+
+/// name of the signing algorithm recorded inside signed manifest
+const SIGNATURE_ALGORITHM: &str = "ed25519";
+/// first line of the text signed for a set of splits
+const SIGNATURE_HEADER: &str = "layer_sword signature v1";
+
+/// lowercase hex text of bytes
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// bytes of hex text, None if it is not hex of even length
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// read 32 bytes of a key in hex from key file
+fn read_key(key_path: &Path) -> Result<[u8; 32], LayerSwordError> {
+    let bad_key = || FileCheckError::KeyFileError { path: key_path.to_path_buf() };
+    let text = fs::read_to_string(key_path).map_err(|_| bad_key())?;
+    let bytes = from_hex(text.trim()).ok_or_else(bad_key)?;
+    Ok(bytes.try_into().map_err(|_| bad_key())?)
+}
+
+/// path of public key file beside a secret key file, with extension 'pub'
+///
+/// # Examples
+///
+/// ```rust
+/// use std::path::Path;
+/// use layer_sword::sign::public_key_path;
+///
+/// assert_eq!(public_key_path(Path::new("keys/release.key")), Path::new("keys/release.pub"));
+/// ```
+pub fn public_key_path(key_path: &Path) -> PathBuf {
+    key_path.with_extension("pub")
+}
+
+/// generate an ed25519 key pair from random bytes of os, write secret key into key file
+/// and public key beside it, return path of public key file
+pub fn generate_key(key_path: &Path) -> Result<PathBuf, LayerSwordError> {
+    let pub_path = public_key_path(key_path);
+    for path in [key_path, pub_path.as_path()] {
+        if path.exists() {
+            return Err(TerminalError::ExistError { path: path.to_string_lossy().to_string() }.into());
+        }
+    }
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed)
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let signing_key = SigningKey::from_bytes(&seed);
+    // secret key is only readable by its owner from the moment it is created
+    let mut key_options = fs::OpenOptions::new();
+    key_options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        key_options.mode(0o600);
+    }
+    let mut key_file = key_options.open(key_path)?;
+    key_file.write_all(format!("{}\n", to_hex(&seed)).as_bytes())?;
+    fs::write(&pub_path, format!("{}\n", to_hex(signing_key.verifying_key().as_bytes())))?;
+    Ok(pub_path)
+}
+
+/// load secret key from key file written by keygen
+pub fn load_signing_key(key_path: &Path) -> Result<SigningKey, LayerSwordError> {
+    Ok(SigningKey::from_bytes(&read_key(key_path)?))
+}
+
+/// load trusted public key from public key file written by keygen
+pub fn load_trusted_key(pub_path: &Path) -> Result<VerifyingKey, LayerSwordError> {
+    VerifyingKey::from_bytes(&read_key(pub_path)?)
+        .map_err(|_| FileCheckError::KeyFileError { path: pub_path.to_path_buf() }.into())
+}
+
+/// text signed for splits, every line keeps index, sha256 and stack_id of a split
+fn signed_text(split_vec: &[JsonValue]) -> String {
+    let mut text = format!("{}\n", SIGNATURE_HEADER);
+    for split in split_vec {
+        text.push_str(&format!("{} {} {} {}\n",
+                               split["index"], split["digest"], split["stack_id"], split["name"]));
+    }
+    text
+}
+
+/// write a manifest of splits signed by the key into output directory, which covers
+/// sha256 of the tar file and stack_id of every split, return path of it
+pub fn sign_splits(signing_key: &SigningKey, out_path: &Path, split_vec: &[SplitOutput])
+                   -> Result<PathBuf, LayerSwordError> {
+    let mut split_vec: Vec<&SplitOutput> = split_vec.iter().collect();
+    split_vec.sort_by_key(|split| (split.index, split.name.clone()));
+    let split_json_vec: Vec<JsonValue> = split_vec
+        .iter()
+        .map(|split| object! {
+            name: split.name.clone(),
+            index: split.index,
+            digest: split.digest.clone(),
+            stack_id: split.stack_id.clone()
+        })
+        .collect();
+    let signature = signing_key.sign(signed_text(&split_json_vec).as_bytes());
+    let manifest = object! {
+        algorithm: SIGNATURE_ALGORITHM,
+        public_key: to_hex(signing_key.verifying_key().as_bytes()),
+        splits: split_json_vec,
+        signature: to_hex(&signature.to_bytes())
+    };
+    let manifest_path = out_path.join(SPLIT_SIGNATURE_NAME);
+    let manifest_part_path = part_path(&manifest_path);
+    fs::write(&manifest_part_path, json::stringify_pretty(manifest, 4))?;
+    fs::rename(&manifest_part_path, &manifest_path)?;
+    Ok(manifest_path)
+}
+
+/// load a signed manifest and check its signature by the trusted key,
+/// return sha256 and stack_id of every split covered by it
+pub fn load_signed_splits(manifest_path: &Path, trusted_key: &VerifyingKey)
+                          -> Result<Vec<(String, String)>, LayerSwordError> {
+    let manifest_name = manifest_path.to_string_lossy();
+    let bad_manifest = |msg: &str| FileCheckError::SignatureError {
+        msg: format!("signed manifest '{}' {}", manifest_name, msg)
+    };
+    let manifest = load_config(manifest_path).map_err(|_| bad_manifest("can't be read"))?;
+    if manifest["algorithm"] != SIGNATURE_ALGORITHM {
+        return Err(bad_manifest("is not signed by ed25519").into());
+    }
+    if manifest["public_key"] != to_hex(trusted_key.as_bytes()) {
+        return Err(bad_manifest(&format!("is signed by untrusted key '{}'",
+                                         manifest["public_key"])).into());
+    }
+    let signature: [u8; 64] = manifest["signature"]
+        .as_str()
+        .and_then(from_hex)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| bad_manifest("has no signature"))?;
+    let split_json_vec: Vec<JsonValue> = manifest["splits"].members().cloned().collect();
+    trusted_key
+        .verify_strict(signed_text(&split_json_vec).as_bytes(), &Signature::from_bytes(&signature))
+        .map_err(|_| bad_manifest("has a bad signature"))?;
+    Ok(split_json_vec
+        .iter()
+        .map(|split| (split["digest"].to_string(), split["stack_id"].to_string()))
+        .collect())
+}
+
+/// check every split of a chain from bottom up is covered by a manifest signed by the trusted
+/// key beside split files, `split_vec` keeps path of split file and sha256 of split tar
+/// of every split, stack_id of them is stacked from their sha256
+pub fn check_signed_chain(trusted_key: &VerifyingKey, split_vec: &[(PathBuf, String)])
+                          -> Result<(), LayerSwordError> {
+    let mut dir_vec: Vec<PathBuf> = Vec::new();
+    for (split_path, _) in split_vec {
+        let dir_path = split_path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+        if !dir_vec.contains(&dir_path) {
+            dir_vec.push(dir_path);
+        }
+    }
+    let mut signed_vec: Vec<(String, String)> = Vec::new();
+    for dir_path in dir_vec {
+        let manifest_path = dir_path.join(SPLIT_SIGNATURE_NAME);
+        if manifest_path.is_file() {
+            signed_vec.extend(load_signed_splits(&manifest_path, trusted_key)?);
+        }
+    }
+    if signed_vec.is_empty() {
+        return Err(FileCheckError::SignatureError {
+            msg: format!("no signed manifest '{}' beside splits", SPLIT_SIGNATURE_NAME)
+        }.into());
+    }
+    let mut stack_id = get_stack_id(&String::new(), &String::new());
+    for (split_path, digest) in split_vec {
+        if !signed_vec.contains(&(digest.clone(), stack_id.clone())) {
+            return Err(FileCheckError::SignatureError {
+                msg: format!("split '{}' is not covered by signed manifest",
+                             split_path.to_string_lossy())
+            }.into());
+        }
+        stack_id = get_stack_id(&stack_id, digest);
+    }
+    Ok(())
+}
@@ -2,12 +2,296 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use std::collections::HashMap;
 
+use ed25519_dalek::SigningKey;
 use fs_extra::{dir, file};
+use json::{JsonValue, object};
 
-use crate::inspector::Inspect;
+use crate::dominator::{SplitMeta, ImageMeta, SourceMeta};
+use crate::inspector::{Inspect, ImageInfo};
 use crate::os_str_to_string;
-use crate::util::{compress_tar, compress_tar_gz, extract_tar};
-use crate::errors::{FileCheckError, InternalError, raise, raise_err, raise_debug};
+use crate::stream::{SplitStream, stream_split_layer};
+use crate::format::{SplitFormat, SPLIT_SUMS_NAME, SPLIT_SIGNATURE_NAME, compress_split, split_name,
+                    part_path, remove_part_files, read_split_sums};
+use crate::verify::verify_split;
+use crate::sign::sign_splits;
+use crate::util::{compress_tar, extract_tar, fetch_split_digest, fetch_split_config,
+                  fetch_split_tar_size, fetch_file_sha256, parallel_map};
+use crate::errors::{LayerSwordError, FileCheckError, InternalError, CheckStage};
+
+/// options of a single split file taking place of those of all splits
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SplitFileOptions {
+    /// container format of the split file
+    pub format: Option<SplitFormat>,
+    /// compress level of the split file from 0 to 9
+    pub compress_level: Option<u8>,
+    /// subdirectory of output directory to write the split file into
+    pub sub_dir: Option<PathBuf>,
+}
+
+/// options of a split procedure
+#[derive(Debug, Clone)]
+pub struct SplitOptions {
+    /// container format of split files
+    pub format: SplitFormat,
+    /// compress level of split files from 0 to 9
+    pub compress_level: u8,
+    /// number of threads compressing splits
+    pub jobs: usize,
+    /// stream splits from image tar file rather than extracting it when the layout allows
+    pub stream: bool,
+    /// options of single split files by names of splits
+    pub split_files: HashMap<String, SplitFileOptions>,
+    /// secret key signing the manifest of splits, which is written before the sums file
+    pub signing_key: Option<SigningKey>,
+}
+
+impl Default for SplitOptions {
+    fn default() -> Self {
+        Self {
+            format: SplitFormat::Gz,
+            compress_level: 6,
+            jobs: 1,
+            stream: true,
+            split_files: HashMap::new(),
+            signing_key: None,
+        }
+    }
+}
+
+impl SplitOptions {
+    /// options of a single split file, when splitting several images, splits kept for one
+    /// image like 'app-1' take those of the split they are named after
+    fn split_file(&self, name: &str, multi_image: bool) -> Option<&SplitFileOptions> {
+        self.split_files.get(name).or_else(|| match multi_image {
+            true => image_split_base(name).and_then(|base| self.split_files.get(base)),
+            false => None,
+        })
+    }
+
+    /// container format of a split file
+    pub fn format_of(&self, name: &str, multi_image: bool) -> SplitFormat {
+        self.split_file(name, multi_image).and_then(|o| o.format).unwrap_or(self.format)
+    }
+
+    /// compress level of a split file
+    pub fn level_of(&self, name: &str, multi_image: bool) -> u8 {
+        self.split_file(name, multi_image)
+            .and_then(|o| o.compress_level)
+            .unwrap_or(self.compress_level)
+    }
+
+    /// path of a split file inside output directory
+    pub fn split_path(&self, out_path: &Path, name: &str, multi_image: bool) -> PathBuf {
+        let mut split_path = out_path.to_path_buf();
+        if let Some(sub_dir) = self.split_file(name, multi_image).and_then(|o| o.sub_dir.as_ref()) {
+            split_path.push(sub_dir);
+        }
+        split_path.push(format!("{}.{}", name, self.format_of(name, multi_image).extension()));
+        split_path
+    }
+}
+
+/// name of the split a split kept for one of several images is named after,
+/// which is the name without its suffix '-<image index>'
+///
+/// # Examples
+///
+/// ```rust
+/// use layer_sword::split::image_split_base;
+///
+/// assert_eq!(image_split_base("app-1"), Some("app"));
+/// assert_eq!(image_split_base("base-os"), None);
+/// assert_eq!(image_split_base("app-"), None);
+/// ```
+pub fn image_split_base(name: &str) -> Option<&str> {
+    match name.rsplit_once('-') {
+        Some((base, index)) if !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit()) =>
+            Some(base),
+        _ => None,
+    }
+}
+
+/// a split file produced by a split procedure
+#[derive(Debug, Clone)]
+pub struct SplitOutput {
+    /// name of the split
+    pub name: String,
+    /// path of the split file
+    pub path: PathBuf,
+    /// container format of the split file
+    pub format: SplitFormat,
+    /// sha256 of the tar file inside split, kept inside metadata of the split file
+    pub digest: String,
+    /// index of the split from bottom up
+    pub index: usize,
+    /// sha256 of the tar file inside the split below
+    pub parent_id: String,
+    /// stacked hash of all the splits from bottom up to this one
+    pub stack_id: String,
+    /// hashes of layer directories(or names of blobs) inside split from bottom up
+    pub dir_hashes: Vec<String>,
+    /// diff_ids of layers inside split from bottom up
+    pub diff_ids: Vec<String>,
+    /// size of the split file
+    pub size: u64,
+    /// size of the tar file inside split
+    pub tar_size: u64,
+}
+
+impl SplitOutput {
+    pub fn to_json(&self) -> JsonValue {
+        object! {
+            name: self.name.clone(),
+            index: self.index,
+            path: self.path.to_string_lossy().to_string(),
+            format: self.format.name(),
+            digest: self.digest.clone(),
+            parent_id: self.parent_id.clone(),
+            stack_id: self.stack_id.clone(),
+            dir_hashes: self.dir_hashes.clone(),
+            diff_ids: self.diff_ids.clone(),
+            size: self.size,
+            tar_size: self.tar_size
+        }
+    }
+}
+
+/// describe a split file by its recorded sha256, inner tar header and split_config.json,
+/// layers inside split are left empty
+pub fn describe_split(split_path: &Path) -> Result<SplitOutput, LayerSwordError> {
+    describe_split_file(split_path, split_path)
+}
+
+/// describe a split file read from a file path, which may be the part file of the split
+fn describe_split_file(file_path: &Path, split_path: &Path) -> Result<SplitOutput, LayerSwordError> {
+    let format = SplitFormat::detect(file_path)
+        .ok_or_else(|| FileCheckError::SplitFormatError { path: split_path.to_path_buf() })?;
+    let name = split_name(split_path, format)
+        .ok_or_else(|| FileCheckError::FileExtensionError {
+            extension: format.extension().to_string(),
+            path: split_path.to_path_buf(),
+        })?;
+    let digest = fetch_split_digest(file_path)
+        .ok_or(FileCheckError::SplitFileError)?;
+    let config = fetch_split_config(file_path)
+        .ok_or(FileCheckError::SplitFileError)?;
+    let index = config["index"]
+        .as_usize()
+        .ok_or(FileCheckError::SplitFileError)?;
+    let tar_size = fetch_split_tar_size(file_path)
+        .ok_or(FileCheckError::SplitFileError)?;
+    Ok(SplitOutput {
+        name,
+        path: split_path.to_path_buf(),
+        format,
+        digest,
+        index,
+        parent_id: config["parent_id"].to_string(),
+        stack_id: config["stack_id"].to_string(),
+        dir_hashes: Vec::new(),
+        diff_ids: Vec::new(),
+        size: fs::metadata(file_path)?.len(),
+        tar_size,
+    })
+}
+
+/// check split files written into part files against sha256 of their split tars
+fn verify_parts(part_vec: &[(PathBuf, PathBuf, String)]) -> Result<(), LayerSwordError> {
+    for (part_path, split_path, digest) in part_vec {
+        let result = verify_split(part_path);
+        if !result.passed() || result.digest != *digest {
+            return Err(FileCheckError::SplitCheckError {
+                path: split_path.clone(),
+                stage: CheckStage::TarHash,
+                right: digest.clone(),
+                real: result.digest,
+            }.into());
+        }
+    }
+    Ok(())
+}
+
+/// write the manifest of splits written into part files signed by the key if any,
+/// otherwise remove the signed manifest of splits published before, which is stale
+fn sign_parts(part_vec: &[(PathBuf, PathBuf, String)],
+              out_path: &Path,
+              signing_key: Option<&SigningKey>)
+              -> Result<(), LayerSwordError> {
+    let signature_path = out_path.join(SPLIT_SIGNATURE_NAME);
+    let signing_key = match signing_key {
+        Some(signing_key) => signing_key,
+        None if signature_path.is_file() => return Ok(fs::remove_file(signature_path)?),
+        None => return Ok(()),
+    };
+    let mut split_vec: Vec<SplitOutput> = Vec::new();
+    for (part_path, split_path, _) in part_vec {
+        split_vec.push(describe_split_file(part_path, split_path)?);
+    }
+    log::info!("Signing manifest of {} splits", split_vec.len());
+    sign_splits(signing_key, out_path, &split_vec)?;
+    Ok(())
+}
+
+/// publish split files written into part files once sha256 of every split tar is verified,
+/// the manifest of splits signed by the key if any is written before them, and the sums file
+/// listing sha256 of split files is published last so that a set of splits is complete
+/// once it appears, part files are removed if any of them is broken or signing fails,
+/// splits published before into the same directory stay listed inside the sums file
+///
+/// `part_vec` holds path of part file, path of split file and sha256 of split tar for every split
+pub fn publish_splits(part_vec: &[(PathBuf, PathBuf, String)],
+                      out_path: &Path,
+                      signing_key: Option<&SigningKey>)
+                      -> Result<(), LayerSwordError> {
+    if let Err(e) = verify_parts(part_vec).and_then(|_| sign_parts(part_vec, out_path, signing_key)) {
+        remove_part_files(part_vec.iter().map(|(part_path, _, _)| part_path.as_path()));
+        return Err(e);
+    }
+    // splits published before into the same directory are kept listed unless they are replaced,
+    // so that splits of several images can share it
+    let sums_path = out_path.join(SPLIT_SUMS_NAME);
+    let mut sums_vec: Vec<(String, String)> = match sums_path.is_file() {
+        true => read_split_sums(&sums_path)?
+            .into_iter()
+            .filter(|(name, _)| out_path.join(name).is_file())
+            .collect(),
+        false => Vec::new(),
+    };
+    // sums file of splits published before is stale once any split is replaced
+    if sums_path.is_file() {
+        fs::remove_file(&sums_path)?;
+    }
+    for (part_path, split_path, _) in part_vec {
+        // split files inside subdirectories are listed by their relative paths
+        let file_name = split_path
+            .strip_prefix(out_path)
+            .unwrap_or_else(|_| Path::new(split_path.file_name().unwrap_or_default()))
+            .to_string_lossy()
+            .replace('\\', "/");
+        sums_vec.retain(|(name, _)| *name != file_name);
+        sums_vec.push((file_name, fetch_file_sha256(part_path)?));
+        fs::rename(part_path, split_path)?;
+    }
+    sums_vec.sort();
+    let sums: String = sums_vec
+        .iter()
+        .map(|(file_name, hash)| format!("{}  {}\n", hash, file_name))
+        .collect();
+    let sums_part_path = part_path(&sums_path);
+    fs::write(&sums_part_path, sums)?;
+    fs::rename(sums_part_path, sums_path)?;
+    Ok(())
+}
+
+/// fetch names of layer directories(or blobs)
+fn layer_names(layer_dir_vec: &[PathBuf]) -> Result<Vec<String>, LayerSwordError> {
+    let mut name_vec: Vec<String> = Vec::new();
+    for path in layer_dir_vec {
+        name_vec.push(os_str_to_string!(path.file_name().unwrap_or_default())?);
+    }
+    Ok(name_vec)
+}
 
 pub trait Split {
     /// deduct item -1 to real value from splits number of layer
@@ -15,29 +299,29 @@ pub trait Split {
                         split_names: &Vec<String>,
                         split_map: HashMap<String, i16>,
                         layer_dir_set: &Vec<PathBuf>)
-                        -> Result<HashMap<String, i16>, FileCheckError> {
+                        -> Result<HashMap<String, i16>, LayerSwordError> {
         let mut deduct_map = split_map;
         let mut layer_num: i16 = 0;
         let mut addup_flag: String = String::new();
         for name in split_names {
-            let split_num: &i16 = raise(deduct_map
+            let split_num: &i16 = deduct_map
                 .get(name)
-                .ok_or_else(|| InternalError::KeyError { key: name.clone() }));
+                .ok_or_else(|| InternalError::KeyError { key: name.clone() })?;
             if *split_num == -1 {
                 if addup_flag == "" {
                     addup_flag = name.clone();
                 } else {
-                    raise_err(InternalError::ImpossibleError {
+                    return Err(InternalError::ImpossibleError {
                         msg: format!("more than 1 split of -1 layers: '{}' and '{}'",
                                      addup_flag, name),
-                    });
+                    }.into());
                 }
             } else if *split_num < -1
             {
-                raise_err(InternalError::ImpossibleError {
+                return Err(InternalError::ImpossibleError {
                     msg: format!("split number cannot be positive or -1, actuall '{:?}'",
                                  split_num),
-                });
+                }.into());
             } else {
                 layer_num += *split_num as i16;
             }
@@ -49,7 +333,7 @@ pub trait Split {
                     msg: format!("layers per split(without deduct) sum to {}, \
                                 not equal to real layers {}",
                                  layer_num, layer_dir_set.len()),
-                });
+                }.into());
             }
         } else {
             if layer_num > (layer_dir_set.len() - 1) as i16 {
@@ -57,7 +341,7 @@ pub trait Split {
                     msg: format!("layers per split(with deduct) sum to {}, \
                                 larger than real layers {}",
                                  layer_num, layer_dir_set.len()),
-                });
+                }.into());
             }
             deduct_map.insert(addup_flag, layer_dir_set.len() as i16 - layer_num);
         }
@@ -69,7 +353,7 @@ pub trait Split {
                               split_names: &Vec<String>,
                               split_map: &HashMap<String, i16>,
                               layer_dir_set: &Vec<PathBuf>,
-                              top_path: &PathBuf) {
+                              top_path: &Path) -> Result<(), LayerSwordError> {
         let mut id_from: i16 = 0;
 
         let mut copy_options_dir = dir::CopyOptions::new();
@@ -77,95 +361,137 @@ pub trait Split {
         copy_options_dir.copy_inside = true;
 
         for name in split_names {
-            let mut split_path = top_path.clone();
+            let mut split_path = top_path.to_path_buf();
             split_path.push(name.clone());
-            raise(fs::create_dir(&split_path));
+            fs::create_dir(&split_path)?;
 
             for id in 0..split_map[name] {
-                let src_path = raise(layer_dir_set
+                let src_path = layer_dir_set
                     .get((id_from + id) as usize)
-                    .ok_or_else(|| InternalError::KeyError { key: (id_from + id).to_string() }));
-                let item_name = raise(src_path
+                    .ok_or_else(|| InternalError::KeyError { key: (id_from + id).to_string() })?;
+                let item_name = src_path
                     .file_name()
-                    .ok_or_else(|| InternalError::FilePathError { path: src_path.clone() }));
+                    .ok_or_else(|| InternalError::FilePathError { path: src_path.clone() })?;
                 let mut dst_path = split_path.clone();
                 dst_path.push(item_name);
-                raise(dir::copy(src_path, dst_path, &copy_options_dir));
+                dir::copy(src_path, dst_path, &copy_options_dir)?;
             }
             id_from += split_map[name];
         }
+        Ok(())
     }
 
     /// copy files inside image
     fn copy_split_files(&self,
                         split_names: &Vec<String>,
                         file_map: HashMap<String, PathBuf>,
-                        top_pathbuf: &PathBuf) {
+                        top_pathbuf: &Path) -> Result<(), LayerSwordError> {
         let mut copy_options_file = file::CopyOptions::new();
         copy_options_file.overwrite = true;
-        let top_layer = raise(split_names
-            .get(split_names.len() - 1)
-            .ok_or_else(|| InternalError::KeyError { key: (split_names.len() - 1).to_string() }));
+        let top_layer = split_names.last()
+            .ok_or_else(|| InternalError::KeyError { key: (split_names.len() - 1).to_string() })?;
         for (_, src_path) in file_map {
-            let filename = raise(src_path
+            let filename = src_path
                 .file_name()
-                .ok_or_else(|| InternalError::FilePathError { path: src_path.clone() }));
-            let filename = os_str_to_string!(filename);
-            let mut dst_pathbuf = top_pathbuf.clone();
+                .ok_or_else(|| InternalError::FilePathError { path: src_path.clone() })?;
+            let filename = os_str_to_string!(filename)?;
+            let mut dst_pathbuf = top_pathbuf.to_path_buf();
             dst_pathbuf.push(top_layer);
             dst_pathbuf.push(filename);
-            raise(file::copy(&src_path, &dst_pathbuf, &copy_options_file));
+            file::copy(&src_path, &dst_pathbuf, &copy_options_file)?;
         }
+        Ok(())
     }
 
-    /// compress a directory into a tar file
+    /// record the layout of source image tar file into splits if needed
+    fn record_archive_layout(&self,
+                             _tar_path: &Path,
+                             _split_names: &Vec<String>,
+                             _split_path: &PathBuf) -> Result<(), LayerSwordError> {
+        Ok(())
+    }
+
+    /// compress a directory into a tar file, return its path and sha256
     fn pack_into_tar(&self,
                      split_path: &PathBuf, name: &String)
-                     -> Result<PathBuf, FileCheckError> {
+                     -> Result<(PathBuf, String), LayerSwordError> {
         let mut tar_path = split_path.clone();
         tar_path.pop();
         tar_path.push(name.as_str().to_owned() + ".tar");
 
         let mut compress_path = split_path.clone();
         compress_path.push(name);
-        compress_tar(&tar_path, &compress_path)?;
-        raise(fs::remove_dir_all(compress_path));
-        Ok(tar_path)
+        let hash = compress_tar(&tar_path, &compress_path)?;
+        fs::remove_dir_all(compress_path)?;
+        Ok((tar_path, hash))
     }
 
-    /// compress all splits into tar file
+    /// compress all splits into tar file with metadata of the image, return their paths and sha256
     fn pack_all_tar(&self,
                     split_names: &Vec<String>,
-                    split_path: PathBuf) -> Result<Vec<PathBuf>, FileCheckError> {
+                    split_path: PathBuf,
+                    meta_vec: &[SplitMeta]) -> Result<Vec<(PathBuf, String)>, LayerSwordError> {
         let mut parent_id = String::new();
         let mut stack_id = String::new();
-        let mut tar_path_vec: Vec<PathBuf> = Vec::new();
+        let mut tar_path_vec: Vec<(PathBuf, String)> = Vec::new();
         for (i, name) in split_names.iter().enumerate() {
             let (tar_path, now_stack_id, now_id) =
                 self.pack_tar_with_config(i,
                                           name,
                                           &split_path,
                                           stack_id,
-                                          parent_id)?;
+                                          parent_id,
+                                          &meta_vec[i])?;
+            tar_path_vec.push((tar_path, now_id.clone()));
             parent_id = now_id;
             stack_id = now_stack_id;
-
-            tar_path_vec.push(tar_path);
         }
         Ok(tar_path_vec)
     }
 
-    /// compress all splits into tar.gz file
-    fn pack_all_gz(&self, out_path: &PathBuf, tar_path_vec: Vec<PathBuf>, compress_level: u8) {
-        for tar_path in tar_path_vec.iter() {
-            let mut gz_path = out_path.clone();
-            gz_path.push(tar_path.file_name().unwrap_or_default());
-            gz_path.set_extension("tar.gz");
-            compress_tar_gz(&gz_path, &tar_path, compress_level);
+    /// compress all splits into part files of split files in format concurrently,
+    /// threads left over by splits are shared by compressing large splits,
+    /// return paths of part files and split files with sha256 of split tars
+    fn pack_all_split(&self,
+                      out_path: &Path,
+                      tar_path_vec: Vec<(PathBuf, String)>,
+                      multi_image: bool,
+                      options: &SplitOptions)
+                      -> Result<Vec<(PathBuf, PathBuf, String)>, LayerSwordError> {
+        let jobs = options.jobs.max(1);
+        let workers = jobs.min(tar_path_vec.len().max(1));
+        let part_vec: Vec<(PathBuf, PathBuf, String)> = tar_path_vec
+            .iter()
+            .map(|(tar_path, digest)| {
+                let name = tar_path.file_stem().unwrap_or_default().to_string_lossy();
+                let split_path = options.split_path(out_path, &name, multi_image);
+                (part_path(&split_path), split_path, digest.clone())
+            })
+            .collect();
+        for (_, split_path, _) in part_vec.iter() {
+            if let Some(dir_path) = split_path.parent() {
+                fs::create_dir_all(dir_path)?;
+            }
         }
+        let item_vec: Vec<(&PathBuf, &PathBuf, &String)> = tar_path_vec
+            .iter()
+            .zip(part_vec.iter())
+            .map(|((tar_path, digest), (part_path, _, _))| (tar_path, part_path, digest))
+            .collect();
+        let result_vec = parallel_map(&item_vec, workers, |(tar_path, part_path, digest)| {
+            let name = tar_path.file_stem().unwrap_or_default().to_string_lossy();
+            compress_split(*part_path, *tar_path, digest, options.format_of(&name, multi_image),
+                           options.level_of(&name, multi_image), jobs / workers)
+        });
+        if let Err(e) = result_vec.into_iter().collect::<Result<Vec<()>, LayerSwordError>>() {
+            remove_part_files(part_vec.iter().map(|(part_path, _, _)| part_path.as_path()));
+            return Err(e);
+        }
+        Ok(part_vec)
     }
 
-    /// function called for a whole split procedure
+    /// function called for a whole split procedure,
+    /// return sha256 of config file of every image and splits produced
     fn split_layer(&self,
                    inspector: Box<dyn Inspect>,
                    tar_path: &Path,
@@ -174,52 +500,251 @@ pub trait Split {
                    work_path: &Path,
                    out_path: &Path,
                    compress_level: u8)
-                   -> Result<(), FileCheckError> {
+                   -> Result<(Vec<String>, Vec<SplitOutput>), LayerSwordError> {
+        let options = SplitOptions { compress_level, ..SplitOptions::default() };
+        let stream = SplitStream {
+            tar_path,
+            split_names: &split_names,
+            split_map: &split_map,
+            work_path,
+            out_path,
+            options: &options,
+        };
+        self.split_image_layer(inspector, &stream)
+    }
+
+    /// function called for a whole split procedure with options
+    fn split_image_layer(&self, inspector: Box<dyn Inspect>, stream: &SplitStream)
+                         -> Result<(Vec<String>, Vec<SplitOutput>), LayerSwordError> {
+        let SplitStream { tar_path, split_names, split_map, work_path, out_path, options } = *stream;
         let mut extract_path = work_path.to_path_buf();
         extract_path.push("merge");
         let mut split_path = work_path.to_path_buf();
         split_path.push("split");
-        log::info!("Extracting tar file of dock image at {}",
-                   raise(tar_path.to_str().ok_or_else(|| InternalError::ConvertError)));
         if tar_path.extension().unwrap_or_default() != "tar" {
             return Err(FileCheckError::FileExtensionError {
                 extension: format!("tar"),
                 path: tar_path.to_path_buf(),
-            })?;
+            }.into());
         }
-        extract_tar(tar_path, &extract_path);
+        if options.stream && self.can_stream() {
+            if let Some(result) = stream_split_layer(self, inspector.as_ref(), stream)? {
+                return Ok(result);
+            }
+        }
+        log::info!("Extracting tar file of dock image at {}",
+                   tar_path.to_str().ok_or(InternalError::ConvertError)?);
+        extract_tar(tar_path, &extract_path)?;
         log::info!("Checking merged dock image files");
         log::info!("[inspect begin]");
-        let (file_map, layer_dir_set) =
-            inspector.inspect(&extract_path)?;
+        let mut image_vec = inspector.inspect_all(&extract_path)?;
         log::info!("[inspect end]");
-        log::info!("Validating number of each layer");
-        let deduct_map =
-            self.deduct_split_map(&split_names, split_map, &layer_dir_set)?;
-        log::info!("Copying layer directories inside splits into dock image");
-        self.copy_split_directories(&split_names, &deduct_map,
-                                    &layer_dir_set, &split_path);
-        log::info!("Copying files inside splits into dock image");
-        self.copy_split_files(&split_names, file_map, &split_path);
-        log::info!("Packing items into tar file under {}",
-                   raise(out_path.to_str().ok_or_else(|| InternalError::ConvertError)));
-        let tar_path_vec = self.pack_all_tar(&split_names, split_path)?;
-        log::info!("Packing items into gz file under {} at compress_level {}",
-                   raise(out_path.to_str().ok_or_else(|| InternalError::ConvertError)),
-                   compress_level);
-        self.pack_all_gz(&out_path.to_path_buf(), tar_path_vec, compress_level);
+        let mut image_digest_vec: Vec<String> = Vec::new();
+        let mut source = SourceMeta::default();
+        for image in image_vec.iter() {
+            let config_path = image.file_map
+                .get("config_path")
+                .ok_or_else(|| InternalError::KeyError { key: "config_path".to_string() })?;
+            let config_digest = fetch_file_sha256(config_path)?;
+            image_digest_vec.push(config_digest.clone());
+            source.images.push(ImageMeta { config_digest, repo_tags: image.repo_tags.clone() });
+            for layer in inspector.describe_layers(image)? {
+                source.diff_id_map.insert(layer.dir_hash, layer.diff_id);
+            }
+        }
+        let multi_image = image_vec.len() > 1;
+        let (tar_path_vec, layers_vec) = if !multi_image {
+            let image = image_vec.remove(0);
+            log::info!("Validating number of each layer");
+            let deduct_map =
+                self.deduct_split_map(split_names, split_map.clone(), &image.layer_dir_vec)?;
+            let name_vec = layer_names(&image.layer_dir_vec)?;
+            let mut layers_vec: Vec<Vec<String>> = Vec::new();
+            let mut layer_from: usize = 0;
+            for name in split_names.iter() {
+                let layer_to = layer_from + deduct_map[name] as usize;
+                layers_vec.push(name_vec[layer_from..layer_to].to_vec());
+                layer_from = layer_to;
+            }
+            log::info!("Copying layer directories inside splits into dock image");
+            self.copy_split_directories(split_names, &deduct_map,
+                                        &image.layer_dir_vec, &split_path)?;
+            log::info!("Copying files inside splits into dock image");
+            self.copy_split_files(split_names, image.file_map, &split_path)?;
+            self.record_archive_layout(tar_path, split_names, &split_path)?;
+            let meta_vec: Vec<SplitMeta> = layers_vec
+                .iter()
+                .map(|layers| SplitMeta {
+                    images: source.images.clone(),
+                    split_names: split_names.clone(),
+                    diff_ids: source.diff_ids(layers),
+                })
+                .collect();
+            log::info!("Packing items into tar file under {}",
+                       out_path.to_str().ok_or(InternalError::ConvertError)?);
+            (self.pack_all_tar(split_names, split_path, &meta_vec)?, layers_vec)
+        } else {
+            log::info!("Splitting {} images inside dock image tar file", image_vec.len());
+            self.split_images(inspector.as_ref(), image_vec, &source,
+                              split_names, split_map.clone(), work_path)?
+                .into_iter()
+                .map(|(tar_path, hash, layers)| ((tar_path, hash), layers))
+                .unzip()
+        };
+        log::info!("Packing items into {} file under {} at compress_level {} on {} threads",
+                   options.format,
+                   out_path.to_str().ok_or(InternalError::ConvertError)?,
+                   options.compress_level,
+                   options.jobs);
+        let part_vec =
+            self.pack_all_split(out_path, tar_path_vec, multi_image, options)?;
+        log::info!("Publishing {} split files under {}", part_vec.len(),
+                   out_path.to_str().ok_or(InternalError::ConvertError)?);
+        publish_splits(&part_vec, out_path, options.signing_key.as_ref())?;
+        let mut output_vec: Vec<SplitOutput> = Vec::new();
+        for ((_, split_path, _), dir_hashes) in part_vec.into_iter().zip(layers_vec) {
+            let mut output = describe_split(&split_path)?;
+            output.diff_ids = source.diff_ids(&dir_hashes);
+            output.dir_hashes = dir_hashes;
+            output_vec.push(output);
+        }
         log::info!("Clean items inside work path");
-        raise(fs::remove_dir_all(work_path));
-        Ok(())
+        fs::remove_dir_all(work_path)?;
+        Ok((image_digest_vec, output_vec))
+    }
+
+    /// copy and compress splits of several images into tar files with sha256 and names of their layers,
+    /// splits holding the same layers of all images are shared, while top splits are kept
+    /// for each image, metadata of a shared split records all images holding it
+    fn split_images(&self,
+                    inspector: &dyn Inspect,
+                    image_vec: Vec<ImageInfo>,
+                    source: &SourceMeta,
+                    split_names: &Vec<String>,
+                    split_map: HashMap<String, i16>,
+                    work_path: &Path)
+                    -> Result<Vec<(PathBuf, String, Vec<String>)>, LayerSwordError> {
+        let split_path = work_path.join("split");
+        // every split of every image is keyed by layers from bottom up to it,
+        // image index is added into key of top splits so that they are never shared
+        let mut node_vec: Vec<(usize, Vec<String>, Vec<usize>)> = Vec::new();
+        let mut image_node_vec: Vec<Vec<usize>> = Vec::new();
+        for (i, image) in image_vec.iter().enumerate() {
+            log::info!("Validating number of each layer for image {}", i);
+            let deduct_map = self.deduct_split_map(
+                split_names, split_map.clone(), &image.layer_dir_vec)?;
+            let mut layer_to: usize = 0;
+            let mut image_nodes: Vec<usize> = Vec::new();
+            for (j, name) in split_names.iter().enumerate() {
+                layer_to += deduct_map[name] as usize;
+                let mut key: Vec<String> = Vec::new();
+                for p in image.layer_dir_vec[..layer_to].iter() {
+                    key.push(os_str_to_string!(p.file_name().unwrap_or_default())?);
+                }
+                if j == split_names.len() - 1 {
+                    key.push(format!("image:{}", i));
+                }
+                let node = match node_vec.iter().position(|(k, nk, _)| *k == j && *nk == key) {
+                    Some(node) => node,
+                    None => {
+                        node_vec.push((j, key, Vec::new()));
+                        node_vec.len() - 1
+                    }
+                };
+                node_vec[node].2.push(i);
+                image_nodes.push(node);
+            }
+            image_node_vec.push(image_nodes);
+        }
+
+        // name splits shared by all images as it is, and others with the first image
+        let node_names: Vec<String> = node_vec
+            .iter()
+            .map(|(j, _, images)| if images.len() == image_vec.len() {
+                split_names[*j].clone()
+            } else {
+                format!("{}-{}", split_names[*j], images[0])
+            })
+            .collect();
+
+        let mut packed: HashMap<usize, (String, String)> = HashMap::new();
+        let mut tar_path_vec: Vec<(PathBuf, String, Vec<String>)> = Vec::new();
+        for (i, image) in image_vec.iter().enumerate() {
+            let mut parent_id = String::new();
+            let mut stack_id = String::new();
+            let mut layer_from: usize = 0;
+            for (j, node) in image_node_vec[i].iter().enumerate() {
+                let layer_to = node_vec[*node].1.len() - (j == split_names.len() - 1) as usize;
+                if !packed.contains_key(node) {
+                    let node_name = &node_names[*node];
+                    log::info!("Copying layer directories of split '{}'", node_name);
+                    let mut node_map: HashMap<String, i16> = HashMap::new();
+                    node_map.insert(node_name.clone(), (layer_to - layer_from) as i16);
+                    self.copy_split_directories(&vec![node_name.clone()], &node_map,
+                                                &image.layer_dir_vec[layer_from..].to_vec(),
+                                                &split_path)?;
+                    if j == split_names.len() - 1 {
+                        log::info!("Copying files of image {} into split '{}'", i, node_name);
+                        let mut image_path = work_path.to_path_buf();
+                        image_path.push(format!("image_{}", i));
+                        fs::create_dir(&image_path)?;
+                        let file_map = inspector.isolate_image(image, &image_path)?;
+                        self.copy_split_files(&vec![node_name.clone()], file_map, &split_path)?;
+                    }
+                    let layers = node_vec[*node].1[layer_from..layer_to].to_vec();
+                    let meta = SplitMeta {
+                        images: node_vec[*node].2
+                            .iter()
+                            .map(|i| source.images[*i].clone())
+                            .collect(),
+                        split_names: split_names.clone(),
+                        diff_ids: source.diff_ids(&layers),
+                    };
+                    let (tar_path, now_stack_id, now_id) =
+                        self.pack_tar_with_config(j,
+                                                  node_name,
+                                                  &split_path,
+                                                  stack_id.clone(),
+                                                  parent_id.clone(),
+                                                  &meta)?;
+                    tar_path_vec.push((tar_path, now_id.clone(), layers));
+                    packed.insert(*node, (now_stack_id, now_id));
+                }
+                let (now_stack_id, now_id) = &packed[node];
+                stack_id = now_stack_id.clone();
+                parent_id = now_id.clone();
+                layer_from = layer_to;
+            }
+        }
+        Ok(tar_path_vec)
+    }
+
+    /// whether splits of one image can be streamed from image tar file without extracting it,
+    /// and streamed back into image tar file when they are merged
+    fn can_stream(&self) -> bool { false }
+
+    /// build config of inspection info and metadata of source images dumped into a split,
+    /// return it with stack_id of the split
+    fn split_config(&self,
+                    _split_index: usize,
+                    _stack_id: &String,
+                    _parent_id: &String,
+                    _meta: &SplitMeta)
+                    -> Result<(JsonValue, String), LayerSwordError> {
+        Err(InternalError::ImpossibleError {
+            msg: "streaming split is not supported by this image layout".to_string(),
+        }.into())
     }
 
     /// compress one split into tar file with config of inspection info
+    /// and metadata of source images
     fn pack_tar_with_config(
         &self,
         split_index: usize,
         split_name: &String,
         split_path: &PathBuf,
         stack_id: String,
-        parent_id: String)
-        -> Result<(PathBuf, String, String), FileCheckError>;
+        parent_id: String,
+        meta: &SplitMeta)
+        -> Result<(PathBuf, String, String), LayerSwordError>;
 }
\ No newline at end of file
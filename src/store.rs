@@ -0,0 +1,233 @@
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::fs;
+
+use json::{JsonValue, object};
+
+use crate::format::{SplitFormat, SPLIT_SUMS_NAME, find_split_file, split_name, part_path,
+                    is_published_split, remove_part_files};
+use crate::util::{fetch_split_digest, fetch_split_item, fetch_split_config, dump_config, load_config};
+use crate::verify::verify_split;
+use crate::errors::{LayerSwordError, FileCheckError, InternalError, CheckStage};
+
+/// directory inside store holding split files named by their sha256
+pub const STORE_SPLITS_DIR: &str = "splits";
+/// directory inside store holding index files of images
+pub const STORE_INDEX_DIR: &str = "index";
+
+/// info of a split file read without extracting it
+struct SplitInfo {
+    path: PathBuf,
+    format: SplitFormat,
+    name: String,
+    digest: String,
+    parent_id: String,
+    index: usize,
+    repo_tags: Vec<String>,
+}
+
+/// read sha256, config and repo tags from a split file
+fn read_split_info(file_path: &Path) -> Result<SplitInfo, LayerSwordError> {
+    let bad_split = || FileCheckError::BadStoreError {
+        msg: format!("'{:?}' is not a valid split", file_path)
+    };
+    let format = SplitFormat::detect(file_path).ok_or_else(bad_split)?;
+    let digest = fetch_split_digest(file_path).ok_or_else(bad_split)?;
+    let config = fetch_split_config(file_path).ok_or_else(bad_split)?;
+    let index = config["index"].as_usize().ok_or_else(bad_split)?;
+    let repo_tags = fetch_split_item(file_path, "manifest.json")
+        .and_then(|data| String::from_utf8(data).ok())
+        .and_then(|text| json::parse(&text).ok())
+        .map(|manifest| manifest
+            .members()
+            .flat_map(|item| item["RepoTags"].members())
+            .filter_map(|tag| tag.as_str())
+            .map(|tag| tag.to_string())
+            .collect())
+        .unwrap_or_default();
+    let name = split_name(file_path, format)
+        .ok_or_else(|| InternalError::FilePathError { path: file_path.to_path_buf() })?;
+    Ok(SplitInfo {
+        path: file_path.to_path_buf(),
+        format,
+        name,
+        digest,
+        parent_id: config["parent_id"].to_string(),
+        index,
+        repo_tags,
+    })
+}
+
+/// replace characters unsafe for a file name, like ':' and '/' inside repo tags
+pub fn sanitize_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "._-".contains(c) { c } else { '_' })
+        .collect()
+}
+
+/// fetch path of the index file of an image inside store
+pub fn index_path(store_path: &Path, image: &str) -> PathBuf {
+    let mut path = store_path.to_path_buf();
+    path.push(STORE_INDEX_DIR);
+    path.push(sanitize_name(image) + ".json");
+    path
+}
+
+/// fetch path of a split inside store by its sha256 and format
+pub fn split_path(store_path: &Path, digest: &str, format: SplitFormat) -> PathBuf {
+    let mut path = store_path.to_path_buf();
+    path.push(STORE_SPLITS_DIR);
+    path.push(format!("{}.{}", digest, format.extension()));
+    path
+}
+
+/// find a split inside store by its sha256 in any format
+pub fn find_store_split(store_path: &Path, digest: &str) -> Option<PathBuf> {
+    let mut path = store_path.to_path_buf();
+    path.push(STORE_SPLITS_DIR);
+    find_split_file(&path, digest)
+}
+
+/// file split files under output directory into store by their sha256,
+/// splits already inside store are not copied again, and an index file of
+/// its chain of splits is written for every image, the sums file of splits moved is removed
+///
+/// every split copied is checked against the sha256 recorded inside it before it appears
+/// inside store, index files are written once all splits are stored, and split files under
+/// output directory are only removed after that, so a failure never loses any split
+pub fn store_splits(out_path: &Path, store_path: &Path) -> Result<Vec<PathBuf>, LayerSwordError> {
+    for dir in [STORE_SPLITS_DIR, STORE_INDEX_DIR] {
+        let mut path = store_path.to_path_buf();
+        path.push(dir);
+        fs::create_dir_all(path)?;
+    }
+
+    let mut split_vec: Vec<SplitInfo> = Vec::new();
+    for entry in fs::read_dir(out_path)? {
+        let path = entry?.path();
+        if path.is_file() && is_published_split(&path) && SplitFormat::detect(&path).is_some() {
+            split_vec.push(read_split_info(&path)?);
+        }
+    }
+    split_vec.sort_by_key(|split| (split.name.len(), split.name.clone()));
+    let digest_map: HashMap<&String, usize> = split_vec
+        .iter()
+        .enumerate()
+        .map(|(i, split)| (&split.digest, i))
+        .collect();
+
+    // tops are splits which are not the parent of any other split
+    let mut chain_vec: Vec<Vec<&SplitInfo>> = Vec::new();
+    for top in split_vec.iter().filter(|s| !split_vec.iter().any(|c| c.parent_id == s.digest)) {
+        let mut chain: Vec<&SplitInfo> = vec![top];
+        let mut now = top;
+        while now.index != 0 {
+            now = digest_map
+                .get(&now.parent_id)
+                .map(|i| &split_vec[*i])
+                .ok_or(FileCheckError::SplitsUnmatchedError { index: now.index - 1 })?;
+            chain.push(now);
+        }
+        chain.reverse();
+        chain_vec.push(chain);
+    }
+
+    let mut part_vec: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for split in split_vec.iter() {
+        if find_store_split(store_path, &split.digest).is_some() {
+            log::info!("Split '{}' already exists inside store", split.name);
+            continue;
+        }
+        let dst_path = split_path(store_path, &split.digest, split.format);
+        let dst_part_path = part_path(&dst_path);
+        part_vec.push((dst_part_path.clone(), dst_path));
+        let result = fs::copy(&split.path, &dst_part_path)
+            .map_err(LayerSwordError::from)
+            .and_then(|_| check_store_part(&dst_part_path, split));
+        if let Err(e) = result {
+            remove_part_files(part_vec.iter().map(|(part_path, _)| part_path.as_path()));
+            return Err(e);
+        }
+    }
+    for (dst_part_path, dst_path) in part_vec.iter() {
+        fs::rename(dst_part_path, dst_path)?;
+    }
+
+    let mut index_path_vec: Vec<PathBuf> = Vec::new();
+    for chain in chain_vec.iter() {
+        let top = chain[chain.len() - 1];
+        let image = top.repo_tags.first().unwrap_or(&top.name).clone();
+        let splits: Vec<JsonValue> = chain
+            .iter()
+            .map(|split| object! { name: split.name.clone(), digest: split.digest.clone() })
+            .collect();
+        let path = index_path(store_path, &image);
+        log::info!("Writing index of image '{}' into store", image);
+        dump_config(object! { image: image, repo_tags: top.repo_tags.clone(), splits: splits },
+                    &path)?;
+        index_path_vec.push(path);
+    }
+
+    for split in split_vec.iter() {
+        fs::remove_file(&split.path)?;
+    }
+    let sums_path = out_path.join(SPLIT_SUMS_NAME);
+    if sums_path.is_file() {
+        fs::remove_file(sums_path)?;
+    }
+    Ok(index_path_vec)
+}
+
+/// check sha256 of split tar inside a split copied into store against the one recorded inside it
+fn check_store_part(part_path: &Path, split: &SplitInfo) -> Result<(), LayerSwordError> {
+    let result = verify_split(part_path);
+    if result.passed() && result.digest == split.digest {
+        return Ok(());
+    }
+    Err(FileCheckError::SplitCheckError {
+        path: split.path.clone(),
+        stage: CheckStage::TarHash,
+        right: split.digest.clone(),
+        real: result.digest,
+    }.into())
+}
+
+/// copy the chain of splits of an image from store into target directory,
+/// return paths of the splits inside store from bottom up
+pub fn fetch_store_chain(store_path: &Path, image: &str, target_path: &Path)
+                         -> Result<Vec<PathBuf>, LayerSwordError> {
+    let path = index_path(store_path, image);
+    if !path.is_file() {
+        return Err(FileCheckError::BadStoreError {
+            msg: format!("no index of image '{}' inside store", image)
+        }.into());
+    }
+    let index = load_config(&path)?;
+    if index["splits"].is_empty() {
+        return Err(FileCheckError::BadStoreError {
+            msg: format!("no splits inside index of image '{}'", image)
+        }.into());
+    }
+    let mut src_path_vec: Vec<PathBuf> = Vec::new();
+    for split in index["splits"].members() {
+        let (name, digest) = match (split["name"].as_str(), split["digest"].as_str()) {
+            (Some(name), Some(digest)) => Ok((name, digest)),
+            _ => Err(FileCheckError::BadStoreError {
+                msg: format!("index of image '{}' parse failed", image)
+            })
+        }?;
+        let src_path = find_store_split(store_path, digest)
+            .ok_or_else(|| FileCheckError::BadStoreError {
+                msg: format!("split '{}' of image '{}' is missing inside store", digest, image)
+            })?;
+        let format = SplitFormat::detect(&src_path)
+            .ok_or_else(|| FileCheckError::BadStoreError {
+                msg: format!("split '{}' of image '{}' is broken inside store", digest, image)
+            })?;
+        let mut dst_path = target_path.to_path_buf();
+        dst_path.push(format!("{}.{}", sanitize_name(name), format.extension()));
+        fs::copy(&src_path, dst_path)?;
+        src_path_vec.push(src_path);
+    }
+    Ok(src_path_vec)
+}
@@ -0,0 +1,922 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::iter::Peekable;
+use std::mem;
+use std::path::{Component, Path, PathBuf};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use tar::{Archive, Builder, Entries, Entry, EntryType, Header};
+use json::JsonValue;
+
+use crate::dominator::{Config, SplitMeta, ImageMeta};
+use crate::inspector::{Inspect, ImageInfo};
+use crate::os_str_to_string;
+use crate::split::{Split, SplitOptions, SplitOutput, describe_split, publish_splits};
+use crate::sign::{load_trusted_key, check_signed_chain};
+use crate::merge::{Merge, MergeOptions, MergeOutput, publish_merged, read_split_configs};
+use crate::verify::verify_split;
+use crate::format::{SplitFormat, open_split, write_split, part_path, remove_part_files};
+use crate::util::{HashReader, HashWriter, container_error, check_container, deterministic_header,
+                  fetch_file_sha256, load_config};
+use crate::errors::{LayerSwordError, FileCheckError, InternalError, CheckStage, report_err};
+
+/// size of blocks inside a tar file
+const BLOCK_SIZE: u64 = 512;
+/// longest path kept inside a gnu header without a long name entry ahead
+const NAME_SIZE: usize = 100;
+
+/// size of data of an entry padded to blocks
+fn padded(size: u64) -> u64 {
+    size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE
+}
+
+/// an entry inside a tar file located by its header
+#[derive(Debug, Clone)]
+pub struct TarItem {
+    /// path of the entry without leading './' or trailing '/'
+    pub path: String,
+    /// type of the entry
+    pub entry_type: EntryType,
+    /// mode kept inside header of the entry
+    pub mode: u32,
+    /// offset of data of the entry inside tar file
+    pub offset: u64,
+    /// size of data of the entry
+    pub size: u64,
+}
+
+impl TarItem {
+    /// whether it is the tar file of a layer directory, which is never extracted
+    fn is_layer_tar(&self) -> bool {
+        let mut part_vec = self.path.split('/');
+        part_vec.next().is_some() && part_vec.next() == Some("layer.tar") && part_vec.next().is_none()
+    }
+}
+
+/// entries of a tar file indexed by their paths, built from headers without reading data
+#[derive(Debug, Clone)]
+pub struct TarIndex {
+    pub item_map: BTreeMap<String, TarItem>,
+}
+
+impl TarIndex {
+    /// read headers of a tar file by seeking over data of its entries,
+    /// gnu long names and pax paths are followed, and later entries replace earlier ones
+    /// of the same path like extracting does
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use layer_sword::stream::TarIndex;
+    /// use layer_sword::errors::LayerSwordError;
+    /// fn main() -> Result<(), LayerSwordError> {
+    ///     let index = TarIndex::scan("base.tar")?;
+    ///     for (path, item) in index.item_map.iter() {
+    ///         println!("{} {}", path, item.size);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn scan<P>(tar_path: P) -> Result<Self, LayerSwordError>
+        where
+            P: AsRef<Path> {
+        let mut file = File::open(&tar_path)?;
+        let tar_size = file.metadata()?.len();
+        let bad_header = |pos: u64| FileCheckError::BadDockerFileError {
+            msg: format!("bad tar header at offset {} inside '{:?}'", pos, tar_path.as_ref())
+        };
+
+        let mut item_map: BTreeMap<String, TarItem> = BTreeMap::new();
+        let mut long_path: Option<Vec<u8>> = None;
+        let mut pax_size: Option<u64> = None;
+        let mut pos: u64 = 0;
+        while pos + BLOCK_SIZE <= tar_size {
+            let mut header = Header::new_old();
+            file.seek(SeekFrom::Start(pos))?;
+            file.read_exact(header.as_mut_bytes())?;
+            if header.as_bytes().iter().all(|byte| *byte == 0) {
+                break;
+            }
+            let cksum: u32 = header
+                .as_bytes()
+                .iter()
+                .enumerate()
+                .map(|(i, byte)| if (148..156).contains(&i) { 32 } else { u32::from(*byte) })
+                .sum();
+            if header.cksum().ok() != Some(cksum) {
+                return Err(bad_header(pos).into());
+            }
+            let offset = pos + BLOCK_SIZE;
+            let entry_type = header.entry_type();
+            let size = match entry_type {
+                EntryType::GNULongName | EntryType::XHeader | EntryType::XGlobalHeader =>
+                    header.entry_size()?,
+                _ => pax_size.take().map_or_else(|| header.entry_size(), Ok)?,
+            };
+            if offset + size > tar_size {
+                return Err(bad_header(pos).into());
+            }
+            pos = offset + padded(size);
+            match entry_type {
+                EntryType::GNULongName => {
+                    let mut data = read_at(&mut file, offset, size)?;
+                    while data.last() == Some(&0) {
+                        data.pop();
+                    }
+                    long_path = Some(data);
+                }
+                EntryType::XHeader => {
+                    for (key, value) in pax_records(&read_at(&mut file, offset, size)?) {
+                        if key == "path" {
+                            long_path = Some(value);
+                        } else if key == "size" {
+                            let value = String::from_utf8(value)
+                                .map_err(|_| InternalError::ConvertError)?;
+                            pax_size = Some(value.parse().map_err(|_| bad_header(pos))?);
+                        }
+                    }
+                }
+                EntryType::XGlobalHeader => {}
+                _ => {
+                    let path_bytes = long_path
+                        .take()
+                        .unwrap_or_else(|| header.path_bytes().into_owned());
+                    let path = String::from_utf8(path_bytes)
+                        .map_err(|_| InternalError::ConvertError)?;
+                    let path = path.trim_start_matches("./").trim_end_matches('/').to_string();
+                    if path.is_empty() || path == "." {
+                        continue;
+                    }
+                    let mode = header.mode()?;
+                    item_map.insert(path.clone(), TarItem { path, entry_type, mode, offset, size });
+                }
+            }
+        }
+        Ok(Self { item_map })
+    }
+
+    /// whether all entries are files or directories inside the tar file,
+    /// so that extracting the tar file only produces them
+    pub fn is_plain(&self) -> bool {
+        self.item_map.values().all(|item| {
+            let is_inside = Path::new(&item.path)
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)));
+            let is_plain = matches!(item.entry_type,
+                EntryType::Regular | EntryType::Continuous | EntryType::Directory);
+            is_inside && is_plain
+        })
+    }
+
+    /// extract all the entries except tar files of layer directories
+    pub fn extract_meta(&self, tar_path: &Path, extract_path: &Path)
+                        -> Result<(), LayerSwordError> {
+        let mut file = File::open(tar_path)?;
+        fs::create_dir_all(extract_path)?;
+        for item in self.item_map.values() {
+            let mut item_path = extract_path.to_path_buf();
+            item_path.push(&item.path);
+            if item.entry_type.is_dir() {
+                fs::create_dir_all(&item_path)?;
+            } else {
+                let parent_path = item_path
+                    .parent()
+                    .ok_or_else(|| InternalError::FilePathError { path: item_path.clone() })?;
+                fs::create_dir_all(parent_path)?;
+                if !item.is_layer_tar() {
+                    fs::write(&item_path, read_at(&mut file, item.offset, item.size)?)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// read a json file inside the tar file without extracting it
+    pub fn load_item(&self, tar_path: &Path, path: &str) -> Result<JsonValue, LayerSwordError> {
+        let item = self.item(path)?;
+        let data = read_at(&mut File::open(tar_path)?, item.offset, item.size)?;
+        let text = String::from_utf8(data).map_err(|_| InternalError::ConvertError)?;
+        Ok(json::parse(&text)?)
+    }
+
+    /// fetch names of items right inside a directory
+    fn child_names(&self, dir_name: &str) -> Vec<String> {
+        let prefix = format!("{}/", dir_name);
+        let mut name_vec: Vec<String> = self.item_map
+            .range(prefix.clone()..)
+            .take_while(|(path, _)| path.starts_with(&prefix))
+            .filter_map(|(path, _)| path[prefix.len()..].split('/').next())
+            .map(|name| name.to_string())
+            .collect();
+        name_vec.sort();
+        name_vec.dedup();
+        name_vec
+    }
+
+    /// fetch the item of a path, as an error if it doesn't exist
+    fn item(&self, path: &str) -> Result<&TarItem, LayerSwordError> {
+        self.item_map
+            .get(path)
+            .ok_or_else(|| InternalError::KeyError { key: path.to_string() }.into())
+    }
+}
+
+/// read data at offset of a file
+fn read_at(file: &mut File, offset: u64, size: u64) -> Result<Vec<u8>, LayerSwordError> {
+    let mut data: Vec<u8> = Vec::with_capacity(size as usize);
+    file.seek(SeekFrom::Start(offset))?;
+    file.take(size).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// parse records of a pax extended header, whose lengths count themselves in decimal
+fn pax_records(data: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let mut record_vec: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut rest = data;
+    while let Some(space) = rest.iter().position(|byte| *byte == b' ') {
+        let len: usize = match std::str::from_utf8(&rest[..space]).ok().and_then(|len| len.parse().ok()) {
+            Some(len) if len > space + 1 && len <= rest.len() => len,
+            _ => break,
+        };
+        let record = &rest[space + 1..len - 1];
+        if let Some(equal) = record.iter().position(|byte| *byte == b'=') {
+            let key = String::from_utf8_lossy(&record[..equal]).to_string();
+            record_vec.push((key, record[equal + 1..].to_vec()));
+        }
+        rest = &rest[len..];
+    }
+    record_vec
+}
+
+/// sha256 of config file of the image and splits produced
+type StreamOutput = (Vec<String>, Vec<SplitOutput>);
+
+/// data of an entry inside a split tar
+enum SplitData {
+    Dir,
+    Item(TarItem),
+    Bytes(Vec<u8>),
+}
+
+/// an entry inside a split tar in the order `compress_tar` packs it
+struct SplitEntry {
+    path: String,
+    data: SplitData,
+}
+
+impl SplitEntry {
+    /// size of the entry inside tar, with the long name entry ahead if needed
+    fn tar_size(&self) -> u64 {
+        let size = match &self.data {
+            SplitData::Dir => 0,
+            SplitData::Item(item) => item.size,
+            SplitData::Bytes(data) => data.len() as u64,
+        };
+        let long_size = match self.path.len() > NAME_SIZE {
+            true => BLOCK_SIZE + padded(self.path.len() as u64 + 1),
+            false => 0,
+        };
+        long_size + BLOCK_SIZE + padded(size)
+    }
+}
+
+/// list entries of a split holding layer directories, files at top and its split config,
+/// in the same order as `compress_tar` walks them after they are copied into a directory
+fn plan_split(index: &TarIndex,
+              layer_vec: &[String],
+              file_vec: &[String],
+              split_config: Vec<u8>)
+              -> Result<Vec<SplitEntry>, LayerSwordError> {
+    let mut top_map: BTreeMap<String, Vec<SplitEntry>> = BTreeMap::new();
+    for layer in layer_vec {
+        let mut entry_vec = vec![SplitEntry { path: layer.clone(), data: SplitData::Dir }];
+        for name in index.child_names(layer) {
+            let path = format!("{}/{}", layer, name);
+            let data = match index.item_map.get(&path) {
+                Some(item) if !item.entry_type.is_dir() => SplitData::Item(item.clone()),
+                Some(_) if index.child_names(&path).is_empty() => SplitData::Dir,
+                _ => return Err(FileCheckError::TooManyDepthError { path }.into()),
+            };
+            entry_vec.push(SplitEntry { path, data });
+        }
+        top_map.insert(layer.clone(), entry_vec);
+    }
+    for name in file_vec {
+        let item = index.item(name)?.clone();
+        top_map.insert(name.clone(), vec![SplitEntry { path: name.clone(), data: SplitData::Item(item) }]);
+    }
+    top_map.insert("split_config.json".to_string(), vec![SplitEntry {
+        path: "split_config.json".to_string(),
+        data: SplitData::Bytes(split_config),
+    }]);
+    Ok(top_map.into_values().flatten().collect())
+}
+
+/// write entries of a split as a tar into writer, reading data of items from image tar file
+/// and keeping sha256 of layer tar files by their layer directories,
+/// return sha256 of the tar
+fn write_entries(source: &mut File,
+                 entry_vec: &[SplitEntry],
+                 writer: &mut dyn Write,
+                 layer_tar_hash: &mut HashMap<String, String>)
+                 -> Result<String, LayerSwordError> {
+    let mut tar = Builder::new(HashWriter::new(writer));
+    for entry in entry_vec {
+        match &entry.data {
+            SplitData::Dir => {
+                let mut header = deterministic_header(EntryType::Directory, true, 0);
+                tar.append_data(&mut header, &entry.path, io::empty())?;
+            }
+            SplitData::Item(item) => {
+                let mut header =
+                    deterministic_header(EntryType::Regular, item.mode & 0o100 == 0o100, item.size);
+                source.seek(SeekFrom::Start(item.offset))?;
+                let mut reader = HashReader::new((&mut *source).take(item.size));
+                tar.append_data(&mut header, &entry.path, &mut reader)?;
+                if let Some(layer) = entry.path.strip_suffix("/layer.tar") {
+                    layer_tar_hash.insert(layer.to_string(), reader.finish());
+                }
+            }
+            SplitData::Bytes(data) => {
+                let mut header = deterministic_header(EntryType::Regular, false, data.len() as u64);
+                tar.append_data(&mut header, &entry.path, data.as_slice())?;
+            }
+        }
+    }
+    let (_, hash) = tar.into_inner()?.finish();
+    Ok(hash)
+}
+
+/// paths and settings of a split procedure of an image tar file
+pub struct SplitStream<'a> {
+    pub tar_path: &'a Path,
+    pub split_names: &'a Vec<String>,
+    pub split_map: &'a HashMap<String, i16>,
+    pub work_path: &'a Path,
+    pub out_path: &'a Path,
+    pub options: &'a SplitOptions,
+}
+
+/// split layers of the only image inside a docker-save tar file by streaming entries of
+/// the tar file straight into split files, while only files other than layer tar files are
+/// extracted into work directory for inspection, and sha256 of layer tar files is checked
+/// as they pass through, split files are published only after all the checks pass
+///
+/// return None if the tar file holds entries or images which are split after extracting
+pub fn stream_split_layer<S>(dominator: &S, inspector: &dyn Inspect, stream: &SplitStream)
+                             -> Result<Option<StreamOutput>, LayerSwordError>
+    where
+        S: Split + ?Sized {
+    let result = stream_split_parts(dominator, inspector, stream);
+    if result.is_err() {
+        let part_vec: Vec<PathBuf> = stream.split_names
+            .iter()
+            .map(|name| part_path(&stream.options.split_path(stream.out_path, name, false)))
+            .collect();
+        remove_part_files(part_vec.iter().map(|path| path.as_path()));
+    }
+    result
+}
+
+/// stream splits into part files inside output directory and publish them once checked
+fn stream_split_parts<S>(dominator: &S, inspector: &dyn Inspect, stream: &SplitStream)
+                         -> Result<Option<StreamOutput>, LayerSwordError>
+    where
+        S: Split + ?Sized {
+    let SplitStream { tar_path, split_names, split_map, work_path, out_path, options } = *stream;
+    let mut extract_path = work_path.to_path_buf();
+    extract_path.push("merge");
+    log::info!("Indexing entries of dock image tar file at {}",
+               tar_path.to_str().ok_or(InternalError::ConvertError)?);
+    let index = TarIndex::scan(tar_path)?;
+    if !index.is_plain() {
+        log::info!("Dock image tar file holds entries other than files and directories");
+        return Ok(None);
+    }
+    log::info!("Extracting files except layer tar files into {}",
+               extract_path.to_str().ok_or(InternalError::ConvertError)?);
+    index.extract_meta(tar_path, &extract_path)?;
+    log::info!("Checking dock image files except layer tar files");
+    log::info!("[inspect begin]");
+    log::info!("Inspecting route of required files");
+    let (file_map, layer_hash_set) = inspector.inspect_route(&extract_path)?;
+    log::info!("Inspecting items inside config file");
+    let config_tar_hash = inspector.inspect_config(&file_map)?;
+    log::info!("Inspecting items inside manifest file");
+    let mut image_vec = inspector.inspect_manifest(&extract_path, &file_map, &layer_hash_set)?;
+    log::info!("[inspect end]");
+    if image_vec.len() != 1 {
+        log::info!("Dock image tar file holds {} images", image_vec.len());
+        fs::remove_dir_all(&extract_path)?;
+        return Ok(None);
+    }
+    let image = image_vec.remove(0);
+
+    let config_path = image.file_map
+        .get("config_path")
+        .ok_or_else(|| InternalError::KeyError { key: "config_path".to_string() })?;
+    let image_digest = fetch_file_sha256(config_path)?;
+    let image_meta = ImageMeta {
+        config_digest: image_digest.clone(),
+        repo_tags: image.repo_tags.clone(),
+    };
+    let diff_ids: Vec<String> = load_config(config_path)?["rootfs"]["diff_ids"]
+        .members()
+        .map(|id| id.to_string())
+        .collect();
+    if diff_ids.len() != image.layer_dir_vec.len() {
+        return Err(FileCheckError::BadDockerFileError {
+            msg: format!("config file has {} diff_ids rather than {}",
+                         diff_ids.len(), image.layer_dir_vec.len())
+        }.into());
+    }
+    let mut name_vec: Vec<String> = Vec::new();
+    for path in image.layer_dir_vec.iter() {
+        name_vec.push(os_str_to_string!(path.file_name().unwrap_or_default())?);
+    }
+    let mut file_vec: Vec<String> = Vec::new();
+    for path in image.file_map.values() {
+        file_vec.push(os_str_to_string!(path.file_name().unwrap_or_default())?);
+    }
+    log::info!("Validating number of each layer");
+    let deduct_map =
+        dominator.deduct_split_map(split_names, split_map.clone(), &image.layer_dir_vec)?;
+
+    let mut source = File::open(tar_path)?;
+    let mut layer_tar_hash: HashMap<String, String> = HashMap::new();
+    let mut part_vec: Vec<(PathBuf, PathBuf, String)> = Vec::new();
+    let mut layers_vec: Vec<(Vec<String>, Vec<String>)> = Vec::new();
+    let mut parent_id = String::new();
+    let mut stack_id = String::new();
+    let mut layer_from: usize = 0;
+    for (i, name) in split_names.iter().enumerate() {
+        let layer_to = layer_from + deduct_map[name] as usize;
+        let layers = name_vec[layer_from..layer_to].to_vec();
+        let top_files = match i == split_names.len() - 1 {
+            true => file_vec.clone(),
+            false => Vec::new(),
+        };
+        let meta = SplitMeta {
+            images: vec![image_meta.clone()],
+            split_names: split_names.clone(),
+            diff_ids: diff_ids[layer_from..layer_to].to_vec(),
+        };
+        let (split_config, now_stack_id) =
+            dominator.split_config(i, &stack_id, &parent_id, &meta)?;
+        let entry_vec = plan_split(&index, &layers, &top_files, split_config.dump().into_bytes())?;
+        let tar_size = entry_vec.iter().map(|entry| entry.tar_size()).sum::<u64>() + 2 * BLOCK_SIZE;
+
+        // splits are only streamed from image tar files of a single image
+        let split_path = options.split_path(out_path, name, false);
+        if let Some(dir_path) = split_path.parent() {
+            fs::create_dir_all(dir_path)?;
+        }
+        let split_part_path = part_path(&split_path);
+        log::info!("Streaming {} layers into split '{}' at {}",
+                   layers.len(), name,
+                   split_part_path.to_str().ok_or(InternalError::ConvertError)?);
+        let now_id = write_split(&split_part_path, &format!("{}.tar", name), tar_size,
+                                 options.format_of(name, false), options.level_of(name, false),
+                                 options.jobs,
+                                 |writer| write_entries(&mut source, &entry_vec, writer,
+                                                        &mut layer_tar_hash))?;
+        part_vec.push((split_part_path, split_path, now_id.clone()));
+        layers_vec.push((layers, diff_ids[layer_from..layer_to].to_vec()));
+        parent_id = now_id;
+        stack_id = now_stack_id;
+        layer_from = layer_to;
+    }
+
+    log::info!("Inspecting file inside each layer");
+    let mut layer_file_map: HashMap<String, Vec<String>> = HashMap::new();
+    for layer in layer_hash_set.iter() {
+        // layers outside of the image are hashed alone
+        if !layer_tar_hash.contains_key(layer) {
+            if let Some(item) = index.item_map.get(&format!("{}/layer.tar", layer)) {
+                source.seek(SeekFrom::Start(item.offset))?;
+                let mut reader = HashReader::new((&mut source).take(item.size));
+                io::copy(&mut reader, &mut io::sink())?;
+                layer_tar_hash.insert(layer.clone(), reader.finish());
+            }
+        }
+        layer_file_map.insert(layer.clone(), index.child_names(layer));
+    }
+    let real_tar_hash: HashSet<String> = layer_tar_hash.into_values().collect();
+    inspector.inspect_layer_files(&extract_path, &layer_file_map, &layer_hash_set,
+                                  &config_tar_hash, &real_tar_hash)?;
+    log::info!("Publishing {} split files under {}", part_vec.len(),
+               out_path.to_str().ok_or(InternalError::ConvertError)?);
+    publish_splits(&part_vec, out_path, options.signing_key.as_ref())?;
+    let mut output_vec: Vec<SplitOutput> = Vec::new();
+    for ((_, split_path, _), (layers, diff_ids)) in part_vec.into_iter().zip(layers_vec) {
+        let mut output = describe_split(&split_path)?;
+        output.diff_ids = diff_ids;
+        output.dir_hashes = layers;
+        output_vec.push(output);
+    }
+    log::info!("Clean items inside work path");
+    fs::remove_dir_all(work_path)?;
+    Ok(Some((vec![image_digest], output_vec)))
+}
+
+/// path of an entry inside a split tar without leading './' or trailing '/'
+fn entry_path<R: Read>(entry: &Entry<R>) -> Result<String, LayerSwordError> {
+    let path = entry.path()?;
+    let path = path.to_str().ok_or(InternalError::ConvertError)?;
+    Ok(path.trim_start_matches("./").trim_end_matches('/').to_string())
+}
+
+/// name of the item at top of a path inside tar
+fn top_name(path: &str) -> &str {
+    path.split('/').next().unwrap_or_default()
+}
+
+/// build headers of an entry of image tar, with the gnu long name entry ahead if needed
+fn entry_head(path: &str, mut header: Header) -> Result<Vec<u8>, LayerSwordError> {
+    let mut tar = Builder::new(Vec::new());
+    tar.append_data(&mut header, path, io::empty())?;
+    // take headers out before the builder appends end of archive
+    Ok(mem::take(tar.get_mut()))
+}
+
+/// peek name of the next item at top of a split tar, skipping its split config,
+/// an empty name is returned for a broken entry so that it is read and reported first
+fn peek_top<R: Read>(entries: &mut Peekable<Entries<R>>) -> Result<Option<String>, LayerSwordError> {
+    loop {
+        let path = match entries.peek() {
+            Some(Ok(entry)) => entry_path(entry)?,
+            Some(Err(_)) => return Ok(Some(String::new())),
+            None => return Ok(None),
+        };
+        if path != "split_config.json" {
+            return Ok(Some(top_name(&path).to_string()));
+        }
+        entries.next();
+    }
+}
+
+/// files inside layer directories and sha256 of layer tar files streamed into image tar
+#[derive(Default)]
+struct StreamedLayers {
+    layer_file_map: HashMap<String, Vec<String>>,
+    layer_tar_hash: HashMap<String, String>,
+}
+
+/// write an entry of a split tar into image tar in the same bytes as `compress_tar` packs it,
+/// files other than layer tar files are extracted into work directory as well
+fn write_entry<R: Read>(entry: &mut Entry<R>,
+                        writer: &mut dyn Write,
+                        extract_path: &Path,
+                        streamed: &mut StreamedLayers)
+                        -> Result<(), LayerSwordError> {
+    let path = entry_path(entry)?;
+    let part_vec: Vec<&str> = path.split('/').collect();
+    if part_vec.len() > 2 {
+        return Err(FileCheckError::TooManyDepthError { path }.into());
+    }
+    if part_vec.len() == 2 {
+        streamed.layer_file_map
+            .entry(part_vec[0].to_string())
+            .or_default()
+            .push(part_vec[1].to_string());
+    }
+    let mut item_path = extract_path.to_path_buf();
+    item_path.push(&path);
+    let entry_type = entry.header().entry_type();
+    if entry_type.is_dir() {
+        writer.write_all(&entry_head(&path, deterministic_header(EntryType::Directory, true, 0))?)?;
+        fs::create_dir_all(&item_path)?;
+        return Ok(());
+    }
+    if !matches!(entry_type, EntryType::Regular | EntryType::Continuous) {
+        return Err(FileCheckError::BadDockerFileError {
+            msg: format!("item '{}' inside splits is neither a file nor a directory", path)
+        }.into());
+    }
+    let size = entry.size();
+    let is_executable = entry.header().mode()? & 0o100 == 0o100;
+    writer.write_all(&entry_head(&path, deterministic_header(EntryType::Regular, is_executable, size))?)?;
+    let count = match part_vec.get(1) {
+        Some(&"layer.tar") => {
+            let mut reader = HashReader::new(&mut *entry);
+            let count = io::copy(&mut reader, writer)?;
+            streamed.layer_tar_hash.insert(part_vec[0].to_string(), reader.finish());
+            count
+        }
+        _ => {
+            let mut data: Vec<u8> = Vec::new();
+            entry.read_to_end(&mut data)?;
+            writer.write_all(&data)?;
+            let parent_path = item_path
+                .parent()
+                .ok_or_else(|| InternalError::FilePathError { path: item_path.clone() })?;
+            fs::create_dir_all(parent_path)?;
+            fs::write(&item_path, &data)?;
+            data.len() as u64
+        }
+    };
+    if count != size {
+        return Err(FileCheckError::SplitFileError.into());
+    }
+    writer.write_all(&vec![0u8; (padded(size) - size) as usize])?;
+    Ok(())
+}
+
+/// stream entries of split tars inside split files into image tar without its end,
+/// items at top of the image are taken from split tars in order of their names,
+/// return sha256 of every split tar hashed as it passes through
+fn write_split_entries(split_path_vec: &[PathBuf],
+                       writer: &mut dyn Write,
+                       extract_path: &Path,
+                       streamed: &mut StreamedLayers)
+                       -> Result<Vec<String>, LayerSwordError> {
+    let mut format_vec: Vec<SplitFormat> = Vec::new();
+    let mut archive_vec: Vec<Archive<Box<dyn Read>>> = Vec::new();
+    for split_path in split_path_vec {
+        let split = open_split(split_path)?;
+        format_vec.push(split.format);
+        archive_vec.push(Archive::new(split.reader));
+    }
+    let mut outer_vec: Vec<Entries<Box<dyn Read>>> = Vec::new();
+    for archive in archive_vec.iter_mut() {
+        outer_vec.push(archive
+            .entries()
+            .map_err(|e| report_err(e, FileCheckError::SplitFileError))?);
+    }
+    let mut split_archive_vec = Vec::new();
+    for outer in outer_vec.iter_mut() {
+        let split_tar = outer
+            .next()
+            .ok_or(FileCheckError::SplitFileError)?
+            .map_err(|e| report_err(e, FileCheckError::SplitFileError))?;
+        split_archive_vec.push(Archive::new(HashReader::new(split_tar)));
+    }
+    let mut entries_vec = Vec::new();
+    for split_archive in split_archive_vec.iter_mut() {
+        entries_vec.push(split_archive
+            .entries()
+            .map_err(|e| report_err(e, FileCheckError::SplitFileError))?
+            .peekable());
+    }
+
+    loop {
+        let mut next: Option<(usize, String)> = None;
+        for (i, entries) in entries_vec.iter_mut().enumerate() {
+            let top = match peek_top(entries)? {
+                Some(top) => top,
+                None => continue,
+            };
+            match &next {
+                Some((j, name)) if *name == top => {
+                    return Err(FileCheckError::BadDockerFileError {
+                        msg: format!("item '{}' is inside both split '{:?}' and '{:?}'",
+                                     top, split_path_vec[*j], split_path_vec[i])
+                    }.into());
+                }
+                Some((_, name)) if *name < top => {}
+                _ => next = Some((i, top)),
+            }
+        }
+        let (i, top) = match next {
+            Some(next) => next,
+            None => break,
+        };
+        loop {
+            let is_inside = match entries_vec[i].peek() {
+                Some(Ok(entry)) => top_name(&entry_path(entry)?) == top,
+                Some(Err(_)) => true,
+                None => false,
+            };
+            if !is_inside {
+                break;
+            }
+            if let Some(entry) = entries_vec[i].next() {
+                let mut entry = entry.map_err(|e| report_err(e, FileCheckError::SplitFileError))?;
+                write_entry(&mut entry, writer, extract_path, streamed)?;
+            }
+        }
+    }
+    drop(entries_vec);
+
+    let mut hash_vec: Vec<String> = Vec::new();
+    for split_archive in split_archive_vec {
+        // padding blocks after the end of split tar are hashed as well
+        let mut reader = split_archive.into_inner();
+        io::copy(&mut reader, &mut io::sink())
+            .map_err(|e| report_err(e, FileCheckError::SplitFileError))?;
+        hash_vec.push(reader.finish());
+    }
+    for outer in outer_vec.iter_mut() {
+        if outer.next().is_some() {
+            return Err(FileCheckError::SplitFileError.into());
+        }
+    }
+    drop(outer_vec);
+    // checksum of container is checked when it is read to the end
+    for ((archive, format), split_path) in archive_vec.into_iter().zip(format_vec).zip(split_path_vec) {
+        if let Err(e) = io::copy(&mut archive.into_inner(), &mut io::sink()) {
+            return Err(container_error(split_path, format, e));
+        }
+    }
+    Ok(hash_vec)
+}
+
+/// name of image tar file being streamed inside output directory before it is published
+const PART_TAR_NAME: &str = ".merge.tar.part";
+
+/// merge the only chain of splits among split files by streaming entries of all the
+/// splits at once straight into the image tar file inside output directory or stdout,
+/// while only files other than layer tar files are extracted into work directory
+/// for inspection, and sha256 of split tars and layer tar files is checked as they pass through,
+/// end of the image tar file is written only after all the checks pass
+///
+/// return None if the splits hold several images which are merged after extracting,
+/// or images are selected by repo tags of splits written before they were recorded inside splits
+pub fn stream_merge_layer<M>(dominator: &M,
+                             inspector: &dyn Inspect,
+                             split_file_vec: &[PathBuf],
+                             work_path: &Path,
+                             out_path: &Path,
+                             options: &MergeOptions)
+                             -> Result<Option<MergeOutput>, LayerSwordError>
+    where
+        M: Merge + ?Sized {
+    let mut extract_path = work_path.to_path_buf();
+    extract_path.push("merge");
+    log::info!("Reading split config from {} split files", split_file_vec.len());
+    let (split_config_vec, digest_vec, split_path_vec) =
+        read_split_configs(dominator, split_file_vec)?;
+    log::info!("Resolving split chains of images by sha256 recorded inside split files");
+    let chain_vec = dominator.link_chains(&split_config_vec, &digest_vec)?;
+    // splits written before repo tags were recorded inside them are selected after extracting
+    let tagged = chain_vec
+        .iter()
+        .all(|chain| !split_config_vec[chain[chain.len() - 1]].get_meta().images.is_empty());
+    if !tagged && (options.image.is_some() || chain_vec.len() > 1) {
+        return Ok(None);
+    }
+    let chain_vec = dominator.select_chains(inspector, &split_config_vec, chain_vec,
+                                            options.image.as_deref())?;
+    if chain_vec.len() != 1 {
+        log::info!("Splits hold {} images", chain_vec.len());
+        return Ok(None);
+    }
+    let chain = &chain_vec[0];
+    let chain_path_vec: Vec<PathBuf> = chain.iter().map(|i| split_path_vec[*i].clone()).collect();
+    for split_path in chain_path_vec.iter() {
+        log::info!("Using split file '{}'", split_path.to_string_lossy());
+    }
+    // sha256 recorded inside split files is checked against split tars while streaming
+    if let Some(trust_path) = &options.trust {
+        log::info!("Checking signed manifest of splits");
+        let split_vec: Vec<(PathBuf, String)> = chain
+            .iter()
+            .map(|i| (split_path_vec[*i].clone(), digest_vec[*i].clone()))
+            .collect();
+        check_signed_chain(&load_trusted_key(trust_path)?, &split_vec)?;
+    }
+
+    let stream_chain = StreamChain {
+        split_config_vec: &split_config_vec,
+        chain,
+        chain_path_vec: &chain_path_vec,
+        digest_vec: &digest_vec,
+    };
+    // bytes written to stdout can't be taken back, so splits are checked before any of them
+    if options.stdout {
+        log::info!("Checking {} splits before streaming them to stdout", chain.len());
+        precheck_chain(dominator, &stream_chain)?;
+    }
+
+    let part_path = out_path.join(PART_TAR_NAME);
+    let tar_path = match options.stdout {
+        true => None,
+        false => Some(part_path.as_path()),
+    };
+    let writer: Box<dyn Write> = match tar_path {
+        Some(tar_path) => Box::new(BufWriter::new(File::create(tar_path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    let mut writer = HashWriter::new(writer);
+    log::info!("Streaming {} splits into merged tar file at {}", chain.len(),
+               tar_path.map_or(Some("stdout"), |path| path.to_str())
+                   .ok_or(InternalError::ConvertError)?);
+    let result = stream_checked_splits(dominator, inspector, &stream_chain, &mut writer,
+                                       &extract_path);
+    let image_vec = match result {
+        Ok(image_vec) => image_vec,
+        Err(e) => {
+            if let Some(tar_path) = tar_path {
+                drop(writer);
+                fs::remove_file(tar_path)?;
+            }
+            return Err(e);
+        }
+    };
+    // end of archive
+    writer.write_all(&[0u8; 2 * BLOCK_SIZE as usize])?;
+    let (mut writer, digest) = writer.finish();
+    writer.flush()?;
+    drop(writer);
+    let mut image_digest_vec: Vec<String> = Vec::new();
+    for image in image_vec.iter() {
+        let config_path = image.file_map
+            .get("config_path")
+            .ok_or_else(|| InternalError::KeyError { key: "config_path".to_string() })?;
+        image_digest_vec.push(fetch_file_sha256(config_path)?);
+    }
+    let path = match tar_path {
+        Some(tar_path) => match publish_merged(tar_path, &digest, out_path, &image_vec, options) {
+            Ok(path) => path,
+            Err(e) => {
+                fs::remove_file(tar_path)?;
+                return Err(e);
+            }
+        },
+        None => PathBuf::from("-"),
+    };
+    log::info!("Clean items inside work path");
+    fs::remove_dir_all(work_path)?;
+    Ok(Some(MergeOutput {
+        path,
+        digest,
+        image_digests: image_digest_vec,
+        splits: chain_path_vec,
+    }))
+}
+
+/// a chain of splits streamed into an image tar file, with configs and recorded sha256
+/// of all the split files
+struct StreamChain<'a> {
+    split_config_vec: &'a [Box<dyn Config>],
+    chain: &'a [usize],
+    chain_path_vec: &'a [PathBuf],
+    digest_vec: &'a [String],
+}
+
+/// read every split of a chain through once, checking checksum of its container,
+/// sha256 of its split tar against the one recorded and the chain built by them
+fn precheck_chain<M>(dominator: &M, stream_chain: &StreamChain) -> Result<(), LayerSwordError>
+    where
+        M: Merge + ?Sized {
+    let StreamChain { split_config_vec, chain, chain_path_vec, digest_vec } = *stream_chain;
+    let mut hash_vec: Vec<String> = Vec::new();
+    for (index, split_path) in chain.iter().zip(chain_path_vec) {
+        let format = SplitFormat::detect(split_path)
+            .ok_or_else(|| FileCheckError::SplitFormatError { path: split_path.clone() })?;
+        check_container(split_path, format)?;
+        let result = verify_split(split_path);
+        if result.digest != digest_vec[*index] {
+            return Err(FileCheckError::SplitCheckError {
+                path: split_path.clone(),
+                stage: CheckStage::TarHash,
+                right: digest_vec[*index].clone(),
+                real: result.digest,
+            }.into());
+        }
+        hash_vec.push(result.digest);
+    }
+    dominator.check_chain_hashes(split_config_vec, chain, &hash_vec)
+}
+
+/// stream a chain of splits into writer and check them with files extracted for inspection,
+/// return the image inspected
+fn stream_checked_splits<M>(dominator: &M,
+                            inspector: &dyn Inspect,
+                            stream_chain: &StreamChain,
+                            writer: &mut dyn Write,
+                            extract_path: &Path)
+                            -> Result<Vec<ImageInfo>, LayerSwordError>
+    where
+        M: Merge + ?Sized {
+    let StreamChain { split_config_vec, chain, chain_path_vec, digest_vec } = *stream_chain;
+    let mut streamed = StreamedLayers::default();
+    let hash_vec = write_split_entries(chain_path_vec, writer, extract_path, &mut streamed)?;
+    log::info!("Check split hash for all the splits");
+    for ((index, split_path), real_hash) in chain.iter().zip(chain_path_vec).zip(hash_vec.iter()) {
+        if digest_vec[*index] != *real_hash {
+            return Err(FileCheckError::SplitCheckError {
+                path: split_path.clone(),
+                stage: CheckStage::TarHash,
+                right: digest_vec[*index].clone(),
+                real: real_hash.clone(),
+            }.into());
+        }
+    }
+    dominator.check_chain_hashes(split_config_vec, chain, &hash_vec)?;
+
+    log::info!("Checking merged dock image files");
+    log::info!("[inspect begin]");
+    log::info!("Inspecting route of required files");
+    let (file_map, layer_hash_set) = inspector.inspect_route(extract_path)?;
+    log::info!("Inspecting items inside config file");
+    let config_tar_hash = inspector.inspect_config(&file_map)?;
+    log::info!("Inspecting file inside each layer");
+    let real_tar_hash: HashSet<String> = streamed.layer_tar_hash.into_values().collect();
+    inspector.inspect_layer_files(extract_path, &streamed.layer_file_map, &layer_hash_set,
+                                  &config_tar_hash, &real_tar_hash)?;
+    log::info!("Inspecting items inside manifest file");
+    let image_vec = inspector.inspect_manifest(extract_path, &file_map, &layer_hash_set)?;
+    log::info!("[inspect end]");
+    Ok(image_vec)
+}
@@ -1,73 +1,209 @@
 use std::fs::{File, read_to_string, write};
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::{io, fs};
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::ffi::OsStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{panic, thread};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, process};
 
 use tar::Archive;
 use sha2::{Sha256, Digest};
 use json::JsonValue;
-use flate2::{Compression, GzBuilder};
+use flate2::{CrcReader, Decompress, FlushDecompress, Status};
 use flate2::read::GzDecoder;
 use walkdir::{WalkDir, DirEntry};
 
-use crate::errors::{FileCheckError, InternalError, raise, report, report_err, GENERATE_PATH};
+use crate::format::{SplitFormat, open_split, fetch_pax_digest};
+use crate::errors::{LayerSwordError, FileCheckError, TerminalError, CheckStage, report, report_err};
 use crate::errors::InternalError::{TooLargeConfigSizeError, VecEmptyError, FilePathError};
 
-/// decompress files with tar.gz suffix
+/// decompress split files in any format, checking sha256 of the inner tar file
+/// and checksum of the container in the same pass as extracting it
 ///
 /// # Examples
 ///
 /// ```no_run
-/// use layer_sword::util::extract_tar_gz;
+/// use layer_sword::util::extract_split;
 /// fn main() -> std::io::Result<()> {
-///     let mut f = extract_tar_gz("base.tar.gz", "tmp");
+///     let mut f = extract_split("base.tar.gz", "tmp");
 ///     Ok(())
 /// }
 /// ```
-pub fn extract_tar_gz<P>(gz_path: P, extract_path: P)
-                         -> Result<(), FileCheckError>
+pub fn extract_split<P>(split_path: P, extract_path: P)
+                        -> Result<(), LayerSwordError>
     where
         P: AsRef<Path> {
-    let file = raise(File::open(&gz_path));
-    let dec = GzDecoder::new(file);
-    let hash_u8 = dec
-        .header()
-        .ok_or_else(|| FileCheckError::SplitFileError)?
-        .comment()
-        .ok_or_else(|| FileCheckError::SplitFileError)?;
-    let hash = raise(String::from_utf8(Vec::from(hash_u8)));
-    let mut archive = Archive::new(dec);
-    let mut file_vec: Vec<PathBuf> = Vec::new();
-    for entry in archive
-        .entries()
-        .map_err(|e| { report_err(e, FileCheckError::SplitFileError) })? {
-        let entry = entry
-            .map_err(|e| report_err(e, FileCheckError::SplitFileError))?;
-        let file_pathbuf = raise(entry.path()).into_owned();
-        file_vec.push(file_pathbuf);
-    }
-    if file_vec.len() != 1 {
-        return Err(FileCheckError::SplitFileError);
-    }
-
-    let file_upk = raise(File::open(&gz_path));
-    let dec_upk = GzDecoder::new(file_upk);
-    let mut archive_upk = Archive::new(dec_upk);
-
-    raise(archive_upk.unpack(&extract_path));
-
-    let mut tar_path = extract_path.as_ref().to_path_buf();
-    let filename = raise(file_vec
-        .get(0)
-        .ok_or_else(|| InternalError::KeyError { key: format!("0") }));
-    tar_path.push(filename);
-    let real_hash = fetch_file_sha256(tar_path);
+    let split_path = split_path.as_ref();
+    let split = open_split(split_path)?;
+    let format = split.format;
+    let read_failed = |e: io::Error| container_error(split_path, format, e);
+    let mut archive = Archive::new(split.reader);
+    let mut entries = archive.entries().map_err(read_failed)?;
+    let mut entry = entries
+        .next()
+        .ok_or(FileCheckError::SplitFileError)?
+        .map_err(read_failed)?;
+    let hash = split.digest
+        .or_else(|| fetch_pax_digest(&mut entry))
+        .ok_or(FileCheckError::SplitFileError)?;
+
+    // the only item of a split is its split tar file right inside extract path
+    let tar_name = entry.path()?.into_owned();
+    let is_flat = tar_name
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    let filename = tar_name
+        .file_name()
+        .filter(|_| is_flat)
+        .ok_or(FileCheckError::SplitFileError)?;
+    let tar_path = extract_path.as_ref().join(filename);
+    let mut writer = HashWriter::new(File::create(&tar_path)?);
+    io::copy(&mut entry, &mut writer).map_err(read_failed)?;
+    let (mut tar_file, real_hash) = writer.finish();
+    tar_file.flush()?;
+    if entries.next().transpose().map_err(read_failed)?.is_some() {
+        return Err(FileCheckError::SplitFileError.into());
+    }
+    // checksum of container is checked when it is read to the end
+    io::copy(&mut archive.into_inner(), &mut io::sink()).map_err(read_failed)?;
+
     if hash != real_hash {
-        FileCheckError::HashCheckError { right: hash, real: real_hash };
+        return Err(FileCheckError::SplitCheckError {
+            path: split_path.to_path_buf(),
+            stage: CheckStage::TarHash,
+            right: hash,
+            real: real_hash,
+        }.into());
     }
     Ok(())
 }
 
+/// check checksum of the container of a split in format by decompressing it through
+pub(crate) fn check_container(split_path: &Path, format: SplitFormat) -> Result<(), LayerSwordError> {
+    let mut reader = open_split(split_path)?.reader;
+    io::copy(&mut reader, &mut io::sink())
+        .map_err(|e| container_error(split_path, format, e))?;
+    Ok(())
+}
+
+/// map an error reading through the container of a split to the check it failed,
+/// a truncated container fails at its end and a gzip one is checked for its header and crc32
+pub(crate) fn container_error(split_path: &Path, format: SplitFormat, e: io::Error) -> LayerSwordError {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        return FileCheckError::SplitCheckError {
+            path: split_path.to_path_buf(),
+            stage: CheckStage::StreamEnd,
+            right: format!("complete {} stream", format.extension()),
+            real: e.to_string(),
+        }.into();
+    }
+    match format {
+        SplitFormat::Gz => match check_gz_crc(split_path) {
+            Err(gz_err) => gz_err,
+            Ok(_) => report_err(e, FileCheckError::SplitFileError).into(),
+        },
+        _ => report_err(e, FileCheckError::SplitFileError).into(),
+    }
+}
+
+/// check header and crc32 inside gzip trailer against the decompressed data
+pub(crate) fn check_gz_crc(gz_path: &Path) -> Result<(), LayerSwordError> {
+    let mut reader = CrcReader::new(GzDecoder::new(File::open(gz_path)?));
+    let e = match io::copy(&mut reader, &mut io::sink()) {
+        Ok(_) => return Ok(()),
+        Err(e) => e,
+    };
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        return Err(container_error(gz_path, SplitFormat::Gz, e));
+    }
+    if reader.get_ref().header().is_none() {
+        return Err(FileCheckError::SplitCheckError {
+            path: gz_path.to_path_buf(),
+            stage: CheckStage::GzipHeader,
+            right: "gzip header".to_string(),
+            real: e.to_string(),
+        }.into());
+    }
+    // decoder may take the last bytes of a truncated deflate stream as the trailer
+    if is_gz_truncated(gz_path)? {
+        return Err(container_error(gz_path, SplitFormat::Gz, io::ErrorKind::UnexpectedEof.into()));
+    }
+    // crc32 is stored little-endian in the first 4 of the last 8 bytes
+    let mut file = File::open(gz_path)?;
+    let mut trailer = [0u8; 8];
+    let right = match file.seek(SeekFrom::End(-8)).and_then(|_| file.read_exact(&mut trailer)) {
+        Ok(_) => format!("{:08x}", u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]])),
+        Err(_) => String::new(),
+    };
+    Err(FileCheckError::SplitCheckError {
+        path: gz_path.to_path_buf(),
+        stage: CheckStage::GzipCrc,
+        right,
+        real: format!("{:08x}", reader.crc().sum()),
+    }.into())
+}
+
+/// size limit of the header of a gzip file read to find where its deflate stream starts
+const MAX_GZ_HEADER_SIZE: u64 = 64 * 1024;
+
+/// fetch length of the header of a gzip file, whose optional fields are flagged by its 4th byte
+fn gz_header_len(head: &[u8]) -> Option<usize> {
+    let flags = *head.get(3)?;
+    let mut len: usize = 10;
+    if flags & 0x04 != 0 {
+        len += 2 + u16::from_le_bytes([*head.get(len)?, *head.get(len + 1)?]) as usize;
+    }
+    // file name and comment end with a zero byte
+    for flag in [0x08, 0x10] {
+        if flags & flag != 0 {
+            len += head.get(len..)?.iter().position(|b| *b == 0)? + 1;
+        }
+    }
+    if flags & 0x02 != 0 {
+        len += 2;
+    }
+    (len <= head.len()).then_some(len)
+}
+
+/// check whether a gzip file ends before its deflate stream or the trailer after it,
+/// a deflate stream which is broken rather than cut short is not truncated
+fn is_gz_truncated(gz_path: &Path) -> Result<bool, LayerSwordError> {
+    let mut file = File::open(gz_path)?;
+    let file_len = file.metadata()?.len();
+    let mut head: Vec<u8> = Vec::new();
+    (&mut file).take(MAX_GZ_HEADER_SIZE).read_to_end(&mut head)?;
+    let header_len = match gz_header_len(&head) {
+        Some(len) => len as u64,
+        None => return Ok(true),
+    };
+    file.seek(SeekFrom::Start(header_len))?;
+
+    let mut decompress = Decompress::new(false);
+    let mut input = vec![0u8; 64 * 1024];
+    let mut output = vec![0u8; 64 * 1024];
+    loop {
+        let size = file.read(&mut input)?;
+        if size == 0 {
+            return Ok(true);
+        }
+        let mut pos: usize = 0;
+        while pos < size {
+            let (in_before, out_before) = (decompress.total_in(), decompress.total_out());
+            match decompress.decompress(&input[pos..size], &mut output, FlushDecompress::None) {
+                Ok(Status::StreamEnd) => return Ok(file_len < header_len + decompress.total_in() + 8),
+                Ok(_) => {}
+                Err(_) => return Ok(false),
+            }
+            if (decompress.total_in(), decompress.total_out()) == (in_before, out_before) {
+                return Ok(false);
+            }
+            pos += (decompress.total_in() - in_before) as usize;
+        }
+    }
+}
+
 /// decompress files with tar suffix
 ///
 /// # Examples
@@ -79,104 +215,387 @@ pub fn extract_tar_gz<P>(gz_path: P, extract_path: P)
 ///     Ok(())
 /// }
 /// ```
-pub fn extract_tar<P>(tar_path: P, extract_path: P)
+pub fn extract_tar<P>(tar_path: P, extract_path: P) -> Result<(), LayerSwordError>
     where
         P: AsRef<Path> {
-    let file = raise(File::open(tar_path));
+    let file = File::open(tar_path)?;
     let mut archive = Archive::new(&file);
     archive.set_preserve_permissions(false);
-    raise(archive.unpack(extract_path));
-}
-
-/// compress tar.gz file from a file with tar suffix
-///
-/// # Examples
-///
-/// ```no_run
-/// use layer_sword::util::compress_tar_gz;
-/// fn main() -> std::io::Result<()> {
-///     let mut f = compress_tar_gz("base.tar.gz", "base.tar", 6);
-///     Ok(())
-/// }
-/// ```
-pub fn compress_tar_gz<P>(gz_path: P, file_path: P, compress_level: u8)
-    where
-        P: AsRef<Path> {
-    let gz_file = raise(File::create(gz_path));
-    let enc = GzBuilder::new()
-        .comment(fetch_file_sha256(&file_path))
-        .write(gz_file, Compression::new(compress_level.into()));
-
-    let mut tar = tar::Builder::new(enc);
-    tar.mode(tar::HeaderMode::Deterministic);
-    let mut file = raise(File::open(&file_path));
-    let filename = raise(file_path
-        .as_ref()
-        .file_name()
-        .ok_or_else(|| InternalError::FilePathError { path: file_path.as_ref().to_path_buf() }));
-    raise(tar.append_file(filename, &mut file));
+    archive.unpack(extract_path)?;
+    Ok(())
 }
 
 /// fetch the file path on the tail of an entry(1 or 2 if exists)
-fn iter_child_path(entry: &DirEntry) -> PathBuf {
+fn iter_child_path(entry: &DirEntry) -> Result<PathBuf, LayerSwordError> {
     let mut depth = entry.depth() - 1;
     let mut mid_list: Vec<&OsStr> = vec![entry.file_name()];
     let mut mid_path = entry.path();
     while depth > 0 {
-        mid_path = raise(mid_path
+        mid_path = mid_path
             .parent()
-            .ok_or_else(|| FilePathError { path: mid_path.into() }));
-        let mid_name = raise(mid_path
+            .ok_or_else(|| FilePathError { path: mid_path.into() })?;
+        let mid_name = mid_path
             .file_name()
-            .ok_or_else(|| FilePathError { path: mid_path.into() }));
+            .ok_or_else(|| FilePathError { path: mid_path.into() })?;
         mid_list.push(mid_name);
         depth -= 1;
     }
-    let mut child_name: PathBuf = raise(mid_list.pop().ok_or_else(|| VecEmptyError)).into();
+    let mut child_name: PathBuf = mid_list.pop().ok_or(VecEmptyError)?.into();
     while mid_list.len() > 0 {
-        let mid_name = raise(mid_list.pop().ok_or_else(|| VecEmptyError));
+        let mid_name = mid_list.pop().ok_or(VecEmptyError)?;
         child_name.push(mid_name);
     }
-    child_name
+    Ok(child_name)
+}
+
+/// writer computing sha256 of all the bytes written through it
+pub struct HashWriter<W: Write> {
+    inner: W,
+    sha256: Sha256,
 }
 
-/// compress tar file from a directory
+impl<W: Write> HashWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, sha256: Sha256::new() }
+    }
+
+    /// return the inner writer with sha256 of all the bytes written, without flushing it
+    /// which would end a block of compressors
+    pub fn finish(self) -> (W, String) {
+        (self.inner, format!("{:x}", self.sha256.finalize()))
+    }
+}
+
+impl<W: Write> Write for HashWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let size = self.inner.write(buf)?;
+        self.sha256.update(&buf[..size]);
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// reader computing sha256 of all the bytes read through it
+pub struct HashReader<R: Read> {
+    inner: R,
+    sha256: Sha256,
+}
+
+impl<R: Read> HashReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, sha256: Sha256::new() }
+    }
+
+    /// sha256 of all the bytes read
+    pub fn finish(self) -> String {
+        format!("{:x}", self.sha256.finalize())
+    }
+}
+
+impl<R: Read> Read for HashReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.inner.read(buf)?;
+        self.sha256.update(&buf[..size]);
+        Ok(size)
+    }
+}
+
+/// compress tar file from a directory, return sha256 of the tar file hashed while writing
 ///
 /// # Examples
 ///
 /// ```no_run
 /// use layer_sword::util::compress_tar;
 /// fn main() -> std::io::Result<()> {
-///     let mut f = compress_tar("base.tar", "base");
+///     let hash = compress_tar("base.tar", "base");
 ///     Ok(())
 /// }
 /// ```
-pub fn compress_tar<P>(tar_path: P, extract_path: P) -> Result<(), FileCheckError>
+pub fn compress_tar<P>(tar_path: P, extract_path: P) -> Result<String, LayerSwordError>
     where
         P: AsRef<Path> {
-    let file = raise(File::create(tar_path));
-    let mut tar = tar::Builder::new(file);
+    compress_tar_with_depth(tar_path, extract_path, 2)
+}
+
+/// compress tar file from a directory whose items are at most `max_depth` deep,
+/// return sha256 of the tar file hashed while writing
+///
+/// # Examples
+///
+/// ```no_run
+/// use layer_sword::util::compress_tar_with_depth;
+/// fn main() -> std::io::Result<()> {
+///     let mut f = compress_tar_with_depth("oci.tar", "oci", 3);
+///     Ok(())
+/// }
+/// ```
+pub fn compress_tar_with_depth<P>(tar_path: P, extract_path: P, max_depth: usize)
+                                  -> Result<String, LayerSwordError>
+    where
+        P: AsRef<Path> {
+    let file = File::create(tar_path)?;
+    let mut tar = tar::Builder::new(HashWriter::new(file));
     tar.mode(tar::HeaderMode::Deterministic);
     let all_extracted_paths = WalkDir::new(extract_path)
         .sort_by_key(|item: &DirEntry| item.clone().into_path());
     for entry in all_extracted_paths {
-        let entry = raise(entry);
+        let entry = entry?;
         let item_path = entry.path();
         let item_name: PathBuf;
         if entry.depth() == 0 {
             continue;
-        } else if entry.depth() <= 2 {
-            item_name = iter_child_path(&entry);
+        } else if entry.depth() <= max_depth {
+            item_name = iter_child_path(&entry)?;
         } else {
             let path = entry.path().to_str().unwrap_or_default().to_string();
-            return Err(FileCheckError::TooManyDepthError { path });
+            return Err(FileCheckError::TooManyDepthError { path }.into());
+        }
+        tar.append_path_with_name(item_path, item_name)?;
+    }
+    let (mut file, hash) = tar.into_inner()?.finish();
+    file.flush()?;
+    Ok(hash)
+}
+
+/// build a gnu header in the same bytes as tar files packed by `compress_tar` do
+/// for a directory or a regular file, which keeps only whether it is executable
+///
+/// # Examples
+///
+/// ```
+/// use layer_sword::util::deterministic_header;
+/// let header = deterministic_header(tar::EntryType::Regular, false, 1024);
+/// assert_eq!(header.mode().unwrap(), 0o644);
+/// assert_eq!(header.size().unwrap(), 1024);
+/// ```
+pub fn deterministic_header(entry_type: tar::EntryType, is_executable: bool, size: u64)
+                            -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_mtime(0);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mode(if entry_type.is_dir() || is_executable { 0o755 } else { 0o644 });
+    header.set_entry_type(entry_type);
+    header.set_size(size);
+    if let Some(gnu) = header.as_gnu_mut() {
+        gnu.set_device_major(0);
+        gnu.set_device_minor(0);
+    }
+    header
+}
+
+/// check whether an item exists at the top level of a tar file
+///
+/// # Examples
+///
+/// ```no_run
+/// use layer_sword::util::tar_has_item;
+/// fn main() -> std::io::Result<()> {
+///     let is_oci = tar_has_item("base.tar", "oci-layout");
+///     Ok(())
+/// }
+/// ```
+pub fn tar_has_item<P>(tar_path: P, item: &str) -> bool
+    where
+        P: AsRef<Path> {
+    let file = match File::open(tar_path) {
+        Ok(f) => f,
+        Err(_) => return false
+    };
+    let mut archive = Archive::new(file);
+    let entries = match archive.entries() {
+        Ok(e) => e,
+        Err(_) => return false
+    };
+    for entry in entries {
+        let entry = match entry {
+            Ok(e) => e,
+            Err(_) => return false
+        };
+        let path = match entry.path() {
+            Ok(p) => p.into_owned(),
+            Err(_) => return false
+        };
+        if path.components().next() == Path::new(item).components().next() {
+            return true;
+        }
+    }
+    false
+}
+
+/// fetch the first item packed inside the tar of a split file
+///
+/// items inside a split are packed in sorted order, so the first one is
+/// enough to tell which kind of image the split is cut from
+///
+/// # Examples
+///
+/// ```no_run
+/// use layer_sword::util::fetch_split_first_item;
+/// fn main() -> std::io::Result<()> {
+///     let item = fetch_split_first_item("os.tar.gz");
+///     Ok(())
+/// }
+/// ```
+pub fn fetch_split_first_item<P>(split_path: P) -> Option<PathBuf>
+    where
+        P: AsRef<Path> {
+    let mut archive = Archive::new(open_split(split_path).ok()?.reader);
+    let split_tar = archive.entries().ok()?.next()?.ok()?;
+    let mut split_archive = Archive::new(split_tar);
+    let first_item = split_archive.entries().ok()?.next()?.ok()?;
+    let path = first_item.path().ok()?.into_owned();
+    Some(path)
+}
+
+/// fetch the sha256 of the inner tar recorded in a split file, which is kept inside
+/// gzip comment, zstd skippable frame or pax extended header of the inner tar
+pub fn fetch_split_digest<P>(split_path: P) -> Option<String>
+    where
+        P: AsRef<Path> {
+    let split = open_split(split_path).ok()?;
+    if split.digest.is_some() {
+        return split.digest;
+    }
+    let mut archive = Archive::new(split.reader);
+    let mut split_tar = archive.entries().ok()?.next()?.ok()?;
+    fetch_pax_digest(&mut split_tar)
+}
+
+/// fetch content of an item inside the inner tar of a split file,
+/// return None if the item doesn't exist
+pub fn fetch_split_item<P>(split_path: P, item: &str) -> Option<Vec<u8>>
+    where
+        P: AsRef<Path> {
+    let mut archive = Archive::new(open_split(split_path).ok()?.reader);
+    let split_tar = archive.entries().ok()?.next()?.ok()?;
+    let mut split_archive = Archive::new(split_tar);
+    for entry in split_archive.entries().ok()? {
+        let mut entry = entry.ok()?;
+        if entry.path().ok()?.as_ref() == Path::new(item) {
+            let mut data: Vec<u8> = Vec::new();
+            io::Read::read_to_end(&mut entry, &mut data).ok()?;
+            return Some(data);
         }
-        raise(tar.append_path_with_name(item_path, item_name));
+    }
+    None
+}
+
+/// fetch split_config.json inside the inner tar of a split file,
+/// return None if it doesn't exist or is broken
+pub fn fetch_split_config<P>(split_path: P) -> Option<JsonValue>
+    where
+        P: AsRef<Path> {
+    fetch_split_item(split_path, "split_config.json")
+        .and_then(|data| String::from_utf8(data).ok())
+        .and_then(|text| json::parse(&text).ok())
+}
+
+/// fetch size of the inner tar of a split file from its header,
+/// return None if the file is broken
+pub fn fetch_split_tar_size<P>(split_path: P) -> Option<u64>
+    where
+        P: AsRef<Path> {
+    let mut archive = Archive::new(open_split(split_path).ok()?.reader);
+    let split_tar = archive.entries().ok()?.next()?.ok()?;
+    split_tar.header().size().ok()
+}
+
+/// guard of a directory created by a procedure, which is removed with everything inside
+/// when the guard is dropped on both success and error, unless it is kept
+///
+/// # Examples
+///
+/// ```rust
+/// use std::env;
+/// use layer_sword::util::{GeneratedDir, unique_work_path};
+///
+/// let path = unique_work_path(&env::temp_dir());
+/// std::fs::create_dir(&path).unwrap();
+/// drop(GeneratedDir::new(&path));
+/// assert!(!path.exists());
+/// ```
+#[derive(Debug)]
+pub struct GeneratedDir {
+    path: PathBuf,
+    kept: bool,
+}
+
+impl GeneratedDir {
+    /// guard a directory created by the procedure itself, existing directories should never be guarded
+    pub fn new(path: &Path) -> Self {
+        Self { path: path.to_path_buf(), kept: false }
+    }
+
+    /// path of the directory guarded
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// keep the directory once the procedure succeeds, return path of it
+    pub fn keep(mut self) -> PathBuf {
+        self.kept = true;
+        self.path.clone()
+    }
+}
+
+impl Drop for GeneratedDir {
+    fn drop(&mut self) {
+        if !self.kept && self.path.is_dir() {
+            if let Err(e) = fs::remove_dir_all(&self.path) {
+                log::error!("Failed to remove directory '{}': {}", self.path.to_string_lossy(), e);
+            }
+        }
+    }
+}
+
+/// create a directory with its missing parents, return path of the topmost directory created,
+/// existing directories are never touched
+fn create_missing_dir(path: &Path) -> Result<Option<PathBuf>, LayerSwordError> {
+    if path.is_file() {
+        return Err(TerminalError::NotDirectoryError { path: path.to_string_lossy().to_string() }.into());
+    }
+    let topmost = path.ancestors()
+        .take_while(|p| !p.as_os_str().is_empty() && !p.exists())
+        .last()
+        .map(|p| p.to_path_buf());
+    fs::create_dir_all(path)?;
+    Ok(topmost)
+}
+
+/// create a directory with its missing parents, return guard of the topmost directory created,
+/// which is removed when the guard is dropped unless it is kept, while existing directories
+/// are never touched
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use layer_sword::util::create_generated_dir;
+///
+/// let created = create_generated_dir(Path::new("out/images")).unwrap();
+/// // keep the directories created once the procedure succeeds
+/// if let Some(created) = created {
+///     created.keep();
+/// }
+/// ```
+pub fn create_generated_dir(path: &Path) -> Result<Option<GeneratedDir>, LayerSwordError> {
+    Ok(create_missing_dir(path)?.map(|created| GeneratedDir::new(&created)))
+}
+
+/// check that a path is not a non-empty directory
+fn check_empty_dir(path: &Path) -> Result<(), LayerSwordError> {
+    if path.is_dir() && fs::read_dir(path)?.next().is_some() {
+        return Err(TerminalError::NotEmptyError { path: path.to_string_lossy().to_string() }.into());
     }
     Ok(())
 }
 
-/// init structure of working directory
+/// init structure of working directory and output directory,
+/// the working directory should be empty if it exists and nothing existing is removed
 ///
 /// # Examples
 ///
@@ -190,41 +609,132 @@ pub fn compress_tar<P>(tar_path: P, extract_path: P) -> Result<(), FileCheckErro
 ///     Ok(())
 /// }
 /// ```
-pub fn init_path(handle_path: &Path, out_path: &Path) {
-    // set path for error clean work
-    let mut path_writer = raise(GENERATE_PATH.write());
-    path_writer.push(handle_path.to_path_buf());
-    path_writer.push(out_path.to_path_buf());
-
-    for path in vec![handle_path, out_path] {
-        if path.exists() {
-            if path.is_dir()
-            {
-                raise(fs::remove_dir_all(path));
-            } else if path.is_file() {
-                raise(fs::remove_file(path));
-            }
-        }
-        let mut ret = fs::create_dir(path);
-        // try several times waiting for remove finished by os
-        let mut try_times: u8 = 0;
-        while ret.is_err() {
-            try_times += 1;
-            ret = fs::create_dir(path);
-            if try_times > 10 {
-                raise(ret);
-                return;
-            }
-        }
+pub fn init_path(handle_path: &Path, out_path: &Path) -> Result<(), LayerSwordError> {
+    check_empty_dir(handle_path)?;
+    create_missing_dir(&handle_path.join("split"))?;
+    create_missing_dir(&handle_path.join("merge"))?;
+    create_missing_dir(out_path)?;
+    Ok(())
+}
+
+/// init output directory, an existing one should be empty unless `force` is set,
+/// files inside it are never removed, return guard of the topmost directory created
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use layer_sword::util::init_out_path;
+///
+/// let created = init_out_path(Path::new("out"), false).unwrap();
+/// if let Some(created) = created {
+///     created.keep();
+/// }
+/// ```
+pub fn init_out_path(out_path: &Path, force: bool) -> Result<Option<GeneratedDir>, LayerSwordError> {
+    if !force {
+        check_empty_dir(out_path)?;
     }
-    let mut split_path = handle_path.to_path_buf();
-    split_path.push("split");
+    create_generated_dir(out_path)
+}
 
-    let mut merge_path = handle_path.to_path_buf();
-    merge_path.push("merge");
+/// init a unique working directory under a parent directory, return guard of it,
+/// which removes the working directory once dropped
+///
+/// # Examples
+///
+/// ```rust
+/// use std::env;
+/// use layer_sword::util::init_work_path;
+///
+/// let work_dir = init_work_path(&env::temp_dir()).unwrap();
+/// let work_path = work_dir.path().to_path_buf();
+/// assert!(work_path.join("split").is_dir());
+/// drop(work_dir);
+/// assert!(!work_path.exists());
+/// ```
+pub fn init_work_path(parent_path: &Path) -> Result<GeneratedDir, LayerSwordError> {
+    let work_dir = GeneratedDir::new(&unique_work_path(parent_path));
+    init_path(work_dir.path(), parent_path)?;
+    Ok(work_dir)
+}
 
-    raise(fs::create_dir(split_path));
-    raise(fs::create_dir(merge_path));
+/// fetch a path for working directory under a parent directory,
+/// which is unique among processes and calls
+///
+/// # Examples
+///
+/// ```rust
+/// use std::path::Path;
+/// use layer_sword::util::unique_work_path;
+///
+/// let parent = Path::new("tmp");
+/// assert_ne!(unique_work_path(parent), unique_work_path(parent));
+/// ```
+pub fn unique_work_path(parent_path: &Path) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    parent_path.join(format!("layer_sword-{}-{}-{}",
+                             process::id(), nanos, COUNTER.fetch_add(1, Ordering::SeqCst)))
+}
+
+/// fetch a path for temporary working directory under temp directory of os,
+/// which is unique among processes and calls
+///
+/// # Examples
+///
+/// ```rust
+/// use layer_sword::util::temp_work_path;
+///
+/// assert_ne!(temp_work_path(), temp_work_path());
+/// ```
+pub fn temp_work_path() -> PathBuf {
+    unique_work_path(&env::temp_dir())
+}
+
+/// map items by a function on at most `jobs` threads, results are kept in order of items
+///
+/// # Examples
+///
+/// ```rust
+/// use layer_sword::util::parallel_map;
+///
+/// let squares = parallel_map(&[1, 2, 3, 4], 3, |x| x * x);
+/// assert_eq!(squares, vec![1, 4, 9, 16]);
+/// ```
+pub fn parallel_map<T, R, F>(items: &[T], jobs: usize, f: F) -> Vec<R>
+    where
+        T: Sync,
+        R: Send,
+        F: Fn(&T) -> R + Sync {
+    let next = AtomicUsize::new(0);
+    let workers = jobs.clamp(1, items.len().max(1));
+    let mut result_vec: Vec<Option<R>> = items.iter().map(|_| None).collect();
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| scope.spawn(|| {
+                let mut done: Vec<(usize, R)> = Vec::new();
+                loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    if i >= items.len() {
+                        break;
+                    }
+                    done.push((i, f(&items[i])));
+                }
+                done
+            }))
+            .collect();
+        for handle in handles {
+            let done = handle.join().unwrap_or_else(|e| panic::resume_unwind(e));
+            for (i, result) in done {
+                result_vec[i] = Some(result);
+            }
+        }
+    });
+    result_vec.into_iter().flatten().collect()
 }
 
 /// load json config from text file
@@ -238,7 +748,18 @@ pub fn init_path(handle_path: &Path, out_path: &Path) {
 ///     Ok(())
 /// }
 /// ```
-pub fn load_config<P>(config_path: P) -> Result<JsonValue, FileCheckError>
+pub fn load_config<P>(config_path: P) -> Result<JsonValue, LayerSwordError>
+    where
+        P: AsRef<Path> + Copy {
+    let contents = read_config_text(config_path)?;
+    let ret = report(
+        json::parse(contents.as_str()),
+        FileCheckError::ConfigFileError)?;
+    Ok(ret)
+}
+
+/// read text of config file, which should be no larger than 1MB
+pub fn read_config_text<P>(config_path: P) -> Result<String, LayerSwordError>
     where
         P: AsRef<Path> + Copy {
     let file = report(
@@ -251,15 +772,12 @@ pub fn load_config<P>(config_path: P) -> Result<JsonValue, FileCheckError>
         return Err(report_err(TooLargeConfigSizeError {
             path: config_path.as_ref().to_path_buf(),
             size: size as usize,
-        }, FileCheckError::ConfigFileError));
+        }, FileCheckError::ConfigFileError).into());
     }
     let contents = report(
         read_to_string(config_path),
         FileCheckError::ConfigFileError)?;
-    let ret = report(
-        json::parse(contents.as_str()),
-        FileCheckError::ConfigFileError)?;
-    Ok(ret)
+    Ok(contents)
 }
 
 /// dump json config into text file
@@ -279,10 +797,11 @@ pub fn load_config<P>(config_path: P) -> Result<JsonValue, FileCheckError>
 ///     Ok(())
 /// }
 /// ```
-pub fn dump_config<P>(config: JsonValue, config_path: P)
+pub fn dump_config<P>(config: JsonValue, config_path: P) -> Result<(), LayerSwordError>
     where
         P: AsRef<Path> {
-    raise(write(config_path, config.dump()));
+    write(config_path, config.dump())?;
+    Ok(())
 }
 
 /// fetch sha256 hash of a file
@@ -296,15 +815,15 @@ pub fn dump_config<P>(config: JsonValue, config_path: P)
 ///     Ok(())
 /// }
 /// ```
-pub fn fetch_file_sha256<P>(path: P) -> String
+pub fn fetch_file_sha256<P>(path: P) -> Result<String, LayerSwordError>
     where
         P: AsRef<Path> {
-    let mut file = raise(File::open(path));
+    let mut file = File::open(path)?;
     let mut sha256 = Sha256::new();
-    raise(io::copy(&mut file, &mut sha256));
+    io::copy(&mut file, &mut sha256)?;
     let hash_result = sha256.finalize();
     let real_hash = format!("{:x}", hash_result);
-    real_hash
+    Ok(real_hash)
 }
 
 /// fetch sha256 hash of a string
@@ -357,10 +876,10 @@ pub fn get_stack_id(last_stack_id: &String, parent_id: &String) -> String {
 /// ```rust
 /// use std::path::PathBuf;
 /// use layer_sword::path_to_string;
-/// use layer_sword::errors::raise_debug;
-/// fn main() -> std::io::Result<()> {
+/// use layer_sword::errors::InternalError;
+/// fn main() -> Result<(), InternalError> {
 ///     let path = PathBuf::from("/");
-///     let path = path_to_string!(path);
+///     let path = path_to_string!(path)?;
 ///     assert_eq!(path, "/".to_string());
 ///     Ok(())
 /// }
@@ -368,7 +887,8 @@ pub fn get_stack_id(last_stack_id: &String, parent_id: &String) -> String {
 #[macro_export]
 macro_rules! path_to_string {
     ($p:expr) => {
-        raise_debug($p.into_os_string().into_string())
+        $p.into_os_string().into_string()
+            .map_err(|_| $crate::errors::InternalError::ConvertError)
     };
 }
 
@@ -378,10 +898,10 @@ macro_rules! path_to_string {
 /// ```rust
 /// use std::ffi::OsStr;
 /// use layer_sword::os_str_to_string;
-/// use layer_sword::errors::raise_debug;
-/// fn main() -> std::io::Result<()> {
+/// use layer_sword::errors::InternalError;
+/// fn main() -> Result<(), InternalError> {
 ///     let path = OsStr::new("/");
-///     let path = os_str_to_string!(path);
+///     let path = os_str_to_string!(path)?;
 ///     assert_eq!(path, "/".to_string());
 ///     Ok(())
 /// }
@@ -389,6 +909,33 @@ macro_rules! path_to_string {
 #[macro_export]
 macro_rules! os_str_to_string {
     ($p:expr) => {
-        raise_debug($p.to_os_string().into_string())
+        $p.to_os_string().into_string()
+            .map_err(|_| $crate::errors::InternalError::ConvertError)
     };
-}
\ No newline at end of file
+}
+/// parse a size in bytes with an optional binary unit K, M, G or T like '4G' or '512MiB'
+///
+/// # Examples
+///
+/// ```rust
+/// use layer_sword::util::parse_size;
+///
+/// assert_eq!(parse_size("4G"), Some(4 * 1024 * 1024 * 1024));
+/// assert_eq!(parse_size("512MiB"), Some(512 * 1024 * 1024));
+/// assert_eq!(parse_size("100"), Some(100));
+/// assert_eq!(parse_size("4X"), None);
+/// ```
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim().to_ascii_uppercase();
+    let size = size.strip_suffix("IB")
+        .or_else(|| size.strip_suffix('B'))
+        .unwrap_or(&size);
+    let (number, shift) = match size.chars().last()? {
+        'K' => (&size[..size.len() - 1], 10),
+        'M' => (&size[..size.len() - 1], 20),
+        'G' => (&size[..size.len() - 1], 30),
+        'T' => (&size[..size.len() - 1], 40),
+        _ => (size, 0),
+    };
+    number.parse::<u64>().ok()?.checked_mul(1 << shift)
+}
@@ -1,6 +1,8 @@
 use std::collections::HashSet;
 use std::iter::FromIterator;
 
+use crate::util::parse_size;
+
 /// validator of int argument for clap parser
 /// # Examples
 ///
@@ -51,4 +53,49 @@ pub fn valid_alphabet(arg: String) -> Result<(), String> {
     } else {
         Err("argument has char not in ascii type".to_string())
     }
-}
\ No newline at end of file
+}
+/// validator of positive int argument for clap parser
+/// # Examples
+///
+/// ```rust
+/// use layer_sword::validator::valid_positive_int;
+///
+/// let x = String::from("4");
+/// assert_eq!(valid_positive_int(x), Ok(()));
+///
+/// let x = String::from("0");
+/// assert_eq!(valid_positive_int(x), Err("argument 0 is not positive".to_string()));
+///
+/// let x = String::from("me");
+/// assert_eq!(valid_positive_int(x), Err("argument is not INT type".to_string()));
+/// ```
+pub fn valid_positive_int(arg: String) -> Result<(), String> {
+    match arg.parse::<usize>() {
+        Err(_) => Err("argument is not INT type".to_string()),
+        Ok(0) => Err("argument 0 is not positive".to_string()),
+        Ok(_) => Ok(())
+    }
+}
+
+/// validator of size argument with an optional binary unit for clap parser
+/// # Examples
+///
+/// ```rust
+/// use layer_sword::validator::valid_size;
+///
+/// let x = String::from("4G");
+/// assert_eq!(valid_size(x), Ok(()));
+///
+/// let x = String::from("0");
+/// assert_eq!(valid_size(x), Err("argument 0 is not a positive size".to_string()));
+///
+/// let x = String::from("4X");
+/// assert_eq!(valid_size(x), Err("argument is not SIZE type like 4G, 512M or 1024".to_string()));
+/// ```
+pub fn valid_size(arg: String) -> Result<(), String> {
+    match parse_size(&arg) {
+        None => Err("argument is not SIZE type like 4G, 512M or 1024".to_string()),
+        Some(0) => Err(format!("argument {} is not a positive size", arg)),
+        Some(_) => Ok(())
+    }
+}
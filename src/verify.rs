@@ -0,0 +1,227 @@
+use std::path::{Path, PathBuf};
+use std::io::{self, Read};
+use std::fs;
+
+use ed25519_dalek::VerifyingKey;
+use sha2::{Sha256, Digest};
+use tar::Archive;
+
+use crate::dominator::SplitMeta;
+use crate::format::{SplitFormat, SPLIT_SIGNATURE_NAME, open_split, fetch_pax_digest, is_published_split,
+                    check_split_sums};
+use crate::sign::load_signed_splits;
+use crate::util::get_stack_id;
+use crate::errors::{LayerSwordError, FileCheckError, InternalError};
+
+/// reader computing sha256 of all the bytes read through it
+struct HashReader<R: Read> {
+    inner: R,
+    sha256: Sha256,
+}
+
+impl<R: Read> Read for HashReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.inner.read(buf)?;
+        self.sha256.update(&buf[..size]);
+        Ok(size)
+    }
+}
+
+/// verification result of one split file
+#[derive(Debug, Clone)]
+pub struct SplitVerify {
+    pub path: PathBuf,
+    pub index: Option<usize>,
+    pub digest: String,
+    pub parent_id: String,
+    pub stack_id: String,
+    /// metadata of source images recorded inside the split
+    pub meta: SplitMeta,
+    pub errors: Vec<String>,
+}
+
+impl SplitVerify {
+    pub fn passed(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// stream through a split file in any format, checking the sha256 recorded inside it
+/// and reading split_config.json without unpacking anything to disk
+pub fn verify_split(split_path: &Path) -> SplitVerify {
+    let mut result = SplitVerify {
+        path: split_path.to_path_buf(),
+        index: None,
+        digest: String::new(),
+        parent_id: String::new(),
+        stack_id: String::new(),
+        meta: SplitMeta::default(),
+        errors: Vec::new(),
+    };
+    if let Err(msg) = stream_split(split_path, &mut result) {
+        result.errors.push(msg);
+    }
+    result
+}
+
+/// fill digest and config of a split into result, return message of the first broken item
+fn stream_split(split_path: &Path, result: &mut SplitVerify) -> Result<(), String> {
+    let split = open_split(split_path).map_err(|e| format!("open failed: {}", e))?;
+    let format = split.format;
+
+    let mut archive = Archive::new(split.reader);
+    let mut entries = archive
+        .entries()
+        .map_err(|e| format!("{} parse failed: {}", format.extension(), e))?;
+    let mut split_tar = entries
+        .next()
+        .ok_or_else(|| format!("no split tar inside {}", format.extension()))?
+        .map_err(|e| format!("{} parse failed: {}", format.extension(), e))?;
+    let comment = split.digest
+        .or_else(|| fetch_pax_digest(&mut split_tar))
+        .ok_or_else(|| format!("no sha256 recorded inside {}", format.extension()))?;
+    let mut reader = HashReader { inner: split_tar, sha256: Sha256::new() };
+
+    let mut config: Option<String> = None;
+    {
+        let mut split_archive = Archive::new(&mut reader);
+        for entry in split_archive
+            .entries()
+            .map_err(|e| format!("split tar parse failed: {}", e))? {
+            let mut entry = entry.map_err(|e| format!("split tar parse failed: {}", e))?;
+            let is_config = entry
+                .path()
+                .map(|path| path.as_ref() == Path::new("split_config.json"))
+                .unwrap_or(false);
+            if is_config {
+                let mut text = String::new();
+                entry.read_to_string(&mut text)
+                    .map_err(|e| format!("split_config.json read failed: {}", e))?;
+                config = Some(text);
+            }
+        }
+    }
+    // padding blocks after the end of split tar are hashed as well
+    io::copy(&mut reader, &mut io::sink())
+        .map_err(|e| format!("split tar read failed: {}", e))?;
+    result.digest = format!("{:x}", reader.sha256.finalize());
+    if entries.next().is_some() {
+        return Err(format!("more than 1 item inside {}", format.extension()));
+    }
+    if comment != result.digest {
+        return Err(format!("sha256 recorded inside {} is '{}' but split tar is '{}'",
+                           format.extension(), comment, result.digest));
+    }
+
+    let config = config
+        .ok_or_else(|| "no split_config.json inside split".to_string())?;
+    let config = json::parse(&config)
+        .map_err(|_| "split_config.json parse failed".to_string())?;
+    result.index = Some(config["index"]
+        .as_usize()
+        .ok_or_else(|| "no index inside split_config.json".to_string())?);
+    result.parent_id = config["parent_id"].to_string();
+    result.stack_id = config["stack_id"].to_string();
+    result.meta = SplitMeta::from_json(&config);
+    Ok(())
+}
+
+/// check parent_id and stack_id of every split against the split below it,
+/// and splits missing above it by names of splits recorded inside it
+pub fn verify_chain(result_vec: &mut [SplitVerify]) {
+    // a split whose index can't be read may be the one above any other
+    let all_indexed = result_vec.iter().all(|split| split.index.is_some());
+    let mut error_vec: Vec<Option<String>> = Vec::new();
+    let mut above_vec: Vec<Option<String>> = Vec::new();
+    for split in result_vec.iter() {
+        let index = match split.index {
+            Some(index) if split.passed() => index,
+            _ => {
+                error_vec.push(None);
+                above_vec.push(None);
+                continue;
+            }
+        };
+        let has_child = result_vec
+            .iter()
+            .any(|child| child.index == Some(index + 1) && child.parent_id == split.digest);
+        above_vec.push(match all_indexed && !has_child {
+            true => split.meta.missing_error(index + 1).map(|e| e.to_string()),
+            false => None,
+        });
+        if index == 0 {
+            if !split.parent_id.is_empty() {
+                error_vec.push(Some(format!("bottom split has parent_id '{}'", split.parent_id)));
+            } else if split.stack_id != get_stack_id(&String::new(), &String::new()) {
+                error_vec.push(Some(format!("stack_id '{}' is broken", split.stack_id)));
+            } else {
+                error_vec.push(None);
+            }
+            continue;
+        }
+        let parent = result_vec
+            .iter()
+            .find(|p| p.index == Some(index - 1) && p.digest == split.parent_id);
+        let below_missing = result_vec.iter().all(|p| p.index != Some(index - 1));
+        error_vec.push(match (parent, split.meta.missing_error(index - 1)) {
+            (None, Some(e)) if below_missing => Some(e.to_string()),
+            (None, _) => Some(format!("no split of index {} with sha256 '{}' as parent",
+                                      index - 1, split.parent_id)),
+            (Some(parent), _) if split.stack_id != get_stack_id(&parent.stack_id, &parent.digest) =>
+                Some(format!("stack_id '{}' is broken", split.stack_id)),
+            (Some(_), _) => None,
+        });
+    }
+    for ((split, error), above) in result_vec.iter_mut().zip(error_vec).zip(above_vec) {
+        split.errors.extend(error);
+        split.errors.extend(above);
+    }
+}
+
+/// check every split under a directory is covered by the signed manifest beside it,
+/// which is signed by the trusted key, failures are recorded into results of splits
+pub fn verify_signed(result_vec: &mut [SplitVerify], target_path: &Path, trusted_key: &VerifyingKey) {
+    let manifest_path = target_path.join(SPLIT_SIGNATURE_NAME);
+    let signed_vec = match manifest_path.is_file() {
+        true => load_signed_splits(&manifest_path, trusted_key).map_err(|e| e.to_string()),
+        false => Err(format!("no signed manifest '{}' beside splits", SPLIT_SIGNATURE_NAME)),
+    };
+    for split in result_vec.iter_mut() {
+        match &signed_vec {
+            Err(msg) => split.errors.push(msg.clone()),
+            Ok(signed_vec) if !signed_vec.contains(&(split.digest.clone(), split.stack_id.clone())) =>
+                split.errors.push("not covered by signed manifest".to_string()),
+            Ok(_) => {}
+        }
+    }
+}
+
+/// verify all split files under a directory, whose formats are detected by magic bytes,
+/// they must match the sums file beside them if any
+pub fn verify_splits(target_path: &Path) -> Result<Vec<SplitVerify>, LayerSwordError> {
+    let mut split_vec: Vec<PathBuf> = Vec::new();
+    for entry in fs::read_dir(target_path)? {
+        let path = entry?.path();
+        if path.is_file() && is_published_split(&path) && SplitFormat::detect(&path).is_some() {
+            split_vec.push(path);
+        }
+    }
+    if split_vec.is_empty() {
+        return Err(FileCheckError::SplitFormatError {
+            path: target_path.to_path_buf(),
+        }.into());
+    }
+    split_vec.sort();
+    check_split_sums(target_path, &split_vec)?;
+
+    let mut result_vec: Vec<SplitVerify> = Vec::new();
+    for split_path in split_vec {
+        log::info!("Verifying split '{}'", split_path
+            .to_str()
+            .ok_or(InternalError::ConvertError)?);
+        result_vec.push(verify_split(&split_path));
+    }
+    verify_chain(&mut result_vec);
+    result_vec.sort_by_key(|split| split.index);
+    Ok(result_vec)
+}
@@ -2,9 +2,11 @@
 mod common;
 
 use std::path::Path;
+use std::process::Command;
+use std::fs;
 
 use layer_sword::client::cli_main;
-use layer_sword::util::fetch_file_sha256;
+use layer_sword::util::{fetch_file_sha256, load_config};
 use layer_sword::errors::LayerSwordError;
 
 use common::{testcase_initial, testcase_destroy};
@@ -26,21 +28,21 @@ fn test_split_basic() -> Result<()> {
     cli_main(args)?;
 
     let os_path = Path::new("tests/out_split_basic/os.tar.gz");
-    let os_hash = fetch_file_sha256(os_path);
+    let os_hash = fetch_file_sha256(os_path)?;
     let os_right =
-        format!("78847ae9c6eef8cd1e84fd76d244bcc96ce45f60b6166a0a0a16ff8e858c8da4");
+        "76c6dcc7eeae8e9fb05604920e8b59b3546531095162e5a5afaf6437b7510256".to_string();
     assert_eq!(os_hash, os_right);
 
     let lib_path = Path::new("tests/out_split_basic/lib.tar.gz");
-    let lib_hash = fetch_file_sha256(lib_path);
+    let lib_hash = fetch_file_sha256(lib_path)?;
     let lib_right =
-        format!("87134c9d4507bfe21be863ecf4ff90a0392bd08ee6a4ad803f8b9c81c1e0318f");
+        "15bb975426312b157e84230641223821208f3bda460ababa6ecbf3f7b5b30b69".to_string();
     assert_eq!(lib_hash, lib_right);
 
     let app_path = Path::new("tests/out_split_basic/app.tar.gz");
-    let app_hash = fetch_file_sha256(app_path);
+    let app_hash = fetch_file_sha256(app_path)?;
     let app_right =
-        format!("6f254b36aca46cd037ca455f0843efba982e7ed338d88c04106096a2f3afd6cc");
+        "ed8070d3c35fd0851282f5e3cc0688c8103f65647ec7561625ccdb83dd629e75".to_string();
     assert_eq!(app_hash, app_right);
 
     testcase_destroy(vec!["tests/work_split_basic", "tests/out_split_basic"]);
@@ -62,21 +64,21 @@ fn test_split_negatives() -> Result<()> {
     cli_main(args)?;
 
     let os_path = Path::new("tests/out_split_negatives/os.tar.gz");
-    let os_hash = fetch_file_sha256(os_path);
+    let os_hash = fetch_file_sha256(os_path)?;
     let os_right =
-        format!("78847ae9c6eef8cd1e84fd76d244bcc96ce45f60b6166a0a0a16ff8e858c8da4");
+        "76c6dcc7eeae8e9fb05604920e8b59b3546531095162e5a5afaf6437b7510256".to_string();
     assert_eq!(os_hash, os_right);
 
     let lib_path = Path::new("tests/out_split_negatives/lib.tar.gz");
-    let lib_hash = fetch_file_sha256(lib_path);
+    let lib_hash = fetch_file_sha256(lib_path)?;
     let lib_right =
-        format!("87134c9d4507bfe21be863ecf4ff90a0392bd08ee6a4ad803f8b9c81c1e0318f");
+        "15bb975426312b157e84230641223821208f3bda460ababa6ecbf3f7b5b30b69".to_string();
     assert_eq!(lib_hash, lib_right);
 
     let app_path = Path::new("tests/out_split_negatives/app.tar.gz");
-    let app_hash = fetch_file_sha256(app_path);
+    let app_hash = fetch_file_sha256(app_path)?;
     let app_right =
-        format!("6f254b36aca46cd037ca455f0843efba982e7ed338d88c04106096a2f3afd6cc");
+        "ed8070d3c35fd0851282f5e3cc0688c8103f65647ec7561625ccdb83dd629e75".to_string();
     assert_eq!(app_hash, app_right);
 
     testcase_destroy(vec!["tests/work_split_negatives", "tests/out_split_negatives"]);
@@ -97,27 +99,54 @@ fn test_split_config() -> Result<()> {
     cli_main(args)?;
 
     let os_path = Path::new("tests/out_split_config/os.tar.gz");
-    let os_hash = fetch_file_sha256(os_path);
+    let os_hash = fetch_file_sha256(os_path)?;
     let os_right =
-        format!("78847ae9c6eef8cd1e84fd76d244bcc96ce45f60b6166a0a0a16ff8e858c8da4");
+        "76c6dcc7eeae8e9fb05604920e8b59b3546531095162e5a5afaf6437b7510256".to_string();
     assert_eq!(os_hash, os_right);
 
     let lib_path = Path::new("tests/out_split_config/lib.tar.gz");
-    let lib_hash = fetch_file_sha256(lib_path);
+    let lib_hash = fetch_file_sha256(lib_path)?;
     let lib_right =
-        format!("87134c9d4507bfe21be863ecf4ff90a0392bd08ee6a4ad803f8b9c81c1e0318f");
+        "15bb975426312b157e84230641223821208f3bda460ababa6ecbf3f7b5b30b69".to_string();
     assert_eq!(lib_hash, lib_right);
 
     let app_path = Path::new("tests/out_split_config/app.tar.gz");
-    let app_hash = fetch_file_sha256(app_path);
+    let app_hash = fetch_file_sha256(app_path)?;
     let app_right =
-        format!("6f254b36aca46cd037ca455f0843efba982e7ed338d88c04106096a2f3afd6cc");
+        "ed8070d3c35fd0851282f5e3cc0688c8103f65647ec7561625ccdb83dd629e75".to_string();
     assert_eq!(app_hash, app_right);
 
     testcase_destroy(vec!["tests/work_split_config", "tests/out_split_config"]);
     Ok(())
 }
 
+#[test]
+fn test_split_config_toml() -> Result<()> {
+    testcase_initial(vec!["tests/work_split_config_toml", "tests/out_split_config_toml"]);
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "split",
+        "-t", "tests/data/base.tar",
+        "-c", "tests/data/config.toml",
+        "-w", "tests/work_split_config_toml",
+        "-o", "tests/out_split_config_toml"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+
+    let os_path = Path::new("tests/out_split_config_toml/os.tar.gz");
+    let os_hash = fetch_file_sha256(os_path)?;
+    let os_right =
+        "76c6dcc7eeae8e9fb05604920e8b59b3546531095162e5a5afaf6437b7510256".to_string();
+    assert_eq!(os_hash, os_right);
+    // app split is written by its own format into its own subdirectory
+    assert!(Path::new("tests/out_split_config_toml/app/app.tar.zst").is_file());
+    assert!(!Path::new("tests/out_split_config_toml/app.tar.gz").exists());
+    let sums = fs::read_to_string("tests/out_split_config_toml/SHA256SUMS")?;
+    assert!(sums.contains("  app/app.tar.zst\n"));
+
+    testcase_destroy(vec!["tests/work_split_config_toml", "tests/out_split_config_toml"]);
+    Ok(())
+}
+
 #[test]
 fn test_merge_basic() -> Result<()> {
     testcase_initial(vec!["tests/work_merge_basic", "tests/out_merge_basic"]);
@@ -130,12 +159,484 @@ fn test_merge_basic() -> Result<()> {
         "-o", "tests/out_merge_basic"].iter().map(|s| s.to_string()).collect();
     cli_main(args)?;
 
-    let tar_path = Path::new("tests/out_merge_basic/merge.tar");
-    let tar_hash = fetch_file_sha256(tar_path);
+    let tar_path = Path::new("tests/out_merge_basic/hello-world_l5.tar");
+    let tar_hash = fetch_file_sha256(tar_path)?;
     let tar_right =
         format!("a82e3d4bcf3194ec7841f6f1f2b4ce34d1107c23ef4e42d4e5073224858cc56b");
     assert_eq!(tar_hash, tar_right);
 
     testcase_destroy(vec!["tests/work_merge_basic", "tests/out_merge_basic"]);
     Ok(())
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_split_max_size() -> Result<()> {
+    testcase_initial(vec!["tests/out_split_max_size"]);
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "split",
+        "--max-split-size", "16K",
+        "-n", "os,lib",
+        "-t", "tests/data/base.tar",
+        "-w", "tests/work_split_max_size",
+        "-o", "tests/out_split_max_size"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+
+    // the bottom layer fills the first split alone
+    let os_path = Path::new("tests/out_split_max_size/os.tar.gz");
+    let os_hash = fetch_file_sha256(os_path)?;
+    let os_right =
+        "10f2920eb4b87446809a72df3dfcdfe28956b0633466d828bcf7cf8534579929".to_string();
+    assert_eq!(os_hash, os_right);
+    assert!(Path::new("tests/out_split_max_size/lib.tar.gz").exists());
+
+    testcase_destroy(vec!["tests/work_split_max_size", "tests/out_split_max_size"]);
+    Ok(())
+}
+
+#[test]
+fn test_split_after_history() -> Result<()> {
+    testcase_initial(vec!["tests/out_split_after_history"]);
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "split",
+        "--split-after", r"CMD \[",
+        "-n", "os,lib",
+        "-t", "tests/data/base.tar",
+        "-w", "tests/work_split_after_history",
+        "-o", "tests/out_split_after_history"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+
+    // the bottom layer is the only one built before 'CMD ["/hello"]'
+    let os_path = Path::new("tests/out_split_after_history/os.tar.gz");
+    let os_hash = fetch_file_sha256(os_path)?;
+    let os_right =
+        "10f2920eb4b87446809a72df3dfcdfe28956b0633466d828bcf7cf8534579929".to_string();
+    assert_eq!(os_hash, os_right);
+    assert!(Path::new("tests/out_split_after_history/lib.tar.gz").exists());
+
+    testcase_destroy(vec!["tests/work_split_after_history", "tests/out_split_after_history"]);
+    Ok(())
+}
+
+#[test]
+fn test_split_force() -> Result<()> {
+    testcase_initial(vec!["tests/out_split_force"]);
+    fs::write("tests/out_split_force/keep.txt", "keep")?;
+
+    let args: Vec<String> = vec![
+        "target/release/layer_sword.exe",
+        "split",
+        "--force",
+        "-n", "os,lib,app",
+        "-l", "1,3,1",
+        "-w", "tests/work_split_force",
+        "-o", "tests/out_split_force",
+        "-t", "tests/data/base.tar"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+
+    // files already inside output directory are kept beside splits
+    assert!(Path::new("tests/out_split_force/keep.txt").exists());
+    assert!(Path::new("tests/out_split_force/os.tar.gz").exists());
+
+    // splits of another image tar file are listed inside sums file beside the ones before
+    let args: Vec<String> = vec![
+        "target/release/layer_sword.exe",
+        "split",
+        "--force",
+        "-n", "base,top",
+        "-l", "2,-1",
+        "-w", "tests/work_split_force",
+        "-o", "tests/out_split_force",
+        "-t", "tests/data/multi.tar"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+    let sums = fs::read_to_string("tests/out_split_force/SHA256SUMS")?;
+    assert_eq!(sums.lines().count(), 6);
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "verify",
+        "-t", "tests/out_split_force"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "merge",
+        "-i", "hello-world:l3",
+        "-t", "tests/out_split_force",
+        "-w", "tests/work_split_force",
+        "-o", "tests/out_split_force_merge"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+    assert!(Path::new("tests/out_split_force_merge/hello-world_l3.tar").exists());
+
+    testcase_destroy(vec!["tests/work_split_force", "tests/out_split_force",
+                          "tests/out_split_force_merge"]);
+    Ok(())
+}
+
+#[test]
+fn test_merge_output_file() -> Result<()> {
+    testcase_initial(vec!["tests/out_merge_output_file"]);
+    // files already inside output directory are kept
+    fs::write("tests/out_merge_output_file/keep.txt", "keep")?;
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "merge",
+        "-t", "tests/data/splits_base",
+        "-w", "tests/work_merge_output_file",
+        "-o", "tests/out_merge_output_file/image.tar"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+
+    let tar_path = Path::new("tests/out_merge_output_file/image.tar");
+    let tar_hash = fetch_file_sha256(tar_path)?;
+    let tar_right =
+        "a82e3d4bcf3194ec7841f6f1f2b4ce34d1107c23ef4e42d4e5073224858cc56b".to_string();
+    assert_eq!(tar_hash, tar_right);
+    assert!(Path::new("tests/out_merge_output_file/keep.txt").exists());
+
+    testcase_destroy(vec!["tests/work_merge_output_file", "tests/out_merge_output_file"]);
+    Ok(())
+}
+
+#[test]
+fn test_merge_stdout() -> Result<()> {
+    testcase_initial(vec!["tests/out_merge_stdout"]);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_layer_sword"))
+        .args(["merge", "-q",
+            "-t", "tests/data/splits_base",
+            "-w", "tests/work_merge_stdout",
+            "-o", "-"])
+        .output()?;
+    assert!(output.status.success());
+    assert!(!Path::new("-").exists());
+    // only the unique working directory inside is removed
+    assert!(fs::read_dir("tests/work_merge_stdout")?.next().is_none());
+
+    let tar_path = Path::new("tests/out_merge_stdout/merge.tar");
+    fs::write(tar_path, output.stdout)?;
+    let tar_hash = fetch_file_sha256(tar_path)?;
+    let tar_right =
+        "a82e3d4bcf3194ec7841f6f1f2b4ce34d1107c23ef4e42d4e5073224858cc56b".to_string();
+    assert_eq!(tar_hash, tar_right);
+
+    testcase_destroy(vec!["tests/out_merge_stdout", "tests/work_merge_stdout"]);
+    Ok(())
+}
+
+#[test]
+fn test_merge_stdout_broken_split() -> Result<()> {
+    testcase_initial(vec!["tests/target_merge_stdout_broken"]);
+    for name in ["os.tar.gz", "lib.tar.gz", "app.tar.gz"] {
+        fs::copy(format!("tests/data/splits_base/{}", name),
+                 format!("tests/target_merge_stdout_broken/{}", name))?;
+    }
+    // crc32 of the bottom split is only found broken once the split is read to the end
+    let os_path = Path::new("tests/target_merge_stdout_broken/os.tar.gz");
+    let mut os = fs::read(os_path)?;
+    let crc_index = os.len() - 8;
+    os[crc_index] ^= 0xff;
+    fs::write(os_path, os)?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_layer_sword"))
+        .args(["merge", "-q",
+            "-t", "tests/target_merge_stdout_broken",
+            "-o", "-"])
+        .output()?;
+    assert!(!output.status.success());
+    // nothing of the broken splits is written to stdout
+    assert!(output.stdout.is_empty());
+
+    testcase_destroy(vec!["tests/target_merge_stdout_broken"]);
+    Ok(())
+}
+#[test]
+fn test_split_merge_oci() -> Result<()> {
+    testcase_initial(vec!["tests/work_split_merge_oci", "tests/out_split_merge_oci",
+                          "tests/out_split_merge_oci_merge"]);
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "split",
+        "-n", "os,app",
+        "-l", "2,-1",
+        "-w", "tests/work_split_merge_oci",
+        "-o", "tests/out_split_merge_oci",
+        "-t", "tests/data/oci.tar"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "merge",
+        "-t", "tests/out_split_merge_oci",
+        "-w", "tests/work_split_merge_oci",
+        "-o", "tests/out_split_merge_oci_merge"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+
+    let tar_path = Path::new("tests/out_split_merge_oci_merge/sword_1.0.tar");
+    let tar_hash = fetch_file_sha256(tar_path)?;
+    let tar_right = fetch_file_sha256("tests/data/oci.tar")?;
+    assert_eq!(tar_hash, tar_right);
+
+    testcase_destroy(vec!["tests/work_split_merge_oci", "tests/out_split_merge_oci",
+                          "tests/out_split_merge_oci_merge"]);
+    Ok(())
+}
+
+#[test]
+fn test_split_merge_multi_image() -> Result<()> {
+    testcase_initial(vec!["tests/work_split_merge_multi", "tests/out_split_merge_multi",
+                          "tests/out_split_merge_multi_merge"]);
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "split",
+        "-n", "os,app",
+        "-l", "2,-1",
+        "-w", "tests/work_split_merge_multi",
+        "-o", "tests/out_split_merge_multi",
+        "-t", "tests/data/multi.tar"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "merge",
+        "-i", "hello-world:l5",
+        "-t", "tests/out_split_merge_multi",
+        "-w", "tests/work_split_merge_multi",
+        "-o", "tests/out_split_merge_multi_merge"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+
+    let tar_path = Path::new("tests/out_split_merge_multi_merge/hello-world_l5.tar");
+    assert!(tar_path.exists());
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "merge",
+        "-i", "hello-world:missing",
+        "-t", "tests/out_split_merge_multi",
+        "-w", "tests/work_split_merge_multi",
+        "-o", "tests/out_split_merge_multi_merge"].iter().map(|s| s.to_string()).collect();
+    assert!(cli_main(args).is_err());
+
+    testcase_destroy(vec!["tests/work_split_merge_multi", "tests/out_split_merge_multi",
+                          "tests/out_split_merge_multi_merge"]);
+    Ok(())
+}
+
+#[test]
+fn test_split_merge_store() -> Result<()> {
+    testcase_initial(vec!["tests/work_split_merge_store", "tests/out_split_merge_store",
+                          "tests/store_split_merge_store"]);
+
+    for target in ["tests/data/base.tar", "tests/data/multi.tar"] {
+        let args: Vec<String> = vec![
+            "target/release/layer_sword.exe",
+            "split",
+            "-n", "os,app",
+            "-l", "2,-1",
+            "-w", "tests/work_split_merge_store",
+            "-o", "tests/out_split_merge_store",
+            "-s", "tests/store_split_merge_store",
+            "-t", target].iter().map(|s| s.to_string()).collect();
+        cli_main(args)?;
+    }
+
+    // 'os' split is shared by the images of 'multi.tar' and stored only once,
+    // while 'os' split of 'base.tar' records another image
+    let splits = fs::read_dir("tests/store_split_merge_store/splits")
+        .map(|entries| entries.count())
+        .unwrap_or_default();
+    assert_eq!(splits, 5);
+    let index_path = Path::new("tests/store_split_merge_store/index/hello-world_l3.json");
+    assert!(index_path.exists());
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "merge",
+        "-s", "tests/store_split_merge_store",
+        "-i", "hello-world:l3",
+        "-w", "tests/work_split_merge_store",
+        "-o", "tests/out_split_merge_store"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+
+    let tar_path = Path::new("tests/out_split_merge_store/hello-world_l3.tar");
+    assert!(tar_path.exists());
+
+    testcase_destroy(vec!["tests/work_split_merge_store", "tests/out_split_merge_store",
+                          "tests/store_split_merge_store"]);
+    Ok(())
+}
+
+#[test]
+fn test_inspect_json() -> Result<()> {
+    testcase_initial(vec!["tests/work_inspect_json"]);
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "inspect",
+        "-j",
+        "-w", "tests/work_inspect_json",
+        "-t", "tests/data/base.tar"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+    // unique working directory inside is cleaned after inspection
+    assert!(fs::read_dir("tests/work_inspect_json")?.next().is_none());
+
+    testcase_destroy(vec!["tests/work_inspect_json"]);
+    Ok(())
+}
+
+#[test]
+fn test_split_merge_report() -> Result<()> {
+    testcase_initial(vec!["tests/work_split_merge_report", "tests/out_split_merge_report",
+                          "tests/report_split_merge_report"]);
+
+    let args: Vec<String> = vec![
+        "target/release/layer_sword.exe",
+        "split",
+        "-n", "os,lib,app",
+        "-l", "1,-1,1",
+        "-w", "tests/work_split_merge_report",
+        "-o", "tests/out_split_merge_report",
+        "-r", "tests/report_split_merge_report/split.json",
+        "-t", "tests/data/base.tar"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+
+    let report = load_config("tests/report_split_merge_report/split.json")?;
+    assert_eq!(report["image_digests"].len(), 1);
+    assert_eq!(report["compress_level"].as_u8(), Some(6));
+    assert_eq!(report["splits"].len(), 3);
+    let layer_nums: Vec<usize> = report["splits"]
+        .members()
+        .map(|split| split["diff_ids"].len())
+        .collect();
+    assert_eq!(layer_nums, vec![1, 3, 1]);
+    let os = &report["splits"][0];
+    let lib = &report["splits"][1];
+    assert_eq!(os["index"].as_usize(), Some(0));
+    assert_eq!(os["parent_id"].as_str(), Some(""));
+    assert_eq!(lib["parent_id"], os["digest"]);
+    assert_eq!(os["dir_hashes"].len(), os["diff_ids"].len());
+    assert!(os["diff_ids"][0].as_str().unwrap_or_default().starts_with("sha256:"));
+    let os_size = fs::metadata("tests/out_split_merge_report/os.tar.gz")?.len();
+    assert_eq!(os["size"].as_u64(), Some(os_size));
+    assert!(os["tar_size"].as_u64().unwrap_or_default() > os_size);
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "merge",
+        "-t", "tests/out_split_merge_report",
+        "-w", "tests/work_split_merge_report",
+        "-o", "tests/out_split_merge_report/merge",
+        "-r", "tests/report_split_merge_report/merge.json"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+
+    let report = load_config("tests/report_split_merge_report/merge.json")?;
+    let tar_right =
+        "a82e3d4bcf3194ec7841f6f1f2b4ce34d1107c23ef4e42d4e5073224858cc56b".to_string();
+    assert_eq!(report["digest"].as_str(), Some(tar_right.as_str()));
+    assert_eq!(report["splits"].len(), 3);
+    assert_eq!(report["splits"][2]["name"].as_str(), Some("app"));
+
+    testcase_destroy(vec!["tests/work_split_merge_report", "tests/out_split_merge_report",
+                          "tests/report_split_merge_report"]);
+    Ok(())
+}
+
+#[test]
+fn test_merge_upto() -> Result<()> {
+    testcase_initial(vec!["tests/out_merge_upto_cmd"]);
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "merge",
+        "--upto", "os",
+        "-t", "tests/data/splits_base",
+        "-w", "tests/work_merge_upto_cmd",
+        "-o", "tests/out_merge_upto_cmd"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+
+    // the base image is named after the last merged split rather than the repo tag
+    assert!(Path::new("tests/out_merge_upto_cmd/os.tar").exists());
+    assert!(!Path::new("tests/out_merge_upto_cmd/hello-world_l5.tar").exists());
+
+    testcase_destroy(vec!["tests/work_merge_upto_cmd", "tests/out_merge_upto_cmd"]);
+    Ok(())
+}
+
+#[test]
+fn test_merge_sources() -> Result<()> {
+    testcase_initial(vec!["tests/target_merge_sources_cmd", "tests/out_merge_sources_cmd"]);
+    for name in ["lib.tar.gz", "app.tar.gz"] {
+        fs::copy(format!("tests/data/splits_base/{}", name),
+                 format!("tests/target_merge_sources_cmd/{}", name))?;
+    }
+    fs::write("tests/target_merge_sources_cmd/notes.txt", "not a split")?;
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "merge",
+        "-t", "tests/target_merge_sources_cmd",
+        "--split", "tests/data/splits_base/os.tar.gz",
+        "-w", "tests/work_merge_sources_cmd",
+        "-o", "tests/out_merge_sources_cmd",
+        "-r", "tests/out_merge_sources_cmd/merge.json"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+
+    let report = load_config("tests/out_merge_sources_cmd/merge.json")?;
+    assert_eq!(report["splits"].len(), 3);
+    assert_eq!(report["splits"][0]["path"].as_str(), Some("tests/data/splits_base/os.tar.gz"));
+    assert!(Path::new("tests/out_merge_sources_cmd/hello-world_l5.tar").exists());
+
+    testcase_destroy(vec!["tests/target_merge_sources_cmd", "tests/work_merge_sources_cmd",
+                          "tests/out_merge_sources_cmd"]);
+    Ok(())
+}
+
+#[test]
+fn test_merge_list() -> Result<()> {
+    testcase_initial(vec!["tests/out_merge_list_cmd"]);
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "merge",
+        "--list",
+        "-t", "tests/data/splits_base",
+        "-o", "tests/out_merge_list_cmd"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+
+    // chains are only listed rather than merged
+    assert!(fs::read_dir("tests/out_merge_list_cmd")?.next().is_none());
+
+    testcase_destroy(vec!["tests/out_merge_list_cmd"]);
+    Ok(())
+}
+
+#[test]
+fn test_split_signed() -> Result<()> {
+    testcase_initial(vec!["tests/key_split_signed_cmd", "tests/out_split_signed_cmd"]);
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "keygen",
+        "-o", "tests/key_split_signed_cmd/release.key"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+    assert!(Path::new("tests/key_split_signed_cmd/release.pub").is_file());
+
+    let args: Vec<String> = vec![
+        "target/release/layer_sword.exe",
+        "split",
+        "-n", "os,lib,app",
+        "-l", "1,3,1",
+        "-t", "tests/data/base.tar",
+        "-w", "tests/key_split_signed_cmd",
+        "-o", "tests/out_split_signed_cmd/splits",
+        "--sign-key", "tests/key_split_signed_cmd/release.key"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+    assert!(Path::new("tests/out_split_signed_cmd/splits/SIGNATURE.json").is_file());
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "verify",
+        "-t", "tests/out_split_signed_cmd/splits",
+        "--trust", "tests/key_split_signed_cmd/release.pub"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "merge",
+        "-t", "tests/out_split_signed_cmd/splits",
+        "-w", "tests/key_split_signed_cmd",
+        "-o", "tests/out_split_signed_cmd/merge",
+        "--trust", "tests/key_split_signed_cmd/release.pub"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+    let tar_hash = fetch_file_sha256("tests/out_split_signed_cmd/merge/hello-world_l5.tar")?;
+    let tar_right =
+        "a82e3d4bcf3194ec7841f6f1f2b4ce34d1107c23ef4e42d4e5073224858cc56b".to_string();
+    assert_eq!(tar_hash, tar_right);
+
+    testcase_destroy(vec!["tests/key_split_signed_cmd", "tests/out_split_signed_cmd"]);
+    Ok(())
+}
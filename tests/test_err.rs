@@ -1,8 +1,21 @@
 #[cfg(test)]
 mod common;
 
+use std::path::Path;
+use std::fs;
+use std::io::{Read, Write};
+
+use flate2::{Compression, GzBuilder};
+use flate2::read::GzDecoder;
+
 use layer_sword::client::cli_main;
-use layer_sword::errors::LayerSwordError;
+use layer_sword::stream::TarIndex;
+use layer_sword::split::publish_splits;
+use layer_sword::store::{store_splits, STORE_SPLITS_DIR, STORE_INDEX_DIR};
+use layer_sword::format::{SPLIT_SUMS_NAME, SPLIT_SIGNATURE_NAME, part_path};
+use layer_sword::sign::{generate_key, load_signing_key};
+use layer_sword::util::{fetch_split_digest, extract_split};
+use layer_sword::errors::{LayerSwordError, FileCheckError, TerminalError, CheckStage};
 
 use common::{testcase_initial, testcase_destroy};
 
@@ -178,4 +191,760 @@ fn test_split_bad_info() -> Result<()> {
 
     testcase_destroy(vec!["tests/work_split_bad_info", "tests/out_split_bad_info"]);
     Ok(())
-}
\ No newline at end of file
+}
+#[test]
+fn test_verify_missing_split() -> Result<()> {
+    testcase_initial(vec!["tests/work_verify_missing_split"]);
+    for name in ["os.tar.gz", "app.tar.gz"] {
+        fs::copy(format!("tests/data/splits_base/{}", name),
+                 format!("tests/work_verify_missing_split/{}", name))
+            .unwrap_or_default();
+    }
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "verify",
+        "-t", "tests/work_verify_missing_split"].iter().map(|s| s.to_string()).collect();
+    let result = cli_main(args);
+    assert!(result.is_err());
+    let error_chk = result.or_else(|e| match e {
+        LayerSwordError::FileCheckError(FileCheckError::VerifyFailedError { failed: 1, total: 2 }) => {
+            println!("{}", e);
+            Err(e)
+        }
+        _ => Ok(())
+    });
+    assert!(error_chk.is_err());
+
+    testcase_destroy(vec!["tests/work_verify_missing_split"]);
+    Ok(())
+}
+
+/// copy splits of base image with 'os' broken on gzip crc32 and 'lib' on inner tar sha256
+fn prepare_broken_splits(dir: &str) {
+    let mut os = fs::read("tests/data/splits_base/os.tar.gz").unwrap_or_default();
+    let crc_index = os.len() - 8;
+    os[crc_index] ^= 0xff;
+    fs::write(format!("{}/os.tar.gz", dir), os).unwrap_or_default();
+
+    let mut lib: Vec<u8> = Vec::new();
+    let lib_file = fs::File::open("tests/data/splits_base/lib.tar.gz").unwrap();
+    GzDecoder::new(lib_file).read_to_end(&mut lib).unwrap_or_default();
+    let lib_file = fs::File::create(format!("{}/lib.tar.gz", dir)).unwrap();
+    let mut enc = GzBuilder::new()
+        .comment("0".repeat(64))
+        .write(lib_file, Compression::default());
+    enc.write_all(&lib).unwrap_or_default();
+    enc.finish().unwrap();
+
+    fs::copy("tests/data/splits_base/app.tar.gz", format!("{}/app.tar.gz", dir))
+        .unwrap_or_default();
+}
+
+#[test]
+fn test_merge_broken_split() -> Result<()> {
+    testcase_initial(vec!["tests/target_merge_broken_split", "tests/work_merge_broken_split",
+                          "tests/out_merge_broken_split"]);
+    prepare_broken_splits("tests/target_merge_broken_split");
+    fs::remove_file("tests/target_merge_broken_split/os.tar.gz").unwrap_or_default();
+    fs::copy("tests/data/splits_base/os.tar.gz", "tests/target_merge_broken_split/os.tar.gz")
+        .unwrap_or_default();
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "merge",
+        "-t", "tests/target_merge_broken_split",
+        "-w", "tests/work_merge_broken_split",
+        "-o", "tests/out_merge_broken_split"].iter().map(|s| s.to_string()).collect();
+    let result = cli_main(args);
+    assert!(result.is_err());
+    let error_chk = result.or_else(|e| match e {
+        LayerSwordError::FileCheckError(FileCheckError::SplitCheckError {
+                                            stage: CheckStage::TarHash, ..
+                                        }) => {
+            println!("{}", e);
+            Err(e)
+        }
+        _ => Ok(())
+    });
+    assert!(error_chk.is_err());
+    // merged tar file streamed before the check is removed
+    let out_num = fs::read_dir("tests/out_merge_broken_split")
+        .map(|entries| entries.count())
+        .unwrap_or_default();
+    assert_eq!(out_num, 0);
+
+    testcase_destroy(vec!["tests/target_merge_broken_split", "tests/work_merge_broken_split",
+                          "tests/out_merge_broken_split"]);
+    Ok(())
+}
+
+#[test]
+fn test_merge_unknown_format() -> Result<()> {
+    testcase_initial(vec!["tests/target_merge_unknown_format", "tests/work_merge_unknown_format",
+                          "tests/out_merge_unknown_format"]);
+    for name in ["os.tar.gz", "lib.tar.gz", "app.tar.gz"] {
+        fs::copy(format!("tests/data/splits_base/{}", name),
+                 format!("tests/target_merge_unknown_format/{}", name))
+            .unwrap_or_default();
+    }
+    fs::write("tests/target_merge_unknown_format/notes.txt", "not a split").unwrap_or_default();
+
+    // files other than splits inside target directory are ignored, but not given by themselves
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "merge",
+        "-t", "tests/target_merge_unknown_format",
+        "--split", "tests/target_merge_unknown_format/notes.txt",
+        "-w", "tests/work_merge_unknown_format",
+        "-o", "tests/out_merge_unknown_format"].iter().map(|s| s.to_string()).collect();
+    let result = cli_main(args);
+    assert!(result.is_err());
+    let error_chk = result.or_else(|e| match e {
+        LayerSwordError::FileCheckError(FileCheckError::SplitFormatError { .. }) => {
+            println!("{}", e);
+            Err(e)
+        }
+        _ => Ok(())
+    });
+    assert!(error_chk.is_err());
+
+    testcase_destroy(vec!["tests/target_merge_unknown_format", "tests/work_merge_unknown_format",
+                          "tests/out_merge_unknown_format"]);
+    Ok(())
+}
+
+#[test]
+fn test_merge_continue_on_error() -> Result<()> {
+    testcase_initial(vec!["tests/target_merge_continue_on_error",
+                          "tests/work_merge_continue_on_error",
+                          "tests/out_merge_continue_on_error"]);
+    prepare_broken_splits("tests/target_merge_continue_on_error");
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "merge",
+        "--continue-on-error",
+        "-t", "tests/target_merge_continue_on_error",
+        "-w", "tests/work_merge_continue_on_error",
+        "-o", "tests/out_merge_continue_on_error"].iter().map(|s| s.to_string()).collect();
+    let result = cli_main(args);
+    assert!(result.is_err());
+    let error_chk = result.or_else(|e| match &e {
+        LayerSwordError::FileCheckError(FileCheckError::SplitsCheckError { failures }) => {
+            println!("{}", e);
+            let mut stages: Vec<String> = failures
+                .iter()
+                .filter_map(|f| match f {
+                    FileCheckError::SplitCheckError { stage, .. } => Some(stage.to_string()),
+                    _ => None
+                })
+                .collect();
+            stages.sort();
+            assert_eq!(stages, vec![format!("gzip crc32"), format!("inner tar sha256")]);
+            Err(e)
+        }
+        _ => Ok(())
+    });
+    assert!(error_chk.is_err());
+
+    testcase_destroy(vec!["tests/target_merge_continue_on_error",
+                          "tests/work_merge_continue_on_error",
+                          "tests/out_merge_continue_on_error"]);
+    Ok(())
+}
+
+#[test]
+fn test_extract_truncated_split() -> Result<()> {
+    testcase_initial(vec!["tests/target_extract_truncated_split", "tests/work_extract_truncated_split"]);
+    let target_path = Path::new("tests/target_extract_truncated_split");
+    let work_path = Path::new("tests/work_extract_truncated_split");
+    // os split is cut in half and compression method inside gzip header of lib split is broken
+    let os = fs::read("tests/data/splits_base/os.tar.gz")?;
+    fs::write(target_path.join("os.tar.gz"), &os[..os.len() / 2])?;
+    let mut lib = fs::read("tests/data/splits_base/lib.tar.gz")?;
+    lib[2] ^= 0xff;
+    fs::write(target_path.join("lib.tar.gz"), lib)?;
+
+    for (name, right_stage) in [("os.tar.gz", CheckStage::StreamEnd), ("lib.tar.gz", CheckStage::GzipHeader)] {
+        let result = extract_split(target_path.join(name), work_path.to_path_buf());
+        assert!(result.is_err());
+        let error_chk = result.or_else(|e| match e {
+            LayerSwordError::FileCheckError(FileCheckError::SplitCheckError { stage, .. })
+            if stage == right_stage => {
+                println!("{}", e);
+                Err(e)
+            }
+            _ => Ok(())
+        });
+        assert!(error_chk.is_err());
+    }
+
+    testcase_destroy(vec!["tests/target_extract_truncated_split", "tests/work_extract_truncated_split"]);
+    Ok(())
+}
+
+#[test]
+fn test_split_stream_bad_layer() -> Result<()> {
+    testcase_initial(vec!["tests/target_split_stream_bad_layer", "tests/out_split_stream_bad_layer"]);
+    // flip a byte inside a layer tar file which is only checked while streaming
+    let tar_path = "tests/target_split_stream_bad_layer/base.tar";
+    fs::copy("tests/data/base.tar", tar_path).unwrap_or_default();
+    let index = TarIndex::scan(tar_path)?;
+    let item = &index.item_map[
+        "81f23e23635f150b4daa84ed1b188c9d7b15d5eccf37437d23033c8aca5ce3cc/layer.tar"];
+    let mut data = fs::read(tar_path).unwrap_or_default();
+    data[item.offset as usize + 1024] ^= 0xff;
+    fs::write(tar_path, data).unwrap_or_default();
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "split",
+        "-t", tar_path,
+        "-w", "tests/work_split_stream_bad_layer",
+        "-o", "tests/out_split_stream_bad_layer",
+        "-n", "os,lib,app",
+        "-l", "1,3,1"].iter().map(|s| s.to_string()).collect();
+    let result = cli_main(args);
+    assert!(result.is_err());
+    let error_chk = result.or_else(|e| match e {
+        LayerSwordError::FileCheckError(FileCheckError::BadDockerFileError { .. }) => {
+            println!("{}", e);
+            Err(e)
+        }
+        _ => Ok(())
+    });
+    assert!(error_chk.is_err());
+    // splits streamed before the check are removed
+    let split_num = fs::read_dir("tests/out_split_stream_bad_layer")
+        .map(|entries| entries.count())
+        .unwrap_or_default();
+    assert_eq!(split_num, 0);
+
+    testcase_destroy(vec!["tests/target_split_stream_bad_layer", "tests/work_split_stream_bad_layer",
+                          "tests/out_split_stream_bad_layer"]);
+    Ok(())
+}
+
+#[test]
+fn test_merge_no_overwrite() -> Result<()> {
+    testcase_initial(vec!["tests/out_merge_no_overwrite"]);
+    fs::write("tests/out_merge_no_overwrite/hello-world_l5.tar", "existing").unwrap_or_default();
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "merge",
+        "--force",
+        "--no-overwrite",
+        "-t", "tests/data/splits_base",
+        "-w", "tests/work_merge_no_overwrite",
+        "-o", "tests/out_merge_no_overwrite"].iter().map(|s| s.to_string()).collect();
+    let result = cli_main(args);
+    assert!(result.is_err());
+    let error_chk = result.or_else(|e| match e {
+        LayerSwordError::TerminalError(TerminalError::ExistError { .. }) => {
+            println!("{}", e);
+            Err(e)
+        }
+        _ => Ok(())
+    });
+    assert!(error_chk.is_err());
+    // existing file is kept and nothing else is left
+    let existing = fs::read_to_string("tests/out_merge_no_overwrite/hello-world_l5.tar")
+        .unwrap_or_default();
+    assert_eq!(existing, "existing");
+    let out_num = fs::read_dir("tests/out_merge_no_overwrite")
+        .map(|entries| entries.count())
+        .unwrap_or_default();
+    assert_eq!(out_num, 1);
+
+    testcase_destroy(vec!["tests/work_merge_no_overwrite", "tests/out_merge_no_overwrite"]);
+    Ok(())
+}
+
+#[test]
+fn test_split_not_empty_output() -> Result<()> {
+    testcase_initial(vec!["tests/out_split_not_empty_output"]);
+    fs::write("tests/out_split_not_empty_output/keep.txt", "keep").unwrap_or_default();
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "split",
+        "-n", "os,lib,app",
+        "-l", "1,3,1",
+        "-w", "tests/work_split_not_empty_output",
+        "-o", "tests/out_split_not_empty_output",
+        "-t", "tests/data/base.tar"].iter().map(|s| s.to_string()).collect();
+    let result = cli_main(args);
+    assert!(result.is_err());
+    let error_chk = result.or_else(|e| match e {
+        LayerSwordError::TerminalError(TerminalError::NotEmptyError { .. }) => {
+            println!("{}", e);
+            Err(e)
+        }
+        _ => Ok(())
+    });
+    assert!(error_chk.is_err());
+    // existing directory is left untouched and working directory is never created
+    let out_num = fs::read_dir("tests/out_split_not_empty_output")
+        .map(|entries| entries.count())
+        .unwrap_or_default();
+    assert_eq!(out_num, 1);
+    assert!(!Path::new("tests/work_split_not_empty_output").exists());
+
+    testcase_destroy(vec!["tests/work_split_not_empty_output", "tests/out_split_not_empty_output"]);
+    Ok(())
+}
+
+#[test]
+fn test_publish_broken_split() -> Result<()> {
+    testcase_initial(vec!["tests/out_publish_broken_split"]);
+    let out_path = Path::new("tests/out_publish_broken_split");
+    let split_path = out_path.join("os.tar.gz");
+    let split_part_path = part_path(&split_path);
+    fs::copy("tests/data/splits_base/os.tar.gz", &split_part_path)?;
+
+    // sha256 of split tar is not the one written
+    let part_vec = vec![(split_part_path.clone(), split_path.clone(), "0".repeat(64))];
+    let result = publish_splits(&part_vec, out_path, None);
+    assert!(result.is_err());
+    let error_chk = result.or_else(|e| match e {
+        LayerSwordError::FileCheckError(FileCheckError::SplitCheckError { .. }) => {
+            println!("{}", e);
+            Err(e)
+        }
+        _ => Ok(())
+    });
+    assert!(error_chk.is_err());
+    // nothing is published and the part file is removed
+    assert!(!split_path.exists());
+    assert!(!split_part_path.exists());
+    assert!(!out_path.join(SPLIT_SUMS_NAME).exists());
+
+    testcase_destroy(vec!["tests/out_publish_broken_split"]);
+    Ok(())
+}
+
+#[test]
+fn test_publish_unsigned_split() -> Result<()> {
+    testcase_initial(vec!["tests/out_publish_unsigned_split", "tests/key_publish_unsigned_split"]);
+    let out_path = Path::new("tests/out_publish_unsigned_split");
+    let key_path = Path::new("tests/key_publish_unsigned_split/release.key");
+    generate_key(key_path)?;
+    let signing_key = load_signing_key(key_path)?;
+    let split_path = out_path.join("os.tar.gz");
+    let split_part_path = part_path(&split_path);
+    fs::copy("tests/data/splits_base/os.tar.gz", &split_part_path)?;
+    let split_digest = fetch_split_digest(&split_part_path).unwrap();
+
+    // signed manifest can not be written over a directory
+    fs::create_dir_all(out_path.join(SPLIT_SIGNATURE_NAME))?;
+    let part_vec = vec![(split_part_path.clone(), split_path.clone(), split_digest)];
+    let result = publish_splits(&part_vec, out_path, Some(&signing_key));
+    assert!(result.is_err());
+    // nothing looking finished is published without its signed manifest
+    assert!(!split_path.exists());
+    assert!(!split_part_path.exists());
+    assert!(!out_path.join(SPLIT_SUMS_NAME).exists());
+
+    testcase_destroy(vec!["tests/out_publish_unsigned_split", "tests/key_publish_unsigned_split"]);
+    Ok(())
+}
+
+#[test]
+fn test_store_broken_split() -> Result<()> {
+    testcase_initial(vec!["tests/out_store_broken_split", "tests/store_store_broken_split"]);
+    let out_path = Path::new("tests/out_store_broken_split");
+    let store_path = Path::new("tests/store_store_broken_split");
+    // sha256 recorded inside split is not the one of its split tar
+    let mut os: Vec<u8> = Vec::new();
+    let os_file = fs::File::open("tests/data/splits_base/os.tar.gz")?;
+    GzDecoder::new(os_file).read_to_end(&mut os)?;
+    let mut enc = GzBuilder::new()
+        .comment("0".repeat(64))
+        .write(fs::File::create(out_path.join("os.tar.gz"))?, Compression::default());
+    enc.write_all(&os)?;
+    enc.finish()?;
+
+    let result = store_splits(out_path, store_path);
+    assert!(result.is_err());
+    let error_chk = result.or_else(|e| match e {
+        LayerSwordError::FileCheckError(FileCheckError::SplitCheckError {
+                                            stage: CheckStage::TarHash, ..
+                                        }) => {
+            println!("{}", e);
+            Err(e)
+        }
+        _ => Ok(vec![])
+    });
+    assert!(error_chk.is_err());
+    // split is kept under output directory and nothing is written into store
+    assert!(out_path.join("os.tar.gz").is_file());
+    assert_eq!(fs::read_dir(store_path.join(STORE_SPLITS_DIR))?.count(), 0);
+    assert_eq!(fs::read_dir(store_path.join(STORE_INDEX_DIR))?.count(), 0);
+
+    testcase_destroy(vec!["tests/out_store_broken_split", "tests/store_store_broken_split"]);
+    Ok(())
+}
+
+#[test]
+fn test_split_large_layer() -> Result<()> {
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "split",
+        "--max-split-size", "1K",
+        "-t", "tests/data/base.tar",
+        "-w", "tests/work_split_large_layer",
+        "-o", "tests/out_split_large_layer"].iter().map(|s| s.to_string()).collect();
+    let result = cli_main(args);
+    assert!(result.is_err());
+    let error_chk = result.or_else(|e| match e {
+        LayerSwordError::TerminalError(TerminalError::BadArgError { .. }) => {
+            println!("{}", e);
+            Err(e)
+        }
+        _ => Ok(())
+    });
+    assert!(error_chk.is_err());
+    // nothing is created before splits are planned
+    assert!(!Path::new("tests/out_split_large_layer").exists());
+
+    testcase_destroy(vec!["tests/work_split_large_layer", "tests/out_split_large_layer"]);
+    Ok(())
+}
+
+#[test]
+fn test_split_reference_no_layer_left() -> Result<()> {
+    // splits of base.tar hold all of its layers, which leaves no layer above the boundary
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "split",
+        "--reference", "tests/data/splits_base",
+        "-t", "tests/data/base.tar",
+        "-w", "tests/work_split_reference_no_layer_left",
+        "-o", "tests/out_split_reference_no_layer_left"].iter().map(|s| s.to_string()).collect();
+    let result = cli_main(args);
+    assert!(result.is_err());
+    let error_chk = result.or_else(|e| match e {
+        LayerSwordError::TerminalError(TerminalError::BadArgError { .. }) => {
+            println!("{}", e);
+            Err(e)
+        }
+        _ => Ok(())
+    });
+    assert!(error_chk.is_err());
+    assert!(!Path::new("tests/out_split_reference_no_layer_left").exists());
+    // the top split extracted to read config is removed
+    assert!(fs::read_dir("tests/work_split_reference_no_layer_left")?.next().is_none());
+
+    testcase_destroy(vec!["tests/work_split_reference_no_layer_left",
+                          "tests/out_split_reference_no_layer_left"]);
+    Ok(())
+}
+
+#[test]
+fn test_split_no_label() -> Result<()> {
+    // base.tar has no 'LABEL layer_sword.split=<name>' marker inside history
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "split",
+        "--split-by-label",
+        "-t", "tests/data/base.tar",
+        "-w", "tests/work_split_no_label",
+        "-o", "tests/out_split_no_label"].iter().map(|s| s.to_string()).collect();
+    let result = cli_main(args);
+    assert!(result.is_err());
+    let error_chk = result.or_else(|e| match e {
+        LayerSwordError::TerminalError(TerminalError::BadArgError { .. }) => {
+            println!("{}", e);
+            Err(e)
+        }
+        _ => Ok(())
+    });
+    assert!(error_chk.is_err());
+    assert!(!Path::new("tests/out_split_no_label").exists());
+
+    testcase_destroy(vec!["tests/work_split_no_label", "tests/out_split_no_label"]);
+    Ok(())
+}
+
+#[test]
+fn test_split_config_bad_key() -> Result<()> {
+    testcase_initial(vec!["tests/work_split_config_bad_key"]);
+    let config_path = Path::new("tests/work_split_config_bad_key/config.json");
+    fs::write(config_path, r#"{"version": 1, "splits": [
+        {"name": "os", "layers": 1},
+        {"name": "app", "layers": "all"}]}"#)?;
+
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "split",
+        "-c", "tests/work_split_config_bad_key/config.json",
+        "-t", "tests/data/base.tar",
+        "-w", "tests/work_split_config_bad_key",
+        "-o", "tests/out_split_config_bad_key"].iter().map(|s| s.to_string()).collect();
+    let result = cli_main(args);
+    assert!(result.is_err());
+    let error_chk = result.or_else(|e| match e {
+        LayerSwordError::TerminalError(TerminalError::ConfigKeyError { ref key, .. })
+        if key == "splits[1].layers" => {
+            println!("{}", e);
+            Err(e)
+        }
+        _ => Ok(())
+    });
+    assert!(error_chk.is_err());
+    assert!(!Path::new("tests/out_split_config_bad_key").exists());
+
+    testcase_destroy(vec!["tests/work_split_config_bad_key"]);
+    Ok(())
+}
+
+#[test]
+fn test_split_bad_level() -> Result<()> {
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "split",
+        "-n", "os,lib,app",
+        "-l", "1,3,1",
+        "-v", "12",
+        "-f", "zst",
+        "-t", "tests/data/base.tar",
+        "-w", "tests/work_split_bad_level",
+        "-o", "tests/out_split_bad_level"].iter().map(|s| s.to_string()).collect();
+    let result = cli_main(args);
+    assert!(result.is_err());
+    let error_chk = result.or_else(|e| match e {
+        LayerSwordError::TerminalError(TerminalError::BadArgError { ref arg, ref msg })
+        if arg == "level" && msg.contains("zstd levels 1 to 19") => {
+            println!("{}", e);
+            Err(e)
+        }
+        _ => Ok(())
+    });
+    assert!(error_chk.is_err());
+    assert!(!Path::new("tests/out_split_bad_level").exists());
+
+    testcase_destroy(vec!["tests/work_split_bad_level", "tests/out_split_bad_level"]);
+    Ok(())
+}
+
+#[test]
+fn test_merge_upto_unknown() -> Result<()> {
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "merge",
+        "--upto", "runtime",
+        "-t", "tests/data/splits_base",
+        "-w", "tests/work_merge_upto_unknown",
+        "-o", "tests/out_merge_upto_unknown"].iter().map(|s| s.to_string()).collect();
+    let result = cli_main(args);
+    assert!(result.is_err());
+    let error_chk = result.or_else(|e| match e {
+        LayerSwordError::TerminalError(TerminalError::BadArgError { .. }) => {
+            println!("{}", e);
+            Err(e)
+        }
+        _ => Ok(())
+    });
+    assert!(error_chk.is_err());
+
+    testcase_destroy(vec!["tests/work_merge_upto_unknown", "tests/out_merge_upto_unknown"]);
+    Ok(())
+}
+
+#[test]
+fn test_merge_missing_top_split() -> Result<()> {
+    testcase_initial(vec!["tests/out_merge_missing_top_split"]);
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "split",
+        "-n", "os,lib,app",
+        "-l", "1,3,1",
+        "-t", "tests/data/base.tar",
+        "-w", "tests/work_merge_missing_top_split",
+        "-o", "tests/out_merge_missing_top_split/splits"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+    fs::remove_file("tests/out_merge_missing_top_split/splits/app.tar.gz")?;
+    fs::remove_file(Path::new("tests/out_merge_missing_top_split/splits").join(SPLIT_SUMS_NAME))?;
+
+    // without sums file, splits below it record the name of the missing one
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "merge",
+        "-t", "tests/out_merge_missing_top_split/splits",
+        "-w", "tests/work_merge_missing_top_split",
+        "-o", "tests/out_merge_missing_top_split/merge"].iter().map(|s| s.to_string()).collect();
+    let result = cli_main(args);
+    assert!(result.is_err());
+    let error_chk = result.or_else(|e| match e {
+        LayerSwordError::FileCheckError(FileCheckError::SplitMissingError { index: 2, count: 3, .. }) => {
+            println!("{}", e);
+            Err(e)
+        }
+        _ => Ok(())
+    });
+    assert!(error_chk.is_err());
+
+    testcase_destroy(vec!["tests/work_merge_missing_top_split", "tests/out_merge_missing_top_split"]);
+    Ok(())
+}
+
+#[test]
+fn test_merge_unrelated_images() -> Result<()> {
+    testcase_initial(vec!["tests/out_merge_unrelated_images"]);
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "split",
+        "-n", "os,app",
+        "-l", "1,-1",
+        "-t", "tests/data/base.tar",
+        "-w", "tests/work_merge_unrelated_images",
+        "-o", "tests/out_merge_unrelated_images/splits"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+    for name in ["os.tar.gz", "lib.tar.gz", "app.tar.gz"] {
+        fs::copy(format!("tests/data/splits_base/{}", name),
+                 format!("tests/out_merge_unrelated_images/splits/base-{}", name))?;
+    }
+    fs::remove_file(Path::new("tests/out_merge_unrelated_images/splits").join(SPLIT_SUMS_NAME))?;
+
+    // complete chains of different tar files are not merged together without a tag
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "merge",
+        "-t", "tests/out_merge_unrelated_images/splits",
+        "-w", "tests/work_merge_unrelated_images",
+        "-o", "tests/out_merge_unrelated_images/merge"].iter().map(|s| s.to_string()).collect();
+    let result = cli_main(args);
+    assert!(result.is_err());
+    let error_chk = result.or_else(|e| match e {
+        LayerSwordError::FileCheckError(FileCheckError::ImageSelectError { .. }) => {
+            println!("{}", e);
+            Err(e)
+        }
+        _ => Ok(())
+    });
+    assert!(error_chk.is_err());
+
+    testcase_destroy(vec!["tests/work_merge_unrelated_images", "tests/out_merge_unrelated_images"]);
+    Ok(())
+}
+
+#[test]
+fn test_merge_unmatched_sums() -> Result<()> {
+    testcase_initial(vec!["tests/out_merge_unmatched_sums"]);
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "split",
+        "-n", "os,lib,app",
+        "-l", "1,3,1",
+        "-t", "tests/data/base.tar",
+        "-w", "tests/work_merge_unmatched_sums",
+        "-o", "tests/out_merge_unmatched_sums/splits"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+    let split_path = Path::new("tests/out_merge_unmatched_sums/splits");
+    let is_sums_error = |result: Result<()>| match result {
+        Err(LayerSwordError::FileCheckError(FileCheckError::SplitSumsError { msg, .. })) => {
+            println!("{}", msg);
+            true
+        }
+        _ => false
+    };
+
+    // a split listed inside sums file but missing is refused by merge and verify
+    fs::rename(split_path.join("lib.tar.gz"), "tests/out_merge_unmatched_sums/lib.tar.gz")?;
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "merge",
+        "-t", "tests/out_merge_unmatched_sums/splits",
+        "-w", "tests/work_merge_unmatched_sums",
+        "-o", "tests/out_merge_unmatched_sums/merge"].iter().map(|s| s.to_string()).collect();
+    assert!(is_sums_error(cli_main(args)));
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "verify",
+        "-t", "tests/out_merge_unmatched_sums/splits"].iter().map(|s| s.to_string()).collect();
+    assert!(is_sums_error(cli_main(args)));
+
+    // so is a split not listed inside sums file
+    fs::rename("tests/out_merge_unmatched_sums/lib.tar.gz", split_path.join("lib.tar.gz"))?;
+    fs::copy("tests/data/splits_base/app.tar.gz", split_path.join("base-app.tar.gz"))?;
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "merge",
+        "-t", "tests/out_merge_unmatched_sums/splits",
+        "-w", "tests/work_merge_unmatched_sums",
+        "-o", "tests/out_merge_unmatched_sums/merge"].iter().map(|s| s.to_string()).collect();
+    assert!(is_sums_error(cli_main(args)));
+    assert!(!Path::new("tests/out_merge_unmatched_sums/merge").exists());
+
+    // and so is a split replaced by another one of the same name
+    fs::remove_file(split_path.join("base-app.tar.gz"))?;
+    fs::copy("tests/data/splits_base/app.tar.gz", split_path.join("app.tar.gz"))?;
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "verify",
+        "-t", "tests/out_merge_unmatched_sums/splits"].iter().map(|s| s.to_string()).collect();
+    assert!(is_sums_error(cli_main(args)));
+
+    testcase_destroy(vec!["tests/work_merge_unmatched_sums", "tests/out_merge_unmatched_sums"]);
+    Ok(())
+}
+
+#[test]
+fn test_merge_foreign_file() -> Result<()> {
+    testcase_initial(vec!["tests/target_merge_foreign_file"]);
+    for name in ["os.tar.gz", "lib.tar.gz", "app.tar.gz"] {
+        fs::copy(format!("tests/data/splits_base/{}", name),
+                 format!("tests/target_merge_foreign_file/{}", name))?;
+    }
+    // a tar.gz file without sha256 or split_config.json
+    let foreign_file = fs::File::create("tests/target_merge_foreign_file/foreign.tar.gz")?;
+    let mut builder = tar::Builder::new(GzBuilder::new().write(foreign_file, Compression::default()));
+    let mut header = tar::Header::new_gnu();
+    header.set_size(5);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, "hello.txt", "hello".as_bytes())?;
+    builder.into_inner()?.finish()?;
+
+    // it is skipped inside target directory
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "merge",
+        "-t", "tests/target_merge_foreign_file",
+        "-w", "tests/work_merge_foreign_file",
+        "-o", "tests/out_merge_foreign_file"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+    assert!(Path::new("tests/out_merge_foreign_file/hello-world_l5.tar").is_file());
+
+    // while it is refused with its path once given by itself
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "merge",
+        "-t", "tests/target_merge_foreign_file",
+        "--split", "tests/target_merge_foreign_file/foreign.tar.gz",
+        "-w", "tests/work_merge_foreign_file",
+        "-o", "tests/out_merge_foreign_file_split"].iter().map(|s| s.to_string()).collect();
+    let result = cli_main(args);
+    assert!(result.is_err());
+    let error_chk = result.or_else(|e| match e {
+        LayerSwordError::FileCheckError(FileCheckError::SplitContentError { ref path })
+        if path == Path::new("tests/target_merge_foreign_file/foreign.tar.gz") => {
+            println!("{}", e);
+            Err(e)
+        }
+        _ => Ok(())
+    });
+    assert!(error_chk.is_err());
+
+    testcase_destroy(vec!["tests/target_merge_foreign_file", "tests/work_merge_foreign_file",
+                          "tests/out_merge_foreign_file", "tests/out_merge_foreign_file_split"]);
+    Ok(())
+}
+
+#[test]
+fn test_merge_untrusted_splits() -> Result<()> {
+    testcase_initial(vec!["tests/out_merge_untrusted_splits"]);
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "keygen",
+        "-o", "tests/out_merge_untrusted_splits/release.key"].iter().map(|s| s.to_string()).collect();
+    cli_main(args)?;
+
+    // splits without a signed manifest are refused
+    let args: Vec<String> = ["target/release/layer_sword.exe",
+        "merge",
+        "-t", "tests/data/splits_base",
+        "-w", "tests/work_merge_untrusted_splits",
+        "-o", "tests/out_merge_untrusted_splits/merge",
+        "--trust", "tests/out_merge_untrusted_splits/release.pub"].iter().map(|s| s.to_string()).collect();
+    let result = cli_main(args);
+    assert!(result.is_err());
+    let error_chk = result.or_else(|e| match e {
+        LayerSwordError::FileCheckError(FileCheckError::SignatureError { .. }) => {
+            println!("{}", e);
+            Err(e)
+        }
+        _ => Ok(())
+    });
+    assert!(error_chk.is_err());
+
+    testcase_destroy(vec!["tests/work_merge_untrusted_splits", "tests/out_merge_untrusted_splits"]);
+    Ok(())
+}
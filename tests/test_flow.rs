@@ -1,16 +1,27 @@
 #[cfg(test)]
 mod common;
 
+use std::fs;
 use std::path::Path;
 use std::collections::HashMap;
 
-use layer_sword::util::{init_path, extract_tar, fetch_file_sha256};
+use layer_sword::util::{init_path, extract_tar, fetch_file_sha256, fetch_split_config,
+                        fetch_string_sha256};
+use layer_sword::dominator::SplitMeta;
 use layer_sword::dominator::base::BaseDominator;
+use layer_sword::dominator::oci::OciDominator;
 use layer_sword::inspector::base::BaseInspector;
-use layer_sword::errors::LayerSwordError;
+use layer_sword::inspector::oci::OciInspector;
+use layer_sword::errors::{LayerSwordError, FileCheckError};
 use layer_sword::inspector::Inspect;
 use layer_sword::split::Split;
-use layer_sword::merge::Merge;
+use layer_sword::merge::{Merge, MergeOptions};
+use layer_sword::verify::{verify_splits, verify_signed};
+use layer_sword::sign::{generate_key, load_trusted_key};
+use layer_sword::format::{SplitFormat, SPLIT_SUMS_NAME, SPLIT_SIGNATURE_NAME};
+use layer_sword::plan::{SplitSpec, SplitStrategy, HistoryRule};
+use layer_sword::job::{SplitJob, MergeJob};
+use layer_sword::config::SplitConfig;
 
 use common::{testcase_initial, testcase_destroy};
 
@@ -23,12 +34,17 @@ fn test_init_path() -> Result<()> {
     let out_path = Path::new("tests/out_init_path");
     testcase_initial(vec![work_path, out_path]);
 
-    init_path(work_path, out_path);
+    init_path(work_path, out_path)?;
     let split_path = Path::new("tests/work_init_path/split");
     let merge_path = Path::new("tests/work_init_path/merge");
     assert_eq!(split_path.exists(), true);
     assert_eq!(merge_path.exists(), true);
 
+    // a non-empty working directory is refused rather than removed
+    let result = init_path(work_path, out_path);
+    assert!(result.is_err());
+    assert!(split_path.exists());
+
     testcase_destroy(vec![work_path, out_path]);
     Ok(())
 }
@@ -41,9 +57,9 @@ fn test_inspect() -> Result<()> {
     let out_path = Path::new("tests/out_inspect");
     testcase_initial(vec![work_path, out_path]);
 
-    init_path(work_path, out_path);
+    init_path(work_path, out_path)?;
     let merge_path = Path::new("tests/work_inspect/merge");
-    extract_tar(tar_path, merge_path);
+    extract_tar(tar_path, merge_path)?;
 
     let inspector = BaseInspector {};
     inspector.inspect(merge_path)?;
@@ -52,6 +68,36 @@ fn test_inspect() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_describe_layers() -> Result<()> {
+    log::info!("Test for 'describe_layers' function.");
+    let tar_path = Path::new("tests/data/base.tar");
+    let work_path = Path::new("tests/work_describe_layers");
+    let out_path = Path::new("tests/out_describe_layers");
+    testcase_initial(vec![work_path, out_path]);
+
+    init_path(work_path, out_path)?;
+    let merge_path = Path::new("tests/work_describe_layers/merge");
+    extract_tar(tar_path, merge_path)?;
+
+    let inspector = BaseInspector {};
+    let image_vec = inspector.inspect_all(merge_path)?;
+    let layer_vec = inspector.describe_layers(&image_vec[0])?;
+    assert_eq!(layer_vec.len(), 5);
+    assert_eq!(layer_vec[0].position, 0);
+    assert_eq!(layer_vec[0].dir_hash,
+               format!("81f23e23635f150b4daa84ed1b188c9d7b15d5eccf37437d23033c8aca5ce3cc"));
+    assert_eq!(layer_vec[0].diff_id,
+               format!("sha256:9c27e219663c25e0f28493790cc0b88bc973ba3b1686355f221c38a36978ac63"));
+    assert_eq!(layer_vec[0].size, 15360);
+    // history of empty layer is skipped
+    assert_eq!(layer_vec[1].created_by,
+               format!("/bin/sh -c #(nop) COPY file:7ed5f0d1c25b25268b1283b520dbb14d5d2f46afc19724ea9881d2f9c0f56752 in / "));
+
+    testcase_destroy(vec![work_path, out_path]);
+    Ok(())
+}
+
 #[test]
 fn test_split_layer() -> Result<()> {
     log::info!("Test for basic split procedure.");
@@ -60,7 +106,7 @@ fn test_split_layer() -> Result<()> {
     let work_path = Path::new("tests/work_split_layer");
     testcase_initial(vec![work_path, out_path]);
 
-    init_path(work_path, out_path);
+    init_path(work_path, out_path)?;
     let mut split_names: Vec<String> = Vec::new();
     split_names.push(format!("os"));
     split_names.push(format!("lib"));
@@ -83,21 +129,21 @@ fn test_split_layer() -> Result<()> {
         compress_level)?;
 
     let os_path = Path::new("tests/out_split_layer/os.tar.gz");
-    let os_hash = fetch_file_sha256(os_path);
+    let os_hash = fetch_file_sha256(os_path)?;
     let os_right =
-        format!("78847ae9c6eef8cd1e84fd76d244bcc96ce45f60b6166a0a0a16ff8e858c8da4");
+        "76c6dcc7eeae8e9fb05604920e8b59b3546531095162e5a5afaf6437b7510256".to_string();
     assert_eq!(os_hash, os_right);
 
     let lib_path = Path::new("tests/out_split_layer/lib.tar.gz");
-    let lib_hash = fetch_file_sha256(lib_path);
+    let lib_hash = fetch_file_sha256(lib_path)?;
     let lib_right =
-        format!("87134c9d4507bfe21be863ecf4ff90a0392bd08ee6a4ad803f8b9c81c1e0318f");
+        "15bb975426312b157e84230641223821208f3bda460ababa6ecbf3f7b5b30b69".to_string();
     assert_eq!(lib_hash, lib_right);
 
     let app_path = Path::new("tests/out_split_layer/app.tar.gz");
-    let app_hash = fetch_file_sha256(app_path);
+    let app_hash = fetch_file_sha256(app_path)?;
     let app_right =
-        format!("6f254b36aca46cd037ca455f0843efba982e7ed338d88c04106096a2f3afd6cc");
+        "ed8070d3c35fd0851282f5e3cc0688c8103f65647ec7561625ccdb83dd629e75".to_string();
     assert_eq!(app_hash, app_right);
 
     testcase_destroy(vec![work_path, out_path]);
@@ -113,7 +159,7 @@ fn test_deduction() -> Result<()> {
     let work_path = Path::new("tests/work_deduction");
     testcase_initial(vec![work_path, out_path]);
 
-    init_path(work_path, out_path);
+    init_path(work_path, out_path)?;
     let mut split_names: Vec<String> = Vec::new();
     split_names.push(format!("os"));
     split_names.push(format!("lib"));
@@ -136,21 +182,21 @@ fn test_deduction() -> Result<()> {
         compress_level)?;
 
     let os_path = Path::new("tests/out_deduction/os.tar.gz");
-    let os_hash = fetch_file_sha256(os_path);
+    let os_hash = fetch_file_sha256(os_path)?;
     let os_right =
-        format!("78847ae9c6eef8cd1e84fd76d244bcc96ce45f60b6166a0a0a16ff8e858c8da4");
+        "76c6dcc7eeae8e9fb05604920e8b59b3546531095162e5a5afaf6437b7510256".to_string();
     assert_eq!(os_hash, os_right);
 
     let lib_path = Path::new("tests/out_deduction/lib.tar.gz");
-    let lib_hash = fetch_file_sha256(lib_path);
+    let lib_hash = fetch_file_sha256(lib_path)?;
     let lib_right =
-        format!("87134c9d4507bfe21be863ecf4ff90a0392bd08ee6a4ad803f8b9c81c1e0318f");
+        "15bb975426312b157e84230641223821208f3bda460ababa6ecbf3f7b5b30b69".to_string();
     assert_eq!(lib_hash, lib_right);
 
     let app_path = Path::new("tests/out_deduction/app.tar.gz");
-    let app_hash = fetch_file_sha256(app_path);
+    let app_hash = fetch_file_sha256(app_path)?;
     let app_right =
-        format!("6f254b36aca46cd037ca455f0843efba982e7ed338d88c04106096a2f3afd6cc");
+        "ed8070d3c35fd0851282f5e3cc0688c8103f65647ec7561625ccdb83dd629e75".to_string();
     assert_eq!(app_hash, app_right);
 
     testcase_destroy(vec![work_path, out_path]);
@@ -165,7 +211,7 @@ fn test_split_four_layer() -> Result<()> {
     let work_path = Path::new("tests/work_split_four_layer");
     testcase_initial(vec![work_path, out_path]);
 
-    init_path(work_path, out_path);
+    init_path(work_path, out_path)?;
     let mut split_names: Vec<String> = Vec::new();
     split_names.push(format!("os"));
     split_names.push(format!("sys"));
@@ -190,27 +236,27 @@ fn test_split_four_layer() -> Result<()> {
         compress_level)?;
 
     let os_path = Path::new("tests/out_split_four_layer/os.tar.gz");
-    let os_hash = fetch_file_sha256(os_path);
+    let os_hash = fetch_file_sha256(os_path)?;
     let os_right =
-        format!("78847ae9c6eef8cd1e84fd76d244bcc96ce45f60b6166a0a0a16ff8e858c8da4");
+        "57299480959f97a151adfae434f458aa93ff72ca491f8da242be6d85e04fe9fc".to_string();
     assert_eq!(os_hash, os_right);
 
     let sys_path = Path::new("tests/out_split_four_layer/sys.tar.gz");
-    let sys_hash = fetch_file_sha256(sys_path);
+    let sys_hash = fetch_file_sha256(sys_path)?;
     let sys_right =
-        format!("062f2165910f5699aa7509fabed901c494d503c413491241b703ad51c2f00dd4");
+        "08dd4b7bdcdbcb0bd683a7ceeedf24107e5d8078e39b6d68ee903a845d960f59".to_string();
     assert_eq!(sys_hash, sys_right);
 
     let lib_path = Path::new("tests/out_split_four_layer/lib.tar.gz");
-    let lib_hash = fetch_file_sha256(lib_path);
+    let lib_hash = fetch_file_sha256(lib_path)?;
     let lib_right =
-        format!("2b19936c3c03947fa115b0c74321e8629c6f5b1dcd189e445384ae0ad72903e3");
+        "14294f5aa80f408b7183da1cef5626b3be196ff0f141ad100971f2088f287bb5".to_string();
     assert_eq!(lib_hash, lib_right);
 
     let app_path = Path::new("tests/out_split_four_layer/app.tar.gz");
-    let app_hash = fetch_file_sha256(app_path);
+    let app_hash = fetch_file_sha256(app_path)?;
     let app_right =
-        format!("8e654b72a036bbe9c4557f35c45f605830bff5249c9e36fbef5d61542de9605d");
+        "9992e2dcf466c0a3be881bb202c46feff84984cb7eb9d4f2f54725d345716431".to_string();
     assert_eq!(app_hash, app_right);
 
     testcase_destroy(vec![work_path, out_path]);
@@ -225,7 +271,7 @@ fn test_split_two_layer() -> Result<()> {
     let work_path = Path::new("tests/work_split_two_layer");
     testcase_initial(vec![work_path, out_path]);
 
-    init_path(work_path, out_path);
+    init_path(work_path, out_path)?;
     let mut split_names: Vec<String> = Vec::new();
     split_names.push(format!("os"));
     split_names.push(format!("lib"));
@@ -246,15 +292,15 @@ fn test_split_two_layer() -> Result<()> {
         compress_level)?;
 
     let os_path = Path::new("tests/out_split_two_layer/os.tar.gz");
-    let os_hash = fetch_file_sha256(os_path);
+    let os_hash = fetch_file_sha256(os_path)?;
     let os_right =
-        format!("78847ae9c6eef8cd1e84fd76d244bcc96ce45f60b6166a0a0a16ff8e858c8da4");
+        "10f2920eb4b87446809a72df3dfcdfe28956b0633466d828bcf7cf8534579929".to_string();
     assert_eq!(os_hash, os_right);
 
     let lib_path = Path::new("tests/out_split_two_layer/lib.tar.gz");
-    let lib_hash = fetch_file_sha256(lib_path);
+    let lib_hash = fetch_file_sha256(lib_path)?;
     let lib_right =
-        format!("ec151b9edfdd803fe50365e3415248ef6648b89aed94933a26e8f51fdd9e1569");
+        "df91efa38fa9459040ea58904cf8042b21eb6bffb6677cacfc9ee05de0c6823e".to_string();
     assert_eq!(lib_hash, lib_right);
 
     testcase_destroy(vec![work_path, out_path]);
@@ -269,13 +315,13 @@ fn test_merge() -> Result<()> {
     let out_path = Path::new("tests/out_merge");
     testcase_initial(vec![work_path, out_path]);
 
-    init_path(work_path, out_path);
+    init_path(work_path, out_path)?;
     let inspector = BaseInspector {};
     let dominator = BaseDominator {};
     dominator.merge_layer(Box::new(inspector), target_path, work_path, out_path)?;
 
-    let tar_path = Path::new("tests/out_merge/merge.tar");
-    let tar_hash = fetch_file_sha256(tar_path);
+    let tar_path = Path::new("tests/out_merge/hello-world_l5.tar");
+    let tar_hash = fetch_file_sha256(tar_path)?;
     let tar_right =
         format!("a82e3d4bcf3194ec7841f6f1f2b4ce34d1107c23ef4e42d4e5073224858cc56b");
     assert_eq!(tar_hash, tar_right);
@@ -284,6 +330,24 @@ fn test_merge() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_verify_splits() -> Result<()> {
+    log::info!("Test for verify procedure without merge.");
+    let target_path = Path::new("tests/data/splits_base");
+
+    let result_vec = verify_splits(target_path)?;
+    assert_eq!(result_vec.len(), 3);
+    for (i, split) in result_vec.iter().enumerate() {
+        assert_eq!(split.index, Some(i));
+        assert!(split.passed());
+    }
+    let os_right =
+        "2c0c0a7af5c60be37e47050317f0bbfc85cdb479577a9642f2022ac09a58a63c".to_string();
+    assert_eq!(result_vec[0].digest, os_right);
+    assert_eq!(result_vec[1].parent_id, os_right);
+    Ok(())
+}
+
 #[test]
 fn test_compress_best() -> Result<()> {
     log::info!("Test for compress at best level.");
@@ -292,7 +356,7 @@ fn test_compress_best() -> Result<()> {
     let work_path = Path::new("tests/work_compress_best");
     testcase_initial(vec![work_path, out_path]);
 
-    init_path(work_path, out_path);
+    init_path(work_path, out_path)?;
     let mut split_names: Vec<String> = Vec::new();
     split_names.push(format!("os"));
     split_names.push(format!("lib"));
@@ -315,23 +379,1069 @@ fn test_compress_best() -> Result<()> {
         compress_level)?;
 
     let os_path = Path::new("tests/out_compress_best/os.tar.gz");
-    let os_hash = fetch_file_sha256(os_path);
+    let os_hash = fetch_file_sha256(os_path)?;
     let os_right =
-        format!("4d8e2ffdb06608da91278e5bfa3aff87bd69c7e88f562bfa62f1e2274ebfa43a");
+        "828c0e1a85aa52f86773e64699020ce24be431325d1f9cf995273e1773111547".to_string();
     assert_eq!(os_hash, os_right);
 
     let lib_path = Path::new("tests/out_compress_best/lib.tar.gz");
-    let lib_hash = fetch_file_sha256(lib_path);
+    let lib_hash = fetch_file_sha256(lib_path)?;
     let lib_right =
-        format!("37ebf36672232ee91a03e1ec0f33bd5f3063f380cf9e50d048d1df3e582e6057");
+        "55dabb7ae6aa21f3bef840db83fa1e384ed07ece170fc7bb68f6704cac60d093".to_string();
     assert_eq!(lib_hash, lib_right);
 
     let app_path = Path::new("tests/out_compress_best/app.tar.gz");
-    let app_hash = fetch_file_sha256(app_path);
+    let app_hash = fetch_file_sha256(app_path)?;
     let app_right =
-        format!("25b75bc8a75d4a2be082d9e5a2c6dd1e86641fc22e39158aedcf911d62c3296e");
+        "6f059267901f661253801e08b437cba755fee5f500bcc2f4d2f646972936d582".to_string();
     assert_eq!(app_hash, app_right);
 
     testcase_destroy(vec![work_path, out_path]);
     Ok(())
-}
\ No newline at end of file
+}
+#[test]
+fn test_oci_inspect() -> Result<()> {
+    log::info!("Test for 'extract_tar' and 'inspect' functions on OCI layout.");
+    let tar_path = Path::new("tests/data/oci.tar");
+    let work_path = Path::new("tests/work_oci_inspect");
+    let out_path = Path::new("tests/out_oci_inspect");
+    testcase_initial(vec![work_path, out_path]);
+
+    init_path(work_path, out_path)?;
+    let merge_path = Path::new("tests/work_oci_inspect/merge");
+    extract_tar(tar_path, merge_path)?;
+
+    let inspector = OciInspector {};
+    let (_, layer_dir_vec) = inspector.inspect(merge_path)?;
+    assert_eq!(layer_dir_vec.len(), 3);
+
+    testcase_destroy(vec![work_path, out_path]);
+    Ok(())
+}
+
+#[test]
+fn test_oci_split_merge() -> Result<()> {
+    log::info!("Test for split and merge procedure on OCI layout.");
+    let tar_path = Path::new("tests/data/oci.tar");
+    let work_path = Path::new("tests/work_oci_split_merge");
+    let split_out_path = Path::new("tests/out_oci_split_merge_splits");
+    let merge_out_path = Path::new("tests/out_oci_split_merge");
+    testcase_initial(vec![work_path, split_out_path, merge_out_path]);
+
+    init_path(work_path, split_out_path)?;
+    let split_names: Vec<String> = vec!["os".to_string(), "lib".to_string(), "app".to_string()];
+    let mut split_map: HashMap<String, i16> = HashMap::new();
+    split_map.insert("os".to_string(), 1);
+    split_map.insert("lib".to_string(), -1);
+    split_map.insert("app".to_string(), 1);
+    let compress_level: u8 = 6;
+
+    let dominator = OciDominator {};
+    dominator.split_layer(
+        Box::new(OciInspector {}),
+        tar_path,
+        split_names,
+        split_map,
+        work_path,
+        split_out_path,
+        compress_level)?;
+
+    init_path(work_path, merge_out_path)?;
+    dominator.merge_layer(Box::new(OciInspector {}), split_out_path, work_path, merge_out_path)?;
+
+    // merged OCI layout is byte-identical to the source one
+    let merge_path = Path::new("tests/out_oci_split_merge/sword_1.0.tar");
+    assert_eq!(fetch_file_sha256(merge_path)?, fetch_file_sha256(tar_path)?);
+
+    testcase_destroy(vec![work_path, split_out_path, merge_out_path]);
+    Ok(())
+}
+
+/// write an OCI layout like the one saved by docker on containerd image store, where
+/// index.json points to a nested image index listing a manifest of a platform not saved
+/// and an attestation manifest, with a blob referenced by nothing
+fn write_nested_oci_tar(tar_path: &Path) -> Result<()> {
+    let source_index = extract_index_json("tests/data/oci.tar")?;
+    let mut manifest = source_index["manifests"][0].clone();
+    manifest.remove("annotations");
+    let nested_index = json::object! {
+        schemaVersion: 2,
+        mediaType: "application/vnd.oci.image.index.v1+json",
+        manifests: [
+            manifest,
+            {
+                mediaType: "application/vnd.oci.image.manifest.v1+json",
+                digest: format!("sha256:{}", "1".repeat(64)),
+                size: 700,
+                platform: { architecture: "arm64", os: "linux" }
+            },
+            {
+                mediaType: "application/vnd.oci.image.manifest.v1+json",
+                digest: format!("sha256:{}", "2".repeat(64)),
+                size: 700,
+                annotations: { "vnd.docker.reference.type": "attestation-manifest" }
+            }
+        ]
+    };
+    let nested_data = json::stringify(nested_index);
+    let nested_hex = fetch_string_sha256(&nested_data);
+    let stray_data = "referenced by nothing".to_string();
+    let stray_hex = fetch_string_sha256(&stray_data);
+    let mut index = json::object! {
+        schemaVersion: 2,
+        mediaType: "application/vnd.oci.image.index.v1+json",
+        manifests: []
+    };
+    for tag in ["sword:1.0", "sword:latest"] {
+        index["manifests"].push(json::object! {
+            mediaType: "application/vnd.oci.image.index.v1+json",
+            digest: format!("sha256:{}", nested_hex),
+            size: nested_data.len(),
+            annotations: {
+                "io.containerd.image.name": format!("docker.io/library/{}", tag),
+                "org.opencontainers.image.ref.name": tag.rsplit(':').next().unwrap_or_default()
+            }
+        })?;
+    }
+
+    let mut builder = tar::Builder::new(fs::File::create(tar_path)?);
+    let mut archive = tar::Archive::new(fs::File::open("tests/data/oci.tar")?);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.as_ref() != Path::new("index.json") {
+            let mut header = entry.header().clone();
+            let path = entry.path()?.into_owned();
+            builder.append_data(&mut header, path, &mut entry)?;
+        }
+    }
+    for (name, data) in [(format!("blobs/sha256/{}", nested_hex), nested_data),
+                         (format!("blobs/sha256/{}", stray_hex), stray_data),
+                         ("index.json".to_string(), json::stringify(index))] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, name, data.as_bytes())?;
+    }
+    builder.finish()?;
+    Ok(())
+}
+
+/// read index.json of an OCI layout tar file
+fn extract_index_json(tar_path: &str) -> Result<json::JsonValue> {
+    let mut archive = tar::Archive::new(fs::File::open(tar_path)?);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.as_ref() == Path::new("index.json") {
+            let mut text = String::new();
+            std::io::Read::read_to_string(&mut entry, &mut text)?;
+            return Ok(json::parse(&text)?);
+        }
+    }
+    Err(FileCheckError::BadOciFileError { msg: "no index.json".to_string() }.into())
+}
+
+#[test]
+fn test_oci_nested_index() -> Result<()> {
+    log::info!("Test for split and merge procedure on OCI layout with a nested image index.");
+    let target_path = Path::new("tests/target_oci_nested_index");
+    let split_out_path = Path::new("tests/out_oci_nested_index_splits");
+    let merge_out_path = Path::new("tests/out_oci_nested_index");
+    testcase_initial(vec![target_path, split_out_path, merge_out_path]);
+    let tar_path = target_path.join("nested.tar");
+    write_nested_oci_tar(&tar_path)?;
+
+    // only blobs referenced by the image manifest are layers
+    let extract_path = target_path.join("extract");
+    extract_tar(tar_path.as_path(), extract_path.as_path())?;
+    let image_vec = OciInspector {}.inspect_all(&extract_path)?;
+    assert_eq!(image_vec.len(), 1);
+    assert_eq!(image_vec[0].layer_dir_vec.len(), 3);
+    assert_eq!(image_vec[0].repo_tags,
+               vec![format!("docker.io/library/sword:1.0"), format!("docker.io/library/sword:latest")]);
+
+    let split_report = SplitJob::new(&tar_path)
+        .split("os", SplitSpec::Count(1))
+        .split("lib", SplitSpec::Rest)
+        .split("app", SplitSpec::Count(1))
+        .out_dir(split_out_path)
+        .run()?;
+    assert_eq!(split_report.splits.len(), 3);
+    let merge_report = MergeJob::new(split_out_path)
+        .out_dir(merge_out_path)
+        .run()?;
+    // merged OCI layout is byte-identical to the source one
+    assert_eq!(merge_report.digest, fetch_file_sha256(&tar_path)?);
+
+    testcase_destroy(vec![target_path, split_out_path, merge_out_path]);
+    Ok(())
+}
+
+#[test]
+fn test_multi_split_merge() -> Result<()> {
+    log::info!("Test for split and merge procedure on tar file of several images.");
+    let tar_path = Path::new("tests/data/multi.tar");
+    let work_path = Path::new("tests/work_multi_split_merge");
+    let split_out_path = Path::new("tests/out_multi_split_merge_splits");
+    let merge_out_path = Path::new("tests/out_multi_split_merge");
+    let check_path = Path::new("tests/work_multi_split_merge_check");
+    testcase_initial(vec![work_path, split_out_path, merge_out_path, check_path]);
+
+    init_path(work_path, split_out_path)?;
+    let split_names: Vec<String> = vec!["os".to_string(), "app".to_string()];
+    let mut split_map: HashMap<String, i16> = HashMap::new();
+    split_map.insert("os".to_string(), 2);
+    split_map.insert("app".to_string(), -1);
+    let compress_level: u8 = 6;
+
+    let dominator = BaseDominator {};
+    dominator.split_layer(
+        Box::new(BaseInspector {}),
+        tar_path,
+        split_names,
+        split_map,
+        work_path,
+        split_out_path,
+        compress_level)?;
+    // layers shared by both images are only kept once
+    assert!(Path::new("tests/out_multi_split_merge_splits/os.tar.gz").exists());
+    assert!(Path::new("tests/out_multi_split_merge_splits/app-0.tar.gz").exists());
+    assert!(Path::new("tests/out_multi_split_merge_splits/app-1.tar.gz").exists());
+
+    init_path(work_path, merge_out_path)?;
+    dominator.merge_layer(Box::new(BaseInspector {}), split_out_path, work_path, merge_out_path)?;
+    let merge_path = Path::new("tests/out_multi_split_merge/merge.tar");
+    extract_tar(merge_path, check_path)?;
+    let image_vec = BaseInspector {}.inspect_all(check_path)?;
+    assert_eq!(image_vec.len(), 2);
+    assert_eq!(image_vec[0].repo_tags, vec![format!("hello-world:l5")]);
+    assert_eq!(image_vec[0].layer_dir_vec.len(), 5);
+    assert_eq!(image_vec[1].repo_tags, vec![format!("hello-world:l3")]);
+    assert_eq!(image_vec[1].layer_dir_vec.len(), 3);
+    testcase_destroy(vec![check_path, merge_out_path]);
+
+    init_path(work_path, merge_out_path)?;
+    let options = MergeOptions {
+        image: Some("hello-world:l3".to_string()),
+        ..Default::default()
+    };
+    let output = dominator.merge_image_layer(Box::new(BaseInspector {}), split_out_path,
+                                             work_path, merge_out_path, &options)?;
+    // archive of only one image is named after its repo tag
+    assert_eq!(output.path, merge_out_path.join("hello-world_l3.tar"));
+    extract_tar(output.path.as_path(), check_path)?;
+    let (_, layer_dir_vec) = BaseInspector {}.inspect(check_path)?;
+    assert_eq!(layer_dir_vec.len(), 3);
+
+    testcase_destroy(vec![work_path, split_out_path, merge_out_path, check_path]);
+    Ok(())
+}
+
+#[test]
+fn test_split_merge_job() -> Result<()> {
+    log::info!("Test for split and merge procedure built by jobs.");
+    let tar_path = Path::new("tests/data/base.tar");
+    let split_out_path = Path::new("tests/out_split_job");
+    let merge_out_path = Path::new("tests/out_merge_job");
+    testcase_initial(vec![split_out_path, merge_out_path]);
+
+    let split_report = SplitJob::new(tar_path)
+        .split("os", SplitSpec::Count(1))
+        .split("lib", SplitSpec::Rest)
+        .split("app", SplitSpec::Count(1))
+        .out_dir(split_out_path)
+        .run()?;
+    let names: Vec<&str> = split_report.splits.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, vec!["os", "lib", "app"]);
+    let layer_nums: Vec<usize> = split_report.splits.iter().map(|s| s.dir_hashes.len()).collect();
+    assert_eq!(layer_nums, vec![1, 3, 1]);
+    let os_hash = fetch_file_sha256(&split_report.splits[0].path)?;
+    let os_right =
+        "76c6dcc7eeae8e9fb05604920e8b59b3546531095162e5a5afaf6437b7510256".to_string();
+    assert_eq!(os_hash, os_right);
+    let verify_vec = verify_splits(split_out_path)?;
+    for (split, verify) in split_report.splits.iter().zip(verify_vec.iter()) {
+        assert_eq!(split.digest, verify.digest);
+    }
+
+    let merge_report = MergeJob::new(split_out_path)
+        .out_dir(merge_out_path)
+        .run()?;
+    assert_eq!(merge_report.splits.len(), 3);
+    assert_eq!(merge_report.digest, fetch_file_sha256(&merge_report.path)?);
+    let tar_right =
+        "a82e3d4bcf3194ec7841f6f1f2b4ce34d1107c23ef4e42d4e5073224858cc56b".to_string();
+    assert_eq!(merge_report.digest, tar_right);
+
+    testcase_destroy(vec![split_out_path, merge_out_path]);
+    Ok(())
+}
+
+#[test]
+fn test_split_merge_formats() -> Result<()> {
+    log::info!("Test for split and merge procedure with zstd, xz and uncompressed splits.");
+    let tar_path = Path::new("tests/data/base.tar");
+    for format in [SplitFormat::Zst, SplitFormat::Xz, SplitFormat::None] {
+        let split_out_path = format!("tests/out_split_format_{}", format);
+        let merge_out_path = format!("tests/out_merge_format_{}", format);
+        testcase_initial(vec![&split_out_path, &merge_out_path]);
+
+        let split_report = SplitJob::new(tar_path)
+            .split("os", SplitSpec::Count(1))
+            .split("lib", SplitSpec::Rest)
+            .split("app", SplitSpec::Count(1))
+            .out_dir(&split_out_path)
+            .format(format)
+            .run()?;
+        let os_path = Path::new(&split_out_path).join(format!("os.{}", format.extension()));
+        assert_eq!(split_report.splits[0].path, os_path);
+        assert_eq!(SplitFormat::detect(&os_path), Some(format));
+        let verify_vec = verify_splits(Path::new(&split_out_path))?;
+        for (split, verify) in split_report.splits.iter().zip(verify_vec.iter()) {
+            assert!(verify.passed());
+            assert_eq!(split.digest, verify.digest);
+        }
+
+        let merge_report = MergeJob::new(&split_out_path)
+            .out_dir(&merge_out_path)
+            .run()?;
+        assert_eq!(merge_report.splits.len(), 3);
+        assert_eq!(merge_report.splits[0], os_path);
+        let tar_right =
+            "a82e3d4bcf3194ec7841f6f1f2b4ce34d1107c23ef4e42d4e5073224858cc56b".to_string();
+        assert_eq!(merge_report.digest, tar_right);
+
+        testcase_destroy(vec![&split_out_path, &merge_out_path]);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_split_stream() -> Result<()> {
+    log::info!("Test for split procedure streaming image tar file into splits.");
+    let tar_path = Path::new("tests/data/base.tar");
+    let work_path = Path::new("tests/work_split_stream");
+    let stream_out_path = Path::new("tests/out_split_stream");
+    let extract_out_path = Path::new("tests/out_split_extract");
+    for format in SplitFormat::ALL {
+        testcase_initial(vec![stream_out_path, extract_out_path]);
+        for (stream, out_path) in [(true, stream_out_path), (false, extract_out_path)] {
+            SplitJob::new(tar_path)
+                .split("os", SplitSpec::Count(1))
+                .split("lib", SplitSpec::Rest)
+                .split("app", SplitSpec::Count(1))
+                .work_dir(work_path)
+                .out_dir(out_path)
+                .format(format)
+                .stream(stream)
+                .run()?;
+            // only the unique working directory inside is removed
+            assert!(fs::read_dir(work_path)?.next().is_none());
+        }
+        // splits streamed are the same as splits packed after extracting
+        for name in ["os", "lib", "app"] {
+            let split_name = format!("{}.{}", name, format.extension());
+            let stream_split = fs::read(stream_out_path.join(&split_name)).unwrap_or_default();
+            let extract_split = fs::read(extract_out_path.join(&split_name)).unwrap_or_default();
+            assert!(!stream_split.is_empty());
+            assert!(stream_split == extract_split);
+        }
+        testcase_destroy(vec![stream_out_path, extract_out_path]);
+    }
+    testcase_destroy(vec![work_path]);
+    Ok(())
+}
+
+#[test]
+fn test_merge_stream() -> Result<()> {
+    log::info!("Test for merge procedure streaming splits into image tar file.");
+    let target_path = Path::new("tests/data/splits_base");
+    let work_path = Path::new("tests/work_merge_stream");
+    let stream_out_path = Path::new("tests/out_merge_stream");
+    let extract_out_path = Path::new("tests/out_merge_extract");
+    testcase_initial(vec![stream_out_path, extract_out_path]);
+
+    let mut digest_vec: Vec<String> = Vec::new();
+    for (stream, out_path) in [(true, stream_out_path), (false, extract_out_path)] {
+        let merge_report = MergeJob::new(target_path)
+            .work_dir(work_path)
+            .out_dir(out_path)
+            .stream(stream)
+            .run()?;
+        assert!(fs::read_dir(work_path)?.next().is_none());
+        assert_eq!(merge_report.splits.len(), 3);
+        assert_eq!(merge_report.image_digests.len(), 1);
+        assert_eq!(merge_report.digest, fetch_file_sha256(&merge_report.path)?);
+        digest_vec.push(merge_report.digest);
+    }
+    // image tar streamed is the same as image tar packed after extracting
+    let tar_right =
+        "a82e3d4bcf3194ec7841f6f1f2b4ce34d1107c23ef4e42d4e5073224858cc56b".to_string();
+    assert_eq!(digest_vec, vec![tar_right.clone(), tar_right]);
+
+    testcase_destroy(vec![work_path, stream_out_path, extract_out_path]);
+    Ok(())
+}
+
+#[test]
+fn test_split_publish() -> Result<()> {
+    log::info!("Test for split procedure publishing splits with a sums file.");
+    let tar_path = Path::new("tests/data/base.tar");
+    let out_path = Path::new("tests/out_split_publish");
+    for stream in [true, false] {
+        testcase_initial(vec![out_path]);
+        let split_report = SplitJob::new(tar_path)
+            .split("os", SplitSpec::Count(1))
+            .split("lib", SplitSpec::Rest)
+            .split("app", SplitSpec::Count(1))
+            .out_dir(out_path)
+            .stream(stream)
+            .run()?;
+        // sums file lists every split file by name, and no part file is left
+        let sums = fs::read_to_string(out_path.join(SPLIT_SUMS_NAME))?;
+        let mut sums_right: Vec<String> = Vec::new();
+        for split in split_report.splits.iter() {
+            let file_name = split.path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            sums_right.push(format!("{}  {}", fetch_file_sha256(&split.path)?, file_name));
+        }
+        sums_right.sort_by_key(|line| line[66..].to_string());
+        assert_eq!(sums.lines().collect::<Vec<&str>>(), sums_right);
+        let file_num = fs::read_dir(out_path)?.count();
+        assert_eq!(file_num, 4);
+        testcase_destroy(vec![out_path]);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_split_size_strategy() -> Result<()> {
+    log::info!("Test for split procedure planning splits by sizes of layers.");
+    let tar_path = Path::new("tests/data/base.tar");
+    let split_out_path = Path::new("tests/out_split_size_strategy");
+    let merge_out_path = Path::new("tests/out_merge_size_strategy");
+
+    // layers are 15360, 1536, 1536, 1536 and 1536 bytes
+    testcase_initial(vec![split_out_path, merge_out_path]);
+    let split_report = SplitJob::new(tar_path)
+        .strategy(SplitStrategy::MaxSize(16 * 1024))
+        .out_dir(split_out_path)
+        .run()?;
+    let names: Vec<&str> = split_report.splits.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, vec!["part0", "part1"]);
+    let layer_nums: Vec<usize> = split_report.splits.iter().map(|s| s.dir_hashes.len()).collect();
+    assert_eq!(layer_nums, vec![1, 4]);
+    let merge_report = MergeJob::new(split_out_path)
+        .out_dir(merge_out_path)
+        .run()?;
+    let tar_right =
+        "a82e3d4bcf3194ec7841f6f1f2b4ce34d1107c23ef4e42d4e5073224858cc56b".to_string();
+    assert_eq!(merge_report.digest, tar_right);
+    testcase_destroy(vec![split_out_path, merge_out_path]);
+
+    testcase_initial(vec![split_out_path]);
+    let split_report = SplitJob::new(tar_path)
+        .strategy(SplitStrategy::Balanced(3))
+        .names(vec![format!("os"), format!("lib"), format!("app")])
+        .out_dir(split_out_path)
+        .run()?;
+    let names: Vec<&str> = split_report.splits.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, vec!["os", "lib", "app"]);
+    let layer_nums: Vec<usize> = split_report.splits.iter().map(|s| s.dir_hashes.len()).collect();
+    assert_eq!(layer_nums, vec![1, 2, 2]);
+    testcase_destroy(vec![split_out_path]);
+    Ok(())
+}
+
+/// write an image tar file holding only manifest and config of the bottom layers of base.tar
+fn write_reference_tar(extract_path: &Path, layer_num: usize, tar_path: &Path) -> Result<()> {
+    let manifest = json::parse(&fs::read_to_string(extract_path.join("manifest.json"))?)?;
+    let config_name = manifest[0]["Config"].to_string();
+    let config = json::parse(&fs::read_to_string(extract_path.join(&config_name))?)?;
+    let diff_ids: Vec<String> = config["rootfs"]["diff_ids"]
+        .members()
+        .take(layer_num)
+        .map(|id| id.to_string())
+        .collect();
+    let layers: Vec<String> = manifest[0]["Layers"]
+        .members()
+        .take(layer_num)
+        .map(|layer| layer.to_string())
+        .collect();
+    let mut reference_manifest = json::JsonValue::new_array();
+    reference_manifest.push(json::object! {
+        Config: "reference.json",
+        RepoTags: vec!["reference:latest"],
+        Layers: layers
+    })?;
+    let mut reference_config = json::JsonValue::new_object();
+    reference_config["rootfs"]["type"] = "layers".into();
+    reference_config["rootfs"]["diff_ids"] = diff_ids.into();
+
+    let mut builder = tar::Builder::new(fs::File::create(tar_path)?);
+    for (name, value) in [("manifest.json", reference_manifest), ("reference.json", reference_config)] {
+        let data = json::stringify(value);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, name, data.as_bytes())?;
+    }
+    builder.finish()?;
+    Ok(())
+}
+
+#[test]
+fn test_split_reference() -> Result<()> {
+    log::info!("Test for split procedure planning splits by reference images.");
+    let tar_path = Path::new("tests/data/base.tar");
+    let reference_path = Path::new("tests/reference_split_reference");
+    let split_out_path = Path::new("tests/out_split_reference");
+    let merge_out_path = Path::new("tests/out_merge_reference");
+
+    testcase_initial(vec![reference_path, split_out_path, merge_out_path]);
+    let extract_path = reference_path.join("base");
+    extract_tar(tar_path, extract_path.as_path())?;
+    let os_path = reference_path.join("os.tar");
+    let lib_path = reference_path.join("lib.tar");
+    write_reference_tar(&extract_path, 1, &os_path)?;
+    write_reference_tar(&extract_path, 3, &lib_path)?;
+
+    // references are sorted by their layers
+    let split_report = SplitJob::new(tar_path)
+        .reference(&lib_path)
+        .reference(&os_path)
+        .names(vec![format!("os"), format!("lib"), format!("app")])
+        .out_dir(split_out_path)
+        .run()?;
+    let names: Vec<&str> = split_report.splits.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, vec!["os", "lib", "app"]);
+    let layer_nums: Vec<usize> = split_report.splits.iter().map(|s| s.dir_hashes.len()).collect();
+    assert_eq!(layer_nums, vec![1, 2, 2]);
+    let merge_report = MergeJob::new(split_out_path)
+        .out_dir(merge_out_path)
+        .run()?;
+    let tar_right =
+        "a82e3d4bcf3194ec7841f6f1f2b4ce34d1107c23ef4e42d4e5073224858cc56b".to_string();
+    assert_eq!(merge_report.digest, tar_right);
+    testcase_destroy(vec![split_out_path, merge_out_path]);
+
+    // the split below reference boundary is the same as the one split by layer numbers
+    testcase_initial(vec![split_out_path, merge_out_path]);
+    let split_report = SplitJob::new(tar_path)
+        .reference(&os_path)
+        .out_dir(split_out_path)
+        .run()?;
+    let names: Vec<&str> = split_report.splits.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, vec!["part0", "part1"]);
+    let os_right = SplitJob::new(tar_path)
+        .split("part0", SplitSpec::Count(1))
+        .split("part1", SplitSpec::Rest)
+        .out_dir(merge_out_path)
+        .run()?;
+    assert_eq!(split_report.splits[0].digest, os_right.splits[0].digest);
+    testcase_destroy(vec![split_out_path, merge_out_path]);
+
+    // another image built on the same reference gets the same layers below reference boundary,
+    // while its split records another source image
+    testcase_initial(vec![split_out_path, merge_out_path]);
+    SplitJob::new("tests/data/multi.tar")
+        .split("os", SplitSpec::Count(2))
+        .split("app", SplitSpec::Rest)
+        .out_dir(split_out_path)
+        .run()?;
+    let other_report = MergeJob::new(split_out_path)
+        .image("hello-world:l3")
+        .out_dir(reference_path.join("other"))
+        .run()?;
+    testcase_destroy(vec![split_out_path]);
+    let split_report = SplitJob::new(tar_path)
+        .reference(&os_path)
+        .names(vec![format!("os"), format!("app")])
+        .out_dir(split_out_path)
+        .run()?;
+    let other_split_report = SplitJob::new(&other_report.path)
+        .reference(&os_path)
+        .names(vec![format!("os"), format!("app")])
+        .out_dir(merge_out_path)
+        .run()?;
+    assert_ne!(split_report.image_digests, other_split_report.image_digests);
+    assert_eq!(split_report.splits[0].diff_ids, other_split_report.splits[0].diff_ids);
+    assert_eq!(split_report.splits[0].dir_hashes, other_split_report.splits[0].dir_hashes);
+    let other_meta = SplitMeta::from_json(
+        &fetch_split_config(&other_split_report.splits[0].path).unwrap_or(json::JsonValue::Null));
+    assert_eq!(other_meta.images[0].repo_tags, vec!["hello-world:l3"]);
+    testcase_destroy(vec![reference_path, split_out_path, merge_out_path]);
+    Ok(())
+}
+
+#[test]
+fn test_split_history() -> Result<()> {
+    log::info!("Test for split procedure planning splits by history of image.");
+    let tar_path = Path::new("tests/data/base.tar");
+    let split_out_path = Path::new("tests/out_split_history");
+    let merge_out_path = Path::new("tests/out_merge_history");
+
+    // layers are built by 'COPY', 'CMD'(empty), 'COPY' * 4
+    testcase_initial(vec![split_out_path, merge_out_path]);
+    let split_report = SplitJob::new(tar_path)
+        .history_rule(HistoryRule::After("file:4a2f4009".to_string()))
+        .history_rule(HistoryRule::After("CMD".to_string()))
+        .names(vec![format!("os"), format!("lib"), format!("app")])
+        .out_dir(split_out_path)
+        .run()?;
+    let names: Vec<&str> = split_report.splits.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, vec!["os", "lib", "app"]);
+    let layer_nums: Vec<usize> = split_report.splits.iter().map(|s| s.dir_hashes.len()).collect();
+    assert_eq!(layer_nums, vec![1, 2, 2]);
+    let merge_report = MergeJob::new(split_out_path)
+        .out_dir(merge_out_path)
+        .run()?;
+    let tar_right =
+        "a82e3d4bcf3194ec7841f6f1f2b4ce34d1107c23ef4e42d4e5073224858cc56b".to_string();
+    assert_eq!(merge_report.digest, tar_right);
+    testcase_destroy(vec![split_out_path, merge_out_path]);
+
+    // matching the top layer leaves no split above it
+    testcase_initial(vec![split_out_path]);
+    let split_report = SplitJob::new(tar_path)
+        .history_rule(HistoryRule::After("COPY".to_string()))
+        .out_dir(split_out_path)
+        .run()?;
+    let names: Vec<&str> = split_report.splits.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, vec!["part0"]);
+    assert_eq!(split_report.splits[0].dir_hashes.len(), 5);
+    testcase_destroy(vec![split_out_path]);
+    Ok(())
+}
+
+#[test]
+fn test_split_config_diff_ids() -> Result<()> {
+    log::info!("Test for split procedure taking splits of diff_ids from config file.");
+    let tar_path = Path::new("tests/data/base.tar");
+    let split_out_path = Path::new("tests/out_split_config_diff_ids");
+    let merge_out_path = Path::new("tests/out_merge_config_diff_ids");
+    testcase_initial(vec![split_out_path, merge_out_path]);
+
+    let config = SplitConfig::load("tests/data/config.yaml")?;
+    let split_report = SplitJob::new(tar_path)
+        .config(&config)
+        .out_dir(split_out_path)
+        .run()?;
+    let layer_nums: Vec<usize> = split_report.splits.iter().map(|s| s.dir_hashes.len()).collect();
+    assert_eq!(layer_nums, vec![1, 3, 1]);
+    let os_hash = fetch_file_sha256(&split_report.splits[0].path)?;
+    let os_right =
+        "76c6dcc7eeae8e9fb05604920e8b59b3546531095162e5a5afaf6437b7510256".to_string();
+    assert_eq!(os_hash, os_right);
+    let merge_report = MergeJob::new(split_out_path)
+        .out_dir(merge_out_path)
+        .run()?;
+    let tar_right =
+        "a82e3d4bcf3194ec7841f6f1f2b4ce34d1107c23ef4e42d4e5073224858cc56b".to_string();
+    assert_eq!(merge_report.digest, tar_right);
+    testcase_destroy(vec![split_out_path, merge_out_path]);
+
+    // diff_ids out of place of the split
+    testcase_initial(vec![split_out_path]);
+    let diff_id = "sha256:bb99f93a88e699d44eda3d1778f3f3589777fed1c472ce469a772d9cf97640c5".to_string();
+    let result = SplitJob::new(tar_path)
+        .split("os", SplitSpec::DiffIds(vec![diff_id]))
+        .split("app", SplitSpec::Rest)
+        .out_dir(split_out_path)
+        .run();
+    assert!(result.is_err());
+    testcase_destroy(vec![split_out_path]);
+    Ok(())
+}
+
+#[test]
+fn test_merge_upto() -> Result<()> {
+    log::info!("Test for merge procedure merging splits up to one of them into a base image.");
+    let tar_path = Path::new("tests/data/base.tar");
+    let split_out_path = Path::new("tests/out_split_upto");
+    let merge_out_path = Path::new("tests/out_merge_upto");
+    let extract_path = Path::new("tests/work_merge_upto");
+    testcase_initial(vec![split_out_path, merge_out_path, extract_path]);
+
+    SplitJob::new(tar_path)
+        .split("os", SplitSpec::Count(1))
+        .split("lib", SplitSpec::Rest)
+        .split("app", SplitSpec::Count(1))
+        .out_dir(split_out_path)
+        .run()?;
+    let merge_report = MergeJob::new(split_out_path)
+        .out_dir(merge_out_path)
+        .upto("lib")
+        .run()?;
+    assert_eq!(merge_report.path, merge_out_path.join("lib.tar"));
+    assert_eq!(merge_report.splits.len(), 3);
+
+    // only layers of 'os' and 'lib' are left inside a valid image
+    fs::create_dir_all(extract_path)?;
+    extract_tar(merge_report.path.as_path(), extract_path)?;
+    let image_vec = BaseInspector {}.inspect_all(extract_path)?;
+    assert_eq!(image_vec.len(), 1);
+    assert_eq!(image_vec[0].layer_dir_vec.len(), 4);
+    assert!(image_vec[0].repo_tags.is_empty());
+    let layer_vec = BaseInspector {}.describe_layers(&image_vec[0])?;
+    let diff_id_vec: Vec<&str> = layer_vec.iter().map(|l| l.diff_id.as_str()).collect();
+    assert_eq!(diff_id_vec[0],
+               "sha256:9c27e219663c25e0f28493790cc0b88bc973ba3b1686355f221c38a36978ac63");
+    assert!(layer_vec.iter().all(|l| l.created_by.contains("COPY")));
+    assert_eq!(merge_report.image_digests.len(), 1);
+    testcase_destroy(vec![merge_out_path, extract_path]);
+
+    // the top split by index merges the whole image
+    testcase_initial(vec![merge_out_path]);
+    let merge_report = MergeJob::new(split_out_path)
+        .out_dir(merge_out_path)
+        .upto("2")
+        .run()?;
+    let tar_right =
+        "a82e3d4bcf3194ec7841f6f1f2b4ce34d1107c23ef4e42d4e5073224858cc56b".to_string();
+    assert_eq!(merge_report.digest, tar_right);
+    testcase_destroy(vec![split_out_path, merge_out_path]);
+    Ok(())
+}
+
+#[test]
+fn test_split_meta() -> Result<()> {
+    log::info!("Test for metadata of source image recorded inside every split.");
+    let tar_path = Path::new("tests/data/base.tar");
+    let split_out_path = Path::new("tests/out_split_meta");
+    let merge_out_path = Path::new("tests/out_merge_meta");
+    testcase_initial(vec![split_out_path, merge_out_path]);
+
+    let split_report = SplitJob::new(tar_path)
+        .split("os", SplitSpec::Count(1))
+        .split("lib", SplitSpec::Rest)
+        .split("app", SplitSpec::Count(1))
+        .out_dir(split_out_path)
+        .run()?;
+    for split in split_report.splits.iter() {
+        let config = fetch_split_config(&split.path).unwrap_or(json::JsonValue::Null);
+        let meta = SplitMeta::from_json(&config);
+        assert_eq!(meta.images.len(), 1);
+        assert_eq!(meta.images[0].config_digest, split_report.image_digests[0]);
+        assert_eq!(meta.images[0].repo_tags, vec!["hello-world:l5"]);
+        assert_eq!(meta.split_names, vec!["os", "lib", "app"]);
+        assert_eq!(meta.diff_ids, split.diff_ids);
+        assert_eq!(config["split_count"].as_usize(), Some(3));
+    }
+
+    // without sums file, a missing top split is named by the splits below it
+    fs::remove_file(split_out_path.join(SPLIT_SUMS_NAME))?;
+    fs::remove_file(&split_report.splits[2].path)?;
+    let verify_vec = verify_splits(split_out_path)?;
+    assert_eq!(verify_vec[1].errors,
+               vec!["Split 'app' (index 2 of 3) of image hello-world:l5 is missing"]);
+    let result = MergeJob::new(split_out_path)
+        .out_dir(merge_out_path)
+        .run();
+    match result {
+        Err(LayerSwordError::FileCheckError(FileCheckError::SplitMissingError {
+                                                name, index, count, image })) => {
+            assert_eq!((name.as_str(), index, count, image.as_str()),
+                       ("app", 2, 3, "hello-world:l5"));
+        }
+        _ => panic!("missing split is not reported"),
+    }
+
+    // so is a missing bottom split by the splits above it
+    fs::remove_file(&split_report.splits[0].path)?;
+    let verify_vec = verify_splits(split_out_path)?;
+    assert_eq!(verify_vec[0].errors,
+               vec!["Split 'os' (index 0 of 3) of image hello-world:l5 is missing",
+                    "Split 'app' (index 2 of 3) of image hello-world:l5 is missing"]);
+    testcase_destroy(vec![split_out_path, merge_out_path]);
+
+    // a split shared by several images records all of them
+    testcase_initial(vec![split_out_path]);
+    let split_report = SplitJob::new("tests/data/multi.tar")
+        .split("os", SplitSpec::Count(2))
+        .split("app", SplitSpec::Rest)
+        .out_dir(split_out_path)
+        .run()?;
+    let config = fetch_split_config(&split_report.splits[0].path).unwrap_or(json::JsonValue::Null);
+    let meta = SplitMeta::from_json(&config);
+    assert_eq!(meta.images.len(), 2);
+    assert_eq!(meta.image_name(), "hello-world:l5, hello-world:l3");
+    testcase_destroy(vec![split_out_path]);
+    Ok(())
+}
+
+#[test]
+fn test_merge_sources() -> Result<()> {
+    log::info!("Test for merge procedure picking the chain among split files of several directories.");
+    let tar_path = Path::new("tests/data/base.tar");
+    let split_out_path = Path::new("tests/out_split_sources");
+    let other_out_path = Path::new("tests/out_split_sources_other");
+    let os_dir_path = Path::new("tests/target_merge_sources_os");
+    let app_dir_path = Path::new("tests/target_merge_sources_app");
+    let merge_out_path = Path::new("tests/out_merge_sources");
+    testcase_initial(vec![split_out_path, other_out_path, os_dir_path, app_dir_path]);
+
+    SplitJob::new(tar_path)
+        .split("os", SplitSpec::Count(1))
+        .split("lib", SplitSpec::Rest)
+        .split("app", SplitSpec::Count(1))
+        .out_dir(split_out_path)
+        .run()?;
+    SplitJob::new(tar_path)
+        .split("os", SplitSpec::Count(1))
+        .split("app", SplitSpec::Rest)
+        .out_dir(other_out_path)
+        .run()?;
+    fs::copy(split_out_path.join("os.tar.gz"), os_dir_path.join("os.tar.gz"))?;
+    fs::copy(split_out_path.join("lib.tar.gz"), app_dir_path.join("lib.tar.gz"))?;
+    fs::copy(split_out_path.join("app.tar.gz"), app_dir_path.join("app.tar.gz"))?;
+    // an app split of another chain and a file other than splits are ignored
+    fs::copy(other_out_path.join("app.tar.gz"), app_dir_path.join("other.tar.gz"))?;
+    fs::write(app_dir_path.join("notes.txt"), "not a split")?;
+
+    let tar_right =
+        "a82e3d4bcf3194ec7841f6f1f2b4ce34d1107c23ef4e42d4e5073224858cc56b".to_string();
+    for stream in [true, false] {
+        testcase_initial(vec![merge_out_path]);
+        let merge_report = MergeJob::new(app_dir_path)
+            .source(os_dir_path)
+            .out_dir(merge_out_path)
+            .stream(stream)
+            .run()?;
+        assert_eq!(merge_report.digest, tar_right);
+        assert_eq!(merge_report.splits, vec![os_dir_path.join("os.tar.gz"),
+                                             app_dir_path.join("lib.tar.gz"),
+                                             app_dir_path.join("app.tar.gz")]);
+        testcase_destroy(vec![merge_out_path]);
+    }
+
+    // a split given twice from several places is only merged once
+    testcase_initial(vec![merge_out_path]);
+    let merge_report = MergeJob::new(split_out_path.join("os.tar.gz"))
+        .source(os_dir_path)
+        .source(app_dir_path)
+        .out_dir(merge_out_path)
+        .stream(false)
+        .run()?;
+    assert_eq!(merge_report.digest, tar_right);
+    assert_eq!(merge_report.splits.len(), 3);
+    assert_eq!(merge_report.splits[0], split_out_path.join("os.tar.gz"));
+
+    // with no complete chain, the missing split is still reported
+    let result = MergeJob::new(app_dir_path)
+        .out_dir(merge_out_path)
+        .force(true)
+        .run();
+    assert!(matches!(result, Err(LayerSwordError::FileCheckError(
+        FileCheckError::SplitMissingError { .. }))));
+    testcase_destroy(vec![split_out_path, other_out_path, os_dir_path, app_dir_path,
+                          merge_out_path]);
+    Ok(())
+}
+
+#[test]
+fn test_merge_chains() -> Result<()> {
+    log::info!("Test for merge procedure choosing one of the chains of splits from several tar files.");
+    let base_out_path = Path::new("tests/out_split_chains_base");
+    let multi_out_path = Path::new("tests/out_split_chains_multi");
+    let target_path = Path::new("tests/target_merge_chains");
+    let merge_out_path = Path::new("tests/out_merge_chains");
+    testcase_initial(vec![base_out_path, multi_out_path, target_path]);
+
+    SplitJob::new("tests/data/base.tar")
+        .split("os", SplitSpec::Count(1))
+        .split("lib", SplitSpec::Rest)
+        .split("app", SplitSpec::Count(1))
+        .out_dir(base_out_path)
+        .run()?;
+    SplitJob::new("tests/data/multi.tar")
+        .split("os", SplitSpec::Count(2))
+        .split("app", SplitSpec::Rest)
+        .out_dir(multi_out_path)
+        .run()?;
+    // both tar files have a split named 'os' at index 0
+    for name in ["os", "lib", "app"] {
+        fs::copy(base_out_path.join(format!("{}.tar.gz", name)),
+                 target_path.join(format!("{}.tar.gz", name)))?;
+    }
+    for name in ["os", "app-0", "app-1"] {
+        fs::copy(multi_out_path.join(format!("{}.tar.gz", name)),
+                 target_path.join(format!("multi-{}.tar.gz", name)))?;
+    }
+
+    let chain_vec = MergeJob::new(target_path).list()?;
+    assert_eq!(chain_vec.len(), 3);
+    let mut tag_vec: Vec<String> = chain_vec.iter().map(|chain| chain.repo_tags.join(",")).collect();
+    tag_vec.sort();
+    assert_eq!(tag_vec, vec!["hello-world:l3", "hello-world:l5", "hello-world:l5"]);
+    let chain = chain_vec
+        .iter()
+        .find(|chain| chain.repo_tags == vec![format!("hello-world:l3")])
+        .unwrap();
+    assert_eq!(chain.splits, vec![target_path.join("multi-os.tar.gz"),
+                                  target_path.join("multi-app-1.tar.gz")]);
+
+    // images of different tar files are not merged together
+    let result = MergeJob::new(target_path).out_dir(merge_out_path).run();
+    assert!(matches!(result, Err(LayerSwordError::FileCheckError(
+        FileCheckError::ImageSelectError { .. }))));
+    let result = MergeJob::new(target_path).out_dir(merge_out_path).image("hello-world:l5").run();
+    assert!(matches!(result, Err(LayerSwordError::FileCheckError(
+        FileCheckError::ImageSelectError { .. }))));
+    testcase_destroy(vec![merge_out_path]);
+
+    for stream in [true, false] {
+        testcase_initial(vec![merge_out_path]);
+        let merge_report = MergeJob::new(target_path)
+            .out_dir(merge_out_path)
+            .image("hello-world:l3")
+            .stream(stream)
+            .run()?;
+        assert_eq!(merge_report.path, merge_out_path.join("hello-world_l3.tar"));
+        assert_eq!(merge_report.splits, chain.splits);
+        testcase_destroy(vec![merge_out_path]);
+    }
+
+    // the only complete chain is merged without a tag
+    fs::remove_file(target_path.join("lib.tar.gz"))?;
+    fs::remove_file(target_path.join("multi-app-0.tar.gz"))?;
+    testcase_initial(vec![merge_out_path]);
+    let merge_report = MergeJob::new(target_path).out_dir(merge_out_path).run()?;
+    assert_eq!(merge_report.splits, chain.splits);
+    testcase_destroy(vec![base_out_path, multi_out_path, target_path, merge_out_path]);
+    Ok(())
+}
+
+#[test]
+fn test_split_signed() -> Result<()> {
+    log::info!("Test for split procedure signing a manifest and merge procedure trusting it.");
+    let key_path = Path::new("tests/key_split_signed");
+    let split_out_path = Path::new("tests/out_split_signed");
+    let unsigned_out_path = Path::new("tests/out_split_unsigned");
+    let merge_out_path = Path::new("tests/out_merge_signed");
+    testcase_initial(vec![key_path, split_out_path, unsigned_out_path]);
+
+    let pub_path = generate_key(&key_path.join("release.key"))?;
+    assert_eq!(pub_path, key_path.join("release.pub"));
+    // secret key is only readable by its owner
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let key_mode = fs::metadata(key_path.join("release.key"))?.permissions().mode();
+        assert_eq!(key_mode & 0o777, 0o600);
+    }
+    // existing keys are never replaced
+    assert!(generate_key(&key_path.join("release.key")).is_err());
+    let other_pub_path = generate_key(&key_path.join("other.key"))?;
+
+    let split_report = SplitJob::new("tests/data/base.tar")
+        .split("os", SplitSpec::Count(1))
+        .split("lib", SplitSpec::Rest)
+        .split("app", SplitSpec::Count(1))
+        .out_dir(split_out_path)
+        .sign_key(key_path.join("release.key"))
+        .run()?;
+    let manifest_path = split_out_path.join(SPLIT_SIGNATURE_NAME);
+    assert_eq!(split_report.signature, Some(manifest_path.clone()));
+
+    let mut result_vec = verify_splits(split_out_path)?;
+    verify_signed(&mut result_vec, split_out_path, &load_trusted_key(&pub_path)?);
+    assert!(result_vec.iter().all(|split| split.passed()));
+
+    let tar_right =
+        "a82e3d4bcf3194ec7841f6f1f2b4ce34d1107c23ef4e42d4e5073224858cc56b".to_string();
+    for stream in [true, false] {
+        testcase_initial(vec![merge_out_path]);
+        let merge_report = MergeJob::new(split_out_path)
+            .out_dir(merge_out_path)
+            .trust(&pub_path)
+            .stream(stream)
+            .run()?;
+        assert_eq!(merge_report.digest, tar_right);
+
+        // a manifest signed by another key is refused
+        let result = MergeJob::new(split_out_path)
+            .out_dir(merge_out_path)
+            .force(true)
+            .trust(&other_pub_path)
+            .stream(stream)
+            .run();
+        assert!(matches!(result, Err(LayerSwordError::FileCheckError(
+            FileCheckError::SignatureError { .. }))));
+        testcase_destroy(vec![merge_out_path]);
+    }
+
+    // splits without a signed manifest are refused
+    SplitJob::new("tests/data/base.tar")
+        .split("os", SplitSpec::Count(1))
+        .split("lib", SplitSpec::Rest)
+        .split("app", SplitSpec::Count(1))
+        .out_dir(unsigned_out_path)
+        .run()?;
+    testcase_initial(vec![merge_out_path]);
+    let result = MergeJob::new(unsigned_out_path)
+        .out_dir(merge_out_path)
+        .trust(&pub_path)
+        .run();
+    assert!(matches!(result, Err(LayerSwordError::FileCheckError(
+        FileCheckError::SignatureError { .. }))));
+
+    // a manifest changed after signing is refused
+    let os_digest = result_vec[0].digest.clone();
+    let manifest = fs::read_to_string(&manifest_path)?;
+    let manifest = manifest.replacen(&os_digest, &"0".repeat(64), 1);
+    fs::write(&manifest_path, manifest)?;
+    let mut result_vec = verify_splits(split_out_path)?;
+    verify_signed(&mut result_vec, split_out_path, &load_trusted_key(&pub_path)?);
+    assert!(result_vec.iter().all(|split| !split.passed()));
+    let result = MergeJob::new(split_out_path)
+        .out_dir(merge_out_path)
+        .force(true)
+        .trust(&pub_path)
+        .run();
+    assert!(matches!(result, Err(LayerSwordError::FileCheckError(
+        FileCheckError::SignatureError { .. }))));
+    testcase_destroy(vec![key_path, split_out_path, unsigned_out_path, merge_out_path]);
+    Ok(())
+}
+
+#[test]
+fn test_job_work_dir() -> Result<()> {
+    log::info!("Test for jobs removing their own working directories on success and error.");
+    let work_path = Path::new("tests/work_job_work_dir");
+    let out_path = Path::new("tests/out_job_work_dir");
+    testcase_initial(vec![work_path]);
+
+    // too many layers are only found after working directory is created
+    let result = SplitJob::new("tests/data/base.tar")
+        .split("os", SplitSpec::Count(1))
+        .split("app", SplitSpec::Count(9))
+        .work_dir(work_path)
+        .out_dir(out_path)
+        .run();
+    assert!(result.is_err());
+    assert!(fs::read_dir(work_path)?.next().is_none());
+    // output directory created by the failed job is removed as well
+    assert!(!out_path.exists());
+
+    SplitJob::new("tests/data/base.tar")
+        .split("os", SplitSpec::Count(1))
+        .split("app", SplitSpec::Rest)
+        .work_dir(work_path)
+        .out_dir(out_path)
+        .run()?;
+    assert!(fs::read_dir(work_path)?.next().is_none());
+    assert!(out_path.join("app.tar.gz").is_file());
+
+    // a failed job never removes directories of jobs before it
+    let result = MergeJob::new("tests/data/splits_base")
+        .work_dir(work_path)
+        .out_dir(out_path)
+        .run();
+    assert!(result.is_err());
+    assert!(fs::read_dir(work_path)?.next().is_none());
+    assert!(out_path.join("app.tar.gz").is_file());
+
+    testcase_destroy(vec![work_path, out_path]);
+    Ok(())
+}
@@ -1,17 +1,151 @@
 #[cfg(test)]
+mod common;
+
+use std::fs::{self, File};
+use std::io;
 use std::path::Path;
 
-use layer_sword::util::fetch_file_sha256;
+use tar::{Builder, EntryType};
+
+use layer_sword::util::{fetch_file_sha256, extract_split, deterministic_header};
+use layer_sword::format::{compress_split, write_split, SplitFormat, PARALLEL_SPLIT_SIZE};
+use layer_sword::stream::TarIndex;
+use layer_sword::inspector::Inspect;
+use layer_sword::inspector::base::BaseInspector;
+use layer_sword::inspector::oci::OciInspector;
 use layer_sword::errors::LayerSwordError;
 
+use common::{testcase_initial, testcase_destroy};
+
 type Result<T> = core::result::Result<T, LayerSwordError>;
 
 #[test]
 fn test_file_sha256() -> Result<()> {
     log::info!("Test for 'fetch_file_sha256' function");
     let tar_path = Path::new("tests/data/base.tar");
-    let hash = fetch_file_sha256(tar_path);
+    let hash = fetch_file_sha256(tar_path)?;
     let right = format!("8de3e6511bb095f7d7d4133e877391f6ee1ec2bfda022bc24e2443277d3966b6");
     assert_eq!(hash, right);
     Ok(())
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_parallel_compress() -> Result<()> {
+    log::info!("Test for 'compress_split' function on a large split with several threads");
+    let work_path = Path::new("tests/work_parallel_compress");
+    testcase_initial(vec![work_path]);
+    let tar_path = work_path.join("big.tar");
+    let data: Vec<u8> = (0..PARALLEL_SPLIT_SIZE + 1000)
+        .map(|i| b"layer_sword"[(i / 4096 % 11) as usize])
+        .collect();
+    fs::write(&tar_path, data).unwrap_or_default();
+    let digest = fetch_file_sha256(&tar_path)?;
+
+    for format in [SplitFormat::Gz, SplitFormat::Zst] {
+        let mut split_vec: Vec<Vec<u8>> = Vec::new();
+        for threads in [1, 4] {
+            let split_path = work_path.join(format!("big-{}.{}", threads, format.extension()));
+            compress_split(&split_path, &tar_path, &digest, format, 1, threads)?;
+            split_vec.push(fs::read(&split_path).unwrap_or_default());
+        }
+        assert!(split_vec[0] == split_vec[1]);
+
+        // sha256 of inner tar is checked while extracting
+        let split_path = work_path.join(format!("big-4.{}", format.extension()));
+        let extract_path = work_path.join(format!("extract-{}", format));
+        fs::create_dir(&extract_path).unwrap_or_default();
+        extract_split(&split_path, &extract_path)?;
+    }
+
+    testcase_destroy(vec![work_path]);
+    Ok(())
+}
+
+#[test]
+fn test_tar_index() -> Result<()> {
+    log::info!("Test for 'TarIndex' reading headers of tar files");
+    let index = TarIndex::scan("tests/data/base.tar")?;
+    assert_eq!(index.item_map.len(), 23);
+    let layer_path = "81f23e23635f150b4daa84ed1b188c9d7b15d5eccf37437d23033c8aca5ce3cc/layer.tar";
+    assert_eq!(index.item_map[layer_path].size, 15360);
+    assert!(index.item_map[layer_path.trim_end_matches("/layer.tar")].entry_type.is_dir());
+    assert!(index.is_plain());
+
+    // long paths are kept inside gnu long name entries
+    let work_path = Path::new("tests/work_tar_index");
+    testcase_initial(vec![work_path]);
+    let tar_path = work_path.join("long.tar");
+    let long_name = format!("{}/layer.tar", "a".repeat(120));
+    let mut tar = Builder::new(File::create(&tar_path)?);
+    let mut header = deterministic_header(EntryType::Regular, false, 5);
+    tar.append_data(&mut header, &long_name, "hello".as_bytes())?;
+    tar.into_inner()?;
+    let index = TarIndex::scan(&tar_path)?;
+    let item = &index.item_map[&long_name];
+    let data = fs::read(&tar_path).unwrap_or_default();
+    assert_eq!(&data[item.offset as usize..(item.offset + item.size) as usize], b"hello");
+
+    testcase_destroy(vec![work_path]);
+    Ok(())
+}
+
+#[test]
+fn test_write_split() -> Result<()> {
+    log::info!("Test for 'write_split' function patching sha256 into split files");
+    let work_path = Path::new("tests/work_write_split");
+    testcase_initial(vec![work_path]);
+    let tar_path = Path::new("tests/data/base.tar");
+    let digest = fetch_file_sha256(tar_path)?;
+    let size = fs::metadata(tar_path)?.len();
+    for format in SplitFormat::ALL {
+        let compress_path = work_path.join(format!("base.{}", format.extension()));
+        compress_split(&compress_path, &tar_path.to_path_buf(), &digest, format, 6, 1)?;
+        let write_path = work_path.join(format!("write.{}", format.extension()));
+        let write_digest = write_split(&write_path, "base.tar", size, format, 6, 1, |writer| {
+            io::copy(&mut File::open(tar_path)?, writer)?;
+            fetch_file_sha256(tar_path)
+        })?;
+        assert_eq!(write_digest, digest);
+        let compress_split = fs::read(&compress_path).unwrap_or_default();
+        let write_split = fs::read(&write_path).unwrap_or_default();
+        assert!(compress_split == write_split);
+    }
+
+    testcase_destroy(vec![work_path]);
+    Ok(())
+}
+
+#[test]
+fn test_layer_sizes() -> Result<()> {
+    log::info!("Test for 'fetch_layer_sizes' function without extracting image tar files");
+    let tar_path = Path::new("tests/data/base.tar");
+    let index = TarIndex::scan(tar_path)?;
+    let size_vec_vec = BaseInspector {}.fetch_layer_sizes(&index, tar_path)?;
+    assert_eq!(size_vec_vec, vec![vec![15360, 1536, 1536, 1536, 1536]]);
+
+    let tar_path = Path::new("tests/data/oci.tar");
+    let index = TarIndex::scan(tar_path)?;
+    let size_vec_vec = OciInspector {}.fetch_layer_sizes(&index, tar_path)?;
+    assert_eq!(size_vec_vec.len(), 1);
+    assert!(!size_vec_vec[0].is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_diff_ids() -> Result<()> {
+    log::info!("Test for 'fetch_diff_ids' function without extracting image tar files");
+    let tar_path = Path::new("tests/data/base.tar");
+    let index = TarIndex::scan(tar_path)?;
+    let diff_id_vec_vec = BaseInspector {}.fetch_diff_ids(&|path| index.load_item(tar_path, path))?;
+    assert_eq!(diff_id_vec_vec.len(), 1);
+    assert_eq!(diff_id_vec_vec[0].len(), 5);
+    // hashes are kept without 'sha256:' prefix
+    assert!(diff_id_vec_vec[0].iter().all(|hash| hash.len() == 64));
+
+    let tar_path = Path::new("tests/data/oci.tar");
+    let index = TarIndex::scan(tar_path)?;
+    let diff_id_vec_vec = OciInspector {}.fetch_diff_ids(&|path| index.load_item(tar_path, path))?;
+    assert_eq!(diff_id_vec_vec.len(), 1);
+    assert_eq!(diff_id_vec_vec[0].len(), OciInspector {}.fetch_layer_sizes(&index, tar_path)?[0].len());
+    Ok(())
+}